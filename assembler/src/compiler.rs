@@ -1,7 +1,8 @@
-use machine::internal::opcode::{Opcode, OpcodeVariant};
-use std::{collections::HashMap};
+use binary::{errors::FormatError, image::{Image, Symbol, SymbolKind, MAX_CELLS}, object::{Import, Object, Relocation, RelocationTarget}};
+use machine::internal::opcode::{JumpTarget, Opcode, OpcodeVariant};
+use std::{collections::{HashMap, HashSet}, path::PathBuf};
 
-use crate::{parser::parse_program, tokens::DataType};
+use crate::{debug::{DataSymbol, DebugInfo, LabelSymbol, LineInfo}, diagnostic::{has_errors, Diagnostic, Severity}, expr::{Base, DataSize, EvalError, Evaluator, Value}, lint::{allows, lint_code, Lint}, listing::{Listing, ListingCode, ListingFile, ListingLine, MapSymbol, Section, SymbolMap}, optimizer::optimize, parser::unescape, source::{strip_comment, SourceSet}, tokens::{anonymous_direction, is_block_label, is_local_label, BinaryOp, ConstValue, DataType, Expr, JumpOperand, LineToken, MetaType, SymbolName, Token, ANONYMOUS_LABEL, BLOCK_LABEL_PREFIX}, verifier::{verify_stack, Program}};
 
 fn combine_hl(high: u32, low: u32) -> u32 {
     // mask to ensure only 16 bits are taken
@@ -12,16 +13,53 @@ fn combine_hl(high: u32, low: u32) -> u32 {
 pub struct CompiledFrame {
     pub binary: Vec<u32>,
     pub header: Header,
+    /// source level information, only present when requested in `CompileOptions`
+    pub debug: Option<DebugInfo>,
+    /// only present when requested in `CompileOptions`
+    pub listing: Option<Listing>,
+    /// only present when requested in `CompileOptions`
+    pub map: Option<SymbolMap>,
+    pub warnings: Vec<Diagnostic>,
 }
 
 #[derive(Debug)]
 pub struct Header {
     pub origin: u32,
+    /// absolute address of `.start`, `origin` is already added (it used to be relative to `origin`)
     pub start: u32,
+    /// number of cells at the beginning of `binary` that hold code, the rest is data
+    pub text_size: u32,
+}
+
+impl CompiledFrame {
+    /// package compiled code into a binary container image, one that `Image::read` accepts
+    pub fn image(&self, stack_size: u32) -> Result<Image, FormatError> {
+        let (text, data) = self.binary.split_at(self.header.text_size as usize);
+        let mut symbols = Vec::new();
+        if let Some(debug) = &self.debug {
+            symbols.extend(debug.labels.iter().map(|l| Symbol { name: l.name.clone(), kind: SymbolKind::Label, address: l.address, size: 0 }));
+            symbols.extend(debug.data.iter().map(|d| Symbol { name: d.name.clone(), kind: SymbolKind::Data, address: d.address, size: d.size }));
+        }
+        let min_cells = self.header.origin.checked_add(self.binary.len() as u32)
+            .and_then(|end| end.checked_add(stack_size))
+            .ok_or_else(|| FormatError::TooLarge(format!("{} stack cells", stack_size)))?;
+        let image = Image {
+            origin: self.header.origin,
+            start: self.header.start,
+            min_cells,
+            stack_size,
+            text: text.to_vec(),
+            data: data.to_vec(),
+            symbols,
+            debug: self.debug.as_ref().map(|d| d.to_string()),
+        };
+        image.validate()?;
+        Ok(image)
+    }
 }
 
 fn pack_u16_to_u32(v: Vec<u16>) -> Vec<u32> {
-    let mut out = Vec::with_capacity((v.len() + 1) / 2);
+    let mut out = Vec::with_capacity(v.len().div_ceil(2));
 
     let mut iter = v.into_iter();
     while let Some(high) = iter.next() {
@@ -40,7 +78,7 @@ fn pack_u16_to_u32(v: Vec<u16>) -> Vec<u32> {
 }
 
 fn pack_u8_to_u32(v: Vec<u8>) -> Vec<u32> {
-    let mut out = Vec::with_capacity((v.len() + 3) / 4);
+    let mut out = Vec::with_capacity(v.len().div_ceil(4));
 
     let mut iter = v.into_iter();
     while let Some(b1) = iter.next() {
@@ -81,121 +119,799 @@ fn pack_u8_to_u32(v: Vec<u8>) -> Vec<u32> {
 }
 
 
-pub fn check_section(target: &str, current: &Option<&str>) {
-    if let Some(s) = current {
-        if target.to_lowercase() != (*s).to_lowercase() {
-            panic!("invalid code for section '{}'", *s);
-        }
-    } else {
-        panic!("invalid code for section");
+pub fn check_section(target: &str, current: &Option<&str>) -> Result<(), String> {
+    match current {
+        Some(s) if target.to_lowercase() != s.to_lowercase() => Err(format!("this belongs in the [{}] section, not [{}]", target, s)),
+        Some(_) => Ok(()),
+        None => Err(format!("this belongs in the [{}] section, but no section was started", target)),
     }
 }
 
 #[derive(Debug)]
 struct DataLookup {
     pub address: u32,
+    /// memory cells taken
+    pub size: u32,
+    /// number of values, in units of the data type
+    pub length: u32,
+}
+
+/// data definition value with `times` expanded and escape sequences decoded
+#[derive(Clone)]
+enum DataItem<'a> {
+    Number(ConstValue<'a>),
+    Text(String),
+}
+
+/// limit on the values a single `times` or `res*` produces
+const MAX_REPETITIONS: u32 = 1 << 24;
+
+/// whether `value` fits in `bits` bits, as an unsigned number or a negative one
+fn fits(value: u32, bits: u32) -> bool {
+    value < (1 << bits) || (-(1 << (bits - 1))..0).contains(&(value as i32))
+}
+
+#[derive(Debug, Default)]
+/// Compiler options
+pub struct CompileOptions {
+    /// generate `DebugInfo` next to the binary
+    pub debug: bool,
+    /// path of the source file, recorded in the debug information and used to resolve `@include`
+    pub file: Option<String>,
+    /// extra directories searched for `@include` files
+    pub include_dirs: Vec<PathBuf>,
+    /// generate a `Listing` of the source with the words of every line
+    pub listing: bool,
+    /// generate a `SymbolMap` of every label and `$identifier`
+    pub map: bool,
+    /// run the peephole optimizer over the instructions
+    pub optimize: bool,
+    /// warn about paths that underflow the stack or change its depth in loops and procedures
+    pub verify_stack: bool,
+}
+
+/// Assembled program before addresses are resolved.
+///
+/// All positions are indexes into `binary` (text followed by data), so the same assembly can
+/// be placed at `origin` as an executable or kept relocatable as an object.
+struct Assembly<'a> {
+    binary: Vec<u32>,
+    origin: u32,
+    text_size: usize,
+    start: Option<usize>,
+    /// labels by full name, local labels are stored as `scope.name`
+    labels: HashMap<String, usize>,
+    /// `(file, line)` of every label, by full name
+    label_lines: HashMap<String, (usize, usize)>,
+    /// position of every `@@`, in order
+    anonymous: Vec<usize>,
+    data_lookup: HashMap<&'a str, DataLookup>,
+    fixups: Vec<Fixup<'a>>,
+    defines: HashMap<&'a str, Expr<'a>>,
+    /// `(position, file, line)` of every instruction and data definition
+    line_info: Vec<(usize, usize, usize)>,
+    data_symbols: Vec<DataSymbol>,
+    /// `(name, file, line)` of every `@global`
+    globals: Vec<(SymbolName<'a>, usize, usize)>,
+    externs: Vec<SymbolName<'a>>,
+}
+
+/// What local and anonymous labels refer to at some point of the program
+#[derive(Debug, Clone, Copy, Default)]
+struct Scope<'a> {
+    /// last label that is not local
+    label: Option<&'a str>,
+    /// number of `@@` seen so far
+    anonymous: usize,
+}
+
+impl Scope<'_> {
+    /// full name of a label, `..name` becomes `scope.name`
+    fn qualify(&self, label: &str) -> String {
+        match label.strip_prefix('.') {
+            Some(local) => format!("{}.{}", self.label.unwrap_or(""), local),
+            None => label.to_string(),
+        }
+    }
 }
 
-pub fn compile(code: String) -> CompiledFrame {
+/// Word holding the value of an expression, filled in once addresses are known
+struct Fixup<'a> {
+    pos: usize,
+    expr: Expr<'a>,
+    scope: Scope<'a>,
+    file: usize,
+    line: usize,
+}
+
+/// Fixups of a program, tagged with the location and scope of the token being assembled
+struct Fixups<'a> {
+    list: Vec<Fixup<'a>>,
+    scope: Scope<'a>,
+    file: usize,
+    line: usize,
+}
+
+impl<'a> Fixups<'a> {
+    fn push(&mut self, pos: usize, expr: Expr<'a>) {
+        self.list.push(Fixup { pos, expr, scope: self.scope, file: self.file, line: self.line });
+    }
+}
+
+impl<'a> Assembly<'a> {
+    fn is_extern(&self, name: SymbolName) -> bool {
+        self.externs.contains(&name)
+    }
+
+    /// address of a label or data identifier, absolute when placed at `origin` and
+    /// relative to the beginning of the assembly otherwise
+    fn symbol(&self, name: SymbolName<'a>, origin: Option<u32>, scope: Scope) -> Result<Value<'a>, String> {
+        let position = match name {
+            SymbolName::Label(label) => match anonymous_direction(label) {
+                Some(true) => match self.anonymous.get(scope.anonymous) {
+                    Some(&pos) => Some(pos as u32),
+                    None => return Err("no anonymous label '@@' after this line".to_string()),
+                },
+                Some(false) => match scope.anonymous.checked_sub(1).and_then(|idx| self.anonymous.get(idx)) {
+                    Some(&pos) => Some(pos as u32),
+                    None => return Err("no anonymous label '@@' before this line".to_string()),
+                },
+                None if is_local_label(label) => match self.labels.get(&scope.qualify(label)) {
+                    Some(&pos) => Some(pos as u32),
+                    None => return Err(format!("undefined local label '.{}' in {}", label, describe_scope(scope))),
+                },
+                None => self.labels.get(label).map(|&pos| pos as u32),
+            },
+            SymbolName::Data(id) => self.data_lookup.get(id).map(|d| d.address),
+        };
+        match (position, origin) {
+            (Some(pos), Some(origin)) => Ok(Value::absolute(pos + origin)),
+            (Some(pos), None) => Ok(Value { value: pos, base: Base::Local }),
+            (None, None) if self.is_extern(name) => Ok(Value { value: 0, base: Base::Import(name) }),
+            (None, Some(_)) if self.is_extern(name) => Err(format!("unresolved external {}, compile as object and link", describe_symbol(name))),
+            (None, _) => Err(format!("undefined {}", describe_symbol(name))),
+        }
+    }
+
+    fn data_size(&self, id: &str) -> Result<DataSize, String> {
+        match self.data_lookup.get(id) {
+            Some(data) => Ok(DataSize { cells: data.size, length: data.length }),
+            None if self.is_extern(SymbolName::Data(id)) => Err(format!("size of external identifier '${}' is not known", id)),
+            None => Err(format!("undefined identifier '${}'", id)),
+        }
+    }
+
+    fn evaluate(&self, fixup: &Fixup<'a>, origin: Option<u32>) -> Result<Value<'a>, EvalError<'a>> {
+        Evaluator::new(&self.defines, |name| self.symbol(name, origin, fixup.scope))
+            .with_sizes(&|id| self.data_size(id))
+            .evaluate(&fixup.expr)
+    }
+
+    /// debug information with every position moved by `base`, expanded macros are attributed
+    /// to the line that invoked them
+    fn debug_info(&self, sources: &SourceSet, base: u32) -> DebugInfo {
+        let mut labels: Vec<LabelSymbol> = self.labels.iter()
+            .map(|(name, pos)| LabelSymbol { name: name.to_string(), address: *pos as u32 + base })
+            .collect();
+        labels.sort_by(|a, b| a.address.cmp(&b.address).then(a.name.cmp(&b.name)));
+        DebugInfo {
+            files: sources.names(),
+            lines: self.line_info.iter()
+                .map(|&(pos, file, line)| {
+                    let (file, line) = sources.location(file, line);
+                    LineInfo { address: pos as u32 + base, file, line }
+                })
+                .collect(),
+            labels,
+            data: self.data_symbols.iter()
+                .map(|d| DataSymbol { address: d.address + base, ..d.clone() })
+                .collect(),
+        }
+    }
+}
+
+impl Assembly<'_> {
+    /// listing of every real source file, `binary` holds the resolved words
+    fn listing(&self, sources: &SourceSet, binary: &[u32], base: u32) -> Listing {
+        let mut index = vec![None; sources.files.len()];
+        let mut files: Vec<ListingFile> = Vec::new();
+        for (idx, file) in sources.files.iter().enumerate().filter(|(_, f)| f.expansion.is_none()) {
+            index[idx] = Some(files.len());
+            let lines = file.content.lines().enumerate()
+                .map(|(num, text)| ListingLine { line: num + 1, text: text.to_string(), code: Vec::new() })
+                .collect();
+            files.push(ListingFile { name: file.name.clone(), lines });
+        }
+        let data_sizes: HashMap<u32, u32> = self.data_symbols.iter().map(|d| (d.address, d.size)).collect();
+        for (idx, &(pos, file, line)) in self.line_info.iter().enumerate() {
+            let end = match data_sizes.get(&(pos as u32)) {
+                Some(&size) if pos >= self.text_size => pos + size as usize,
+                // instructions run up to the next one
+                _ => self.line_info.get(idx + 1).map_or(self.text_size, |next| next.0).min(self.text_size),
+            };
+            let expansion = sources.files[file].expansion.is_some().then(|| sources.expanded_text(file, line).trim().to_string());
+            let (file, line) = sources.location(file, line);
+            let Some(listed) = index[file].and_then(|f| files[f].lines.get_mut(line - 1)) else {
+                continue;
+            };
+            listed.code.push(ListingCode {
+                address: pos as u32 + base,
+                words: binary[pos..end].to_vec(),
+                instruction: pos < self.text_size,
+                expansion,
+            });
+        }
+        Listing { files }
+    }
+
+    /// `(position, file, line)` of every instruction in order, with their positions
+    fn instructions(&self) -> (Vec<(usize, usize, usize)>, Vec<usize>) {
+        let mut lines: Vec<(usize, usize, usize)> = self.line_info.iter().copied().filter(|&(pos, ..)| pos < self.text_size).collect();
+        lines.sort_by_key(|&(pos, ..)| pos);
+        let instructions = lines.iter().map(|&(pos, ..)| pos).collect();
+        (lines, instructions)
+    }
+
+    /// warnings of the stack verifier, `binary` holds the resolved words
+    fn verify_stack(&self, sources: &SourceSet, binary: &[u32], start: usize) -> Vec<Diagnostic> {
+        let (lines, instructions) = self.instructions();
+        let program = Program { text: &binary[..self.text_size], origin: self.origin, instructions: &instructions, labels: &self.labels };
+        verify_stack(&program, start).into_iter()
+            .map(|problem| {
+                let (_, file, line) = lines[instructions.binary_search(&problem.pos).unwrap_or(0)];
+                sources.diagnostic(Severity::Warning, problem.message, file, line, None)
+            })
+            .collect()
+    }
+
+    /// full names of the labels and the data identifiers `expr` refers to, through `@define`s
+    fn references<'e>(&'e self, expr: &'e Expr, scope: Scope, labels: &mut Vec<String>, data: &mut HashSet<&'e str>, defines: &mut Vec<&'e str>) {
+        match expr {
+            Expr::Number(_) => {},
+            Expr::Define(name) => {
+                if !defines.contains(name) && let Some(expr) = self.defines.get(name) {
+                    defines.push(name);
+                    self.references(expr, scope, labels, data, defines);
+                }
+            },
+            Expr::Symbol(SymbolName::Label(label)) if anonymous_direction(label).is_none() => labels.push(scope.qualify(label)),
+            Expr::Symbol(SymbolName::Label(_)) => {},
+            Expr::Symbol(SymbolName::Data(id)) | Expr::SizeOf(id) | Expr::LengthOf(id) => {
+                data.insert(id);
+            },
+            Expr::Not(expr) | Expr::Neg(expr) => self.references(expr, scope, labels, data, defines),
+            Expr::Binary(_, left, right) => {
+                self.references(left, scope, labels, data, defines);
+                self.references(right, scope, labels, data, defines);
+            },
+        }
+    }
+
+    /// warnings of every lint that no comment allows, `binary` holds the resolved words
+    fn lint(&self, sources: &SourceSet, binary: &[u32], start: usize) -> Vec<Diagnostic> {
+        let (lines, instructions) = self.instructions();
+        let program = Program { text: &binary[..self.text_size], origin: self.origin, instructions: &instructions, labels: &self.labels };
+        let mut used_labels: HashSet<String> = HashSet::from(["start".to_string()]);
+        let mut used_data: HashSet<&str> = HashSet::new();
+        let mut roots = vec![start];
+        for (name, ..) in &self.globals {
+            match name {
+                SymbolName::Label(label) => {
+                    used_labels.insert(label.to_string());
+                    roots.extend(self.labels.get(*label));
+                },
+                SymbolName::Data(id) => {
+                    used_data.insert(id);
+                },
+            }
+        }
+        for fixup in &self.fixups {
+            let mut labels = Vec::new();
+            self.references(&fixup.expr, fixup.scope, &mut labels, &mut used_data, &mut Vec::new());
+            // anything but the target of a direct jump or call may be jumped to indirectly
+            let operand = fixup.pos.checked_sub(1)
+                .filter(|pos| fixup.pos < self.text_size && instructions.binary_search(pos).is_ok())
+                .and_then(|pos| Opcode::extract(binary[pos]).ok());
+            let direct = match operand {
+                Some((Opcode::Jump, variant)) => variant.indirect_jump().is_none(),
+                Some((Opcode::Call, variant)) => variant == OpcodeVariant::CallConst,
+                Some((Opcode::SafeCall, variant)) => variant == OpcodeVariant::SafeCallConst,
+                _ => false,
+            };
+            if !direct {
+                roots.extend(labels.iter().filter_map(|label| self.labels.get(label)));
+            }
+            used_labels.extend(labels);
+        }
+
+        let mut findings: Vec<(Lint, usize, usize, String)> = Vec::new();
+        for (name, &(file, line)) in &self.label_lines {
+            if !used_labels.contains(name) {
+                findings.push((Lint::UnusedLabel, file, line, format!("label '.{}' is never used", name)));
+            }
+        }
+        for data in &self.data_symbols {
+            let defined = self.line_info.iter().find(|&&(pos, ..)| pos >= self.text_size && pos == data.address as usize);
+            if !used_data.contains(data.name.as_str()) && let Some(&(_, file, line)) = defined {
+                findings.push((Lint::UnusedData, file, line, format!("identifier '${}' is never used", data.name)));
+            }
+        }
+        for finding in lint_code(&program, &roots, binary.len() > self.text_size) {
+            let (_, file, line) = lines[instructions.binary_search(&finding.pos).unwrap_or(0)];
+            findings.push((finding.lint, file, line, finding.message));
+        }
+
+        // a comment on the line, or on a comment line right before it, allows a lint
+        let allowed = |lint: Lint, file: usize, line: usize| {
+            sources.reported_at(file, line).into_iter().any(|(file, line)| {
+                let mut text = sources.files[file].content.lines().skip(line.saturating_sub(2));
+                let before = if line > 1 { text.next() } else { None };
+                text.next().is_some_and(|l| allows(l, lint)) || before.is_some_and(|l| strip_comment(l).trim().is_empty() && allows(l, lint))
+            })
+        };
+        let mut warnings: Vec<Diagnostic> = findings.into_iter()
+            .filter(|&(lint, file, line, _)| !allowed(lint, file, line))
+            .map(|(lint, file, line, message)| {
+                sources.diagnostic(Severity::Warning, message, file, line, None)
+                    .with_note(format!("lint '{}', allow it with '; lint: allow({})'", lint.id(), lint.id()))
+            })
+            .collect();
+        warnings.sort_by(|a, b| a.file.cmp(&b.file).then(a.line.cmp(&b.line)));
+        warnings
+    }
+
+    fn symbol_map(&self, base: u32, start: u32) -> SymbolMap {
+        let section = |pos: usize| if pos < self.text_size { Section::Text } else { Section::Data };
+        let mut symbols: Vec<MapSymbol> = self.data_symbols.iter()
+            .map(|d| MapSymbol { name: d.name.clone(), kind: SymbolKind::Data, section: Section::Data, address: d.address + base, size: d.size })
+            .collect();
+        for (name, &pos) in &self.labels {
+            // a label covers everything up to the next symbol or the end of its section
+            let end = match section(pos) {
+                Section::Text => self.text_size,
+                Section::Data => self.binary.len(),
+            };
+            let next = self.labels.values().copied().chain(self.data_symbols.iter().map(|d| d.address as usize))
+                .filter(|&p| p > pos)
+                .min()
+                .map_or(end, |p| p.min(end));
+            symbols.push(MapSymbol { name: name.clone(), kind: SymbolKind::Label, section: section(pos), address: pos as u32 + base, size: (next - pos) as u32 });
+        }
+        symbols.sort_by(|a, b| a.address.cmp(&b.address).then(a.name.cmp(&b.name)));
+        SymbolMap {
+            origin: base,
+            start,
+            text_size: self.text_size as u32,
+            data_size: (self.binary.len() - self.text_size) as u32,
+            symbols,
+        }
+    }
+}
+
+pub fn compile(code: String) -> Result<CompiledFrame, Vec<Diagnostic>> {
+    compile_with_options(code, &CompileOptions::default())
+}
+
+/// load root source and everything it includes
+fn load_sources(code: String, options: &CompileOptions) -> Result<SourceSet, Vec<Diagnostic>> {
+    SourceSet::load(options.file.as_deref(), code, &options.include_dirs).map_err(|e| vec![e.to_diagnostic()])
+}
+
+/// assembly placed at its `@org` with every address resolved in the returned words, together
+/// with the address of `.start` and every problem found
+fn assemble_executable<'a>(sources: &'a SourceSet, options: &CompileOptions) -> (Assembly<'a>, Vec<u32>, u32, Vec<Diagnostic>) {
+    let (tokens, mut diagnostics) = sources.tokens();
+    let tokens = if options.optimize { optimize(tokens) } else { tokens };
+    let assembly = assemble(tokens, sources, &mut diagnostics);
+    let origin = assembly.origin;
+    let mut result = assembly.binary.clone();
+    for fixup in &assembly.fixups {
+        match assembly.evaluate(fixup, Some(origin)) {
+            Ok(value) => result[fixup.pos] = value.value,
+            Err(e) => diagnostics.push(sources.diagnostic(Severity::Error, e.message, fixup.file, fixup.line, e.span)),
+        }
+    }
+    let start = match assembly.start {
+        Some(start) => start as u32 + origin,
+        None => {
+            diagnostics.push(Diagnostic::error("no '.start' label found", &sources.files[0].name));
+            0
+        },
+    };
+    (assembly, result, start, diagnostics)
+}
+
+/// Compile code into an executable placed at its `@org`.
+///
+/// Every problem found is reported, errors make the compilation fail while warnings are
+/// returned in `CompiledFrame::warnings`.
+pub fn compile_with_options(code: String, options: &CompileOptions) -> Result<CompiledFrame, Vec<Diagnostic>> {
+    let sources = load_sources(code, options)?;
+    let (assembly, result, start, mut diagnostics) = assemble_executable(&sources, options);
+    if has_errors(&diagnostics) {
+        return Err(diagnostics);
+    }
+    let origin = assembly.origin;
+    if options.verify_stack && let Some(start) = assembly.start {
+        diagnostics.extend(assembly.verify_stack(&sources, &result, start));
+    }
+    Ok(CompiledFrame{
+        header: Header { origin, start, text_size: assembly.text_size as u32 },
+        debug: options.debug.then(|| assembly.debug_info(&sources, origin)),
+        listing: options.listing.then(|| assembly.listing(&sources, &result, origin)),
+        map: options.map.then(|| assembly.symbol_map(origin, start)),
+        binary: result,
+        warnings: diagnostics,
+    })
+}
+
+/// Compile code like `compile_with_options` and run every `Lint` over the program.
+///
+/// Returns the warnings of the compiler followed by one for every finding that no
+/// `; lint: allow(<id>)` comment on its line, or on a comment line right before it, silences.
+/// Errors make linting fail.
+pub fn lint(code: String, options: &CompileOptions) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let sources = load_sources(code, options)?;
+    let (assembly, result, _, mut diagnostics) = assemble_executable(&sources, options);
+    if has_errors(&diagnostics) {
+        return Err(diagnostics);
+    }
+    if let Some(start) = assembly.start {
+        diagnostics.extend(assembly.lint(&sources, &result, start));
+    }
+    Ok(diagnostics)
+}
+
+/// Compile code into a relocatable object.
+///
+/// Addresses are stored relative to the beginning of the object and every word holding an
+/// address gets a relocation entry, references to `@extern` symbols are left for the linker.
+/// Returns the object together with the warnings found.
+pub fn compile_object(code: String, options: &CompileOptions) -> Result<(Object, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let sources = load_sources(code, options)?;
+    let (tokens, mut diagnostics) = sources.tokens();
+    let tokens = if options.optimize { optimize(tokens) } else { tokens };
+    let assembly = assemble(tokens, &sources, &mut diagnostics);
+    let mut binary = assembly.binary.clone();
+    let mut imports: Vec<Import> = Vec::new();
+    let mut relocations: Vec<Relocation> = Vec::new();
+    let mut import_index = |name: &str, kind: SymbolKind| -> u32 {
+        match imports.iter().position(|i| i.name == name && i.kind == kind) {
+            Some(idx) => idx as u32,
+            None => {
+                imports.push(Import { name: name.to_string(), kind });
+                imports.len() as u32 - 1
+            },
+        }
+    };
+    for fixup in &assembly.fixups {
+        let value = match assembly.evaluate(fixup, None) {
+            Ok(value) => value,
+            Err(e) => {
+                diagnostics.push(sources.diagnostic(Severity::Error, e.message, fixup.file, fixup.line, e.span));
+                continue;
+            },
+        };
+        binary[fixup.pos] = value.value;
+        let target = match value.base {
+            Base::Absolute => continue,
+            Base::Local => RelocationTarget::Local,
+            Base::Import(SymbolName::Label(name)) => RelocationTarget::Import(import_index(name, SymbolKind::Label)),
+            Base::Import(SymbolName::Data(name)) => RelocationTarget::Import(import_index(name, SymbolKind::Data)),
+        };
+        relocations.push(Relocation { offset: fixup.pos as u32, target });
+    }
+    relocations.sort_by_key(|r| r.offset);
+
+    let mut exports: Vec<Symbol> = Vec::new();
+    if let Some(start) = assembly.start {
+        exports.push(Symbol { name: "start".to_string(), kind: SymbolKind::Label, address: start as u32, size: 0 });
+    }
+    for &(global, file, line) in &assembly.globals {
+        let symbol = match global {
+            SymbolName::Label(name) => assembly.labels.get(name)
+                .map(|&pos| Symbol { name: name.to_string(), kind: SymbolKind::Label, address: pos as u32, size: 0 }),
+            SymbolName::Data(name) => assembly.data_symbols.iter().find(|d| d.name == name)
+                .map(|d| Symbol { name: name.to_string(), kind: SymbolKind::Data, address: d.address, size: d.size }),
+        };
+        let Some(symbol) = symbol else {
+            let (SymbolName::Label(span) | SymbolName::Data(span)) = global;
+            let message = format!("global {} is not defined", describe_symbol(global));
+            diagnostics.push(sources.diagnostic(Severity::Error, message, file, line, Some(span)));
+            continue;
+        };
+        if !exports.contains(&symbol) {
+            exports.push(symbol);
+        }
+    }
+    if has_errors(&diagnostics) {
+        return Err(diagnostics);
+    }
+    let (text, data) = binary.split_at(assembly.text_size);
+    let object = Object {
+        origin: assembly.origin,
+        text: text.to_vec(),
+        data: data.to_vec(),
+        exports,
+        imports,
+        relocations,
+        debug: options.debug.then(|| assembly.debug_info(&sources, 0).to_string()),
+    };
+    Ok((object, diagnostics))
+}
+
+fn describe_symbol(name: SymbolName) -> String {
+    match name {
+        SymbolName::Label(label) if anonymous_direction(label).is_some() => format!("anonymous label '{}'", label),
+        SymbolName::Label(label) => format!("label '.{}'", label),
+        SymbolName::Data(id) => format!("identifier '${}'", id),
+    }
+}
+
+fn describe_scope(scope: Scope) -> String {
+    match scope.label {
+        Some(label) => format!("'.{}'", label),
+        None => "the code before the first label".to_string(),
+    }
+}
+
+/// append an operand word, anything but a plain number is filled in later
+fn push_operand<'a>(result: &mut Vec<u32>, fixups: &mut Fixups<'a>, value: ConstValue<'a>) {
+    match value {
+        ConstValue::Number(n) => result.push(n),
+        value => {
+            fixups.push(result.len(), value.into_expr());
+            result.push(0);
+        },
+    }
+}
+
+/// append a jump with the condition of the direct jump variant `condition`
+fn push_jump<'a>(result: &mut Vec<u32>, fixups: &mut Fixups<'a>, condition: OpcodeVariant, target: JumpOperand<'a>) {
+    let indirect = |target: JumpTarget| {
+        let variant = condition.to_indirect_jump(target).expect("every jump condition has indirect variants");
+        combine_hl(Opcode::Jump as u32, variant as u32)
+    };
+    match target {
+        JumpOperand::Const(value) => {
+            result.push(combine_hl(Opcode::Jump as u32, condition as u32));
+            push_operand(result, fixups, value);
+        },
+        JumpOperand::Reg(reg) => {
+            result.push(indirect(JumpTarget::Reg));
+            result.push(reg);
+        },
+        JumpOperand::Addr(value) => {
+            result.push(indirect(JumpTarget::Addr));
+            push_operand(result, fixups, value);
+        },
+        JumpOperand::IdOffsetReg(id, reg) => {
+            result.push(indirect(JumpTarget::AddrOffsetReg));
+            fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
+            result.push(0);
+            result.push(reg);
+        },
+    }
+}
+
+/// error message when `name` is a local or anonymous label, which `meta` cannot refer to
+fn named_label_only(name: SymbolName, meta: &str) -> Option<String> {
+    match name {
+        SymbolName::Label(label) if anonymous_direction(label).is_some() => Some(format!("'{}' cannot refer to an anonymous label", meta)),
+        SymbolName::Label(label) if is_local_label(label) => Some(format!("'{}' cannot refer to a local label, use its full name '.scope.{}'", meta, &label[1..])),
+        _ => None,
+    }
+}
+
+/// error for a name defined twice, with a note pointing at the first definition
+fn duplicate(sources: &SourceSet, what: String, name: &str, first: (usize, usize), file: usize, line: usize) -> Diagnostic {
+    let (first_file, first_line) = sources.trace(first.0, first.1).swap_remove(0);
+    sources.diagnostic(Severity::Error, format!("{} is defined more than once", what), file, line, Some(name))
+        .with_note(format!("first defined at {}:{}", first_file, first_line))
+}
+
+fn assemble<'a>(tokens: Vec<LineToken<'a>>, sources: &SourceSet, diagnostics: &mut Vec<Diagnostic>) -> Assembly<'a> {
+    let error = |message: String, file: usize, line: usize, span: Option<&str>| {
+        sources.diagnostic(Severity::Error, message, file, line, span)
+    };
+
+    let mut defines: HashMap<&'a str, Expr<'a>> = HashMap::new();
+    let mut define_lines: HashMap<&'a str, (usize, usize)> = HashMap::new();
+    for t in &tokens {
+        if let Token::Meta(MetaType::Define(name, expr)) = &t.token {
+            if let Some(&first) = define_lines.get(name) {
+                diagnostics.push(duplicate(sources, format!("constant '{}'", name), name, first, t.file, t.line));
+                continue;
+            }
+            define_lines.insert(name, (t.file, t.line));
+            defines.insert(name, expr.clone());
+        }
+    }
+    // `@org` and data values are needed before addresses are known, errors evaluate to 0
+    let constant = |value: ConstValue<'a>, file: usize, line: usize, diagnostics: &mut Vec<Diagnostic>| -> u32 {
+        let evaluator = Evaluator::new(&defines, |_| Err("labels and data identifiers cannot be used here".to_string()));
+        match evaluator.evaluate(&value.into_expr()) {
+            Ok(value) => value.value,
+            Err(e) => {
+                diagnostics.push(error(e.message, file, line, e.span));
+                0
+            },
+        }
+    };
+
     let mut result: Vec<u32> = Vec::new();
     let mut origin: u32 = 0;
-    let content = code.as_str();
-    let mut label_usage = HashMap::<usize, &str>::new();
-    let mut labels = HashMap::<&str, usize>::new();
-    let (_, tokens) = parse_program(content).unwrap();
+    let mut origin_line: Option<(usize, usize)> = None;
+    let mut fixups = Fixups { list: Vec::new(), scope: Scope::default(), file: 0, line: 0 };
+    let mut labels = HashMap::<String, usize>::new();
+    let mut anonymous: Vec<usize> = Vec::new();
     let mut current_section:Option<&str> = None;
 
-    let mut data_list: Vec<(&str, DataType, Vec<u32>)> = Vec::new();
+    let mut data_list: Vec<(&str, DataType, Vec<u32>, u32)> = Vec::new();
+    // `(data_list index, cells, file, line)` of every `@align`, applied before that entry is placed
+    let mut data_align: Vec<(usize, u32, usize, usize)> = Vec::new();
+    // `dw` values relative to the beginning of the `data_list` entry they belong to
+    let mut data_fixups: Vec<(usize, Fixup<'a>)> = Vec::new();
     let mut data_lookup: HashMap<&str, DataLookup> = HashMap::new();
-    let mut data_usage: HashMap<usize, (&str, u32)> = HashMap::new();
     let mut start_pos: Option<u32> = None;
+    let mut line_info: Vec<(usize, usize, usize)> = Vec::new();
+    let mut data_lines: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut label_lines: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut globals: Vec<(SymbolName, usize, usize)> = Vec::new();
+    let mut externs: Vec<SymbolName> = Vec::new();
 
-    for token in tokens {
+    for LineToken { file, line, token } in tokens {
+        fixups.file = file;
+        fixups.line = line;
         match token {
             crate::tokens::Token::Meta(meta_type) => {
                 match meta_type {
                     crate::tokens::MetaType::Org(n) => {
-                        origin = n;
+                        if let Some((first_file, first_line)) = origin_line {
+                            let (first_file, first_line) = sources.trace(first_file, first_line).swap_remove(0);
+                            diagnostics.push(sources.diagnostic(Severity::Warning, "'@org' overrides an earlier '@org', only the last one is used", file, line, None)
+                                .with_note(format!("earlier '@org' at {}:{}", first_file, first_line)));
+                        }
+                        origin_line = Some((file, line));
+                        origin = constant(n, file, line, diagnostics);
                     },
+                    crate::tokens::MetaType::Align(n) => {
+                        if let Err(message) = check_section("data", &current_section) {
+                            diagnostics.push(error(message, file, line, None));
+                        }
+                        match constant(n, file, line, diagnostics) {
+                            0 => diagnostics.push(error("alignment must be at least 1".to_string(), file, line, None)),
+                            n => data_align.push((data_list.len(), n, file, line)),
+                        }
+                    },
+                    // collected before assembling
+                    crate::tokens::MetaType::Define(..) => {},
+                    // already spliced in by `SourceSet::tokens`
                     crate::tokens::MetaType::Include(_) => {},
+                    crate::tokens::MetaType::Global(name) => {
+                        if let Some(message) = named_label_only(name, "@global") {
+                            diagnostics.push(error(message, file, line, None));
+                            continue;
+                        }
+                        globals.push((name, file, line));
+                    },
+                    crate::tokens::MetaType::Extern(name) => {
+                        if let Some(message) = named_label_only(name, "@extern") {
+                            diagnostics.push(error(message, file, line, None));
+                            continue;
+                        }
+                        externs.push(name);
+                    },
                 }
             },
             crate::tokens::Token::Section(sec) => {
                 if sec.to_lowercase() == "text" || sec.to_lowercase() == "data" {
                     current_section = Some(sec);
                 } else {
-                    panic!("invalid section '{}'", sec);
+                    diagnostics.push(error(format!("unknown section '[{}]', expected [text] or [data]", sec), file, line, Some(sec)));
                 }
             },
             crate::tokens::Token::DataDef(id, typ, values) => {
-                check_section("data", &current_section);
-                let result = match typ {
+                if let Err(message) = check_section("data", &current_section) {
+                    diagnostics.push(error(message, file, line, None));
+                }
+                if let Some(&first) = data_lines.get(id) {
+                    diagnostics.push(duplicate(sources, format!("identifier '${}'", id), id, first, file, line));
+                    continue;
+                }
+                let number = |n: ConstValue<'a>, diagnostics: &mut Vec<Diagnostic>| constant(n, file, line, diagnostics);
+                let mut items: Vec<DataItem> = Vec::new();
+                for value in values {
+                    let (value, count) = match value {
+                        crate::tokens::DataValue::Times(value, count) => (*value, number(count, diagnostics)),
+                        value => (value, 1),
+                    };
+                    if count > MAX_REPETITIONS {
+                        diagnostics.push(error(format!("repetition count {} is too large", count), file, line, None));
+                        continue;
+                    }
+                    let item = match value {
+                        crate::tokens::DataValue::Number(n) => DataItem::Number(n),
+                        crate::tokens::DataValue::String(s) => match unescape(s) {
+                            Ok(text) => DataItem::Text(text),
+                            Err(message) => {
+                                diagnostics.push(error(message, file, line, Some(s)));
+                                continue;
+                            },
+                        },
+                        crate::tokens::DataValue::Times(..) => unreachable!("`times` cannot be nested"),
+                    };
+                    items.extend(std::iter::repeat_n(item, count as usize));
+                }
+                let (result, length) = match typ {
                     crate::tokens::DataType::Byte => {
                         let mut acc: Vec<u8> = Vec::new();
-                        for value in values {
-                            match value {
-                                crate::tokens::DataValue::Number(n) => {
-                                    if n > u8::MAX as u32 {
-                                        panic!("Byte value overflow");
+                        for item in items {
+                            match item {
+                                DataItem::Number(n) => {
+                                    let n = number(n, diagnostics);
+                                    if !fits(n, 8) {
+                                        diagnostics.push(error(format!("value {} does not fit in a byte", n as i32), file, line, None));
                                     }
                                     acc.push(n as u8);
                                 },
-                                crate::tokens::DataValue::String(s) => {
-                                    acc.append(&mut s.chars().map(|c| c as u8).collect());
-                                }
+                                // UTF-8 encoded
+                                DataItem::Text(text) => acc.extend(text.bytes()),
                             }
                         }
-                        pack_u8_to_u32(acc)
+                        let length = acc.len();
+                        (pack_u8_to_u32(acc), length)
                     },
                     crate::tokens::DataType::Word => {
                         let mut acc: Vec<u16> = Vec::new();
-                        for value in values {
-                            match value {
-                                crate::tokens::DataValue::Number(n) => {
-                                    if n > u16::MAX as u32 {
-                                        panic!("Word value overflow");
+                        for item in items {
+                            match item {
+                                DataItem::Number(n) => {
+                                    let n = number(n, diagnostics);
+                                    if !fits(n, 16) {
+                                        diagnostics.push(error(format!("value {} does not fit in a word", n as i32), file, line, None));
                                     }
                                     acc.push(n as u16);
                                 },
-                                crate::tokens::DataValue::String(s) => {
-                                    acc.append(&mut s.chars().map(|c| c as u16).collect());
-                                }
+                                // UTF-16 encoded
+                                DataItem::Text(text) => acc.extend(text.encode_utf16()),
                             }
                         }
-                        pack_u16_to_u32(acc)
+                        let length = acc.len();
+                        (pack_u16_to_u32(acc), length)
                     },
                     crate::tokens::DataType::DoubleWord => {
                         let mut acc: Vec<u32> = Vec::new();
-                        for value in values {
-                            match value {
-                                crate::tokens::DataValue::Number(n) => {
-                                    acc.push(n);
+                        for item in items {
+                            match item {
+                                DataItem::Number(ConstValue::Number(n)) => acc.push(n),
+                                // may hold addresses, filled in once the data is placed
+                                DataItem::Number(value) => {
+                                    let fixup = Fixup { pos: acc.len(), expr: value.into_expr(), scope: fixups.scope, file, line };
+                                    data_fixups.push((data_list.len(), fixup));
+                                    acc.push(0);
                                 },
-                                crate::tokens::DataValue::String(s) => {
-                                    acc.append(&mut s.chars().map(|c| c as u32).collect());
+                                DataItem::Text(text) => {
+                                    acc.append(&mut text.chars().map(|c| c as u32).collect());
                                 }
                             }
                         }
-                        acc
+                        let length = acc.len();
+                        (acc, length)
                     },
                 };
-                data_list.push((id, typ, result));
+                data_lines.insert(id, (file, line));
+                data_list.push((id, typ, result, length as u32));
             },
             crate::tokens::Token::Command(cmd) => {
-                check_section("text", &current_section);
+                if let Err(message) = check_section("text", &current_section) {
+                    diagnostics.push(error(message, file, line, None));
+                }
+                line_info.push((result.len(), file, line));
                 match cmd {
                     crate::tokens::Cmd::PushConst(const_value) => {
-                        match const_value {
-                            crate::tokens::ConstValue::Number(val) => {
-                                result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushConst as u32));
-                                result.push(val);
-                            },
-                            crate::tokens::ConstValue::Label(val) => {
-                                result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushConst as u32));
-                                label_usage.insert(result.len(), val);
-                                result.push(0);
-                            },
-                        }
+                        result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushConst as u32));
+                        push_operand(&mut result, &mut fixups, const_value);
                     },
                     crate::tokens::Cmd::Inc(reg) => {
                         result.push(combine_hl(Opcode::Inc as u32, OpcodeVariant::Default as u32));
@@ -203,36 +919,36 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::PushIdAddress(id) => {
                         result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushConst as u32));
-                        data_usage.insert(result.len(), (id, 0));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
                         result.push(0);
                     },
                     crate::tokens::Cmd::PushIdValueConst(id, offset) => {
                         result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushAddr as u32));
-                        data_usage.insert(result.len(), (id, offset));
+                        fixups.push(result.len(), Expr::Binary(BinaryOp::Add, Box::new(Expr::Symbol(SymbolName::Data(id))), Box::new(offset.into_expr())));
                         result.push(0);
                     },
                     crate::tokens::Cmd::PushIdValueReg(id, reg) => {
                         result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushAddrOffsetReg as u32));
-                        data_usage.insert(result.len(), (id, 0));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
                         result.push(0);
                         result.push(reg);
                     },
                     crate::tokens::Cmd::MoveIdAddress(reg, id) => {
                         result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveConst as u32));
                         result.push(reg);
-                        data_usage.insert(result.len(), (id, 0));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
                         result.push(0);
                     },
                     crate::tokens::Cmd::MoveIdValueConst(reg, id, offset) => {
                         result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveAddr as u32));
                         result.push(reg);
-                        data_usage.insert(result.len(), (id, offset));
+                        fixups.push(result.len(), Expr::Binary(BinaryOp::Add, Box::new(Expr::Symbol(SymbolName::Data(id))), Box::new(offset.into_expr())));
                         result.push(0);
                     },
                     crate::tokens::Cmd::MoveIdValueReg(reg, id, reg_v) => {
                         result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveAddrOffsetReg as u32));
                         result.push(reg);
-                        data_usage.insert(result.len(), (id, 0));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
                         result.push(0);
                         result.push(reg_v);
                     },
@@ -255,7 +971,7 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::PushAddr(val) => {
                         result.push(combine_hl(Opcode::Push as u32, OpcodeVariant::PushAddr as u32));
-                        result.push(val);
+                        push_operand(&mut result, &mut fixups, val);
                     },
                     crate::tokens::Cmd::PopReg(val) => {
                         result.push(combine_hl(Opcode::Pop as u32, OpcodeVariant::PopReg as u32));
@@ -263,7 +979,17 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::PopAddr(val) => {
                         result.push(combine_hl(Opcode::Pop as u32, OpcodeVariant::PopAddr as u32));
-                        result.push(val);
+                        push_operand(&mut result, &mut fixups, val);
+                    },
+                    crate::tokens::Cmd::PopAddrReg(reg) => {
+                        result.push(combine_hl(Opcode::Pop as u32, OpcodeVariant::PopAddrReg as u32));
+                        result.push(reg);
+                    },
+                    crate::tokens::Cmd::PopIdValueReg(id, reg) => {
+                        result.push(combine_hl(Opcode::Pop as u32, OpcodeVariant::PopAddrOffsetReg as u32));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
+                        result.push(0);
+                        result.push(reg);
                     },
                     crate::tokens::Cmd::Add => {
                         result.push(combine_hl(Opcode::Add as u32, OpcodeVariant::Default as u32));
@@ -275,19 +1001,9 @@ pub fn compile(code: String) -> CompiledFrame {
                         result.push(combine_hl(Opcode::Swap as u32, OpcodeVariant::Default as u32));
                     },
                     crate::tokens::Cmd::MoveConst(val, const_value) => {
-                        match const_value {
-                            crate::tokens::ConstValue::Number(n) => {
-                                result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveConst as u32));
-                                result.push(val);
-                                result.push(n);
-                            },
-                            crate::tokens::ConstValue::Label(label) => {
-                                result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveConst as u32));
-                                result.push(val);
-                                label_usage.insert(result.len(), label);
-                                result.push(0);
-                            },
-                        }
+                        result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveConst as u32));
+                        result.push(val);
+                        push_operand(&mut result, &mut fixups, const_value);
                     },
                     crate::tokens::Cmd::MoveReg(val, reg) => {
                         result.push(combine_hl(Opcode::Move as u32, OpcodeVariant::MoveReg as u32));
@@ -305,59 +1021,35 @@ pub fn compile(code: String) -> CompiledFrame {
                         result.push(reg_val);
                     },
                     crate::tokens::Cmd::StoreConst(val, const_value) => {
-                        match const_value {
-                            crate::tokens::ConstValue::Number(n) => {
-                                result.push(combine_hl(Opcode::Store as u32, OpcodeVariant::StoreConst as u32));
-                                result.push(val);
-                                result.push(n);
-                            },
-                            crate::tokens::ConstValue::Label(label) => {
-                                result.push(combine_hl(Opcode::Store as u32, OpcodeVariant::StoreConst as u32));
-                                result.push(val);
-                                label_usage.insert(result.len(), label);
-                                result.push(0);
-                            },
-                        }
+                        result.push(combine_hl(Opcode::Store as u32, OpcodeVariant::StoreConst as u32));
+                        push_operand(&mut result, &mut fixups, val);
+                        push_operand(&mut result, &mut fixups, const_value);
                     },
                     crate::tokens::Cmd::StoreReg(val, reg) => {
                         result.push(combine_hl(Opcode::Store as u32, OpcodeVariant::StoreReg as u32));
                         result.push(val);
                         result.push(reg);
                     },
-                    crate::tokens::Cmd::Jmp(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::Default as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jmp(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::Default, target);
                     },
-                    crate::tokens::Cmd::Jnz(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::JumpNotZero as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jnz(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::JumpNotZero, target);
                     },
-                    crate::tokens::Cmd::Jz(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::JumpZero as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jz(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::JumpZero, target);
                     },
-                    crate::tokens::Cmd::Jg(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::JumpGreater as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jg(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::JumpGreater, target);
                     },
-                    crate::tokens::Cmd::Jge(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::JumpGreaterEqual as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jge(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::JumpGreaterEqual, target);
                     },
-                    crate::tokens::Cmd::Jl(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::JumpLesser as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jl(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::JumpLesser, target);
                     },
-                    crate::tokens::Cmd::Jle(label) => {
-                        result.push(combine_hl(Opcode::Jump as u32, OpcodeVariant::JumpLesserEqual as u32));
-                        label_usage.insert(result.len(), label);
-                        result.push(0);
+                    crate::tokens::Cmd::Jle(target) => {
+                        push_jump(&mut result, &mut fixups, OpcodeVariant::JumpLesserEqual, target);
                     },
                     crate::tokens::Cmd::And => {
                         result.push(combine_hl(Opcode::And as u32, OpcodeVariant::Default as u32));
@@ -373,7 +1065,7 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::ShrConst(val) => {
                         result.push(combine_hl(Opcode::SHR as u32, OpcodeVariant::SHRConst as u32));
-                        result.push(val);
+                        push_operand(&mut result, &mut fixups, val);
                     },
                     crate::tokens::Cmd::ShrReg(val) => {
                         result.push(combine_hl(Opcode::SHR as u32, OpcodeVariant::SHRReg as u32));
@@ -381,24 +1073,15 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::ShlConst(val) => {
                         result.push(combine_hl(Opcode::SHL as u32, OpcodeVariant::SHLConst as u32));
-                        result.push(val);
+                        push_operand(&mut result, &mut fixups, val);
                     },
                     crate::tokens::Cmd::ShlReg(reg) => {
                         result.push(combine_hl(Opcode::SHL as u32, OpcodeVariant::SHLReg as u32));
                         result.push(reg);
                     },
                     crate::tokens::Cmd::CallConst(const_value) => {
-                        match const_value {
-                            crate::tokens::ConstValue::Number(val) => {
-                                result.push(combine_hl(Opcode::Call as u32, OpcodeVariant::CallConst as u32));
-                                result.push(val);
-                            },
-                            crate::tokens::ConstValue::Label(label) => {
-                                result.push(combine_hl(Opcode::Call as u32, OpcodeVariant::CallConst as u32));
-                                label_usage.insert(result.len(), label);
-                                result.push(0);
-                            },
-                        }
+                        result.push(combine_hl(Opcode::Call as u32, OpcodeVariant::CallConst as u32));
+                        push_operand(&mut result, &mut fixups, const_value);
                     },
                     crate::tokens::Cmd::CallReg(reg) => {
                         result.push(combine_hl(Opcode::Call as u32, OpcodeVariant::CallReg as u32));
@@ -406,20 +1089,17 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::CallAddr(addr) => {
                         result.push(combine_hl(Opcode::Call as u32, OpcodeVariant::CallAddr as u32));
-                        result.push(addr);
+                        push_operand(&mut result, &mut fixups, addr);
+                    },
+                    crate::tokens::Cmd::CallIdValueReg(id, reg) => {
+                        result.push(combine_hl(Opcode::Call as u32, OpcodeVariant::CallAddrOffsetReg as u32));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
+                        result.push(0);
+                        result.push(reg);
                     },
                     crate::tokens::Cmd::SafeCallConst(const_value) => {
-                        match const_value {
-                            crate::tokens::ConstValue::Number(val) => {
-                                result.push(combine_hl(Opcode::SafeCall as u32, OpcodeVariant::SafeCallConst as u32));
-                                result.push(val);
-                            },
-                            crate::tokens::ConstValue::Label(label) => {
-                                result.push(combine_hl(Opcode::SafeCall as u32, OpcodeVariant::SafeCallConst as u32));
-                                label_usage.insert(result.len(), label);
-                                result.push(0);
-                            },
-                        }
+                        result.push(combine_hl(Opcode::SafeCall as u32, OpcodeVariant::SafeCallConst as u32));
+                        push_operand(&mut result, &mut fixups, const_value);
                     },
                     crate::tokens::Cmd::SafeCallReg(reg) => {
                         result.push(combine_hl(Opcode::SafeCall as u32, OpcodeVariant::SafeCallReg as u32));
@@ -427,7 +1107,13 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::SafeCallAddr(addr) => {
                         result.push(combine_hl(Opcode::SafeCall as u32, OpcodeVariant::SafeCallAddr as u32));
-                        result.push(addr);
+                        push_operand(&mut result, &mut fixups, addr);
+                    },
+                    crate::tokens::Cmd::SafeCallIdValueReg(id, reg) => {
+                        result.push(combine_hl(Opcode::SafeCall as u32, OpcodeVariant::SafeCallAddrOffsetReg as u32));
+                        fixups.push(result.len(), Expr::Symbol(SymbolName::Data(id)));
+                        result.push(0);
+                        result.push(reg);
                     },
                     crate::tokens::Cmd::Ret => {
                         result.push(combine_hl(Opcode::Ret as u32, OpcodeVariant::Default as u32));
@@ -437,7 +1123,7 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::DupConst(val) => {
                         result.push(combine_hl(Opcode::Dup as u32, OpcodeVariant::DupConst as u32));
-                        result.push(val);
+                        push_operand(&mut result, &mut fixups, val);
                     },
                     crate::tokens::Cmd::DupReg(reg) => {
                         result.push(combine_hl(Opcode::Dup as u32, OpcodeVariant::DupReg as u32));
@@ -445,45 +1131,88 @@ pub fn compile(code: String) -> CompiledFrame {
                     },
                     crate::tokens::Cmd::Int(module, function) => {
                         result.push(combine_hl(Opcode::Int as u32, OpcodeVariant::Default as u32));
-                        result.push(module);
-                        result.push(function);
+                        push_operand(&mut result, &mut fixups, module);
+                        push_operand(&mut result, &mut fixups, function);
                     },
                     crate::tokens::Cmd::Term => {
                         result.push(combine_hl(Opcode::Terminate as u32, OpcodeVariant::Default as u32));
                     },
                 }
             },
+            crate::tokens::Token::Label(ANONYMOUS_LABEL) => {
+                if let Err(message) = check_section("text", &current_section) {
+                    diagnostics.push(error(message, file, line, Some(ANONYMOUS_LABEL)));
+                }
+                anonymous.push(result.len());
+                fixups.scope.anonymous += 1;
+            },
             crate::tokens::Token::Label(label) => {
-                if label == "start" && start_pos.is_none() {
+                if is_block_label(label) && !sources.is_directive(file) {
+                    let message = format!("labels starting with '{}' are reserved for '@if' and '@while'", BLOCK_LABEL_PREFIX);
+                    diagnostics.push(error(message, file, line, Some(label)));
+                    continue;
+                }
+                let name = fixups.scope.qualify(label);
+                if !is_local_label(label) && !is_block_label(label) {
+                    fixups.scope.label = Some(label);
+                }
+                if let Some(&first) = label_lines.get(&name) {
+                    diagnostics.push(duplicate(sources, format!("label '.{}'", name), label, first, file, line));
+                    continue;
+                }
+                if name == "start" {
                     start_pos = Some(result.len() as u32);
-                    labels.insert(label, result.len());
-                } else {
-                    if label == "start" {
-                        panic!("there should only be one 'start' label in code");
-                    }
-                    check_section("text", &current_section);
-                    labels.insert(label, result.len());
+                } else if let Err(message) = check_section("text", &current_section) {
+                    diagnostics.push(error(message, file, line, Some(label)));
                 }
+                label_lines.insert(name.clone(), (file, line));
+                labels.insert(name, result.len());
             },
         }
     }
-    for (k, v) in label_usage {
-        result[k] = labels[v] as u32 + origin;
-    }
-    for (name, _typ, cont) in data_list {
-        let addr = result.len() as u32 + origin;
-        let _len = cont.len();
+    let text_size = result.len();
+    let mut data_symbols: Vec<DataSymbol> = Vec::new();
+    let mut addresses: Vec<usize> = Vec::new();
+    let align = |result: &mut Vec<u32>, idx: usize, diagnostics: &mut Vec<Diagnostic>| {
+        for &(_, cells, file, line) in data_align.iter().filter(|a| a.0 == idx) {
+            let aligned = (origin as u64 + result.len() as u64).next_multiple_of(cells as u64);
+            if aligned > MAX_CELLS as u64 {
+                diagnostics.push(error(format!("aligning to {} cells does not fit in memory", cells), file, line, None));
+                continue;
+            }
+            result.resize((aligned - origin as u64) as usize, 0);
+        }
+    };
+    let data_count = data_list.len();
+    for (idx, (name, typ, cont, length)) in data_list.into_iter().enumerate() {
+        align(&mut result, idx, diagnostics);
+        let addr = result.len() as u32;
+        addresses.push(result.len());
+        let (file, line) = data_lines[name];
+        line_info.push((result.len(), file, line));
+        data_symbols.push(DataSymbol { name: name.to_string(), address: addr, size: cont.len() as u32, typ });
         cont.iter().for_each(|v| result.push(*v));
-        data_lookup.insert(name, DataLookup { address: addr as u32 });
-    }
-    for (k, (v, offset)) in data_usage {
-        result[k] = data_lookup[v].address + offset;
+        data_lookup.insert(name, DataLookup { address: addr, size: cont.len() as u32, length });
     }
-    if start_pos.is_none() {
-        panic!("no '.start' label found");
+    align(&mut result, data_count, diagnostics);
+    for (idx, mut fixup) in data_fixups {
+        fixup.pos += addresses[idx];
+        fixups.list.push(fixup);
     }
-    CompiledFrame{
+    Assembly {
         binary: result,
-        header: Header { origin: origin, start: start_pos.unwrap() },
+        origin,
+        text_size,
+        start: start_pos.map(|s| s as usize),
+        labels,
+        label_lines,
+        anonymous,
+        data_lookup,
+        fixups: fixups.list,
+        defines,
+        line_info,
+        data_symbols,
+        globals,
+        externs,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,222 @@
+use std::fmt::Display;
+
+use crate::tokens::DataType;
+
+/// Header line of the textual debug info format
+const DEBUG_MAGIC: &str = "myvm-debug 1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Maps the first word of an instruction (or data definition) to its source line
+pub struct LineInfo {
+    pub address: u32,
+    /// index into `DebugInfo::files`
+    pub file: usize,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Code label symbol
+pub struct LabelSymbol {
+    pub name: String,
+    pub address: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// `$identifier` symbol from the `[data]` section
+pub struct DataSymbol {
+    pub name: String,
+    pub address: u32,
+    /// size in memory cells
+    pub size: u32,
+    pub typ: DataType,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// # Debug information
+///
+/// Source level information produced by the assembler next to the binary, so runtime errors,
+/// profilers and debuggers can talk about files, lines and names instead of raw addresses.
+///
+/// All addresses are absolute (origin already applied).
+pub struct DebugInfo {
+    pub files: Vec<String>,
+    /// sorted by address
+    pub lines: Vec<LineInfo>,
+    pub labels: Vec<LabelSymbol>,
+    pub data: Vec<DataSymbol>,
+}
+
+impl DataType {
+    /// assembly keyword of data type
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            DataType::Byte => "b",
+            DataType::Word => "w",
+            DataType::DoubleWord => "dw",
+        }
+    }
+
+    /// parse data type from its assembly keyword
+    pub fn from_keyword(keyword: &str) -> Option<DataType> {
+        match keyword.to_lowercase().as_str() {
+            "b" => Some(DataType::Byte),
+            "w" => Some(DataType::Word),
+            "dw" => Some(DataType::DoubleWord),
+            _ => None,
+        }
+    }
+}
+
+impl DebugInfo {
+    /// source line of the instruction containing `address`
+    pub fn line_for(&self, address: u32) -> Option<&LineInfo> {
+        let idx = self.lines.partition_point(|l| l.address <= address);
+        if idx == 0 {
+            return None;
+        }
+        Some(&self.lines[idx - 1])
+    }
+
+    /// first address generated for a source line
+    pub fn address_for(&self, file: usize, line: usize) -> Option<u32> {
+        self.lines.iter().find(|l| l.file == file && l.line == line).map(|l| l.address)
+    }
+
+    /// first line with code at or after a source line, where a breakpoint on it stops
+    pub fn line_at_or_after(&self, file: usize, line: usize) -> Option<&LineInfo> {
+        self.lines.iter().filter(|l| l.file == file && l.line >= line).min_by_key(|l| (l.line, l.address))
+    }
+
+    /// nearest label at or before `address`
+    pub fn label_for(&self, address: u32) -> Option<&LabelSymbol> {
+        self.labels.iter().filter(|l| l.address <= address).max_by_key(|l| l.address)
+    }
+
+    /// data identifier whose memory contains `address`
+    pub fn data_for(&self, address: u32) -> Option<&DataSymbol> {
+        self.data.iter().find(|d| address >= d.address && address < d.address + d.size)
+    }
+
+    /// find label by name
+    pub fn label(&self, name: &str) -> Option<&LabelSymbol> {
+        self.labels.iter().find(|l| l.name == name)
+    }
+
+    /// find data identifier by name
+    pub fn data(&self, name: &str) -> Option<&DataSymbol> {
+        self.data.iter().find(|d| d.name == name)
+    }
+
+    /// human readable location of `address`, e.g. `hello.asm:12 (.start+4)`
+    pub fn describe(&self, address: u32) -> String {
+        let mut out = String::new();
+        if let Some(line) = self.line_for(address) {
+            let file = self.files.get(line.file).map(|f| f.as_str()).unwrap_or("?");
+            out.push_str(&format!("{}:{}", file, line.line));
+        } else {
+            out.push_str(&format!("0x{:08x}", address));
+        }
+        if let Some(label) = self.label_for(address) {
+            let offset = address - label.address;
+            if offset == 0 {
+                out.push_str(&format!(" (.{})", label.name));
+            } else {
+                out.push_str(&format!(" (.{}+{})", label.name, offset));
+            }
+        }
+        out
+    }
+
+    /// debug info with every address mapped through `f`, and the addresses of labels through `label`
+    pub fn relocate(mut self, f: impl Fn(u32) -> u32, label: impl Fn(u32) -> u32) -> DebugInfo {
+        self.lines.iter_mut().for_each(|l| l.address = f(l.address));
+        self.labels.iter_mut().for_each(|l| l.address = label(l.address));
+        self.data.iter_mut().for_each(|d| d.address = f(d.address));
+        self.lines.sort_by_key(|l| l.address);
+        self
+    }
+
+    /// append debug info of another program part (e.g. a linked object)
+    pub fn merge(&mut self, other: DebugInfo) {
+        let base = self.files.len();
+        self.files.extend(other.files);
+        self.lines.extend(other.lines.into_iter().map(|l| LineInfo { file: l.file + base, ..l }));
+        self.lines.sort_by_key(|l| l.address);
+        self.labels.extend(other.labels);
+        self.labels.sort_by(|a, b| a.address.cmp(&b.address).then(a.name.cmp(&b.name)));
+        self.data.extend(other.data);
+        self.data.sort_by_key(|d| d.address);
+    }
+
+    /// parse debug info from its textual (sidecar) representation
+    pub fn parse(content: &str) -> Result<DebugInfo, String> {
+        let mut lines = content.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == DEBUG_MAGIC => {},
+            _ => return Err("missing debug info header".to_string()),
+        }
+        let mut info = DebugInfo::default();
+        for (num, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = || format!("invalid debug info record at line {}", num + 1);
+            let fields: Vec<&str> = line.splitn(5, ' ').collect();
+            match fields[0] {
+                "file" if fields.len() >= 3 => {
+                    // file names may contain spaces, so take the rest of the record
+                    let name = line.splitn(3, ' ').nth(2).ok_or_else(err)?;
+                    info.files.push(name.to_string());
+                },
+                "line" if fields.len() == 4 => {
+                    info.lines.push(LineInfo {
+                        address: parse_hex(fields[1]).ok_or_else(err)?,
+                        file: fields[2].parse().map_err(|_| err())?,
+                        line: fields[3].parse().map_err(|_| err())?,
+                    });
+                },
+                "label" if fields.len() == 3 => {
+                    info.labels.push(LabelSymbol {
+                        address: parse_hex(fields[1]).ok_or_else(err)?,
+                        name: fields[2].to_string(),
+                    });
+                },
+                "data" if fields.len() == 5 => {
+                    info.data.push(DataSymbol {
+                        address: parse_hex(fields[1]).ok_or_else(err)?,
+                        size: fields[2].parse().map_err(|_| err())?,
+                        typ: DataType::from_keyword(fields[3]).ok_or_else(err)?,
+                        name: fields[4].to_string(),
+                    });
+                },
+                _ => return Err(err()),
+            }
+        }
+        info.lines.sort_by_key(|l| l.address);
+        Ok(info)
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+impl Display for DebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", DEBUG_MAGIC)?;
+        for (idx, file) in self.files.iter().enumerate() {
+            writeln!(f, "file {} {}", idx, file)?;
+        }
+        for line in &self.lines {
+            writeln!(f, "line 0x{:08x} {} {}", line.address, line.file, line.line)?;
+        }
+        for label in &self.labels {
+            writeln!(f, "label 0x{:08x} {}", label.address, label.name)?;
+        }
+        for data in &self.data {
+            writeln!(f, "data 0x{:08x} {} {} {}", data.address, data.size, data.typ.keyword(), data.name)?;
+        }
+        Ok(())
+    }
+}
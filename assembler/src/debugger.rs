@@ -0,0 +1,511 @@
+use machine::{errors::VMError, internal::machine::Machine};
+
+use crate::{debug::DebugInfo, parser::parse_number};
+
+/// instructions a single `continue`, `next` or `finish` may run before it is stopped
+pub const STEP_LIMIT: u64 = 100_000_000;
+
+/// words shown per line by `x`
+const MEMORY_LINE: usize = 8;
+
+const HELP: &str = "\
+break <loc>        stop at a location: .label, *address, file:line or a line of the first file
+watch <addr>       stop when the memory cell at an address changes, <addr> is a number, .label or $identifier
+delete [n]         delete breakpoint or watchpoint n, or all of them
+info breakpoints   list breakpoints and watchpoints
+step [n]           run n instructions, going into calls
+next [n]           run n instructions, running calls to their return
+finish             run until the current procedure returns
+continue           run until a breakpoint, a watchpoint or the end of the program
+run                start the program over and run it
+info registers     show registers
+info flags         show flags
+print <what>       show a register (r0-r7, pc), the cells of a $identifier or an address
+x[/n] <addr> [n]   show n memory cells from an address
+stack              show the stack from the bottom to the top
+backtrace          show the location of every active call, the innermost first
+help               show this help
+quit               leave the debugger
+an empty line repeats the previous command";
+
+#[derive(Debug)]
+/// Why the machine stopped
+pub enum Stop {
+    /// the requested instructions ran
+    Done,
+    Breakpoint { id: usize, address: u32 },
+    /// the memory cell at `address` changed from `old` to `new`
+    Watchpoint { id: usize, address: u32, old: u32, new: u32 },
+    /// `term` ran
+    Terminated,
+    Error(VMError),
+    /// the instruction limit ran out, `STEP_LIMIT` unless given to `resume_for`
+    Limit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How far `resume` runs the machine
+pub enum Resume {
+    /// one instruction
+    Step,
+    /// one instruction, or a whole call
+    Next,
+    /// until the current procedure returns
+    Finish,
+    /// until something stops it
+    Continue,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// a command ran, with the text it shows
+    Output(String),
+    /// the debugger should end
+    Quit,
+}
+
+enum PointKind {
+    Break,
+    /// value of the watched cell when it was last seen
+    Watch(u32),
+}
+
+/// Breakpoint or watchpoint, numbered together as in gdb
+struct Point {
+    id: usize,
+    address: u32,
+    kind: PointKind,
+}
+
+/// # Debugger
+///
+/// Runs a loaded machine instruction by instruction, stopping at breakpoints on code
+/// addresses and at watchpoints on memory cells whose value changes. `next` and `finish`
+/// follow the call stack of the machine, so they also work on code reached through indirect
+/// calls. Debug information, when available, names addresses after their source lines,
+/// labels and data identifiers.
+///
+/// The program starts stopped at its entry point. Commands follow gdb, see `help`.
+pub struct Debugger {
+    machine: Machine,
+    /// machine as loaded, `run` starts over from it
+    initial: Machine,
+    info: Option<DebugInfo>,
+    points: Vec<Point>,
+    next_id: usize,
+    /// false once the program terminated or failed
+    running: bool,
+    /// command repeated by an empty line
+    last: String,
+}
+
+impl Debugger {
+    /// debugger for a machine loaded with a program and stopped at its entry point
+    pub fn new(machine: Machine, info: Option<DebugInfo>) -> Debugger {
+        Debugger { initial: machine.clone(), machine, info, points: Vec::new(), next_id: 1, running: true, last: String::new() }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// machine to change registers and flags in, memory is written with `write_memory`
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    /// write memory, without the change stopping at the watchpoints on it
+    pub fn write_memory(&mut self, address: u32, words: &[u32]) -> Result<(), VMError> {
+        self.machine.load_data(address, words)?;
+        self.refresh_watchpoints();
+        Ok(())
+    }
+
+    fn refresh_watchpoints(&mut self) {
+        for point in &mut self.points {
+            if let PointKind::Watch(value) = &mut point.kind {
+                *value = self.machine.memory.read(point.address).unwrap_or(0);
+            }
+        }
+    }
+
+    pub fn info(&self) -> Option<&DebugInfo> {
+        self.info.as_ref()
+    }
+
+    /// whether the program can still run, it cannot once it terminated or failed
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// start the program over, keeping breakpoints and watchpoints
+    pub fn restart(&mut self) {
+        self.machine = self.initial.clone();
+        self.running = true;
+        self.refresh_watchpoints();
+    }
+
+    /// stop whenever the instruction at `address` is about to run, returns its number
+    pub fn add_breakpoint(&mut self, address: u32) -> usize {
+        self.add_point(address, PointKind::Break)
+    }
+
+    /// stop whenever the memory cell at `address` changes, returns its number
+    pub fn add_watchpoint(&mut self, address: u32) -> Result<usize, VMError> {
+        let value = self.machine.memory.read(address)?;
+        Ok(self.add_point(address, PointKind::Watch(value)))
+    }
+
+    fn add_point(&mut self, address: u32, kind: PointKind) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.points.push(Point { id, address, kind });
+        id
+    }
+
+    /// delete a breakpoint or watchpoint, returns whether it existed
+    pub fn delete(&mut self, id: usize) -> bool {
+        let before = self.points.len();
+        self.points.retain(|p| p.id != id);
+        self.points.len() != before
+    }
+
+    /// delete every breakpoint and watchpoint
+    pub fn delete_all(&mut self) {
+        self.points.clear();
+    }
+
+    /// program counter and the address of every active call, the innermost first
+    pub fn backtrace(&self) -> Vec<u32> {
+        let mut frames = vec![self.machine.register.pc];
+        frames.extend(self.machine.call_frames().into_iter().rev());
+        frames
+    }
+
+    /// run the machine until `mode` is done or something stops it first
+    pub fn resume(&mut self, mode: Resume) -> Stop {
+        self.resume_for(mode, STEP_LIMIT)
+    }
+
+    /// `resume` giving up with `Stop::Limit` after `limit` instructions, `Next` and `Finish`
+    /// count calls from where they are resumed
+    pub fn resume_for(&mut self, mode: Resume, limit: u64) -> Stop {
+        let depth = self.machine.call_frames().len();
+        match mode {
+            Resume::Step => self.run(limit, |_| true),
+            Resume::Next => self.run(limit, |machine| machine.call_frames().len() <= depth),
+            Resume::Finish => self.run(limit, |machine| machine.call_frames().len() < depth),
+            Resume::Continue => self.run(limit, |_| false),
+        }
+    }
+
+    /// run until `done` holds after an instruction, checking watchpoints after every
+    /// instruction and breakpoints before every instruction but the first
+    fn run(&mut self, limit: u64, done: impl Fn(&Machine) -> bool) -> Stop {
+        if !self.running {
+            return Stop::Terminated;
+        }
+        for _ in 0..limit {
+            match self.machine.step() {
+                Ok(false) => {},
+                Ok(true) => {
+                    self.running = false;
+                    return Stop::Terminated;
+                },
+                Err(e) => {
+                    self.running = false;
+                    return Stop::Error(e);
+                },
+            }
+            let mut changed = None;
+            for point in &mut self.points {
+                if let PointKind::Watch(old) = &mut point.kind {
+                    let new = self.machine.memory.read(point.address).unwrap_or(0);
+                    if new != *old {
+                        changed.get_or_insert(Stop::Watchpoint { id: point.id, address: point.address, old: *old, new });
+                        *old = new;
+                    }
+                }
+            }
+            if let Some(stop) = changed {
+                return stop;
+            }
+            if done(&self.machine) {
+                return Stop::Done;
+            }
+            let pc = self.machine.register.pc;
+            if let Some(point) = self.points.iter().find(|p| matches!(p.kind, PointKind::Break) && p.address == pc) {
+                return Stop::Breakpoint { id: point.id, address: pc };
+            }
+        }
+        Stop::Limit
+    }
+
+    /// Address of a code location: `.label`, `*address`, `file:line` or a line of the first
+    /// file. Lines without code resolve to the next line that has some.
+    pub fn code_address(&self, location: &str) -> Result<u32, String> {
+        if let Some(address) = location.strip_prefix('*') {
+            return number(address);
+        }
+        if let Some(name) = location.strip_prefix('.') {
+            return match self.info.as_ref().and_then(|info| info.label(name)) {
+                Some(label) => Ok(label.address),
+                None => Err(format!("no label '{}'", location)),
+            };
+        }
+        let info = self.info.as_ref().ok_or_else(|| format!("no debug information for '{}', use '*<address>'", location))?;
+        let (file, line) = match location.rsplit_once(':') {
+            Some((file, line)) => {
+                let idx = info.files.iter().position(|f| f == file || f.ends_with(&format!("/{}", file)));
+                (idx.ok_or_else(|| format!("no file '{}' in the debug information", file))?, line)
+            },
+            None => (0, location),
+        };
+        let line: usize = line.parse().map_err(|_| format!("'{}' is not a location", location))?;
+        info.line_at_or_after(file, line)
+            .map(|l| l.address)
+            .ok_or_else(|| format!("no code at or after line {} of {}", line, info.files[file]))
+    }
+
+    /// Address given as a number, `.label` or `$identifier`, optionally followed by `+offset`,
+    /// with the size of an identifier given without an offset
+    pub fn data_address(&self, word: &str) -> Result<(u32, Option<u32>), String> {
+        let (base, offset) = match word.split_once('+') {
+            Some((base, offset)) => (base.trim(), Some(number(offset.trim())?)),
+            None => (word, None),
+        };
+        let (address, size) = if let Some(name) = base.strip_prefix('$') {
+            match self.info.as_ref().and_then(|info| info.data(name)) {
+                Some(data) => (data.address, Some(data.size)),
+                None => return Err(format!("no identifier '{}'", base)),
+            }
+        } else if base.starts_with('.') {
+            (self.code_address(base)?, None)
+        } else {
+            (number(base)?, None)
+        };
+        match offset {
+            Some(offset) => Ok((address.wrapping_add(offset), None)),
+            None => Ok((address, size)),
+        }
+    }
+
+    /// address with its source line and label, e.g. `0x00000004 main.asm:5 (.loop+2)`
+    pub fn location(&self, address: u32) -> String {
+        match &self.info {
+            Some(info) if info.line_for(address).is_some() => format!("0x{:08x} {}", address, info.describe(address)),
+            Some(info) => info.describe(address),
+            None => format!("0x{:08x}", address),
+        }
+    }
+
+    /// `$identifier+offset` holding an address, or the address itself
+    fn data_name(&self, address: u32) -> String {
+        match self.info.as_ref().and_then(|info| info.data_for(address)) {
+            Some(data) if data.address == address => format!("${}", data.name),
+            Some(data) => format!("${}+{}", data.name, address - data.address),
+            None => format!("0x{:08x}", address),
+        }
+    }
+
+    /// text of the source line of an address, when its file can be read
+    fn source_line(&self, address: u32) -> Option<String> {
+        let info = self.info.as_ref()?;
+        let line = info.line_for(address)?;
+        let content = std::fs::read_to_string(info.files.get(line.file)?).ok()?;
+        let text = content.lines().nth(line.line.checked_sub(1)?)?;
+        Some(format!("{}\t{}", line.line, text))
+    }
+
+    /// where the machine is now, with its source line
+    fn here(&self) -> String {
+        let pc = self.machine.register.pc;
+        match self.source_line(pc) {
+            Some(text) => format!("{}\n{}", self.location(pc), text),
+            None => self.location(pc),
+        }
+    }
+
+    /// text shown for a stop
+    pub fn describe_stop(&self, stop: &Stop) -> String {
+        let pc = self.machine.register.pc;
+        match stop {
+            Stop::Done => self.here(),
+            Stop::Breakpoint { id, .. } => format!("breakpoint {}, {}", id, self.here()),
+            Stop::Watchpoint { id, address, old, new } => {
+                format!("watchpoint {}: {}\nold value = {}\nnew value = {}\n{}", id, self.data_name(*address), old, new, self.here())
+            },
+            Stop::Terminated => format!("program terminated at {}", self.location(pc)),
+            Stop::Error(e) => format!("runtime error: {} at {}", e, self.location(pc)),
+            Stop::Limit => format!("stopped after {} instructions at {}", STEP_LIMIT, self.here()),
+        }
+    }
+
+    /// run a command, an empty line repeats the previous one
+    pub fn command(&mut self, line: &str) -> Result<Reply, String> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        if line.is_empty() {
+            return Ok(Reply::Output(String::new()));
+        }
+        self.last = line.clone();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let output = match words.as_slice() {
+            ["break" | "b", location] => {
+                let address = self.code_address(location)?;
+                let id = self.add_breakpoint(address);
+                format!("breakpoint {} at {}", id, self.location(address))
+            },
+            ["watch", address] => {
+                let (address, _) = self.data_address(address)?;
+                let id = self.add_watchpoint(address).map_err(|e| format!("cannot watch 0x{:08x}: {}", address, e))?;
+                format!("watchpoint {}: {}", id, self.data_name(address))
+            },
+            ["delete" | "d"] => {
+                self.delete_all();
+                "deleted all breakpoints and watchpoints".to_string()
+            },
+            ["delete" | "d", id] => {
+                let id = number(id)? as usize;
+                if !self.delete(id) {
+                    return Err(format!("no breakpoint or watchpoint {}", id));
+                }
+                format!("deleted {}", id)
+            },
+            ["info" | "i", "breakpoints" | "break" | "b" | "watchpoints"] => {
+                let lines: Vec<String> = self.points.iter()
+                    .map(|p| match p.kind {
+                        PointKind::Break => format!("{}\tbreakpoint\t{}", p.id, self.location(p.address)),
+                        PointKind::Watch(value) => format!("{}\twatchpoint\t{} = {}", p.id, self.data_name(p.address), value),
+                    })
+                    .collect();
+                match lines.is_empty() {
+                    true => "no breakpoints or watchpoints".to_string(),
+                    false => lines.join("\n"),
+                }
+            },
+            [mode @ ("step" | "s" | "stepi" | "si" | "next" | "n" | "nexti" | "ni"), rest @ ..] if rest.len() <= 1 => {
+                let count = match rest.first() {
+                    Some(count) => number(count)?,
+                    None => 1,
+                };
+                let mode = if mode.starts_with('s') { Resume::Step } else { Resume::Next };
+                self.require_running()?;
+                let mut stop = Stop::Done;
+                for _ in 0..count {
+                    stop = self.resume(mode);
+                    if !matches!(stop, Stop::Done) {
+                        break;
+                    }
+                }
+                self.describe_stop(&stop)
+            },
+            ["finish" | "fin"] => {
+                self.require_running()?;
+                if self.machine.call_frames().is_empty() {
+                    return Err("'finish' is not meaningful in the outermost frame".to_string());
+                }
+                let stop = self.resume(Resume::Finish);
+                self.describe_stop(&stop)
+            },
+            ["continue" | "c"] => {
+                self.require_running()?;
+                let stop = self.resume(Resume::Continue);
+                self.describe_stop(&stop)
+            },
+            ["run" | "r"] => {
+                self.restart();
+                let stop = self.resume(Resume::Continue);
+                self.describe_stop(&stop)
+            },
+            ["info" | "i", "registers" | "reg" | "r"] | ["registers" | "regs"] => {
+                let register = &self.machine.register;
+                let values = [
+                    ("r0", register.r0), ("r1", register.r1), ("r2", register.r2), ("r3", register.r3),
+                    ("r4", register.r4), ("r5", register.r5), ("r6", register.r6), ("r7", register.r7),
+                    ("pc", register.pc),
+                ];
+                values.iter().map(|(name, value)| format!("{} = 0x{:08x} {}", name, value, value)).collect::<Vec<_>>().join("\n")
+            },
+            ["info" | "i", "flags"] | ["flags"] => {
+                let flag = &self.machine.flag;
+                format!("zero={} negative={} overflow={} carry={}", flag.zero as u8, flag.negative as u8, flag.overflow as u8, flag.carry as u8)
+            },
+            ["print" | "p", what] => self.print(what)?,
+            [x, address, rest @ ..] if (*x == "x" || x.starts_with("x/")) && rest.len() <= 1 => {
+                let (start, size) = self.data_address(address)?;
+                let count = match (x.strip_prefix("x/"), rest.first()) {
+                    (Some(count), None) | (None, Some(&count)) => number(count)?,
+                    (None, None) => size.unwrap_or(MEMORY_LINE as u32),
+                    (Some(_), Some(_)) => return Err("give the count either as x/<n> or after the address".to_string()),
+                };
+                self.memory(start, count)?
+            },
+            ["stack"] | ["info" | "i", "stack"] => {
+                let stack = self.machine.memory.stack();
+                let values: Vec<String> = stack.iter().map(|v| v.to_string()).collect();
+                format!("<{}> {}", stack.len(), values.join(" ")).trim_end().to_string()
+            },
+            ["backtrace" | "bt" | "where"] => {
+                self.backtrace().iter().enumerate()
+                    .map(|(idx, &address)| format!("#{} {}", idx, self.location(address)))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            },
+            ["help" | "h"] => HELP.to_string(),
+            ["quit" | "q"] => return Ok(Reply::Quit),
+            _ => return Err(format!("unknown command '{}', see help", line)),
+        };
+        Ok(Reply::Output(output))
+    }
+
+    fn require_running(&self) -> Result<(), String> {
+        match self.running {
+            true => Ok(()),
+            false => Err("the program is not running, 'run' starts it again".to_string()),
+        }
+    }
+
+    /// value of a register, the cells of an identifier or the cell at an address
+    fn print(&self, what: &str) -> Result<String, String> {
+        let register = match what {
+            "pc" => Some(100),
+            _ => what.strip_prefix('r').and_then(|n| n.parse::<u32>().ok()).filter(|n| *n < 8),
+        };
+        if let Some(reg) = register {
+            let value = self.machine.read_register(reg).map_err(|e| e.to_string())?;
+            return Ok(format!("{} = 0x{:08x} {}", what, value, value));
+        }
+        let (address, size) = self.data_address(what)?;
+        let mut values = Vec::new();
+        for address in address..address.saturating_add(size.unwrap_or(1)) {
+            values.push(self.machine.memory.read(address).map_err(|e| e.to_string())?.to_string());
+        }
+        Ok(format!("{} = {}", what, values.join(" ")))
+    }
+
+    /// `count` memory cells from `start`, `MEMORY_LINE` a line
+    fn memory(&self, start: u32, count: u32) -> Result<String, String> {
+        let mut words = Vec::new();
+        for address in start..start.saturating_add(count) {
+            words.push(self.machine.memory.read(address).map_err(|e| format!("cannot read 0x{:08x}: {}", address, e))?);
+        }
+        Ok(words.chunks(MEMORY_LINE).enumerate()
+            .map(|(idx, chunk)| {
+                let values: Vec<String> = chunk.iter().map(|w| format!("{:08x}", w)).collect();
+                format!("0x{:08x}: {}", start as usize + idx * MEMORY_LINE, values.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// number argument of a command
+fn number(word: &str) -> Result<u32, String> {
+    match parse_number(word) {
+        Ok(("", value)) => Ok(value),
+        _ => Err(format!("'{}' is not a number", word)),
+    }
+}
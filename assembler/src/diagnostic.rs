@@ -0,0 +1,80 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// # Diagnostic
+///
+/// Error or warning about the source code, with enough context to point at the offending text.
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    /// 1-based line, 0 when the problem has no single location
+    pub line: usize,
+    /// 1-based column of the first underlined character
+    pub column: usize,
+    /// number of characters to underline
+    pub len: usize,
+    /// text of the source line
+    pub snippet: String,
+    /// extra context such as macro expansions or earlier definitions
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// error that is not tied to a line of `file`
+    pub fn error(message: impl Into<String>, file: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            file: file.into(),
+            line: 0,
+            column: 0,
+            len: 0,
+            snippet: String::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+/// true when any of the diagnostics is an error
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        writeln!(f, "{}: {}", severity, self.message)?;
+        if self.line == 0 {
+            write!(f, " --> {}", self.file)?;
+        } else {
+            let gutter = " ".repeat(self.line.to_string().len());
+            writeln!(f, "{} --> {}:{}:{}", gutter, self.file, self.line, self.column)?;
+            writeln!(f, "{} |", gutter)?;
+            writeln!(f, "{} | {}", self.line, self.snippet.replace('\t', " "))?;
+            write!(f, "{} | {}{}", gutter, " ".repeat(self.column.saturating_sub(1)), "^".repeat(self.len.max(1)))?;
+        }
+        for note in &self.notes {
+            write!(f, "\n = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use crate::tokens::{BinaryOp, Expr, SymbolName};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the value of an expression is relative to
+pub enum Base<'a> {
+    /// plain number
+    Absolute,
+    /// address inside the object being assembled
+    Local,
+    /// address of a symbol defined in another object
+    Import(SymbolName<'a>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Evaluated expression, `value` is added to the address of `base`
+pub struct Value<'a> {
+    pub value: u32,
+    pub base: Base<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Evaluation error, `span` is the name that caused it when there is one
+pub struct EvalError<'a> {
+    pub message: String,
+    pub span: Option<&'a str>,
+}
+
+impl<'a> EvalError<'a> {
+    fn new(message: String) -> EvalError<'a> {
+        EvalError { message, span: None }
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn absolute(value: u32) -> Value<'a> {
+        Value { value, base: Base::Absolute }
+    }
+}
+
+fn op_name(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::And => "&",
+        BinaryOp::Or => "|",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Size of a data identifier
+pub struct DataSize {
+    /// memory cells taken
+    pub cells: u32,
+    /// number of values, in units of the data type
+    pub length: u32,
+}
+
+/// Evaluates constant expressions against `@define`s and a symbol lookup
+pub struct Evaluator<'a, 'd, F> {
+    defines: &'d HashMap<&'a str, Expr<'a>>,
+    symbol: F,
+    sizes: Option<&'d dyn Fn(&'a str) -> Result<DataSize, String>>,
+}
+
+impl<'a, 'd, F> Evaluator<'a, 'd, F>
+where
+    F: Fn(SymbolName<'a>) -> Result<Value<'a>, String>,
+{
+    /// `symbol` resolves labels and data identifiers, it can refuse them where addresses are not allowed
+    pub fn new(defines: &'d HashMap<&'a str, Expr<'a>>, symbol: F) -> Self {
+        Evaluator { defines, symbol, sizes: None }
+    }
+
+    /// resolve `sizeof` and `lengthof` with `sizes`, without it they are errors
+    pub fn with_sizes(mut self, sizes: &'d dyn Fn(&'a str) -> Result<DataSize, String>) -> Self {
+        self.sizes = Some(sizes);
+        self
+    }
+
+    pub fn evaluate(&self, expr: &Expr<'a>) -> Result<Value<'a>, EvalError<'a>> {
+        self.eval(expr, &mut Vec::new())
+    }
+
+    fn eval(&self, expr: &Expr<'a>, expanding: &mut Vec<&'a str>) -> Result<Value<'a>, EvalError<'a>> {
+        match expr {
+            Expr::Number(n) => Ok(Value::absolute(*n)),
+            Expr::Symbol(name) => {
+                let span = match name {
+                    SymbolName::Label(s) | SymbolName::Data(s) => *s,
+                };
+                (self.symbol)(*name).map_err(|message| EvalError { message, span: Some(span) })
+            },
+            Expr::Define(name) => {
+                if expanding.contains(name) {
+                    return Err(EvalError { message: format!("constant '{}' is defined in terms of itself", name), span: Some(name) });
+                }
+                let Some(value) = self.defines.get(name) else {
+                    return Err(EvalError { message: format!("undefined constant '{}'", name), span: Some(name) });
+                };
+                expanding.push(name);
+                let result = self.eval(value, expanding);
+                expanding.pop();
+                result
+            },
+            Expr::Not(inner) => {
+                let value = self.eval(inner, expanding)?;
+                if value.base != Base::Absolute {
+                    return Err(EvalError::new("operator '~' cannot be applied to an address".to_string()));
+                }
+                Ok(Value::absolute(!value.value))
+            },
+            Expr::Neg(inner) => {
+                let value = self.eval(inner, expanding)?;
+                if value.base != Base::Absolute {
+                    return Err(EvalError::new("an address cannot be negated".to_string()));
+                }
+                Ok(Value::absolute(value.value.wrapping_neg()))
+            },
+            Expr::SizeOf(id) => Ok(Value::absolute(self.data_size(id, "sizeof")?.cells)),
+            Expr::LengthOf(id) => Ok(Value::absolute(self.data_size(id, "lengthof")?.length)),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs, expanding)?;
+                let rhs = self.eval(rhs, expanding)?;
+                binary(*op, lhs, rhs).map_err(EvalError::new)
+            },
+        }
+    }
+}
+
+impl<'a, 'd, F> Evaluator<'a, 'd, F> {
+    fn data_size(&self, id: &'a str, operator: &str) -> Result<DataSize, EvalError<'a>> {
+        let Some(sizes) = self.sizes else {
+            return Err(EvalError { message: format!("'{}' cannot be used here", operator), span: Some(id) });
+        };
+        sizes(id).map_err(|message| EvalError { message, span: Some(id) })
+    }
+}
+
+fn binary<'a>(op: BinaryOp, lhs: Value<'a>, rhs: Value<'a>) -> Result<Value<'a>, String> {
+    let overflow = || format!("overflow in '{} {} {}'", lhs.value, op_name(op), rhs.value);
+    let base = match (op, lhs.base, rhs.base) {
+        (_, Base::Absolute, Base::Absolute) => Base::Absolute,
+        (BinaryOp::Add, base, Base::Absolute) | (BinaryOp::Add, Base::Absolute, base) => base,
+        (BinaryOp::Sub, base, Base::Absolute) => base,
+        // distance between two local addresses does not change with relocation
+        (BinaryOp::Sub, Base::Local, Base::Local) => Base::Absolute,
+        _ => return Err(format!("operator '{}' cannot be applied to these addresses", op_name(op))),
+    };
+    let value = match op {
+        BinaryOp::Add => lhs.value.checked_add(rhs.value).ok_or_else(overflow)?,
+        BinaryOp::Sub => lhs.value.checked_sub(rhs.value).ok_or_else(overflow)?,
+        BinaryOp::Mul => lhs.value.checked_mul(rhs.value).ok_or_else(overflow)?,
+        BinaryOp::Div => {
+            if rhs.value == 0 {
+                return Err("division by zero".to_string());
+            }
+            lhs.value / rhs.value
+        },
+        BinaryOp::Shl => {
+            let value = lhs.value.checked_shl(rhs.value).ok_or_else(overflow)?;
+            if value >> rhs.value != lhs.value {
+                return Err(overflow());
+            }
+            value
+        },
+        BinaryOp::Shr => lhs.value.checked_shr(rhs.value).ok_or_else(overflow)?,
+        BinaryOp::And => lhs.value & rhs.value,
+        BinaryOp::Or => lhs.value | rhs.value,
+    };
+    Ok(Value { value, base })
+}
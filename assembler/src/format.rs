@@ -0,0 +1,222 @@
+use nom::Offset;
+
+use crate::{diagnostic::Diagnostic, parser::{parse_line, KEYWORDS, MNEMONICS}, source::{quoted_chars, strip_comment, DIRECTIVES}};
+
+/// spaces added for every level of indentation
+const INDENT: usize = 4;
+
+/// register names, written in lowercase like mnemonics
+const REGISTERS: [&str; 9] = ["r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "pc"];
+
+/// data types and reservations following the identifier of a data definition
+const DATA_TYPES: [&str; 6] = ["b", "w", "dw", "resb", "resw", "resdw"];
+
+/// Source line split into its parts, before blank lines and comments are laid out
+struct Line {
+    /// columns before the code, `None` for blank and comment only lines
+    indent: Option<usize>,
+    code: String,
+    /// comment including its `;`
+    comment: Option<String>,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// number with a lowercase base prefix and digits, decimal numbers without leading zeros
+fn number(word: &str) -> String {
+    let lower = word.to_ascii_lowercase();
+    let valid = match (lower.strip_prefix("0x"), lower.strip_prefix("0b")) {
+        (Some(digits), _) => !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()),
+        (_, Some(digits)) => !digits.is_empty() && digits.chars().all(|c| c == '0' || c == '1'),
+        _ => lower.chars().all(|c| c.is_ascii_digit()),
+    };
+    if !valid {
+        word.to_string()
+    } else if lower.starts_with("0x") || lower.starts_with("0b") {
+        lower
+    } else {
+        let trimmed = lower.trim_start_matches('0');
+        if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
+    }
+}
+
+/// Code of a line with single spaces between words, numbers normalized and mnemonics,
+/// registers and keywords in lowercase. Strings, character literals and names of labels,
+/// data identifiers and macro parameters are kept as written.
+fn normalize(code: &str) -> String {
+    let chars = quoted_chars(code);
+    let mut out = String::with_capacity(code.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c, quoted) = chars[i];
+        if quoted || !(c.is_whitespace() || is_name_char(c)) {
+            out.push(c);
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            if !out.is_empty() && !out.ends_with(' ') {
+                out.push(' ');
+            }
+            i += 1;
+            continue;
+        }
+        let mut j = i;
+        while j < chars.len() && !chars[j].2 && is_name_char(chars[j].1) {
+            j += 1;
+        }
+        let word = &code[start..chars.get(j).map_or(code.len(), |n| n.0)];
+        let lower = word.to_ascii_lowercase();
+        let normalized = match out.chars().last() {
+            Some('.' | '$' | '%') => word.to_string(),
+            // directives and anonymous label references
+            Some('@') => lower,
+            _ if c.is_ascii_digit() => number(word),
+            _ if REGISTERS.contains(&lower.as_str()) || MNEMONICS.contains(&lower.as_str()) || KEYWORDS.contains(&lower.as_str()) => lower,
+            _ => word.to_string(),
+        };
+        out += &normalized;
+        i = j;
+    }
+    if out.starts_with('[') {
+        // section names are not case sensitive
+        return out.trim_end().to_ascii_lowercase();
+    }
+    let mut words: Vec<String> = out.trim_end().split(' ').map(str::to_string).collect();
+    // data type of a data definition and condition of `@if` and `@while`
+    let second_lower = match words.first().map(String::as_str) {
+        Some(first) if first.starts_with('$') => words.get(1).is_some_and(|w| DATA_TYPES.contains(&w.to_ascii_lowercase().as_str())),
+        Some("@if" | "@while") => true,
+        _ => false,
+    };
+    if second_lower && let Some(word) = words.get_mut(1) {
+        *word = word.to_ascii_lowercase();
+    }
+    words.join(" ")
+}
+
+/// error for the unparsed end `rest` of line `line`
+fn parse_error(file: &str, line: usize, raw: &str, rest: &str) -> Diagnostic {
+    let code = strip_comment(raw).trim_start();
+    let message = if code.trim_end() == rest { format!("unable to parse '{}'", rest) } else { format!("unexpected '{}'", rest) };
+    Diagnostic {
+        line,
+        column: raw[..raw.offset(rest)].chars().count() + 1,
+        len: rest.chars().count(),
+        snippet: raw.to_string(),
+        ..Diagnostic::error(message, file)
+    }
+}
+
+/// # Formatter
+///
+/// Rewrite assembly source in the canonical style: sections, labels, data definitions and
+/// directives flush left, instructions and macro invocations indented by four spaces plus four
+/// for every enclosing `@if` or `@while`, single spaces between words, lowercase mnemonics,
+/// registers and keywords, normalized numbers and trailing comments aligned within each group
+/// of lines. Comments are kept and runs of blank lines become a single one.
+///
+/// Lines that are not macro bodies, macro invocations or control-flow directives must parse,
+/// otherwise nothing is formatted and every such line is reported.
+///
+/// # Params
+///
+/// * `text`: source code
+/// * `file`: name of the source used in diagnostics
+pub fn format_source(text: &str, file: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    let mut depth = 0;
+    let mut in_macro = false;
+    for (idx, raw) in text.lines().enumerate() {
+        let code = strip_comment(raw);
+        let comment = raw[code.len()..].trim_end();
+        let code = normalize(code);
+        let first = code.split(' ').next().unwrap_or("");
+        let level = if code.is_empty() {
+            None
+        } else if first.starts_with(['[', '.', '$']) || first.starts_with("@@") {
+            Some(0)
+        } else {
+            match first {
+                "@macro" => {
+                    in_macro = true;
+                    Some(0)
+                },
+                "@endm" => {
+                    in_macro = false;
+                    Some(0)
+                },
+                "@if" | "@while" => {
+                    depth += 1;
+                    Some(depth)
+                },
+                "@else" => Some(depth),
+                "@endif" | "@endw" => {
+                    depth = usize::saturating_sub(depth, 1);
+                    Some(depth + 1)
+                },
+                _ if first.starts_with('@') => Some(0),
+                _ => Some(depth + 1),
+            }
+        };
+        let lexical = in_macro || first == "@endm" || DIRECTIVES.contains(&first)
+            || (!first.starts_with(['[', '.', '$', '@']) && !MNEMONICS.contains(&first));
+        if !code.is_empty() && !lexical {
+            let rest = match parse_line(raw) {
+                Ok((rest, _)) => rest,
+                Err(_) => raw.trim_start(),
+            };
+            let rest = strip_comment(rest).trim_end();
+            if !rest.is_empty() {
+                errors.push(parse_error(file, idx + 1, raw, rest));
+            }
+        }
+        lines.push(Line {
+            indent: level.map(|l| l * INDENT),
+            code,
+            comment: (!comment.is_empty()).then(|| comment.to_string()),
+        });
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut start = 0;
+    while start < lines.len() {
+        if lines[start].indent.is_none() && lines[start].comment.is_none() {
+            start += 1;
+            continue;
+        }
+        let end = lines[start..].iter().position(|l| l.indent.is_none() && l.comment.is_none()).map_or(lines.len(), |p| start + p);
+        let group = &lines[start..end];
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let width = |l: &Line| l.indent.unwrap_or(0) + l.code.chars().count();
+        let column = group.iter().filter(|l| l.indent.is_some() && l.comment.is_some()).map(width).max().unwrap_or(0) + 1;
+        let mut previous = 0;
+        for (i, line) in group.iter().enumerate() {
+            match (line.indent, &line.comment) {
+                (Some(indent), comment) => {
+                    let code = format!("{}{}", " ".repeat(indent), line.code);
+                    match comment {
+                        Some(comment) => out += &format!("{:width$}{}\n", code, comment, width = column),
+                        None => out += &format!("{}\n", code),
+                    }
+                    previous = indent;
+                },
+                // comment lines are indented like the code they precede, or else follow
+                (None, comment) => {
+                    let indent = group[i..].iter().find_map(|l| l.indent).unwrap_or(previous);
+                    out += &format!("{}{}\n", " ".repeat(indent), comment.as_deref().unwrap_or(""));
+                },
+            }
+        }
+        start = end;
+    }
+    Ok(out)
+}
@@ -0,0 +1,446 @@
+use std::{collections::{HashMap, VecDeque}, io::{ErrorKind, Read, Write}, net::TcpStream};
+
+use machine::errors::VMError;
+
+use crate::debugger::{Debugger, Resume, Stop};
+
+/// instructions run between two checks for an interrupt from the client
+const POLL_STEPS: u64 = 100_000;
+
+/// registers in the order of the target description, `g` and `p`
+const REGISTERS: usize = 10;
+const PC: usize = 8;
+const FLAGS: usize = 9;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.myvm.core">
+    <flags id="myvm_flags" size="4">
+      <field name="zero" start="0" end="0"/>
+      <field name="negative" start="1" end="1"/>
+      <field name="overflow" start="2" end="2"/>
+      <field name="carry" start="3" end="3"/>
+    </flags>
+    <reg name="r0" bitsize="32" type="uint32" regnum="0"/>
+    <reg name="r1" bitsize="32" type="uint32"/>
+    <reg name="r2" bitsize="32" type="uint32"/>
+    <reg name="r3" bitsize="32" type="uint32"/>
+    <reg name="r4" bitsize="32" type="uint32"/>
+    <reg name="r5" bitsize="32" type="uint32"/>
+    <reg name="r6" bitsize="32" type="uint32"/>
+    <reg name="r7" bitsize="32" type="uint32"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="flags" bitsize="32" type="myvm_flags"/>
+  </feature>
+</target>
+"#;
+
+/// Stream a client is connected through
+pub trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        std::os::unix::net::UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+/// What to do after a packet
+enum Action {
+    Reply(String),
+    /// reply, then close the connection
+    Close(Option<String>),
+}
+
+/// Connection to a client of `serve`
+struct Stub<S: Connection> {
+    stream: S,
+    debugger: Debugger,
+    ack: bool,
+    /// bytes read while polling for an interrupt
+    pending: VecDeque<u8>,
+    /// debugger breakpoint and watchpoint numbers by address
+    breakpoints: HashMap<u32, usize>,
+    watchpoints: HashMap<u32, usize>,
+    /// last packet sent, sent again when the client asks for it
+    last: Vec<u8>,
+}
+
+/// # GDB remote stub
+///
+/// Speaks the GDB remote serial protocol for a debugger, so gdb and other clients speaking
+/// it can attach to a machine with `target remote`. The target has the registers `r0`-`r7`,
+/// `pc` and `flags` (bits zero, negative, overflow and carry), described by `target.xml`.
+///
+/// Memory is addressed by cell as in the machine, so `pc` and breakpoints use the addresses
+/// of the assembler. Lengths stay in bytes, every cell being 4 little-endian bytes, so
+/// memory is read in whole cells and written in multiples of 4 bytes.
+///
+/// Supports register and memory reads and writes, `s`, `c` and their `vCont` forms,
+/// software breakpoints (`Z0`) and write watchpoints (`Z2`), and interrupting a running
+/// machine with Ctrl-C.
+///
+/// Serves the client until it detaches, kills the program or disconnects, and returns the
+/// debugger as the client left it.
+pub fn serve<S: Connection>(stream: S, debugger: Debugger) -> std::io::Result<Debugger> {
+    let mut stub = Stub {
+        stream,
+        debugger,
+        ack: true,
+        pending: VecDeque::new(),
+        breakpoints: HashMap::new(),
+        watchpoints: HashMap::new(),
+        last: Vec::new(),
+    };
+    stub.run()?;
+    Ok(stub.debugger)
+}
+
+impl<S: Connection> Stub<S> {
+    fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            let Some(byte) = self.byte()? else {
+                return Ok(());
+            };
+            match byte {
+                b'+' => {},
+                b'-' => {
+                    let last = self.last.clone();
+                    self.stream.write_all(&last)?;
+                },
+                // interrupt while stopped
+                0x03 => self.send("T02thread:1;")?,
+                b'$' => {
+                    let Some(packet) = self.packet()? else {
+                        continue;
+                    };
+                    match self.handle(&packet) {
+                        Action::Reply(reply) => self.send(&reply)?,
+                        Action::Close(reply) => {
+                            if let Some(reply) = reply {
+                                self.send(&reply)?;
+                            }
+                            return Ok(());
+                        },
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// next byte from the client, `None` once it disconnected
+    fn byte(&mut self) -> std::io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut buf = [0u8; 1];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buf[0])),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Rest of a packet after its `$`, acknowledged, or `None` after asking for it again
+    /// because its checksum does not match
+    fn packet(&mut self) -> std::io::Result<Option<String>> {
+        let mut data = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            let byte = self.byte()?.ok_or(ErrorKind::UnexpectedEof)?;
+            if byte == b'#' {
+                break;
+            }
+            sum = sum.wrapping_add(byte);
+            data.push(byte);
+        }
+        let high = self.byte()?.ok_or(ErrorKind::UnexpectedEof)?;
+        let low = self.byte()?.ok_or(ErrorKind::UnexpectedEof)?;
+        let checksum = std::str::from_utf8(&[high, low]).ok().and_then(|h| u8::from_str_radix(h, 16).ok());
+        if self.ack {
+            if checksum != Some(sum) {
+                self.stream.write_all(b"-")?;
+                return Ok(None);
+            }
+            self.stream.write_all(b"+")?;
+        }
+        // `}` escapes the next byte
+        let mut unescaped = Vec::new();
+        let mut bytes = data.into_iter();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'}' => unescaped.push(bytes.next().unwrap_or(0) ^ 0x20),
+                byte => unescaped.push(byte),
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&unescaped).into_owned()))
+    }
+
+    fn send(&mut self, reply: &str) -> std::io::Result<()> {
+        let mut data = Vec::new();
+        for byte in reply.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => data.extend_from_slice(&[b'}', byte ^ 0x20]),
+                byte => data.push(byte),
+            }
+        }
+        let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+        self.stream.write_all(&packet)?;
+        self.stream.flush()?;
+        self.last = packet;
+        Ok(())
+    }
+
+    /// whether the client sent an interrupt, keeping anything else it sent for later
+    fn interrupted(&mut self) -> std::io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0u8; 64];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Ok(false),
+                Ok(count) => self.pending.extend(&buf[..count]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        result?;
+        match self.pending.iter().position(|b| *b == 0x03) {
+            Some(idx) => {
+                self.pending.remove(idx);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> Action {
+        let reply = match packet {
+            "?" => self.stop_reply(None),
+            "g" => (0..REGISTERS).map(|n| hex_word(self.register(n))).collect(),
+            "k" => return Action::Close(None),
+            "D" | "vKill" => return Action::Close(Some("OK".to_string())),
+            "qC" => "QC1".to_string(),
+            "qAttached" => "1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qSymbol::" => "OK".to_string(),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            "QStartNoAckMode" => {
+                self.ack = false;
+                "OK".to_string()
+            },
+            _ if packet.starts_with("qSupported") => "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".to_string(),
+            _ if packet.starts_with('H') || packet.starts_with('T') => "OK".to_string(),
+            _ if packet.starts_with("vKill;") || packet.starts_with("D;") => return Action::Close(Some("OK".to_string())),
+            _ => match self.command(packet) {
+                Some(reply) => reply,
+                None => "E01".to_string(),
+            },
+        };
+        Action::Reply(reply)
+    }
+
+    /// packets with arguments, `None` when they are malformed or fail
+    fn command(&mut self, packet: &str) -> Option<String> {
+        // an empty packet is not a command, and the kind of one may not be a single byte
+        let Some(kind) = packet.chars().next() else { return Some(String::new()) };
+        let (kind, args) = packet.split_at(kind.len_utf8());
+        match kind {
+            "G" => {
+                let values = (0..REGISTERS).map(|n| parse_hex_word(args.get(n * 8..n * 8 + 8)?)).collect::<Option<Vec<u32>>>()?;
+                values.into_iter().enumerate().for_each(|(n, value)| self.set_register(n, value));
+                Some("OK".to_string())
+            },
+            "p" => {
+                let n = usize::from_str_radix(args, 16).ok().filter(|n| *n < REGISTERS)?;
+                Some(hex_word(self.register(n)))
+            },
+            "P" => {
+                let (n, value) = args.split_once('=')?;
+                let n = usize::from_str_radix(n, 16).ok().filter(|n| *n < REGISTERS)?;
+                self.set_register(n, parse_hex_word(value)?);
+                Some("OK".to_string())
+            },
+            "m" => {
+                let (address, length) = args.split_once(',')?;
+                let (address, length) = (u32::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?);
+                let memory = &self.debugger.machine().memory;
+                let mut bytes = Vec::new();
+                for cell in 0..length.div_ceil(4) as u32 {
+                    match memory.read(address.wrapping_add(cell)) {
+                        Ok(word) => bytes.extend_from_slice(&word.to_le_bytes()),
+                        Err(_) => break,
+                    }
+                }
+                bytes.truncate(length);
+                // a read that fails from its first cell is an error, a partial read is not
+                (!bytes.is_empty() || length == 0).then(|| bytes.iter().map(|b| format!("{:02x}", b)).collect())
+            },
+            "M" => {
+                let (header, data) = args.split_once(':')?;
+                let (address, length) = header.split_once(',')?;
+                let (address, length) = (u32::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?);
+                if length % 4 != 0 || Some(data.len()) != length.checked_mul(2) {
+                    return None;
+                }
+                let words = (0..length / 4).map(|n| parse_hex_word(data.get(n * 8..n * 8 + 8)?)).collect::<Option<Vec<u32>>>()?;
+                if !words.is_empty() {
+                    self.debugger.write_memory(address, &words).ok()?;
+                }
+                Some("OK".to_string())
+            },
+            "s" | "c" => {
+                if !args.is_empty() {
+                    self.debugger.machine_mut().set_start(u32::from_str_radix(args, 16).ok()?);
+                }
+                Some(self.resume(if kind == "s" { Resume::Step } else { Resume::Continue }))
+            },
+            "v" if args.starts_with("Cont;") => {
+                // all-stop with a single thread, so the first action is the one to take
+                let action = args["Cont;".len()..].split(';').next()?.split(':').next()?;
+                match action.chars().next()? {
+                    's' | 'S' => Some(self.resume(Resume::Step)),
+                    'c' | 'C' => Some(self.resume(Resume::Continue)),
+                    _ => None,
+                }
+            },
+            "q" if args.starts_with("Xfer:features:read:target.xml:") => {
+                let (offset, length) = args["Xfer:features:read:target.xml:".len()..].split_once(',')?;
+                let (offset, length) = (usize::from_str_radix(offset, 16).ok()?, usize::from_str_radix(length, 16).ok()?);
+                let end = offset.saturating_add(length);
+                let chunk = TARGET_XML.get(offset.min(TARGET_XML.len())..end.min(TARGET_XML.len()))?;
+                let more = end < TARGET_XML.len();
+                Some(format!("{}{}", if more { "m" } else { "l" }, chunk))
+            },
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let (typ, address) = (parts.next()?, u32::from_str_radix(parts.next()?, 16).ok()?);
+                let insert = kind == "Z";
+                match typ {
+                    "0" | "1" => {
+                        if insert && !self.breakpoints.contains_key(&address) {
+                            let id = self.debugger.add_breakpoint(address);
+                            self.breakpoints.insert(address, id);
+                        } else if !insert && let Some(id) = self.breakpoints.remove(&address) {
+                            self.debugger.delete(id);
+                        }
+                    },
+                    "2" => {
+                        if insert && !self.watchpoints.contains_key(&address) {
+                            let id = self.debugger.add_watchpoint(address).ok()?;
+                            self.watchpoints.insert(address, id);
+                        } else if !insert && let Some(id) = self.watchpoints.remove(&address) {
+                            self.debugger.delete(id);
+                        }
+                    },
+                    // read and access watchpoints are not supported
+                    _ => return Some(String::new()),
+                }
+                Some("OK".to_string())
+            },
+            // anything else is not supported, which an empty reply tells the client
+            _ => Some(String::new()),
+        }
+    }
+
+    /// run the machine, checking for an interrupt every `POLL_STEPS` instructions
+    fn resume(&mut self, mode: Resume) -> String {
+        loop {
+            let stop = self.debugger.resume_for(mode, POLL_STEPS);
+            if !matches!(stop, Stop::Limit) {
+                return self.stop_reply(Some(stop));
+            }
+            match self.interrupted() {
+                Ok(false) => {},
+                _ => return "T02thread:1;".to_string(),
+            }
+        }
+    }
+
+    /// stop reply for a stop, or for the current state
+    fn stop_reply(&self, stop: Option<Stop>) -> String {
+        let pc = format!("{:02x}:{};", PC, hex_word(self.debugger.machine().register.pc));
+        match stop {
+            None if self.debugger.running() => format!("T05{}thread:1;", pc),
+            None | Some(Stop::Terminated) => "W00".to_string(),
+            Some(Stop::Done | Stop::Limit) => format!("T05{}thread:1;", pc),
+            Some(Stop::Breakpoint { .. }) => format!("T05{}swbreak:;thread:1;", pc),
+            Some(Stop::Watchpoint { address, .. }) => format!("T05{}watch:{:x};thread:1;", pc, address),
+            Some(Stop::Error(e)) => format!("X{:02x}", signal(&e)),
+        }
+    }
+
+    fn register(&self, n: usize) -> u32 {
+        let machine = self.debugger.machine();
+        match n {
+            PC => machine.register.pc,
+            FLAGS => {
+                let flag = &machine.flag;
+                flag.zero as u32 | (flag.negative as u32) << 1 | (flag.overflow as u32) << 2 | (flag.carry as u32) << 3
+            },
+            n => machine.read_register(n as u32).unwrap_or(0),
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u32) {
+        let machine = self.debugger.machine_mut();
+        match n {
+            PC => machine.register.pc = value,
+            FLAGS => {
+                machine.flag.zero = value & 1 != 0;
+                machine.flag.negative = value & 2 != 0;
+                machine.flag.overflow = value & 4 != 0;
+                machine.flag.carry = value & 8 != 0;
+            },
+            n => {
+                let _ = machine.register.set(n as u32, value);
+            },
+        }
+    }
+}
+
+/// signal a runtime error is reported with
+fn signal(error: &VMError) -> u8 {
+    match error {
+        // SIGFPE
+        VMError::DivisionByZero => 8,
+        // SIGILL
+        VMError::InvalidOpcode | VMError::InvalidModule | VMError::InvalidFunction => 4,
+        // SIGSEGV
+        _ => 11,
+    }
+}
+
+/// register or memory word in target byte order
+fn hex_word(value: u32) -> String {
+    value.to_le_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex_word(hex: &str) -> Option<u32> {
+    if hex.len() != 8 {
+        return None;
+    }
+    let mut bytes = [0u8; 4];
+    for (idx, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(idx * 2..idx * 2 + 2)?, 16).ok()?;
+    }
+    Some(u32::from_le_bytes(bytes))
+}
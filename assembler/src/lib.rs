@@ -1,3 +1,17 @@
 pub mod tokens;
 pub mod parser;
-pub mod compiler;
\ No newline at end of file
+pub mod compiler;
+pub mod debug;
+pub mod linker;
+pub mod loader;
+pub mod listing;
+pub mod optimizer;
+pub mod verifier;
+pub mod lint;
+pub mod repl;
+pub mod debugger;
+pub mod gdb;
+pub mod source;
+pub mod expr;
+pub mod diagnostic;
+pub mod format;
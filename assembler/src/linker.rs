@@ -0,0 +1,179 @@
+use std::{collections::HashMap, fmt::Display};
+
+use binary::{image::{Image, Symbol, SymbolKind, MAX_CELLS}, object::{Object, RelocationTarget}};
+
+use crate::debug::DebugInfo;
+
+#[derive(Debug, PartialEq, Eq)]
+/// Linker errors
+pub enum LinkError {
+    /// imported symbol is not exported by any object (symbol, importing object)
+    UndefinedSymbol(String, String),
+    /// symbol is exported by more than one object (symbol, first object, second object)
+    DuplicateSymbol(String, String, String),
+    /// no object defines a `.start` label
+    MissingStart,
+    /// debug section of an object cannot be parsed (object, message)
+    InvalidDebugInfo(String, String),
+    /// objects, placed at the origin and followed by the stack, do not fit in memory (origin)
+    TooLarge(u32),
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::UndefinedSymbol(name, object) => write!(f, "undefined symbol '{}' referenced in '{}'", name, object),
+            LinkError::DuplicateSymbol(name, first, second) => write!(f, "duplicate symbol '{}' defined in '{}' and '{}'", name, first, second),
+            LinkError::MissingStart => write!(f, "no object defines a '.start' label"),
+            LinkError::InvalidDebugInfo(object, message) => write!(f, "invalid debug information in '{}': {}", object, message),
+            LinkError::TooLarge(origin) => write!(f, "objects placed at 0x{:08x} and the stack do not fit in {} memory cells", origin, MAX_CELLS),
+        }
+    }
+}
+
+fn symbol_display(kind: SymbolKind, name: &str) -> String {
+    match kind {
+        SymbolKind::Label => format!(".{}", name),
+        SymbolKind::Data => format!("${}", name),
+    }
+}
+
+/// Where an object ended up in the linked image
+struct Placement {
+    text_base: u32,
+    data_base: u32,
+    text_len: u32,
+}
+
+impl Placement {
+    /// absolute address of an object-relative address
+    fn address(&self, relative: u32) -> u32 {
+        if relative < self.text_len {
+            self.text_base + relative
+        } else {
+            self.data_base + relative - self.text_len
+        }
+    }
+
+    /// absolute address of an object-relative label, which may also be right after the last
+    /// instruction and then points at the text of the next object
+    fn label(&self, relative: u32) -> u32 {
+        if relative == self.text_len { self.text_base + relative } else { self.address(relative) }
+    }
+
+    /// absolute address of an exported symbol
+    fn symbol(&self, symbol: &Symbol) -> u32 {
+        match symbol.kind {
+            SymbolKind::Label => self.label(symbol.address),
+            SymbolKind::Data => self.address(symbol.address),
+        }
+    }
+}
+
+/// Link named objects into an executable image.
+///
+/// Text of all objects is placed first (in the given order) followed by their data, starting at
+/// the origin of the object that defines `.start`. All errors are collected and returned together.
+pub fn link(objects: &[(String, Object)], stack_size: u32) -> Result<Image, Vec<LinkError>> {
+    let mut errors = Vec::new();
+
+    // global symbol table
+    let mut globals: HashMap<(SymbolKind, &str), (usize, &Symbol)> = HashMap::new();
+    for (idx, (name, object)) in objects.iter().enumerate() {
+        for symbol in &object.exports {
+            let key = (symbol.kind, symbol.name.as_str());
+            if let Some((first, _)) = globals.get(&key) {
+                errors.push(LinkError::DuplicateSymbol(symbol_display(symbol.kind, &symbol.name), objects[*first].0.clone(), name.clone()));
+            } else {
+                globals.insert(key, (idx, symbol));
+            }
+        }
+    }
+    let origin = match globals.get(&(SymbolKind::Label, "start")) {
+        Some((idx, _)) => objects[*idx].1.origin,
+        None => {
+            errors.push(LinkError::MissingStart);
+            0
+        },
+    };
+
+    // layout, every address is below `end` once it fits
+    let text_total: u64 = objects.iter().map(|(_, o)| o.text.len() as u64).sum();
+    let data_total: u64 = objects.iter().map(|(_, o)| o.data.len() as u64).sum();
+    let end = origin as u64 + text_total + data_total + stack_size as u64;
+    if end > MAX_CELLS as u64 {
+        errors.push(LinkError::TooLarge(origin));
+        return Err(errors);
+    }
+    let text_total = text_total as u32;
+    let mut placements = Vec::new();
+    let (mut text_base, mut data_base) = (origin, origin + text_total);
+    for (_, object) in objects {
+        placements.push(Placement { text_base, data_base, text_len: object.text.len() as u32 });
+        text_base += object.text.len() as u32;
+        data_base += object.data.len() as u32;
+    }
+
+    // relocation
+    let mut text = Vec::new();
+    let mut data = Vec::new();
+    for (idx, (name, object)) in objects.iter().enumerate() {
+        let mut words = object.text.clone();
+        words.extend_from_slice(&object.data);
+        for relocation in &object.relocations {
+            let word = &mut words[relocation.offset as usize];
+            match relocation.target {
+                RelocationTarget::Local => *word = placements[idx].address(*word),
+                RelocationTarget::Import(i) => {
+                    let import = &object.imports[i as usize];
+                    match globals.get(&(import.kind, import.name.as_str())) {
+                        Some((owner, symbol)) => *word = word.wrapping_add(placements[*owner].symbol(symbol)),
+                        None => {
+                            let error = LinkError::UndefinedSymbol(symbol_display(import.kind, &import.name), name.clone());
+                            if !errors.contains(&error) {
+                                errors.push(error);
+                            }
+                        },
+                    }
+                },
+            }
+        }
+        let (t, d) = words.split_at(object.text.len());
+        text.extend_from_slice(t);
+        data.extend_from_slice(d);
+    }
+
+    // debug information
+    let mut debug: Option<DebugInfo> = None;
+    for (idx, (name, object)) in objects.iter().enumerate() {
+        let Some(content) = &object.debug else { continue };
+        match DebugInfo::parse(content) {
+            Ok(info) => {
+                let info = info.relocate(|a| placements[idx].address(a), |a| placements[idx].label(a));
+                debug.get_or_insert_with(DebugInfo::default).merge(info);
+            },
+            Err(e) => errors.push(LinkError::InvalidDebugInfo(name.clone(), e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut symbols: Vec<Symbol> = globals.values()
+        .map(|(owner, s)| Symbol { address: placements[*owner].symbol(s), ..(*s).clone() })
+        .collect();
+    symbols.sort_by(|a, b| a.address.cmp(&b.address).then(a.name.cmp(&b.name)));
+    let start = symbols.iter().find(|s| s.kind == SymbolKind::Label && s.name == "start").map(|s| s.address).unwrap_or(origin);
+    let len = text.len() as u32 + data.len() as u32;
+    Ok(Image {
+        origin,
+        start,
+        min_cells: origin + len + stack_size,
+        stack_size,
+        text,
+        data,
+        symbols,
+        debug: debug.map(|d| d.to_string()),
+    })
+}
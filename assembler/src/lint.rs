@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+
+use machine::internal::opcode::{JumpTarget, Opcode, OpcodeVariant};
+
+use crate::{source::strip_comment, verifier::{Program, Step}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// # Lint
+///
+/// Check run by `myvm lint`, each silenced on a line with `; lint: allow(<id>)`
+pub enum Lint {
+    /// instructions that no path from `.start`, a `@global` or an address taken reaches
+    Unreachable,
+    /// label that nothing refers to
+    UnusedLabel,
+    /// `$identifier` that nothing refers to
+    UnusedData,
+    /// `call` or `safecall` of a procedure that never reaches `ret`
+    NoReturn,
+    /// registers a `safecall` target sets and the caller reads, although `ret` restores them
+    SafecallResult,
+    /// `r3` written and then overwritten by the remainder of `div` before it is read
+    DivClobbersR3,
+    /// last instruction of the text continuing into whatever follows it
+    FallsIntoData,
+    /// `int` that no interrupt module implements
+    UnknownInterrupt,
+}
+
+/// every lint, in the order they are documented
+pub const LINTS: [Lint; 8] = [
+    Lint::Unreachable,
+    Lint::UnusedLabel,
+    Lint::UnusedData,
+    Lint::NoReturn,
+    Lint::SafecallResult,
+    Lint::DivClobbersR3,
+    Lint::FallsIntoData,
+    Lint::UnknownInterrupt,
+];
+
+impl Lint {
+    pub fn id(self) -> &'static str {
+        match self {
+            Lint::Unreachable => "unreachable",
+            Lint::UnusedLabel => "unused-label",
+            Lint::UnusedData => "unused-data",
+            Lint::NoReturn => "no-return",
+            Lint::SafecallResult => "safecall-result",
+            Lint::DivClobbersR3 => "div-clobbers-r3",
+            Lint::FallsIntoData => "falls-into-data",
+            Lint::UnknownInterrupt => "unknown-interrupt",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Lint> {
+        LINTS.into_iter().find(|l| l.id() == id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Problem found by `lint_code`, at the instruction starting at `pos`
+pub struct Finding {
+    pub lint: Lint,
+    pub pos: usize,
+    pub message: String,
+}
+
+/// Whether the comment of a source line allows `lint`, as in `; lint: allow(unreachable, no-return)`
+pub fn allows(line: &str, lint: Lint) -> bool {
+    let comment = line[strip_comment(line).len()..].trim_start_matches(';').trim();
+    let Some(rest) = comment.strip_prefix("lint:") else {
+        return false;
+    };
+    let Some(ids) = rest.trim_start().strip_prefix("allow(").and_then(|r| r.split(')').next()) else {
+        return false;
+    };
+    ids.split(',').any(|id| id.trim() == lint.id())
+}
+
+/// register names by number, `pc` is not tracked
+const REGISTERS: [&str; 8] = ["r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7"];
+
+const R3: u8 = 1 << 3;
+
+/// bit of register `reg` in a register set
+fn bit(reg: u32) -> u8 {
+    if reg < 8 { 1 << reg } else { 0 }
+}
+
+/// `(read, written)` registers of the instruction at `pos`, as sets of bits
+fn registers(program: &Program, pos: usize) -> (u8, u8) {
+    let text = program.text;
+    let operand = |n: usize| bit(text.get(pos + 1 + n).copied().unwrap_or(u32::MAX));
+    let Ok((opcode, variant)) = Opcode::extract(text[pos]) else {
+        return (0, 0);
+    };
+    match (opcode, variant) {
+        (Opcode::Push, OpcodeVariant::PushReg) | (Opcode::Pop, OpcodeVariant::PopAddrReg) => (operand(0), 0),
+        (Opcode::Push, OpcodeVariant::PushAddrOffsetReg) | (Opcode::Pop, OpcodeVariant::PopAddrOffsetReg) => (operand(1), 0),
+        (Opcode::Pop, OpcodeVariant::PopReg) => (0, operand(0)),
+        (Opcode::Move, OpcodeVariant::MoveReg | OpcodeVariant::MoveAddrReg) => (operand(1), operand(0)),
+        (Opcode::Move, OpcodeVariant::MoveAddrOffsetReg) => (operand(2), operand(0)),
+        (Opcode::Move, _) => (0, operand(0)),
+        (Opcode::Store, OpcodeVariant::StoreReg) => (operand(1), 0),
+        (Opcode::Inc | Opcode::Dec, _) => (operand(0), operand(0)),
+        (Opcode::SHR, OpcodeVariant::SHRReg) | (Opcode::SHL, OpcodeVariant::SHLReg) | (Opcode::Dup, OpcodeVariant::DupReg) => (operand(0), 0),
+        (Opcode::Div, _) => (0, R3),
+        (Opcode::Jump, variant) => match variant.indirect_jump() {
+            Some((_, JumpTarget::Reg)) => (operand(0), 0),
+            Some((_, JumpTarget::AddrOffsetReg)) => (operand(1), 0),
+            _ => (0, 0),
+        },
+        (Opcode::Call, OpcodeVariant::CallReg) | (Opcode::SafeCall, OpcodeVariant::SafeCallReg) => (operand(0), 0),
+        (Opcode::Call, OpcodeVariant::CallAddrOffsetReg) | (Opcode::SafeCall, OpcodeVariant::SafeCallAddrOffsetReg) => (operand(1), 0),
+        _ => (0, 0),
+    }
+}
+
+/// `r0, r2` for a register set
+fn describe(set: u8) -> String {
+    REGISTERS.iter().enumerate().filter(|(n, _)| set & (1 << n) != 0).map(|(_, r)| *r).collect::<Vec<_>>().join(", ")
+}
+
+/// instructions the one at `pos` continues with, calls continue with their target and after it
+fn successors(program: &Program, pos: usize) -> Vec<usize> {
+    let next = program.next(pos);
+    match program.step(pos) {
+        Step::Stack { .. } | Step::Unknown { .. } => next.into_iter().collect(),
+        Step::Jump { target, conditional } => target.into_iter().chain(next.filter(|_| conditional)).collect(),
+        Step::Call(target) => target.into_iter().chain(next).collect(),
+        Step::Ret | Step::Stop => Vec::new(),
+    }
+}
+
+/// every instruction reached from `roots`
+fn reach(program: &Program, roots: &[usize]) -> HashSet<usize> {
+    let mut reached = HashSet::new();
+    let mut pending: Vec<usize> = roots.to_vec();
+    while let Some(pos) = pending.pop() {
+        if program.instructions.binary_search(&pos).is_err() || !reached.insert(pos) {
+            continue;
+        }
+        pending.extend(successors(program, pos));
+    }
+    reached
+}
+
+/// Registers that may have been written when each reached instruction runs, callees start
+/// with what their callers wrote
+fn written_before(program: &Program, roots: &[usize], procedures: &mut HashMap<usize, Procedure>) -> HashMap<usize, u8> {
+    let mut written: HashMap<usize, u8> = HashMap::new();
+    let mut pending: Vec<(usize, u8)> = roots.iter().map(|&root| (root, 0)).collect();
+    while let Some((pos, set)) = pending.pop() {
+        if program.instructions.binary_search(&pos).is_err() {
+            continue;
+        }
+        let before = match written.get(&pos) {
+            Some(&known) if known | set == known => continue,
+            Some(&known) => known | set,
+            None => set,
+        };
+        written.insert(pos, before);
+        let after = before | registers(program, pos).1;
+        let next = program.next(pos);
+        match program.step(pos) {
+            Step::Stack { .. } | Step::Unknown { .. } => pending.extend(next.map(|n| (n, after))),
+            Step::Jump { target, conditional } => {
+                pending.extend(target.map(|t| (t, after)));
+                pending.extend(next.filter(|_| conditional).map(|n| (n, after)));
+            },
+            Step::Call(target) => {
+                let safe = matches!(Opcode::extract(program.text[pos]), Ok((Opcode::SafeCall, _)));
+                let callee = match target {
+                    Some(target) if !safe => procedure(program, target, procedures).writes,
+                    _ => 0,
+                };
+                pending.extend(target.map(|t| (t, after)));
+                pending.extend(next.map(|n| (n, after | callee)));
+            },
+            Step::Ret | Step::Stop => {},
+        }
+    }
+    written
+}
+
+/// What a procedure does, from its entry to its `ret`s
+#[derive(Clone, Copy)]
+struct Procedure {
+    /// `ret`, or an indirect jump that may lead to one, is reached
+    returns: bool,
+    /// registers written by any instruction reached, called procedures included
+    writes: u8,
+}
+
+/// summary of the procedure at `entry`, recursive calls are assumed to return and write nothing
+fn procedure(program: &Program, entry: usize, cache: &mut HashMap<usize, Procedure>) -> Procedure {
+    if let Some(&known) = cache.get(&entry) {
+        return known;
+    }
+    cache.insert(entry, Procedure { returns: true, writes: 0 });
+    let mut summary = Procedure { returns: false, writes: 0 };
+    let mut calls = Vec::new();
+    let mut seen = HashSet::new();
+    let mut pending = vec![entry];
+    while let Some(pos) = pending.pop() {
+        if program.instructions.binary_search(&pos).is_err() || !seen.insert(pos) {
+            continue;
+        }
+        summary.writes |= registers(program, pos).1;
+        let next = program.next(pos);
+        match program.step(pos) {
+            Step::Stack { .. } | Step::Unknown { .. } => pending.extend(next),
+            Step::Jump { target, conditional } => {
+                match target {
+                    Some(target) => pending.push(target),
+                    None => summary.returns = true,
+                }
+                pending.extend(next.filter(|_| conditional));
+            },
+            Step::Ret => summary.returns = true,
+            Step::Call(target) => {
+                calls.extend(target);
+                pending.extend(next);
+            },
+            Step::Stop => {},
+        }
+    }
+    for target in calls {
+        summary.writes |= procedure(program, target, cache).writes;
+    }
+    cache.insert(entry, summary);
+    summary
+}
+
+/// Instructions following `pos` without jumps or calls, with the one that ends the run
+fn straight_line<'a>(program: &'a Program, pos: usize) -> impl Iterator<Item = usize> + 'a {
+    let mut current = program.next(pos);
+    std::iter::from_fn(move || {
+        let pos = current?;
+        current = match program.step(pos) {
+            Step::Stack { .. } | Step::Unknown { .. } => program.next(pos),
+            _ => None,
+        };
+        Some(pos)
+    })
+}
+
+/// # Code lints
+///
+/// Check the instructions of a program: code that nothing reaches from `roots`, calls that
+/// never return, results of `safecall` targets that `ret` discards, `r3` values clobbered by
+/// `div`, code running off the end of the text and interrupts no module implements. `data`
+/// tells whether data follows the text.
+///
+/// Paths are not followed through indirect jumps and calls, so everything whose address is
+/// taken should be among the roots.
+pub fn lint_code(program: &Program, roots: &[usize], data: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut report = |lint: Lint, pos: usize, message: String| findings.push(Finding { lint, pos, message });
+    let reached = reach(program, roots);
+    let mut procedures = HashMap::new();
+    let written = written_before(program, roots, &mut procedures);
+    // every unreachable run of code is reported once, and again at every label inside it
+    let labeled: HashSet<usize> = program.labels.values().copied().collect();
+    let mut previous_reached = true;
+    for &pos in program.instructions {
+        let is_reached = reached.contains(&pos);
+        if !is_reached && (previous_reached || labeled.contains(&pos)) {
+            report(Lint::Unreachable, pos, "unreachable code: nothing jumps, calls or falls through to this instruction".to_string());
+        }
+        previous_reached = is_reached;
+        let (opcode, variant) = match Opcode::extract(program.text[pos]) {
+            Ok(decoded) => decoded,
+            Err(_) => continue,
+        };
+        match (opcode, program.step(pos)) {
+            (Opcode::Int, Step::Stop) => {
+                let (module, function) = (program.text[pos + 1], program.text[pos + 2]);
+                report(Lint::UnknownInterrupt, pos, format!("no interrupt module implements 'int {} {}'", module, function));
+            },
+            (Opcode::Call | Opcode::SafeCall, Step::Call(Some(target))) => {
+                let callee = procedure(program, target, &mut procedures);
+                if !callee.returns {
+                    report(Lint::NoReturn, pos, format!("{} never reaches 'ret', so this call does not return", program.name(target)));
+                } else if variant == OpcodeVariant::SafeCallConst {
+                    // registers the caller set itself are the ones `safecall` is meant to keep
+                    let mut written = written.get(&pos).copied().unwrap_or(0);
+                    let mut lost = 0;
+                    for after in straight_line(program, pos) {
+                        let (reads, writes) = registers(program, after);
+                        lost |= reads & callee.writes & !written;
+                        written |= writes;
+                    }
+                    if lost != 0 {
+                        report(Lint::SafecallResult, pos, format!(
+                            "{} sets {} but 'safecall' restores it on return, so the value read after the call is not the one it set",
+                            program.name(target), describe(lost)));
+                    }
+                }
+            },
+            _ => {},
+        }
+        if opcode != Opcode::Div && registers(program, pos).1 & R3 != 0 {
+            for after in straight_line(program, pos) {
+                let (reads, writes) = registers(program, after);
+                if reads & R3 != 0 {
+                    break;
+                }
+                if writes & R3 != 0 {
+                    if matches!(Opcode::extract(program.text[after]), Ok((Opcode::Div, _))) {
+                        report(Lint::DivClobbersR3, pos, "r3 is overwritten by the remainder of a following 'div' before this value is read".to_string());
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(&last) = program.instructions.last()
+        && reached.contains(&last)
+        && program.next(last).is_none()
+        && matches!(program.step(last), Step::Stack { .. } | Step::Unknown { .. } | Step::Call(_) | Step::Jump { conditional: true, .. })
+    {
+        let message = if data {
+            "execution continues past the last instruction into the data that follows it"
+        } else {
+            "execution continues past the last instruction"
+        };
+        report(Lint::FallsIntoData, last, message.to_string());
+    }
+    findings.sort_by_key(|f| f.pos);
+    findings
+}
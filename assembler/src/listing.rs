@@ -0,0 +1,140 @@
+use std::fmt::Display;
+
+use binary::image::SymbolKind;
+
+/// words shown on one row of a listing, longer code continues on the next rows
+const WORDS_PER_ROW: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Words generated for one instruction or data definition
+pub struct ListingCode {
+    pub address: u32,
+    pub words: Vec<u32>,
+    /// first word is an instruction, shown split into opcode and variant
+    pub instruction: bool,
+    /// expanded line the code was assembled from, with the macro arguments substituted, for lines invoking a macro
+    pub expansion: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Source line with the code generated for it
+pub struct ListingLine {
+    pub line: usize,
+    pub text: String,
+    pub code: Vec<ListingCode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingFile {
+    pub name: String,
+    pub lines: Vec<ListingLine>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// # Assembly listing
+///
+/// Every line of every source file with the address and words generated for it. Code of
+/// included files is listed under those files, code expanded from macros under the invoking line.
+pub struct Listing {
+    pub files: Vec<ListingFile>,
+}
+
+impl ListingCode {
+    /// words formatted for a listing, `opcode:variant` for instructions
+    fn formatted_words(&self) -> Vec<String> {
+        self.words.iter().enumerate()
+            .map(|(idx, word)| match idx == 0 && self.instruction {
+                true => format!("{:04x}:{:04x}", word >> 16, word & 0xffff),
+                false => format!("{:08x}", word),
+            })
+            .collect()
+    }
+}
+
+impl Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // one word is `opcode:variant`, one character wider than the others
+        let empty = " ".repeat(WORDS_PER_ROW * 9);
+        for (idx, file) in self.files.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "; {}", file.name)?;
+            for line in &file.lines {
+                // macro invocations get a row of their own, followed by the expanded code
+                let expanded = line.code.first().is_none_or(|c| c.expansion.is_some());
+                if expanded {
+                    writeln!(f, "{}", format!("{:>5}  {:8}  {}  {}", line.line, "", empty, line.text).trim_end())?;
+                }
+                for code in &line.code {
+                    let words = code.formatted_words();
+                    for (row, chunk) in words.chunks(WORDS_PER_ROW).enumerate() {
+                        let address = code.address + (row * WORDS_PER_ROW) as u32;
+                        let number = if row == 0 && !expanded { line.line.to_string() } else { String::new() };
+                        let text = match (row, &code.expansion) {
+                            (0, Some(expansion)) => format!("  + {}", expansion),
+                            (0, None) => line.text.clone(),
+                            _ => String::new(),
+                        };
+                        writeln!(f, "{}", format!("{:>5}  {:08x}  {:<width$}  {}", number, address, chunk.join(" "), text, width = empty.len()).trim_end())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Text,
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Label or `$identifier` in a symbol map
+pub struct MapSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub section: Section,
+    pub address: u32,
+    /// memory cells up to the next symbol for labels, cells taken for data
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// # Symbol map
+///
+/// Every label and `$identifier` of a program with its absolute address, sorted by address.
+pub struct SymbolMap {
+    pub origin: u32,
+    pub start: u32,
+    pub text_size: u32,
+    pub data_size: u32,
+    pub symbols: Vec<MapSymbol>,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Section::Text => write!(f, "text"),
+            Section::Data => write!(f, "data"),
+        }
+    }
+}
+
+impl Display for SymbolMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "origin 0x{:08x}  start 0x{:08x}  text {} cells  data {} cells", self.origin, self.start, self.text_size, self.data_size)?;
+        writeln!(f)?;
+        writeln!(f, "{:<10}  {:<7}  {:>6}  symbol", "address", "section", "size")?;
+        for symbol in &self.symbols {
+            let sigil = match symbol.kind {
+                SymbolKind::Label => '.',
+                SymbolKind::Data => '$',
+            };
+            writeln!(f, "0x{:08x}  {:<7}  {:>6}  {}{}", symbol.address, symbol.section.to_string(), symbol.size, sigil, symbol.name)?;
+        }
+        Ok(())
+    }
+}
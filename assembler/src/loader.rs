@@ -0,0 +1,59 @@
+use binary::image::{Image, SymbolKind, MAX_CELLS};
+use machine::internal::machine::{Machine, MachineOptions};
+
+use crate::{debug::{DataSymbol, DebugInfo, LabelSymbol}, tokens::DataType};
+
+/// memory cells of a machine when the binary needs fewer
+pub const DEFAULT_CELLS: u32 = 2048;
+/// stack cells of a machine when the binary does not store its stack size
+pub const DEFAULT_STACK: u32 = 256;
+
+/// sidecar debug info path for a binary
+pub fn debug_path(binary: &str) -> String {
+    format!("{}.dbg", binary)
+}
+
+/// Machine loaded with an image and set to its entry point, with the given number of memory
+/// and stack cells or else the ones the image asks for
+pub fn load_image(image: &Image, cells: Option<u32>, stack: Option<u32>) -> Result<Machine, String> {
+    let stack = stack.unwrap_or(if image.stack_size > 0 { image.stack_size } else { DEFAULT_STACK });
+    let required = image.origin.checked_add(image.len()).and_then(|end| end.checked_add(stack))
+        .filter(|end| *end <= MAX_CELLS)
+        .ok_or_else(|| format!("binary and a stack of {} cells do not fit in {} memory cells", stack, MAX_CELLS))?
+        .max(image.min_cells);
+    let cells = match cells {
+        Some(c) if c < required => return Err(format!("binary requires at least {} memory cells, {} given", required, c)),
+        Some(c) => c,
+        None => required.max(DEFAULT_CELLS),
+    };
+
+    let mut machine = Machine::new(MachineOptions{
+        memory_cells: cells,
+        memory_stack_size: stack,
+    }).map_err(|e| format!("unable to create machine: {}", e))?;
+    machine.load_data(image.origin, &image.memory()).map_err(|e| format!("unable to load binary: {}", e))?;
+    machine.set_start(image.start);
+    Ok(machine)
+}
+
+/// debug info embedded in the image read from `path` or next to it, or else its exported symbols
+pub fn load_debug(path: &str, image: &Image) -> Option<DebugInfo> {
+    let debug = match &image.debug {
+        Some(d) => Some(d.clone()),
+        None => std::fs::read_to_string(debug_path(path)).ok(),
+    };
+    if let Some(info) = debug.and_then(|c| DebugInfo::parse(&c).ok()) {
+        return Some(info);
+    }
+    if image.symbols.is_empty() {
+        return None;
+    }
+    let mut info = DebugInfo::default();
+    for symbol in &image.symbols {
+        match symbol.kind {
+            SymbolKind::Label => info.labels.push(LabelSymbol { name: symbol.name.clone(), address: symbol.address }),
+            SymbolKind::Data => info.data.push(DataSymbol { name: symbol.name.clone(), address: symbol.address, size: symbol.size, typ: DataType::Word }),
+        }
+    }
+    Some(info)
+}
@@ -0,0 +1,133 @@
+use crate::tokens::{Cmd, ConstValue, LineToken, Token};
+
+/// Flags an instruction sets, whatever their previous value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flags {
+    /// zero and negative
+    ZeroNegative,
+    /// zero, negative, overflow and carry
+    All,
+}
+
+impl Flags {
+    fn covers(self, other: Flags) -> bool {
+        self == Flags::All || other == Flags::ZeroNegative
+    }
+}
+
+/// How an instruction relates to the flags left by the instructions before it
+enum FlagUse {
+    /// flags are read, here or wherever control goes next
+    Read,
+    Written(Flags),
+    Untouched,
+}
+
+fn flag_use(cmd: &Cmd) -> FlagUse {
+    match cmd {
+        // conditions, code elsewhere that may test what was left behind, and the final state
+        // that stays visible to whoever runs the program
+        Cmd::Jmp(_) | Cmd::Jnz(_) | Cmd::Jz(_) | Cmd::Jg(_) | Cmd::Jge(_) | Cmd::Jl(_) | Cmd::Jle(_)
+            | Cmd::CallConst(_) | Cmd::CallReg(_) | Cmd::CallAddr(_) | Cmd::CallIdValueReg(..)
+            | Cmd::SafeCallConst(_) | Cmd::SafeCallReg(_) | Cmd::SafeCallAddr(_) | Cmd::SafeCallIdValueReg(..)
+            | Cmd::Ret | Cmd::Int(..) | Cmd::Term => FlagUse::Read,
+        Cmd::Add | Cmd::Sub | Cmd::Mul | Cmd::Div | Cmd::Inc(_) | Cmd::Dec(_) => FlagUse::Written(Flags::All),
+        Cmd::PopReg(_) | Cmd::PopAddr(_) | Cmd::PopAddrReg(_) | Cmd::PopIdValueReg(..) | Cmd::Drop | Cmd::Dup => FlagUse::Written(Flags::ZeroNegative),
+        _ => FlagUse::Untouched,
+    }
+}
+
+/// whether `flags` are overwritten before anything can read them
+fn flags_dead(tokens: &[LineToken], flags: Flags) -> bool {
+    for t in tokens {
+        match &t.token {
+            Token::Command(cmd) => match flag_use(cmd) {
+                FlagUse::Read => return false,
+                FlagUse::Written(written) if written.covers(flags) => return true,
+                _ => {},
+            },
+            // may be jumped to from anywhere
+            Token::Label(_) => return false,
+            _ => {},
+        }
+    }
+    false
+}
+
+fn is_one(value: &ConstValue) -> bool {
+    matches!(value, ConstValue::Number(1))
+}
+
+/// Replacement for the commands at the start of `tokens`, with the number of commands it replaces
+fn rewrite<'a>(tokens: &[LineToken<'a>]) -> Option<(usize, Vec<Cmd<'a>>)> {
+    let mut cmds = Vec::new();
+    for t in tokens.iter().take(4) {
+        match &t.token {
+            Token::Command(cmd) => cmds.push(cmd),
+            _ => break,
+        }
+    }
+    let dead = |len: usize, flags: Flags| flags_dead(&tokens[len..], flags);
+    match cmds.as_slice() {
+        [Cmd::Swap, Cmd::Swap, ..] => Some((2, vec![])),
+        // flags of `add` and `pop` match those of `inc`
+        [Cmd::PushReg(a), Cmd::PushConst(one), Cmd::Add, Cmd::PopReg(b), ..]
+            | [Cmd::PushConst(one), Cmd::PushReg(a), Cmd::Add, Cmd::PopReg(b), ..]
+            if a == b && is_one(one) => Some((4, vec![Cmd::Inc(*a)])),
+        // `dec` sets overflow and carry differently than `sub`
+        [Cmd::PushConst(one), Cmd::PushReg(a), Cmd::Sub, Cmd::PopReg(b), ..]
+            if a == b && is_one(one) && dead(4, Flags::All) => Some((4, vec![Cmd::Dec(*a)])),
+        // `pop` and `drop` set flags that `move` does not
+        [Cmd::PushReg(a), Cmd::PopReg(b), ..] if dead(2, Flags::ZeroNegative) => {
+            Some((2, if a == b { vec![] } else { vec![Cmd::MoveReg(*b, *a)] }))
+        },
+        [Cmd::PushConst(value), Cmd::PopReg(b), ..] if dead(2, Flags::ZeroNegative) => {
+            Some((2, vec![Cmd::MoveConst(*b, value.clone())]))
+        },
+        [Cmd::PushReg(_) | Cmd::PushConst(_), Cmd::Drop, ..] if dead(2, Flags::ZeroNegative) => Some((2, vec![])),
+        _ => None,
+    }
+}
+
+/// # Peephole optimizer
+///
+/// Rewrites short instruction sequences into cheaper ones and removes code that cannot be
+/// reached, without changing registers, stack, memory or any flag that is read later.
+/// Labels are never removed and no pattern spans one, so every jump target stays in place.
+pub fn optimize(tokens: Vec<LineToken>) -> Vec<LineToken> {
+    let mut tokens = remove_unreachable(tokens);
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let Some((len, replacement)) = rewrite(&tokens[idx..]) else {
+            idx += 1;
+            continue;
+        };
+        let (file, line) = (tokens[idx].file, tokens[idx].line);
+        let replacement = replacement.into_iter().map(|cmd| LineToken { file, line, token: Token::Command(cmd) });
+        tokens.splice(idx..idx + len, replacement);
+        // a rewrite can complete a pattern that starts a few commands earlier
+        idx = idx.saturating_sub(3);
+    }
+    tokens
+}
+
+/// drop commands after `jmp`, `ret` and `term` up to the next label
+fn remove_unreachable(tokens: Vec<LineToken>) -> Vec<LineToken> {
+    let mut reachable = true;
+    tokens.into_iter()
+        .filter(|t| match &t.token {
+            Token::Label(_) => {
+                reachable = true;
+                true
+            },
+            Token::Command(cmd) => {
+                let keep = reachable;
+                if matches!(cmd, Cmd::Jmp(_) | Cmd::Ret | Cmd::Term) {
+                    reachable = false;
+                }
+                keep
+            },
+            _ => true,
+        })
+        .collect()
+}
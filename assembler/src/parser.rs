@@ -1,15 +1,25 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case, take_till, take_until, take_while1},
-    character::{complete::{alphanumeric1, char, digit1, line_ending, multispace1, space0}, multispace0},
-    combinator::{map, map_res, opt, value},
+    bytes::complete::{tag, tag_no_case, take_till, take_while, take_while1},
+    character::{complete::{alphanumeric1, char, digit1, line_ending, multispace1, satisfy, space0, space1}, multispace0},
+    combinator::{map, map_res, not, opt, recognize, value},
     error::{Error, ErrorKind},
-    multi::separated_list0,
+    multi::{many0, separated_list0},
     sequence::{delimited, pair, preceded, terminated},
     Err, IResult, Parser,
 };
 
-use crate::tokens::{Cmd, ConstValue, DataAddressOffset, DataType, DataValue, MetaType, Token};
+use crate::tokens::{ANONYMOUS_LABEL, BinaryOp, Cmd, ConstValue, DataAddressOffset, DataType, DataValue, Expr, JumpOperand, LineToken, MetaType, SymbolName, Token};
+
+/// Words with a meaning inside expressions and data definitions, these cannot be `@define` names
+pub const KEYWORDS: &[&str] = &["sizeof", "lengthof", "times"];
+
+/// Instruction mnemonics, these cannot be used as macro names
+pub const MNEMONICS: &[&str] = &[
+    "push", "pop", "add", "sub", "mul", "div", "drop", "swap", "dup", "and", "or", "xor", "not",
+    "shr", "shl", "inc", "dec", "move", "store", "call", "safecall", "ret", "jmp", "jnz", "jz",
+    "jg", "jge", "jl", "jle", "int", "term",
+];
 
 // ----------------- Basic parsers -----------------
 
@@ -31,43 +41,212 @@ pub fn parse_number(input: &str) -> IResult<&str, u32> {
     .parse(input)
 }
 
+/// text between double quotes, `\"` does not end it; escapes are decoded by `unescape`
 pub fn parse_str(input: &str) -> IResult<&str, &str> {
-    delimited(char('"'), take_until("\""), char('"')).parse(input)
+    let body = recognize(many0(alt((
+        take_while1(|c| c != '"' && c != '\\'),
+        recognize(pair(char('\\'), satisfy(|_| true))),
+    ))));
+    delimited(char('"'), body, char('"')).parse(input)
+}
+
+/// value of the escape sequence after a `\`: `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\'` or `\xHH`
+fn escape(chars: &mut std::str::Chars) -> Result<char, String> {
+    let c = chars.next().ok_or("incomplete escape sequence '\\'")?;
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '\\' | '"' | '\'' => Ok(c),
+        'x' => {
+            let hex: String = chars.by_ref().take(2).collect();
+            match u8::from_str_radix(&hex, 16) {
+                Ok(value) if hex.len() == 2 => Ok(value as char),
+                _ => Err(format!("invalid escape sequence '\\x{}', expected two hex digits", hex)),
+            }
+        },
+        c => Err(format!("unknown escape sequence '\\{}'", c)),
+    }
+}
+
+/// decode the escape sequences of a string returned by `parse_str`
+pub fn unescape(raw: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        out.push(if c == '\\' { escape(&mut chars)? } else { c });
+    }
+    Ok(out)
+}
+
+/// character literal such as `'A'` or `'\n'`, its value is the code point
+pub fn parse_char_literal(input: &str) -> IResult<&str, u32> {
+    let escaped = recognize(pair(char('\\'), alt((recognize(pair(char('x'), take_while1(|c: char| c.is_ascii_hexdigit()))), recognize(satisfy(|_| true))))));
+    let plain = recognize(satisfy(|c| c != '\'' && c != '\\'));
+    let (rem, raw) = delimited(char('\''), alt((escaped, plain)), char('\'')).parse(input)?;
+    match unescape(raw) {
+        Ok(value) => Ok((rem, value.chars().next().map(|c| c as u32).unwrap_or(0))),
+        Err(_) => Err(Err::Error(Error::new(input, ErrorKind::Escaped))),
+    }
 }
 
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Name of a label or data identifier: letters, digits and `_`, in parts joined by single dots
+pub fn parse_name(input: &str) -> IResult<&str, &str> {
+    let part = || take_while1(is_name_char);
+    recognize(pair(part(), many0(pair(char('.'), part())))).parse(input)
+}
+
+/// `.name` or, for a label local to the previous one, `..name`; the leading `.` is dropped
 pub fn parse_label(input: &str) -> IResult<&str, &str> {
-    preceded(tag("."), alphanumeric1).parse(input)
+    preceded(tag("."), recognize(pair(opt(char('.')), parse_name))).parse(input)
+}
+
+/// `@@`, an anonymous label
+pub fn parse_anonymous_label(input: &str) -> IResult<&str, &str> {
+    tag(ANONYMOUS_LABEL).parse(input)
+}
+
+/// `@f` or `@b`, the next or previous anonymous label
+pub fn parse_anonymous_ref(input: &str) -> IResult<&str, &str> {
+    terminated(
+        recognize(pair(char('@'), satisfy(|c| matches!(c, 'f' | 'F' | 'b' | 'B')))),
+        not(satisfy(is_name_char)),
+    ).parse(input)
 }
 
-pub fn parse_address(input: &str) -> IResult<&str, u32> {
-    preceded(tag("&"), parse_number).parse(input)
+/// label used as an operand, named or anonymous
+pub fn parse_label_ref(input: &str) -> IResult<&str, &str> {
+    alt((parse_label, parse_anonymous_ref)).parse(input)
 }
 
+pub fn parse_address(input: &str) -> IResult<&str, ConstValue<'_>> {
+    preceded(tag("&"), parse_const_value).parse(input)
+}
+
+/// Number, label or constant expression
 pub fn parse_const_value(input: &str) -> IResult<&str, ConstValue<'_>> {
+    map(parse_expr, |expr| match expr {
+        Expr::Number(n) => ConstValue::Number(n),
+        Expr::Symbol(SymbolName::Label(label)) => ConstValue::Label(label),
+        expr => ConstValue::Expr(expr),
+    })
+    .parse(input)
+}
+
+// ----------------- Constant expressions -----------------
+
+/// name of a `@define`, registers and instruction mnemonics are not valid names
+pub fn parse_define_name(input: &str) -> IResult<&str, &str> {
+    let (rem, name) = recognize(pair(
+        satisfy(|c| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    )).parse(input)?;
+    let is_reg = matches!(parse_reg(name), Ok(("", _)));
+    let lower = name.to_ascii_lowercase();
+    if is_reg || MNEMONICS.contains(&lower.as_str()) || KEYWORDS.contains(&lower.as_str()) {
+        return Err(Err::Error(Error::new(input, ErrorKind::Verify)));
+    }
+    Ok((rem, name))
+}
+
+fn parse_primary(input: &str) -> IResult<&str, Expr<'_>> {
     alt((
-        map(parse_number, ConstValue::Number),
-        map(parse_label, ConstValue::Label),
+        map(parse_number, Expr::Number),
+        map(parse_char_literal, Expr::Number),
+        map(parse_symbol_name, Expr::Symbol),
+        map(preceded(pair(tag_no_case("sizeof"), space1), parse_identifier), Expr::SizeOf),
+        map(preceded(pair(tag_no_case("lengthof"), space1), parse_identifier), Expr::LengthOf),
+        map(parse_define_name, Expr::Define),
+        delimited(pair(char('('), space0), parse_expr, pair(space0, char(')'))),
+        map(preceded(pair(char('~'), space0), parse_primary), |e| Expr::Not(Box::new(e))),
+        // no space after a unary minus, so `1 -2` is two values in data definitions
+        map(preceded(char('-'), parse_primary), |e| Expr::Neg(Box::new(e))),
     ))
     .parse(input)
 }
 
+/// binary operators from lowest to highest precedence
+const OPERATORS: &[&[(&str, BinaryOp)]] = &[
+    &[("|", BinaryOp::Or)],
+    &[("&", BinaryOp::And)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div)],
+];
+
+fn parse_binary(input: &str, level: usize) -> IResult<&str, Expr<'_>> {
+    if level == OPERATORS.len() {
+        return parse_primary(input);
+    }
+    let (mut rem, mut lhs) = parse_binary(input, level + 1)?;
+    loop {
+        let trimmed = rem.trim_start_matches([' ', '\t']);
+        let Some((token, op)) = OPERATORS[level].iter().find(|(token, _)| trimmed.starts_with(token)) else {
+            break;
+        };
+        // `a -b` is `a` followed by the negative number `-b`
+        let after = &trimmed[token.len()..];
+        if *op == BinaryOp::Sub && trimmed.len() < rem.len() && !after.starts_with([' ', '\t']) {
+            break;
+        }
+        // an operator without a right hand side is left for the caller
+        let Ok((after, rhs)) = preceded(space0, |i| parse_binary(i, level + 1)).parse(&trimmed[token.len()..]) else {
+            break;
+        };
+        lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+        rem = after;
+    }
+    Ok((rem, lhs))
+}
+
+/// Constant expression with `| & << >> + - * /` (C precedence), unary `~` and parentheses
+/// over numbers, labels, data identifiers and `@define` names
+pub fn parse_expr(input: &str) -> IResult<&str, Expr<'_>> {
+    parse_binary(input, 0)
+}
+
 pub fn parse_meta(input: &str) -> IResult<&str, MetaType<'_>> {
     let (rem, keyword) = preceded(
         tag_no_case("@"),
-        alt((tag_no_case("org"), tag_no_case("include")))
+        alt((tag_no_case("org"), tag_no_case("include"), tag_no_case("global"), tag_no_case("extern"), tag_no_case("define"), tag_no_case("align")))
     ).parse(input)?;
 
     if keyword.eq_ignore_ascii_case("org") {
-        let (rem, number) = preceded(multispace1, parse_number).parse(rem)?;
+        let (rem, number) = preceded(multispace1, parse_const_value).parse(rem)?;
         Ok((rem, MetaType::Org(number)))
+    } else if keyword.eq_ignore_ascii_case("align") {
+        let (rem, number) = preceded(space1, parse_const_value).parse(rem)?;
+        Ok((rem, MetaType::Align(number)))
+    } else if keyword.eq_ignore_ascii_case("define") {
+        let (rem, name) = preceded(space1, parse_define_name).parse(rem)?;
+        let (rem, expr) = preceded(space1, parse_expr).parse(rem)?;
+        Ok((rem, MetaType::Define(name, expr)))
     } else if keyword.eq_ignore_ascii_case("include") {
         let (rem, path) = preceded(multispace1, parse_str).parse(rem)?;
         Ok((rem, MetaType::Include(path)))
+    } else if keyword.eq_ignore_ascii_case("global") {
+        let (rem, name) = preceded(multispace1, parse_symbol_name).parse(rem)?;
+        Ok((rem, MetaType::Global(name)))
+    } else if keyword.eq_ignore_ascii_case("extern") {
+        let (rem, name) = preceded(multispace1, parse_symbol_name).parse(rem)?;
+        Ok((rem, MetaType::Extern(name)))
     } else {
         Err(Err::Error(Error::new(input, ErrorKind::Tag)))
     }
 }
 
+pub fn parse_symbol_name(input: &str) -> IResult<&str, SymbolName<'_>> {
+    alt((
+        map(parse_label_ref, SymbolName::Label),
+        map(parse_identifier, SymbolName::Data),
+    )).parse(input)
+}
+
 pub fn parse_section(input: &str) -> IResult<&str, &str> {
     delimited(tag("["), alphanumeric1, tag("]")).parse(input)
 }
@@ -85,19 +264,40 @@ pub fn parse_data_type(input: &str) -> IResult<&str, DataType> {
     )).parse(input)
 }
 
-pub fn parse_data_values(input: &'_ str) -> IResult<&'_ str, Vec<DataValue<'_>>> {
-    separated_list0(multispace1, alt((
-        map(parse_number, DataValue::Number),
+/// value, optionally repeated with `times count`
+fn parse_data_value(input: &str) -> IResult<&str, DataValue<'_>> {
+    let (rem, value) = alt((
+        map(parse_const_value, DataValue::Number),
         map(parse_str, DataValue::String),
-    ))).parse(input)
+    )).parse(input)?;
+    let (rem, count) = opt(preceded((space1, tag_no_case("times"), space1), parse_const_value)).parse(rem)?;
+    match count {
+        Some(count) => Ok((rem, DataValue::Times(Box::new(value), count))),
+        None => Ok((rem, value)),
+    }
+}
+
+pub fn parse_data_values(input: &'_ str) -> IResult<&'_ str, Vec<DataValue<'_>>> {
+    separated_list0(space1, parse_data_value).parse(input)
+}
+
+/// `resb count`, `resw count` or `resdw count`: `count` zero values of the data type
+fn parse_reservation(input: &str) -> IResult<&str, (DataType, Vec<DataValue<'_>>)> {
+    let (rem, typ) = preceded(tag_no_case("res"), parse_data_type).parse(input)?;
+    let (rem, count) = preceded(space1, parse_const_value).parse(rem)?;
+    let zero = Box::new(DataValue::Number(ConstValue::Number(0)));
+    Ok((rem, (typ, vec![DataValue::Times(zero, count)])))
 }
 
 pub fn parse_identifier(input: &str) -> IResult<&str, &str> {
-    preceded(tag("$"), alphanumeric1).parse(input)
+    preceded(tag("$"), parse_name).parse(input)
 }
 
 pub fn parse_data_def(input: &'_ str) -> IResult<&'_ str, Token<'_>> {
-    let (rem, id) = preceded(multispace0(), parse_identifier).parse(input)?;
+    let (rem, id) = preceded(space0, parse_identifier).parse(input)?;
+    if let Ok((rem, (typ, values))) = preceded(space1, parse_reservation).parse(rem) {
+        return Ok((rem, Token::DataDef(id, typ, values)));
+    }
     let (rem, typ) = preceded(multispace1, parse_data_type).parse(rem)?;
     let (rem, values) = preceded(multispace1, parse_data_values).parse(rem)?;
     Ok((rem, Token::DataDef(id, typ, values)))
@@ -110,7 +310,7 @@ pub fn parse_id_address(input: &'_ str) -> IResult<&'_ str, Cmd<'_>> {
 pub fn parse_id_offset_const(input: &'_ str) -> IResult<&'_ str, DataAddressOffset<'_>> {
     let (rem , id) = preceded(multispace0(),parse_identifier).parse(input)?;
     let (rem, _) = preceded(multispace0(), tag("+")).parse(rem)?;
-    let (rem, number) = terminated(preceded(multispace0(), parse_number), multispace0()).parse(rem)?;
+    let (rem, number) = terminated(preceded(multispace0(), parse_const_value), multispace0()).parse(rem)?;
     Ok((rem, DataAddressOffset::Const(id, number)))
 }
 
@@ -144,12 +344,21 @@ pub fn parse_push_id_value(input: &'_ str) -> IResult<&'_ str, Cmd<'_>> {
     let (rem, _) = tag_no_case("push").parse(input)?;
     let (rem, val) = preceded(multispace1, parse_id_address_with_offset).parse(rem)?;
     match val {
-        DataAddressOffset::Zero(id) => Ok((rem, Cmd::PushIdValueConst(id, 0))),
+        DataAddressOffset::Zero(id) => Ok((rem, Cmd::PushIdValueConst(id, ConstValue::Number(0)))),
         DataAddressOffset::Const(id, n) => Ok((rem, Cmd::PushIdValueConst(id, n))),
         DataAddressOffset::Reg(id, r) => Ok((rem, Cmd::PushIdValueReg(id, r))),
     }
 }
 
+pub fn parse_pop_id_value(input: &'_ str) -> IResult<&'_ str, Cmd<'_>> {
+    let (rem, _) = tag_no_case("pop").parse(input)?;
+    let (rem, val) = preceded(multispace1, parse_id_address_with_offset).parse(rem)?;
+    match val {
+        DataAddressOffset::Zero(id) => Ok((rem, Cmd::PopAddr(id_address(id, None)))),
+        DataAddressOffset::Const(id, n) => Ok((rem, Cmd::PopAddr(id_address(id, Some(n))))),
+        DataAddressOffset::Reg(id, r) => Ok((rem, Cmd::PopIdValueReg(id, r))),
+    }
+}
 
 // ----------------- Register parser -----------------
 
@@ -176,7 +385,7 @@ pub fn parse_reg(input: &str) -> IResult<&str, u32> {
 pub fn parse_number_or_const(input: &str) -> IResult<&str, ConstValue<'_>> {
     alt((
         map(parse_reg, ConstValue::Number),
-        map(parse_address, ConstValue::Number),  // Add this line to handle addresses
+        parse_address,
         parse_const_value,
     ))
     .parse(input)
@@ -185,7 +394,7 @@ pub fn parse_number_or_const(input: &str) -> IResult<&str, ConstValue<'_>> {
 macro_rules! unary_cmd {
     ($kw:expr, $parser:expr, $variant:ident) => {
         |input| {
-            let (rem, val) = preceded(pair(tag_no_case($kw), multispace1), $parser).parse(input)?;
+            let (rem, val) = preceded(pair(tag_no_case($kw), space1), $parser).parse(input)?;
             Ok((rem, Cmd::$variant(val)))
         }
     };
@@ -206,12 +415,13 @@ fn parse_dec(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("dec", parse_re
 fn parse_push_address(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("push", parse_address, PushAddr)(input) }
 fn parse_pop_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("pop", parse_reg, PopReg)(input) }
 fn parse_pop_address(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("pop", parse_address, PopAddr)(input) }
-fn parse_dup_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("dup", parse_number, DupConst)(input) }
+fn parse_pop_address_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("pop", preceded(tag("&"), parse_reg), PopAddrReg)(input) }
+fn parse_dup_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("dup", parse_const_value, DupConst)(input) }
 fn parse_dup_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("dup", parse_reg, DupReg)(input) }
 fn parse_dup(input: &str) -> IResult<&str, Cmd<'_>> { map(tag_no_case("dup"), |_| Cmd::Dup).parse(input) }
-fn parse_shr_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("shr", parse_number, ShrConst)(input) }
+fn parse_shr_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("shr", parse_const_value, ShrConst)(input) }
 fn parse_shr_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("shr", parse_reg, ShrReg)(input) }
-fn parse_shl_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("shl", parse_number, ShlConst)(input) }
+fn parse_shl_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("shl", parse_const_value, ShlConst)(input) }
 fn parse_shl_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("shl", parse_reg, ShlReg)(input) }
 fn parse_call_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("call", parse_const_value, CallConst)(input) }
 fn parse_call_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("call", parse_reg, CallReg)(input) }
@@ -220,6 +430,21 @@ fn parse_safecall_const(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("saf
 fn parse_safecall_reg(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("safecall", parse_reg, SafeCallReg)(input) }
 fn parse_safecall_address(input: &str) -> IResult<&str, Cmd<'_>> { unary_cmd!("safecall", parse_address, SafeCallAddr)(input) }
 
+/// `call [$id]`, `call [$id + const]` or `call [$id + reg]`, and the same for `safecall`
+fn parse_call_id_value(input: &str) -> IResult<&str, Cmd<'_>> {
+    let (rem, safe) = alt((value(true, tag_no_case("safecall")), value(false, tag_no_case("call")))).parse(input)?;
+    let (rem, val) = preceded(space1, parse_id_address_with_offset).parse(rem)?;
+    let cmd = match (val, safe) {
+        (DataAddressOffset::Zero(id), false) => Cmd::CallAddr(id_address(id, None)),
+        (DataAddressOffset::Zero(id), true) => Cmd::SafeCallAddr(id_address(id, None)),
+        (DataAddressOffset::Const(id, n), false) => Cmd::CallAddr(id_address(id, Some(n))),
+        (DataAddressOffset::Const(id, n), true) => Cmd::SafeCallAddr(id_address(id, Some(n))),
+        (DataAddressOffset::Reg(id, reg), false) => Cmd::CallIdValueReg(id, reg),
+        (DataAddressOffset::Reg(id, reg), true) => Cmd::SafeCallIdValueReg(id, reg),
+    };
+    Ok((rem, cmd))
+}
+
 // ----------------- Keyword commands -----------------
 
 fn parse_add(input: &str) -> IResult<&str, Cmd<'_>> { keyword_cmd!("add", Add).parse(input) }
@@ -235,47 +460,71 @@ fn parse_term(input: &str) -> IResult<&str, Cmd<'_>> { keyword_cmd!("term", Term
 fn parse_mul(input: &str) -> IResult<&str, Cmd<'_>> { keyword_cmd!("mul", Mul).parse(input) }
 fn parse_div(input: &str) -> IResult<&str, Cmd<'_>> { keyword_cmd!("div", Div).parse(input) }
 
-// ----------------- Jump commands (parse label as target) -----------------
+// ----------------- Jump commands -----------------
+
+/// address of `[$id]` or `[$id + offset]`
+fn id_address<'a>(id: &'a str, offset: Option<ConstValue<'a>>) -> ConstValue<'a> {
+    let id = Expr::Symbol(SymbolName::Data(id));
+    match offset {
+        Some(offset) => ConstValue::Expr(Expr::Binary(BinaryOp::Add, Box::new(id), Box::new(offset.into_expr()))),
+        None => ConstValue::Expr(id),
+    }
+}
+
+/// label or constant, `&addr`, `[$id]`, `[$id + const]`, `[$id + reg]` or a register
+pub fn parse_jump_operand(input: &str) -> IResult<&str, JumpOperand<'_>> {
+    alt((
+        map(parse_address, JumpOperand::Addr),
+        map(parse_id_address_with_offset, |offset| match offset {
+            DataAddressOffset::Zero(id) => JumpOperand::Addr(id_address(id, None)),
+            DataAddressOffset::Const(id, n) => JumpOperand::Addr(id_address(id, Some(n))),
+            DataAddressOffset::Reg(id, reg) => JumpOperand::IdOffsetReg(id, reg),
+        }),
+        map(parse_const_value, JumpOperand::Const),
+        map(parse_reg, JumpOperand::Reg),
+    ))
+    .parse(input)
+}
 
 fn parse_jmp(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jmp")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jmp(target)))
 }
 
 fn parse_jnz(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jnz")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jnz(target)))
 }
 
 fn parse_jz(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jz")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jz(target)))
 }
 
 fn parse_jg(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jg")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jg(target)))
 }
 
 fn parse_jge(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jge")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jge(target)))
 }
 
 fn parse_jl(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jl")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jl(target)))
 }
 
 fn parse_jle(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("jle")(input)?;
-    let (rem, target) = preceded(multispace1, parse_label).parse(rem)?;
+    let (rem, target) = preceded(multispace1, parse_jump_operand).parse(rem)?;
     Ok((rem, Cmd::Jle(target)))
 }
 
@@ -287,6 +536,9 @@ fn parse_jle(input: &str) -> IResult<&str, Cmd<'_>> {
 fn parse_move(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("move").parse(input)?;
     let (rem, dest) = delimited(multispace1, parse_reg, multispace1).parse(rem)?;  // Changed from parse_number to parse_reg
+    if let Ok((rem, src)) = parse_reg(rem) {
+        return Ok((rem, Cmd::MoveReg(dest, src)));
+    }
     let (rem, src) = parse_number_or_const(rem)?;
     Ok((rem, Cmd::MoveConst(dest, src)))
 }
@@ -300,7 +552,7 @@ fn parse_move_addr_reg(input: &str) -> IResult<&str, Cmd<'_>> {
 
 fn parse_store(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, _) = tag_no_case("store").parse(input)?;
-    let (rem, dest) = delimited(multispace1, parse_number, multispace1).parse(rem)?;
+    let (rem, dest) = delimited(multispace1, parse_const_value, multispace1).parse(rem)?;
     let (rem, src) = parse_number_or_const(rem)?;
     Ok((rem, Cmd::StoreConst(dest, src)))
 }
@@ -317,7 +569,7 @@ pub fn parse_move_id_value(input: &'_ str) -> IResult<&'_ str, Cmd<'_>> {
     let (rem, reg) = preceded(multispace1, parse_reg).parse(rem)?;
     let (rem, val) = preceded(multispace1, parse_id_address_with_offset).parse(rem)?;
     match val {
-        DataAddressOffset::Zero(id) => Ok((rem, Cmd::MoveIdValueConst(reg, id, 0))),
+        DataAddressOffset::Zero(id) => Ok((rem, Cmd::MoveIdValueConst(reg, id, ConstValue::Number(0)))),
         DataAddressOffset::Const(id, n) => Ok((rem, Cmd::MoveIdValueConst(reg, id, n))),
         DataAddressOffset::Reg(id, r) => Ok((rem, Cmd::MoveIdValueReg(reg, id, r))),
     }
@@ -331,8 +583,8 @@ fn parse_int(input: &str) -> IResult<&str, Cmd<'_>> {
     let (rem, (module, function)) = preceded(
         multispace1,
         pair(
-            parse_number,
-            preceded(multispace1, parse_number)
+            parse_const_value,
+            preceded(multispace1, parse_const_value)
         )
     ).parse(rem)?;
     Ok((rem, Cmd::Int(module, function)))
@@ -394,6 +646,9 @@ pub fn parse_command(input: &str) -> IResult<&str, Cmd<'_>> {
             parse_safecall_const,
             parse_safecall_address,
             parse_safecall_reg,
+            parse_call_id_value,
+            parse_pop_id_value,
+            parse_pop_address_reg,
         )),
     ))
     .parse(input)
@@ -404,7 +659,7 @@ pub fn parse_command(input: &str) -> IResult<&str, Cmd<'_>> {
 
 pub fn parse_token(input: &str) -> IResult<&str, Token<'_>> {
     alt((
-        map(parse_label, Token::Label),
+        map(alt((parse_label, parse_anonymous_label)), Token::Label),
         map(parse_meta, Token::Meta),
         map(parse_command, Token::Command),
         map(parse_section, Token::Section),
@@ -434,7 +689,47 @@ pub fn parse_line(input: &str) -> IResult<&str, Option<Token<'_>>> {
 }
 
 pub fn parse_program(input: &str) -> IResult<&str, Vec<Token<'_>>> {
-    let (rem, lines) = separated_list0(line_ending, parse_line).parse(input)?;
-    let tokens = lines.into_iter().flatten().collect();
+    let (rem, lines) = parse_program_lines(input)?;
+    let tokens = lines.into_iter().map(|l| l.token).collect();
     Ok((rem, tokens))
 }
+
+/// Parses a program like `parse_program` but keeps the (1-based) source line of every token
+pub fn parse_program_lines<'a>(input: &'a str) -> IResult<&'a str, Vec<LineToken<'a>>> {
+    let (rem, lines) = separated_list0(line_ending, |line: &'a str| -> IResult<&'a str, Option<(usize, Token<'a>)>> {
+        let (rem, token) = parse_line(line)?;
+        Ok((rem, token.map(|t| (input.len() - line.len(), t))))
+    }).parse(input)?;
+
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut scanned = 0;
+    for (offset, token) in lines.into_iter().flatten() {
+        line += input[scanned..offset].matches('\n').count();
+        scanned = offset;
+        tokens.push(LineToken { file: 0, line, token });
+    }
+    Ok((rem, tokens))
+}
+
+/// Parses a program line by line like `parse_program_lines` but never gives up: every line that
+/// cannot be parsed completely contributes the unparsed rest of the line to the second list
+pub fn parse_lines(input: &str) -> (Vec<LineToken<'_>>, Vec<&str>) {
+    let mut tokens = Vec::new();
+    let mut unparsed = Vec::new();
+    for (idx, line) in input.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (rem, token) = match parse_line(line) {
+            Ok(result) => result,
+            Err(_) => (line.trim_start(), None),
+        };
+        if let Some(token) = token {
+            tokens.push(LineToken { file: 0, line: idx + 1, token });
+        }
+        let rem = rem[..rem.find(';').unwrap_or(rem.len())].trim_end();
+        if !rem.is_empty() {
+            unparsed.push(rem);
+        }
+    }
+    (tokens, unparsed)
+}
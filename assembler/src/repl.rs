@@ -0,0 +1,359 @@
+use std::collections::{HashMap, VecDeque};
+
+use binary::{image::SymbolKind, object::RelocationTarget};
+use machine::{errors::VMError, internal::machine::{Machine, MachineOptions}};
+
+use crate::{compiler::{compile_object, CompileOptions}, debug::DebugInfo, diagnostic::Diagnostic, parser::parse_number};
+
+/// memory cells of a session machine
+pub const SESSION_CELLS: u32 = 1 << 16;
+/// stack cells of a session machine
+pub const SESSION_STACK: u32 = 1024;
+/// first address of the data defined in a session, code grows from 0 up to it
+pub const SESSION_DATA: u32 = 1 << 15;
+/// instructions a single input may run before it is stopped
+pub const STEP_LIMIT: u64 = 10_000_000;
+/// inputs `:undo` can revert, each keeps a whole machine of `SESSION_CELLS` cells
+pub const UNDO_DEPTH: usize = 32;
+
+/// name of the input in diagnostics
+const INPUT: &str = "<input>";
+
+/// words shown per line by `:mem`
+const MEMORY_LINE: usize = 8;
+
+const HELP: &str = "\
+instructions, labels (.name), data definitions ($name dw 1 2) and @define are assembled and run at once
+:regs                  show registers
+:flags                 show flags
+:stack                 show the stack from the bottom to the top
+:mem <addr> [count]    show memory, <addr> is a number, .label or $identifier
+:label <name> [addr]   name an address, the next instruction by default
+:load <file>           load the code and data of a source file without running it
+:reset                 start over with an empty machine
+:undo                  revert the last input, up to 32 of them
+:help                  show this help
+:quit                  leave the session";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Eval {
+    /// the input was assembled and run
+    Done,
+    /// a command ran, with the text it shows
+    Output(String),
+    /// the session should end
+    Quit,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Compile(Vec<Diagnostic>),
+    /// the machine is left as the error found it, `:undo` reverts the input
+    Runtime(VMError),
+    /// the input ran for `STEP_LIMIT` instructions and was stopped
+    StepLimit,
+    /// invalid command or argument
+    Command(String),
+}
+
+/// Everything an input can change, kept whole so `:undo` can bring it back
+#[derive(Clone)]
+struct State {
+    machine: Machine,
+    /// `(address, size)` of every label and data identifier defined so far
+    symbols: HashMap<(SymbolKind, String), (u32, u32)>,
+    /// `@define` lines, given again before every input
+    defines: Vec<String>,
+    /// address the code of the next input is placed at
+    next_code: u32,
+    /// address the data of the next input is placed at
+    next_data: u32,
+}
+
+impl State {
+    fn new() -> Result<State, VMError> {
+        let machine = Machine::new(MachineOptions { memory_cells: SESSION_CELLS, memory_stack_size: SESSION_STACK })?;
+        Ok(State { machine, symbols: HashMap::new(), defines: Vec::new(), next_code: 0, next_data: SESSION_DATA })
+    }
+}
+
+/// # Interactive assembly session
+///
+/// Every input is assembled into code placed right after the code of the previous ones, ending
+/// with a `term` that the next input overwrites, and run at once in the same machine. Labels
+/// and data identifiers stay defined for later inputs, so jumps and calls can go back to
+/// earlier code, and registers, flags, memory and the stack carry over from one input to the
+/// next. Data is kept apart from the code, starting at `SESSION_DATA`.
+///
+/// Lines starting with `:` are commands, see `:help`.
+pub struct Session {
+    state: State,
+    /// state before the last `UNDO_DEPTH` inputs that changed it, the latest last
+    history: VecDeque<State>,
+}
+
+impl Session {
+    pub fn new() -> Result<Session, VMError> {
+        Ok(Session { state: State::new()?, history: VecDeque::new() })
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.state.machine
+    }
+
+    /// address of a label or data identifier defined in the session
+    pub fn symbol(&self, kind: SymbolKind, name: &str) -> Option<u32> {
+        self.state.symbols.get(&(kind, name.to_string())).map(|&(address, _)| address)
+    }
+
+    /// run a command, or assemble and run one line of code
+    pub fn eval(&mut self, line: &str) -> Result<Eval, SessionError> {
+        let line = line.trim();
+        if let Some(command) = line.strip_prefix(':') {
+            return self.command(command);
+        }
+        if line.is_empty() || line.starts_with(';') {
+            return Ok(Eval::Done);
+        }
+        let snapshot = self.state.clone();
+        let result = self.input(line);
+        match &result {
+            // nothing changed
+            Err(SessionError::Compile(_) | SessionError::Command(_)) => self.state = snapshot,
+            _ => self.remember(snapshot),
+        }
+        result
+    }
+
+    /// keep a state for `:undo`, forgetting the oldest beyond `UNDO_DEPTH`
+    fn remember(&mut self, state: State) {
+        if self.history.len() == UNDO_DEPTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(state);
+    }
+
+    fn input(&mut self, line: &str) -> Result<Eval, SessionError> {
+        if line.starts_with('@') {
+            let options = CompileOptions { file: Some(INPUT.to_string()), ..Default::default() };
+            let unit = format!("{}{}\n", self.prefix(), line);
+            compile_object(unit, &options).map_err(|d| SessionError::Compile(self.input_lines(d)))?;
+            if line.to_ascii_lowercase().starts_with("@define") {
+                self.state.defines.push(line.to_string());
+            }
+            return Ok(Eval::Done);
+        }
+        let unit = match line.starts_with('$') {
+            true => format!("{}[data]\n{}\n", self.prefix(), line),
+            false => format!("{}[text]\n{}\nterm\n", self.prefix(), line),
+        };
+        let start = self.state.next_code;
+        let text_len = self.place(unit, None)?;
+        if text_len == 0 {
+            return Ok(Eval::Done);
+        }
+        // the next input overwrites the `term`
+        self.state.next_code = start + text_len - 1;
+        self.run(start)?;
+        Ok(Eval::Done)
+    }
+
+    /// `@define` lines given before every input
+    fn prefix(&self) -> String {
+        self.state.defines.iter().map(|d| format!("{}\n", d)).collect()
+    }
+
+    /// diagnostics with the lines of the input counted from the input itself
+    fn input_lines(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let offset = self.state.defines.len() + 1;
+        diagnostics.into_iter()
+            .map(|d| Diagnostic { line: if d.file == INPUT { d.line.saturating_sub(offset).max(1) } else { d.line }, ..d })
+            .collect()
+    }
+
+    /// Assemble a unit and load its code at `next_code` and its data at `next_data`, moving
+    /// both past it. `file` is the path of a loaded file. Returns the number of code words.
+    fn place(&mut self, unit: String, file: Option<&str>) -> Result<u32, SessionError> {
+        // every symbol defined so far, names given in the unit win
+        let mut source = unit;
+        for (kind, name) in self.state.symbols.keys().filter(|(_, name)| !name.contains('.')) {
+            match kind {
+                SymbolKind::Label => source += &format!("@extern .{}\n", name),
+                SymbolKind::Data => source += &format!("@extern ${}\n", name),
+            }
+        }
+        let options = CompileOptions { debug: true, file: Some(file.unwrap_or(INPUT).to_string()), ..Default::default() };
+        let (object, _) = compile_object(source, &options).map_err(|d| match file {
+            Some(_) => SessionError::Compile(d),
+            None => SessionError::Compile(self.input_lines(d)),
+        })?;
+        let debug = DebugInfo::parse(object.debug.as_deref().unwrap_or_default()).map_err(SessionError::Command)?;
+
+        let state = &mut self.state;
+        let (text_len, data_len) = (object.text.len() as u32, object.data.len() as u32);
+        let (code, data) = (state.next_code, state.next_data);
+        if code + text_len > SESSION_DATA {
+            return Err(SessionError::Command("no room left for code, use :reset".to_string()));
+        }
+        if data + data_len > SESSION_CELLS - SESSION_STACK {
+            return Err(SessionError::Command("no room left for data, use :reset".to_string()));
+        }
+        let place = |relative: u32| if relative < text_len { code + relative } else { data + relative - text_len };
+        let mut words = object.text.clone();
+        words.extend_from_slice(&object.data);
+        for relocation in &object.relocations {
+            let word = &mut words[relocation.offset as usize];
+            match relocation.target {
+                RelocationTarget::Local => *word = place(*word),
+                RelocationTarget::Import(idx) => {
+                    let import = &object.imports[idx as usize];
+                    let Some(&(address, _)) = state.symbols.get(&(import.kind, import.name.clone())) else {
+                        let name = match import.kind {
+                            SymbolKind::Label => format!(".{}", import.name),
+                            SymbolKind::Data => format!("${}", import.name),
+                        };
+                        return Err(SessionError::Command(format!("'{}' is not defined", name)));
+                    };
+                    *word = word.wrapping_add(address);
+                },
+            }
+        }
+        let (text, data_words) = words.split_at(text_len as usize);
+        for (address, words) in [(code, text), (data, data_words)] {
+            if !words.is_empty() {
+                state.machine.load_data(address, words).map_err(SessionError::Runtime)?;
+            }
+        }
+        for label in debug.labels {
+            state.symbols.insert((SymbolKind::Label, label.name), (place(label.address), 0));
+        }
+        for symbol in debug.data {
+            state.symbols.insert((SymbolKind::Data, symbol.name), (place(symbol.address), symbol.size));
+        }
+        state.next_code += text_len;
+        state.next_data += data_len;
+        Ok(text_len)
+    }
+
+    /// run from `start` until `term`
+    fn run(&mut self, start: u32) -> Result<(), SessionError> {
+        let machine = &mut self.state.machine;
+        machine.set_start(start);
+        for _ in 0..STEP_LIMIT {
+            if machine.step().map_err(SessionError::Runtime)? {
+                return Ok(());
+            }
+        }
+        Err(SessionError::StepLimit)
+    }
+
+    /// address given to a command as a number, `.label` or `$identifier`, with the size of
+    /// identifiers
+    fn address(&self, word: &str) -> Result<(u32, Option<u32>), SessionError> {
+        let symbol = match (word.strip_prefix('.'), word.strip_prefix('$')) {
+            (Some(name), _) => Some((SymbolKind::Label, name)),
+            (_, Some(name)) => Some((SymbolKind::Data, name)),
+            _ => None,
+        };
+        match symbol {
+            Some((kind, name)) => match self.state.symbols.get(&(kind, name.to_string())) {
+                Some(&(address, size)) => Ok((address, (kind == SymbolKind::Data).then_some(size))),
+                None => Err(SessionError::Command(format!("'{}' is not defined", word))),
+            },
+            None => Ok((number(word)?, None)),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Result<Eval, SessionError> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let machine = &self.state.machine;
+        let output = match words.as_slice() {
+            ["regs"] => {
+                let register = &machine.register;
+                let values = [
+                    ("r0", register.r0), ("r1", register.r1), ("r2", register.r2), ("r3", register.r3),
+                    ("r4", register.r4), ("r5", register.r5), ("r6", register.r6), ("r7", register.r7),
+                    ("pc", register.pc),
+                ];
+                values.iter().map(|(name, value)| format!("{} = 0x{:08x} {}", name, value, value)).collect::<Vec<_>>().join("\n")
+            },
+            ["flags"] => {
+                let flag = &machine.flag;
+                format!("zero={} negative={} overflow={} carry={}", flag.zero as u8, flag.negative as u8, flag.overflow as u8, flag.carry as u8)
+            },
+            ["stack"] => {
+                let stack = machine.memory.stack();
+                let values: Vec<String> = stack.iter().map(|v| v.to_string()).collect();
+                format!("<{}> {}", stack.len(), values.join(" ")).trim_end().to_string()
+            },
+            ["mem", start, rest @ ..] if rest.len() <= 1 => {
+                let (start, size) = self.address(start)?;
+                let count = match rest.first() {
+                    Some(count) => number(count)?,
+                    None => size.unwrap_or(MEMORY_LINE as u32),
+                };
+                let mut words = Vec::new();
+                for address in start..start.saturating_add(count) {
+                    words.push(machine.memory.read(address).map_err(SessionError::Runtime)?);
+                }
+                words.chunks(MEMORY_LINE).enumerate()
+                    .map(|(idx, chunk)| {
+                        let values: Vec<String> = chunk.iter().map(|w| format!("{:08x}", w)).collect();
+                        format!("0x{:08x}: {}", start as usize + idx * MEMORY_LINE, values.join(" "))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            },
+            ["label", name, rest @ ..] if rest.len() <= 1 => {
+                let name = name.strip_prefix('.').unwrap_or(name);
+                let address = match rest.first() {
+                    Some(word) => self.address(word)?.0,
+                    None => self.state.next_code,
+                };
+                self.remember(self.state.clone());
+                self.state.symbols.insert((SymbolKind::Label, name.to_string()), (address, 0));
+                format!(".{} = 0x{:08x}", name, address)
+            },
+            ["load", path] => {
+                let code = std::fs::read_to_string(path).map_err(|e| SessionError::Command(format!("unable to read '{}': {}", path, e)))?;
+                let snapshot = self.state.clone();
+                let start = self.state.next_code;
+                match self.place(format!("{}{}\n", self.prefix(), code), Some(path)) {
+                    Ok(text_len) => {
+                        self.remember(snapshot);
+                        format!("loaded {} words of code at 0x{:08x}", text_len, start)
+                    },
+                    Err(e) => {
+                        self.state = snapshot;
+                        return Err(e);
+                    },
+                }
+            },
+            ["reset"] => {
+                let fresh = State::new().map_err(SessionError::Runtime)?;
+                let previous = std::mem::replace(&mut self.state, fresh);
+                self.remember(previous);
+                "machine reset".to_string()
+            },
+            ["undo"] => match self.history.pop_back() {
+                Some(state) => {
+                    self.state = state;
+                    "undone".to_string()
+                },
+                None => return Err(SessionError::Command("nothing to undo".to_string())),
+            },
+            ["help"] => HELP.to_string(),
+            ["quit" | "q"] => return Ok(Eval::Quit),
+            _ => return Err(SessionError::Command(format!("unknown command ':{}', see :help", command.trim()))),
+        };
+        Ok(Eval::Output(output))
+    }
+}
+
+/// number argument of a command
+fn number(word: &str) -> Result<u32, SessionError> {
+    match parse_number(word) {
+        Ok(("", value)) => Ok(value),
+        _ => Err(SessionError::Command(format!("'{}' is not a number", word))),
+    }
+}
@@ -0,0 +1,729 @@
+use std::{collections::HashMap, fmt::Display, path::{Path, PathBuf}};
+
+use nom::Offset;
+
+use crate::{diagnostic::{Diagnostic, Severity}, parser::{parse_lines, parse_meta, MNEMONICS}, tokens::{LineToken, MetaType}};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Origin of a file generated by expanding a macro or a control-flow directive
+pub struct Expansion {
+    /// macro name, or the directive such as `@if`
+    pub name: String,
+    /// `(file, line)` of the invocation
+    pub invocation: (usize, usize),
+    /// `(file, line)` of the first body line in the macro definition, directives point at the
+    /// generated file itself
+    pub body: (usize, usize),
+}
+
+#[derive(Debug)]
+/// Source file taking part in a compilation
+pub struct SourceFile {
+    /// name used in messages and debug information
+    pub name: String,
+    /// canonical path, `None` for in-memory sources and macro expansions
+    pub path: Option<PathBuf>,
+    pub content: String,
+    /// set when this file is the body of an expanded macro or directive
+    pub expansion: Option<Expansion>,
+    /// content with directives, macro definitions and invocations blanked out
+    code: String,
+    /// `(line, file index)` of every `@include`, macro invocation and directive in this file
+    splices: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+/// Errors found while loading sources, each with a chain of `(file, line)` pairs
+pub enum SourceError {
+    /// included file cannot be found in any search directory
+    NotFound { path: String, chain: Vec<(String, usize)> },
+    /// file includes itself directly or indirectly
+    Cycle { path: String, chain: Vec<(String, usize)> },
+    /// file is included more than once
+    Duplicate { path: String, chain: Vec<(String, usize)> },
+    /// invalid macro definition or invocation, `trace` starts at the offending line and
+    /// continues with every invocation it was expanded from
+    Macro { message: String, trace: Vec<(String, usize)> },
+    /// invalid or unbalanced `@if`, `@while` or `@proc` directive, `trace` is like for `Macro`
+    Block { message: String, trace: Vec<(String, usize)> },
+}
+
+impl SourceError {
+    /// diagnostic pointing at the `@include` or macro line that failed
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let (message, locations) = match self {
+            SourceError::NotFound { path, chain } => (format!("included file '{}' not found", path), chain),
+            SourceError::Cycle { path, chain } => (format!("include cycle through '{}'", path), chain),
+            SourceError::Duplicate { path, chain } => (format!("file '{}' is included more than once", path), chain),
+            SourceError::Macro { message, trace } | SourceError::Block { message, trace } => (message.clone(), trace),
+        };
+        let mut diagnostic = Diagnostic::error(message, "<source>");
+        let mut locations = locations.iter();
+        let first = match self {
+            // the failing include is the innermost one
+            SourceError::Macro { .. } | SourceError::Block { .. } => locations.next(),
+            _ => locations.next_back(),
+        };
+        if let Some((file, line)) = first {
+            diagnostic.file = file.clone();
+            diagnostic.line = *line;
+            diagnostic.column = 1;
+        }
+        for (file, line) in locations {
+            let note = match self {
+                SourceError::Macro { .. } | SourceError::Block { .. } => format!("expanded from {}:{}", file, line),
+                _ => format!("included from {}:{}", file, line),
+            };
+            diagnostic.notes.push(note);
+        }
+        diagnostic
+    }
+}
+
+fn format_chain(chain: &[(String, usize)]) -> String {
+    chain.iter().map(|(file, line)| format!("{}:{}", file, line)).collect::<Vec<_>>().join(" -> ")
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::NotFound { path, chain } => write!(f, "included file '{}' not found (include chain: {})", path, format_chain(chain)),
+            SourceError::Cycle { path, chain } => write!(f, "include cycle: {} -> {}", format_chain(chain), path),
+            SourceError::Duplicate { path, chain } => write!(f, "file '{}' is included more than once (include chain: {})", path, format_chain(chain)),
+            SourceError::Macro { message, trace } | SourceError::Block { message, trace } => {
+                match trace.first() {
+                    Some((file, line)) => write!(f, "{}:{}: {}", file, line, message)?,
+                    None => write!(f, "{}", message)?,
+                }
+                for (file, line) in trace.iter().skip(1) {
+                    write!(f, ", expanded from {}:{}", file, line)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Macro defined with `@macro name params...` ... `@endm`
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+    /// `(file, line)` of the first body line
+    body_at: (usize, usize),
+}
+
+/// Kind of an open control-flow block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    If,
+    /// `@if` block after its `@else`
+    Else,
+    While,
+    Proc,
+}
+
+impl BlockKind {
+    fn opening(&self) -> &'static str {
+        match self {
+            BlockKind::If | BlockKind::Else => "@if",
+            BlockKind::While => "@while",
+            BlockKind::Proc => "@proc",
+        }
+    }
+
+    fn closing(&self) -> &'static str {
+        match self {
+            BlockKind::If | BlockKind::Else => "@endif",
+            BlockKind::While => "@endw",
+            BlockKind::Proc => "@endp",
+        }
+    }
+}
+
+/// Control-flow block opened in the file being processed
+struct Block {
+    kind: BlockKind,
+    /// index of the file generated for the opening directive, keeps labels unique
+    id: usize,
+    /// line of the opening directive
+    line: usize,
+}
+
+/// control-flow directives, expanded into jumps and generated labels
+pub(crate) const DIRECTIVES: [&str; 7] = ["@if", "@else", "@endif", "@while", "@endw", "@proc", "@endp"];
+
+/// conditions of `@if` and `@while` with the jump taken when they do not hold
+const CONDITIONS: [(&str, &str); 6] = [
+    ("z", "jnz"),
+    ("nz", "jz"),
+    ("g", "jle"),
+    ("ge", "jl"),
+    ("l", "jge"),
+    ("le", "jg"),
+];
+
+/// State shared while walking all files in program order
+struct Loader<'a> {
+    include_dirs: &'a [PathBuf],
+    macros: HashMap<String, Macro>,
+    /// `(file index, line)` of every `@include` currently being loaded
+    chain: Vec<(usize, usize)>,
+}
+
+/// characters of a line with their byte index and whether they are part of a string or
+/// character literal, where `\` escapes the next character
+pub fn quoted_chars(line: &str) -> Vec<(usize, char, bool)> {
+    let mut out = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        let inside = quote.is_some();
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            _ => {},
+        }
+        out.push((idx, c, inside || quote.is_some()));
+    }
+    out
+}
+
+/// code of a line without its comment, `;` inside strings is kept
+pub fn strip_comment(line: &str) -> &str {
+    match quoted_chars(line).into_iter().find(|&(_, c, quoted)| c == ';' && !quoted) {
+        Some((idx, _, _)) => &line[..idx],
+        None => line,
+    }
+}
+
+/// split a line into whitespace separated words, strings in quotes are kept as one word
+fn split_words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (idx, c, quoted) in quoted_chars(line) {
+        if c.is_whitespace() && !quoted {
+            if let Some(s) = start.take() {
+                words.push(&line[s..idx]);
+            }
+        } else if start.is_none() {
+            start = Some(idx);
+        }
+    }
+    if let Some(s) = start {
+        words.push(&line[s..]);
+    }
+    words
+}
+
+fn is_name(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+type QuotedChars = std::iter::Peekable<std::vec::IntoIter<(usize, char, bool)>>;
+
+fn take_name(chars: &mut QuotedChars) -> String {
+    let mut name = String::new();
+    while let Some(&(_, c, _)) = chars.peek() && (c.is_ascii_alphanumeric() || c == '_') {
+        name.push(c);
+        chars.next();
+    }
+    name
+}
+
+/// Substitute `%param` with its argument and `%%name` with a local label name unique to
+/// expansion `id`, so `.%%name` does not end the scope of the caller's local labels.
+/// Strings and comments are copied unchanged.
+fn substitute(line: &str, params: &[String], args: &[&str], id: usize) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = quoted_chars(line).into_iter().peekable();
+    while let Some((idx, c, quoted)) = chars.next() {
+        match c {
+            ';' if !quoted => {
+                out.push_str(&line[idx..]);
+                break;
+            },
+            '%' if !quoted && matches!(chars.peek(), Some((_, '%', _))) => {
+                chars.next();
+                let name = take_name(&mut chars);
+                if name.is_empty() {
+                    return Err("expected a label name after '%%'".to_string());
+                }
+                out.push_str(&format!(".{}.{}", name, id));
+            },
+            '%' if !quoted => {
+                let name = take_name(&mut chars);
+                match params.iter().position(|p| *p == name) {
+                    Some(idx) => out.push_str(args[idx]),
+                    None => return Err(format!("unknown macro parameter '%{}'", name)),
+                }
+            },
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Debug)]
+/// # Source set
+///
+/// Root source, every file reachable through `@include` and one generated file per macro
+/// expansion and control-flow directive. Files are owned here so tokens of all of them can borrow from one place; the
+/// root file always has index 0.
+pub struct SourceSet {
+    pub files: Vec<SourceFile>,
+}
+
+impl SourceSet {
+    /// load root source, resolve its includes recursively and expand macros
+    ///
+    /// # Params
+    ///
+    /// * `name`: path of the root file (`None` for in-memory code, includes are resolved from the current directory)
+    /// * `content`: root source code
+    /// * `include_dirs`: extra directories searched after the directory of the including file
+    pub fn load(name: Option<&str>, content: String, include_dirs: &[PathBuf]) -> Result<SourceSet, SourceError> {
+        let root = SourceFile {
+            name: name.unwrap_or("<source>").to_string(),
+            path: name.and_then(|n| Path::new(n).canonicalize().ok()),
+            content,
+            expansion: None,
+            code: String::new(),
+            splices: Vec::new(),
+        };
+        let mut set = SourceSet { files: vec![root] };
+        let mut loader = Loader { include_dirs, macros: HashMap::new(), chain: Vec::new() };
+        set.process(0, &mut loader)?;
+        Ok(set)
+    }
+
+    /// walk a file line by line, loading includes and expanding macros as they appear
+    fn process(&mut self, idx: usize, loader: &mut Loader) -> Result<(), SourceError> {
+        let lines: Vec<String> = self.files[idx].content.lines().map(str::to_string).collect();
+        let mut code = Vec::with_capacity(lines.len());
+        let mut blocks = Vec::new();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = i + 1;
+            let words = split_words(strip_comment(&lines[i]));
+            let first = words.first().map(|w| w.to_lowercase()).unwrap_or_default();
+            if first == "@macro" {
+                let end = self.define_macro(idx, line, &words, &lines, loader)?;
+                code.extend(std::iter::repeat_n(String::new(), end - i));
+                i = end;
+                continue;
+            }
+            if first == "@endm" {
+                return Err(self.macro_error("'@endm' without '@macro'", idx, line));
+            }
+            if DIRECTIVES.contains(&first.as_str()) {
+                self.directive(idx, line, &first, &words[1..], &mut blocks)?;
+                code.push(String::new());
+            } else if first == "@include" {
+                if let Ok((_, MetaType::Include(path))) = parse_meta(lines[i].trim_start()) {
+                    self.include(idx, line, path.to_string(), loader)?;
+                }
+                code.push(String::new());
+            } else if loader.macros.contains_key(&first) {
+                self.expand(idx, line, &first, &words[1..], loader)?;
+                code.push(String::new());
+            } else {
+                code.push(lines[i].clone());
+            }
+            i += 1;
+        }
+        if let Some(block) = blocks.last() {
+            let message = format!("'{}' is missing '{}'", block.kind.opening(), block.kind.closing());
+            return Err(self.block_error(&message, idx, block.line));
+        }
+        self.files[idx].code = code.join("\n");
+        Ok(())
+    }
+
+    /// Expand a control-flow directive at `line` of file `idx` into a generated file. Blocks
+    /// are closed in the file that opens them and labels are named `__if.N...` after the block,
+    /// outside of any scope.
+    fn directive(&mut self, idx: usize, line: usize, directive: &str, args: &[&str], blocks: &mut Vec<Block>) -> Result<(), SourceError> {
+        let id = self.files.len();
+        let takes_argument = matches!(directive, "@if" | "@while" | "@proc");
+        if let Some(extra) = args.get(usize::from(takes_argument)) {
+            return Err(self.block_error(&format!("unexpected '{}' after '{}'", extra, directive), idx, line));
+        }
+        let argument = match args.first() {
+            Some(argument) => *argument,
+            None if directive == "@proc" => return Err(self.block_error("procedure name expected after '@proc'", idx, line)),
+            None if takes_argument => return Err(self.block_error(&format!("condition expected after '{}'", directive), idx, line)),
+            None => "",
+        };
+        let code = match directive {
+            "@if" => {
+                let jump = self.condition(idx, line, directive, argument)?;
+                blocks.push(Block { kind: BlockKind::If, id, line });
+                vec![format!("    {} .__if.{}.else", jump, id)]
+            },
+            "@else" => {
+                if let Some(block) = blocks.last().filter(|b| b.kind == BlockKind::Else) {
+                    let message = format!("'@if' of line {} already has an '@else'", block.line);
+                    return Err(self.block_error(&message, idx, line));
+                }
+                let block = self.close(idx, line, directive, BlockKind::If, blocks)?;
+                blocks.push(Block { kind: BlockKind::Else, ..block });
+                vec![format!("    jmp .__if.{}.end", block.id), format!(".__if.{}.else", block.id)]
+            },
+            "@endif" => {
+                let block = self.close(idx, line, directive, BlockKind::If, blocks)?;
+                match block.kind {
+                    BlockKind::Else => vec![format!(".__if.{}.end", block.id)],
+                    _ => vec![format!(".__if.{}.else", block.id)],
+                }
+            },
+            "@while" => {
+                let jump = self.condition(idx, line, directive, argument)?;
+                blocks.push(Block { kind: BlockKind::While, id, line });
+                vec![format!(".__while.{}", id), format!("    {} .__while.{}.end", jump, id)]
+            },
+            "@endw" => {
+                let block = self.close(idx, line, directive, BlockKind::While, blocks)?;
+                vec![format!("    jmp .__while.{}", block.id), format!(".__while.{}.end", block.id)]
+            },
+            "@proc" => {
+                if let Some(block) = blocks.last() {
+                    let message = format!("'@proc' cannot be placed inside '{}'", block.kind.opening());
+                    return Err(self.block_error(&message, idx, line));
+                }
+                if !argument.split('.').all(is_name) {
+                    return Err(self.block_error(&format!("invalid procedure name '{}'", argument), idx, line));
+                }
+                blocks.push(Block { kind: BlockKind::Proc, id, line });
+                vec![format!(".{}", argument)]
+            },
+            _ => {
+                self.close(idx, line, directive, BlockKind::Proc, blocks)?;
+                vec!["    ret".to_string()]
+            },
+        };
+        let content = code.join("\n");
+        self.files.push(SourceFile {
+            name: format!("<{}>", directive),
+            path: None,
+            code: content.clone(),
+            content,
+            expansion: Some(Expansion { name: directive.to_string(), invocation: (idx, line), body: (id, 1) }),
+            splices: Vec::new(),
+        });
+        self.files[idx].splices.push((line, id));
+        Ok(())
+    }
+
+    /// jump taken when the condition of `@if` or `@while` does not hold
+    fn condition(&self, idx: usize, line: usize, directive: &str, condition: &str) -> Result<&'static str, SourceError> {
+        match CONDITIONS.iter().find(|(name, _)| name.eq_ignore_ascii_case(condition)) {
+            Some((_, jump)) => Ok(jump),
+            None => {
+                let message = format!("unknown condition '{}' after '{}', expected z, nz, g, ge, l or le", condition, directive);
+                Err(self.block_error(&message, idx, line))
+            },
+        }
+    }
+
+    /// pop the innermost block, which `directive` must close
+    fn close(&self, idx: usize, line: usize, directive: &str, kind: BlockKind, blocks: &mut Vec<Block>) -> Result<Block, SourceError> {
+        match blocks.last() {
+            Some(block) if block.kind.opening() == kind.opening() => Ok(blocks.pop().unwrap()),
+            Some(block) => {
+                let message = format!("'{}' found while '{}' of line {} is still open", directive, block.kind.opening(), block.line);
+                Err(self.block_error(&message, idx, line))
+            },
+            None => Err(self.block_error(&format!("'{}' without '{}'", directive, kind.opening()), idx, line)),
+        }
+    }
+
+    /// record a macro whose `@macro` header is at `line`, returns the index of the line after `@endm`
+    fn define_macro(&self, idx: usize, line: usize, header: &[&str], lines: &[String], loader: &mut Loader) -> Result<usize, SourceError> {
+        let Some(name) = header.get(1) else {
+            return Err(self.macro_error("macro name expected after '@macro'", idx, line));
+        };
+        if !is_name(name) {
+            return Err(self.macro_error(&format!("invalid macro name '{}'", name), idx, line));
+        }
+        let key = name.to_lowercase();
+        if MNEMONICS.contains(&key.as_str()) {
+            return Err(self.macro_error(&format!("macro '{}' would shadow an instruction", name), idx, line));
+        }
+        if loader.macros.contains_key(&key) {
+            return Err(self.macro_error(&format!("macro '{}' is already defined", name), idx, line));
+        }
+        let mut params: Vec<String> = Vec::new();
+        for param in &header[2..] {
+            if !is_name(param) || params.iter().any(|p| p == param) {
+                return Err(self.macro_error(&format!("invalid or repeated parameter '{}' of macro '{}'", param, name), idx, line));
+            }
+            params.push(param.to_string());
+        }
+        let mut end = line;
+        loop {
+            let Some(text) = lines.get(end) else {
+                return Err(self.macro_error(&format!("macro '{}' is missing '@endm'", name), idx, line));
+            };
+            let first = split_words(strip_comment(text)).first().map(|w| w.to_lowercase()).unwrap_or_default();
+            if first == "@endm" {
+                break;
+            }
+            if first == "@macro" {
+                return Err(self.macro_error("macros cannot be defined inside a macro", idx, end + 1));
+            }
+            end += 1;
+        }
+        loader.macros.insert(key, Macro {
+            params,
+            body: lines[line..end].to_vec(),
+            body_at: (idx, line + 1),
+        });
+        Ok(end + 1)
+    }
+
+    /// expand macro `key` invoked at `line` of file `idx` into a new file
+    fn expand(&mut self, idx: usize, line: usize, key: &str, args: &[&str], loader: &mut Loader) -> Result<(), SourceError> {
+        let m = &loader.macros[key];
+        if args.len() != m.params.len() {
+            let message = format!("macro '{}' expects {} argument(s), found {}", key, m.params.len(), args.len());
+            return Err(self.macro_error(&message, idx, line));
+        }
+        let mut parent = Some(idx);
+        while let Some(p) = parent {
+            match &self.files[p].expansion {
+                Some(e) if e.name == key => {
+                    return Err(self.macro_error(&format!("macro '{}' expands itself", key), idx, line));
+                },
+                Some(e) => parent = Some(e.invocation.0),
+                None => parent = None,
+            }
+        }
+        let id = self.files.len();
+        let mut body = Vec::with_capacity(m.body.len());
+        for (offset, text) in m.body.iter().enumerate() {
+            match substitute(text, &m.params, args, id) {
+                Ok(text) => body.push(text),
+                Err(message) => {
+                    let (file, body_line) = m.body_at;
+                    let mut trace = vec![(self.files[file].name.clone(), body_line + offset)];
+                    trace.extend(self.trace(idx, line));
+                    return Err(SourceError::Macro { message, trace });
+                },
+            }
+        }
+        self.files.push(SourceFile {
+            name: format!("<macro {}>", key),
+            path: None,
+            content: body.join("\n"),
+            expansion: Some(Expansion { name: key.to_string(), invocation: (idx, line), body: m.body_at }),
+            code: String::new(),
+            splices: Vec::new(),
+        });
+        self.files[idx].splices.push((line, id));
+        self.process(id, loader)
+    }
+
+    /// load file included at `line` of file `idx`
+    fn include(&mut self, idx: usize, line: usize, requested_path: String, loader: &mut Loader) -> Result<(), SourceError> {
+        let (real, _) = self.location(idx, line);
+        let base = match &self.files[real].path {
+            Some(p) => p.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+            None => PathBuf::from("."),
+        };
+        loader.chain.push((idx, line));
+        let chain = |loader: &Loader| -> Vec<(String, usize)> {
+            loader.chain.iter().map(|&(file, line)| (self.files[file].name.clone(), line)).collect()
+        };
+        let found = std::iter::once(&base).chain(loader.include_dirs.iter())
+            .map(|dir| dir.join(&requested_path))
+            .find(|p| p.is_file());
+        let Some(found) = found else {
+            return Err(SourceError::NotFound { path: requested_path, chain: chain(loader) });
+        };
+        let canonical = found.canonicalize().unwrap_or(found.clone());
+        let name = found.to_string_lossy().trim_start_matches("./").to_string();
+        if let Some(existing) = self.files.iter().position(|f| f.path.as_ref() == Some(&canonical)) {
+            // includes made by macro expansions belong to the file the macro was expanded in
+            let in_chain = loader.chain.iter().any(|&(file, line)| self.location(file, line).0 == existing);
+            if in_chain {
+                return Err(SourceError::Cycle { path: name, chain: chain(loader) });
+            }
+            return Err(SourceError::Duplicate { path: name, chain: chain(loader) });
+        }
+        let content = std::fs::read_to_string(&found)
+            .map_err(|_| SourceError::NotFound { path: requested_path.clone(), chain: chain(loader) })?;
+        self.files.push(SourceFile { name, path: Some(canonical), content, expansion: None, code: String::new(), splices: Vec::new() });
+        let included = self.files.len() - 1;
+        self.files[idx].splices.push((line, included));
+        self.process(included, loader)?;
+        loader.chain.pop();
+        Ok(())
+    }
+
+    fn macro_error(&self, message: &str, file: usize, line: usize) -> SourceError {
+        SourceError::Macro { message: message.to_string(), trace: self.trace(file, line) }
+    }
+
+    fn block_error(&self, message: &str, file: usize, line: usize) -> SourceError {
+        SourceError::Block { message: message.to_string(), trace: self.trace(file, line) }
+    }
+
+    /// source locations of a line, starting with the line itself (inside a macro definition for
+    /// expanded code) followed by every invocation it was expanded from
+    pub fn trace(&self, file: usize, line: usize) -> Vec<(String, usize)> {
+        let mut out = Vec::new();
+        let (mut file, mut line) = (file, line);
+        while let Some(expansion) = &self.files[file].expansion {
+            let (body_file, body_line) = expansion.body;
+            out.push((self.files[body_file].name.clone(), body_line + line - 1));
+            (file, line) = expansion.invocation;
+        }
+        out.push((self.files[file].name.clone(), line));
+        out
+    }
+
+    /// `(file, line)` in a real source file, expanded code maps to the outermost invocation
+    pub fn location(&self, file: usize, line: usize) -> (usize, usize) {
+        let (mut file, mut line) = (file, line);
+        while let Some(expansion) = &self.files[file].expansion {
+            (file, line) = expansion.invocation;
+        }
+        (file, line)
+    }
+
+    /// `(file, line)` whose text is shown for a line, the macro definition for expanded code
+    fn text_location(&self, file: usize, line: usize) -> (usize, usize) {
+        match &self.files[file].expansion {
+            Some(expansion) => (expansion.body.0, expansion.body.1 + line - 1),
+            None => (file, line),
+        }
+    }
+
+    /// text of a line, the line of the macro definition for expanded code
+    pub fn line_text(&self, file: usize, line: usize) -> &str {
+        let (file, line) = self.text_location(file, line);
+        self.files[file].content.lines().nth(line - 1).unwrap_or("")
+    }
+
+    /// true for the code generated by a control-flow directive
+    pub fn is_directive(&self, file: usize) -> bool {
+        self.files[file].expansion.as_ref().is_some_and(|e| DIRECTIVES.contains(&e.name.as_str()))
+    }
+
+    /// text of a line as it was assembled, with the arguments of an expanded macro substituted
+    pub fn expanded_text(&self, file: usize, line: usize) -> &str {
+        self.files[file].content.lines().nth(line - 1).unwrap_or("")
+    }
+
+    /// line a diagnostic is reported at, code generated by a control-flow directive is
+    /// reported at the directive
+    fn reported_line(&self, file: usize, line: usize) -> (usize, usize) {
+        let (mut file, mut line) = (file, line);
+        while let Some(expansion) = self.files[file].expansion.as_ref().filter(|e| e.body.0 == file) {
+            (file, line) = expansion.invocation;
+        }
+        (file, line)
+    }
+
+    /// `(file, line)` of every line a diagnostic for a line points at, in the order of its
+    /// trace: the macro definition line for expanded code, then every invocation
+    pub fn reported_at(&self, file: usize, line: usize) -> Vec<(usize, usize)> {
+        let (mut file, mut line) = self.reported_line(file, line);
+        let mut out = Vec::new();
+        while let Some(expansion) = &self.files[file].expansion {
+            out.push((expansion.body.0, expansion.body.1 + line - 1));
+            (file, line) = expansion.invocation;
+        }
+        out.push((file, line));
+        out
+    }
+
+    /// Diagnostic for a line of `file`, underlining `span` when it is a slice of that line
+    /// and the code of the whole line otherwise
+    pub fn diagnostic(&self, severity: Severity, message: impl Into<String>, file: usize, line: usize, span: Option<&str>) -> Diagnostic {
+        let (file, line) = self.reported_line(file, line);
+        let trace = self.trace(file, line);
+        let (text_file, text_line) = self.text_location(file, line);
+        let snippet = self.files[text_file].content.lines().nth(text_line - 1).unwrap_or("").to_string();
+        let code = strip_comment(&snippet);
+        let indent = code.len() - code.trim_start().len();
+        let mut column = code[..indent].chars().count() + 1;
+        let mut len = code.trim().chars().count();
+        if let Some(span) = span {
+            let source = &self.files[file].code;
+            let line_start: usize = source.split('\n').take(line - 1).map(|l| l.len() + 1).sum();
+            let line_end = source[line_start.min(source.len())..].find('\n').map_or(source.len(), |p| line_start + p);
+            let start = source.as_ptr() as usize;
+            let in_line = (start + line_start..=start + line_end).contains(&(span.as_ptr() as usize))
+                && span.as_ptr() as usize + span.len() <= start + line_end;
+            let located = if self.files[file].expansion.is_none() && in_line {
+                Some(source[line_start..source.offset(span)].chars().count())
+            } else {
+                // expanded code differs from the definition, look the text up instead
+                snippet.find(span).map(|idx| snippet[..idx].chars().count())
+            };
+            if let Some(offset) = located {
+                column = offset + 1;
+                len = span.chars().count();
+                // underline the sigil of labels and data identifiers too
+                if offset > 0 && matches!(snippet.chars().nth(offset - 1), Some('.' | '$')) {
+                    column -= 1;
+                    len += 1;
+                }
+            }
+        }
+        Diagnostic {
+            severity,
+            message: message.into(),
+            file: trace[0].0.clone(),
+            line: trace[0].1,
+            column,
+            len,
+            snippet,
+            notes: trace[1..].iter().map(|(file, line)| format!("expanded from {}:{}", file, line)).collect(),
+        }
+    }
+
+    /// names of all files, indexed like `LineToken::file`
+    pub fn names(&self) -> Vec<String> {
+        self.files.iter().map(|f| f.name.clone()).collect()
+    }
+
+    /// tokens of the whole program with every `@include` and macro invocation replaced by the
+    /// tokens of the included file or expansion, together with an error for every line that
+    /// cannot be parsed
+    pub fn tokens(&self) -> (Vec<LineToken<'_>>, Vec<Diagnostic>) {
+        let mut out = Vec::new();
+        let mut diagnostics = Vec::new();
+        self.splice(0, &mut out, &mut diagnostics);
+        (out, diagnostics)
+    }
+
+    fn splice<'a>(&'a self, idx: usize, out: &mut Vec<LineToken<'a>>, diagnostics: &mut Vec<Diagnostic>) {
+        let file = &self.files[idx];
+        let (tokens, unparsed) = parse_lines(&file.code);
+        for rest in unparsed {
+            let line = file.code[..file.code.offset(rest)].matches('\n').count() + 1;
+            let line_text = file.code.lines().nth(line - 1).unwrap_or("").trim();
+            let message = if line_text.starts_with(rest) {
+                format!("unable to parse '{}'", rest)
+            } else {
+                format!("unexpected '{}'", rest)
+            };
+            diagnostics.push(self.diagnostic(Severity::Error, message, idx, line, Some(rest)));
+        }
+        let mut splices = file.splices.iter().peekable();
+        for mut token in tokens {
+            while let Some((_, spliced)) = splices.next_if(|(line, _)| *line < token.line) {
+                self.splice(*spliced, out, diagnostics);
+            }
+            token.file = idx;
+            out.push(token);
+        }
+        for (_, spliced) in splices {
+            self.splice(*spliced, out, diagnostics);
+        }
+    }
+}
@@ -1,16 +1,104 @@
 #[derive(Debug)]
 pub enum MetaType<'a> {
-    Org(u32),
+    Org(ConstValue<'a>),
+    /// pad the data section to a multiple of a number of cells
+    Align(ConstValue<'a>),
     Include(&'a str),
+    /// named constant expression
+    Define(&'a str, Expr<'a>),
+    /// export a label or data identifier from an object
+    Global(SymbolName<'a>),
+    /// label or data identifier defined in another object
+    Extern(SymbolName<'a>),
+}
+
+/// Reference to a label (`.name`) or a data identifier (`$name`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolName<'a> {
+    /// name without the leading `.`, so local labels start with `.` and anonymous
+    /// references are `@f` or `@b`
+    Label(&'a str),
+    Data(&'a str),
+}
+
+/// Anonymous label definition, `@f` refers to the next one and `@b` to the previous one
+pub const ANONYMOUS_LABEL: &str = "@@";
+
+/// Labels written `..name` are local to the last label that is not local
+pub fn is_local_label(label: &str) -> bool {
+    label.starts_with('.')
+}
+
+/// Start of the labels generated for `@if` and `@while`, reserved for them
+pub const BLOCK_LABEL_PREFIX: &str = "__";
+
+/// Labels generated for `@if` and `@while` belong to no scope, so a block may contain labels that are not local
+pub fn is_block_label(label: &str) -> bool {
+    label.starts_with(BLOCK_LABEL_PREFIX)
+}
+
+/// `Some(true)` for `@f`, `Some(false)` for `@b`
+pub fn anonymous_direction(label: &str) -> Option<bool> {
+    if label.eq_ignore_ascii_case("@f") {
+        Some(true)
+    } else if label.eq_ignore_ascii_case("@b") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    And,
+    Or,
+}
+
+/// Constant expression, evaluated by the compiler once `@define`s, labels and data
+/// identifiers are known
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr<'a> {
+    Number(u32),
+    /// name given with `@define`
+    Define(&'a str),
+    /// address of a label or data identifier
+    Symbol(SymbolName<'a>),
+    /// bitwise not (`~`)
+    Not(Box<Expr<'a>>),
+    /// two's complement negation (`-`)
+    Neg(Box<Expr<'a>>),
+    /// number of memory cells taken by a data identifier (`sizeof $name`)
+    SizeOf(&'a str),
+    /// number of values of a data identifier, in units of its data type (`lengthof $name`)
+    LengthOf(&'a str),
+    Binary(BinaryOp, Box<Expr<'a>>, Box<Expr<'a>>),
 }
 
 #[derive(Debug, Clone)]
 pub enum ConstValue<'a> {
     Number(u32),
-    Label(&'a str)
+    Label(&'a str),
+    /// anything but a plain number or label
+    Expr(Expr<'a>),
 }
 
-#[derive(Debug, Clone)]
+impl<'a> ConstValue<'a> {
+    pub fn into_expr(self) -> Expr<'a> {
+        match self {
+            ConstValue::Number(n) => Expr::Number(n),
+            ConstValue::Label(label) => Expr::Symbol(SymbolName::Label(label)),
+            ConstValue::Expr(expr) => expr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataType {
     Byte,
     Word,
@@ -19,27 +107,47 @@ pub enum DataType {
 
 #[derive(Debug, Clone)]
 pub enum DataValue<'a> {
-    Number(u32),
+    Number(ConstValue<'a>),
+    /// text between the quotes, escape sequences are not decoded yet
     String(&'a str),
+    /// value repeated a number of times (`value times count`)
+    Times(Box<DataValue<'a>>, ConstValue<'a>),
 }
 
 #[derive(Debug)]
 pub enum DataAddressOffset<'a> {
     Zero(&'a str),
-    Const(&'a str, u32),
+    Const(&'a str, ConstValue<'a>),
     Reg(&'a str, u32),
 }
 
+/// Where a jump goes
+#[derive(Debug, Clone)]
+pub enum JumpOperand<'a> {
+    /// label or constant address
+    Const(ConstValue<'a>),
+    /// address held in a register
+    Reg(u32),
+    /// address stored in memory, `&addr` or `[$id + const]`
+    Addr(ConstValue<'a>),
+    /// address stored in memory at `[$id + register]`
+    IdOffsetReg(&'a str, u32),
+}
+
 #[derive(Debug, Clone)]
 pub enum Cmd<'a> {
     PushConst(ConstValue<'a>),
     PushReg(u32),
-    PushAddr(u32),
+    PushAddr(ConstValue<'a>),
     PushIdAddress(&'a str),
-    PushIdValueConst(&'a str, u32),
+    PushIdValueConst(&'a str, ConstValue<'a>),
     PushIdValueReg(&'a str, u32),
     PopReg(u32),
-    PopAddr(u32),
+    PopAddr(ConstValue<'a>),
+    /// pop into `[$id + register]`
+    PopIdValueReg(&'a str, u32),
+    /// pop into the address held in a register
+    PopAddrReg(u32),
     Add,
     Drop,
     Sub,
@@ -49,17 +157,17 @@ pub enum Cmd<'a> {
     MoveAddr(u32, u32),
     MoveAddrReg(u32, u32),
     MoveIdAddress(u32, &'a str),
-    MoveIdValueConst(u32, &'a str, u32),
+    MoveIdValueConst(u32, &'a str, ConstValue<'a>),
     MoveIdValueReg(u32, &'a str, u32),
-    StoreConst(u32, ConstValue<'a>),
+    StoreConst(ConstValue<'a>, ConstValue<'a>),
     StoreReg(u32, u32),
-    Jmp(&'a str),
-    Jnz(&'a str),
-    Jz(&'a str),
-    Jg(&'a str),
-    Jge(&'a str),
-    Jl(&'a str),
-    Jle(&'a str),
+    Jmp(JumpOperand<'a>),
+    Jnz(JumpOperand<'a>),
+    Jz(JumpOperand<'a>),
+    Jg(JumpOperand<'a>),
+    Jge(JumpOperand<'a>),
+    Jl(JumpOperand<'a>),
+    Jle(JumpOperand<'a>),
     And,
     Or,
     Xor,
@@ -68,21 +176,24 @@ pub enum Cmd<'a> {
     Div,
     Inc(u32),
     Dec(u32),
-    ShrConst(u32),
+    ShrConst(ConstValue<'a>),
     ShrReg(u32),
-    ShlConst(u32),
+    ShlConst(ConstValue<'a>),
     ShlReg(u32),
     CallConst(ConstValue<'a>),
     CallReg(u32),
-    CallAddr(u32),
+    CallAddr(ConstValue<'a>),
+    /// call the address stored at `[$id + register]`
+    CallIdValueReg(&'a str, u32),
     SafeCallConst(ConstValue<'a>),
     SafeCallReg(u32),
-    SafeCallAddr(u32),
+    SafeCallAddr(ConstValue<'a>),
+    SafeCallIdValueReg(&'a str, u32),
     Ret,
     Dup,
-    DupConst(u32),
+    DupConst(ConstValue<'a>),
     DupReg(u32),
-    Int(u32, u32),
+    Int(ConstValue<'a>, ConstValue<'a>),
     Term,
 }
 
@@ -94,3 +205,12 @@ pub enum Token<'a> {
     Section(&'a str),
     DataDef(&'a str, DataType, Vec<DataValue<'a>>),
 }
+
+/// A token together with the source file and line it was parsed from
+#[derive(Debug)]
+pub struct LineToken<'a> {
+    /// index of the file in `SourceSet::files` (always 0 straight out of the parser)
+    pub file: usize,
+    pub line: usize,
+    pub token: Token<'a>,
+}
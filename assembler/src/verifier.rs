@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use machine::internal::opcode::{Opcode, OpcodeVariant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Stack problem found by `verify_stack`, at the instruction starting at `pos`
+pub struct StackProblem {
+    pub pos: usize,
+    pub message: String,
+}
+
+/// Assembled text to verify
+pub struct Program<'a> {
+    /// text words with every address resolved
+    pub text: &'a [u32],
+    /// address of `text[0]`
+    pub origin: u32,
+    /// position of every instruction in `text`, in ascending order
+    pub instructions: &'a [usize],
+    /// labels by full name, local labels are stored as `scope.name`
+    pub labels: &'a HashMap<String, usize>,
+}
+
+/// What an instruction does to the stack and to control flow
+pub(crate) enum Step {
+    /// pops `pops` values, then leaves the depth changed by `net`
+    Stack { pops: i64, net: i64 },
+    Jump { target: Option<usize>, conditional: bool },
+    Call(Option<usize>),
+    Ret,
+    /// `term`, or an `int` that stops the machine
+    Stop,
+    /// pops `pops` values and then changes the depth by an amount only known at runtime
+    Unknown { pops: i64 },
+}
+
+/// Stack effect of a procedure, from its entry to its `ret`
+#[derive(Clone, Copy)]
+struct Summary {
+    /// values popped below the depth at the entry
+    needs: i64,
+    /// depth at the first `ret` reached, relative to the entry; `None` when no `ret` is reached
+    effect: Option<i64>,
+}
+
+/// pops and net change of the interrupt functions of the IO module
+fn interrupt_step(module: u32, function: u32) -> Step {
+    match (module, function) {
+        (0, 0 | 3..=8) => Step::Stack { pops: 1, net: -1 },
+        // counted prints pop the count, then that many values; `print until` pops up to a value
+        (0, 1 | 2) => Step::Unknown { pops: 1 },
+        _ => Step::Stop,
+    }
+}
+
+impl Program<'_> {
+    /// text position of an address, when it is the start of an instruction
+    fn position(&self, address: u32) -> Option<usize> {
+        let pos = address.checked_sub(self.origin)? as usize;
+        self.instructions.binary_search(&pos).ok().map(|_| pos)
+    }
+
+    /// effect of the instruction at `pos`
+    pub(crate) fn step(&self, pos: usize) -> Step {
+        let text = self.text;
+        let operand = |n: usize| text.get(pos + 1 + n).copied().unwrap_or(0);
+        let Ok((opcode, variant)) = Opcode::extract(text[pos]) else {
+            return Step::Stop;
+        };
+        match (opcode, variant) {
+            (Opcode::Push, _) => Step::Stack { pops: 0, net: 1 },
+            (Opcode::Pop | Opcode::Drop, _) => Step::Stack { pops: 1, net: -1 },
+            (Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::And | Opcode::Or | Opcode::Xor, _) => Step::Stack { pops: 2, net: -1 },
+            (Opcode::Swap, _) => Step::Stack { pops: 2, net: 0 },
+            (Opcode::Not | Opcode::SHR | Opcode::SHL, _) => Step::Stack { pops: 1, net: 0 },
+            (Opcode::Move | Opcode::Store | Opcode::Inc | Opcode::Dec, _) => Step::Stack { pops: 0, net: 0 },
+            (Opcode::Dup, OpcodeVariant::Default) => Step::Stack { pops: 1, net: 1 },
+            (Opcode::Dup, OpcodeVariant::DupConst) => match operand(0) {
+                0 => Step::Stack { pops: 0, net: 0 },
+                n => Step::Stack { pops: 1, net: n as i64 },
+            },
+            (Opcode::Dup, _) => Step::Unknown { pops: 0 },
+            (Opcode::Int, _) => interrupt_step(operand(0), operand(1)),
+            (Opcode::Jump, variant) => match variant.indirect_jump() {
+                Some((condition, _)) => Step::Jump { target: None, conditional: condition != OpcodeVariant::Default },
+                None => Step::Jump { target: self.position(operand(0)), conditional: variant != OpcodeVariant::Default },
+            },
+            (Opcode::Call, OpcodeVariant::CallConst) | (Opcode::SafeCall, OpcodeVariant::SafeCallConst) => Step::Call(self.position(operand(0))),
+            (Opcode::Call | Opcode::SafeCall, _) => Step::Call(None),
+            (Opcode::Ret, _) => Step::Ret,
+            (Opcode::Terminate, _) => Step::Stop,
+        }
+    }
+
+    /// `.label` at a position, preferring labels that are not local
+    pub(crate) fn name(&self, pos: usize) -> String {
+        let mut names: Vec<&String> = self.labels.iter().filter(|(_, p)| **p == pos).map(|(n, _)| n).collect();
+        names.sort_by_key(|n| (n.contains('.'), n.len(), n.as_str()));
+        match names.first() {
+            Some(name) => format!("'.{}'", name),
+            None => format!("the procedure at 0x{:08x}", pos as u32 + self.origin),
+        }
+    }
+
+    /// instruction after the one at `pos`
+    pub(crate) fn next(&self, pos: usize) -> Option<usize> {
+        let idx = self.instructions.binary_search(&pos).ok()?;
+        self.instructions.get(idx + 1).copied()
+    }
+}
+
+struct Verifier<'a> {
+    program: &'a Program<'a>,
+    /// `None` while the procedure is being verified, so recursion is assumed stack neutral
+    summaries: HashMap<usize, Option<Summary>>,
+    problems: Vec<StackProblem>,
+}
+
+impl Verifier<'_> {
+    fn report(&mut self, pos: usize, message: String) {
+        let problem = StackProblem { pos, message };
+        if !self.problems.contains(&problem) {
+            self.problems.push(problem);
+        }
+    }
+
+    /// Follow every path from `entry`, where `empty` tells whether the stack is known to be
+    /// empty there. Depths are relative to the entry.
+    fn walk(&mut self, entry: usize, empty: bool) -> Summary {
+        let mut summary = Summary { needs: 0, effect: None };
+        let mut depths: HashMap<usize, i64> = HashMap::new();
+        let mut pending = vec![(entry, 0i64, None::<usize>)];
+        while let Some((pos, depth, from)) = pending.pop() {
+            if let Some(&known) = depths.get(&pos) {
+                match from {
+                    Some(from) if known != depth && pos <= from => {
+                        self.report(from, format!("stack leak: every iteration of this loop changes the stack depth by {:+}", depth - known));
+                    },
+                    _ if known != depth => {
+                        self.report(pos, format!("paths reach this instruction with stack depths that differ by {}", (depth - known).abs()));
+                    },
+                    _ => {},
+                }
+                continue;
+            }
+            depths.insert(pos, depth);
+            // values taken from below the entry, an underflow when the stack started empty
+            let mut take = |verifier: &mut Self, pops: i64, what: String| {
+                if pops <= depth {
+                    return true;
+                }
+                if empty {
+                    let message = format!("stack underflow: {} {} value(s) but the stack holds {}", what, pops, depth.max(0));
+                    verifier.report(pos, message);
+                    return false;
+                }
+                summary.needs = summary.needs.max(pops - depth);
+                true
+            };
+            let fallthrough = self.program.next(pos);
+            match self.program.step(pos) {
+                Step::Stack { pops, net } => {
+                    if take(self, pops, "this takes".to_string()) && let Some(next) = fallthrough {
+                        pending.push((next, depth + net, Some(pos)));
+                    }
+                },
+                Step::Unknown { pops } => {
+                    take(self, pops, "this takes".to_string());
+                },
+                Step::Jump { target, conditional } => {
+                    if conditional && let Some(next) = fallthrough {
+                        pending.push((next, depth, Some(pos)));
+                    }
+                    if let Some(target) = target {
+                        pending.push((target, depth, Some(pos)));
+                    }
+                },
+                Step::Call(Some(target)) => {
+                    let callee = self.summary(target);
+                    let what = format!("{} takes", self.program.name(target));
+                    if take(self, callee.needs, what) && let (Some(effect), Some(next)) = (callee.effect, fallthrough) {
+                        pending.push((next, depth + effect, Some(pos)));
+                    }
+                },
+                Step::Call(None) | Step::Stop => {},
+                Step::Ret if empty => {},
+                Step::Ret => {
+                    if depth != 0 {
+                        let (count, more) = (depth.abs(), if depth > 0 { "more" } else { "fewer" });
+                        self.report(pos, format!("'ret' leaves {} {} value(s) on the stack than on entry to {}", count, more, self.program.name(entry)));
+                    }
+                    summary.effect.get_or_insert(depth);
+                },
+            }
+        }
+        summary
+    }
+
+    /// stack effect of the procedure at `entry`, verified the first time it is called
+    fn summary(&mut self, entry: usize) -> Summary {
+        match self.summaries.get(&entry) {
+            Some(Some(summary)) => *summary,
+            Some(None) => Summary { needs: 0, effect: Some(0) },
+            None => {
+                self.summaries.insert(entry, None);
+                let summary = self.walk(entry, false);
+                self.summaries.insert(entry, Some(summary));
+                summary
+            },
+        }
+    }
+}
+
+/// # Stack verifier
+///
+/// Follows every path from `start`, where the stack is empty, through jumps and calls and
+/// reports instructions that pop from an empty stack, loops that change the stack depth on
+/// every iteration, places reached with different depths and `ret`s reached with a different
+/// depth than on entry to their procedure. Procedures may take values pushed by their caller,
+/// which is checked at every call.
+///
+/// Paths are not followed through indirect jumps and calls, or after instructions whose effect
+/// on the stack depends on runtime values such as `dup r0` or `int 0 1`.
+pub fn verify_stack(program: &Program, start: usize) -> Vec<StackProblem> {
+    let mut verifier = Verifier { program, summaries: HashMap::new(), problems: Vec::new() };
+    if program.instructions.binary_search(&start).is_ok() {
+        verifier.walk(start, true);
+    }
+    let mut problems = verifier.problems;
+    problems.sort_by_key(|p| p.pos);
+    problems
+}
@@ -0,0 +1,174 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile, compile_with_options, CompileOptions}, source::{SourceError, SourceSet}};
+
+    use crate::common::run;
+
+    fn error(code: &str) -> String {
+        match SourceSet::load(Some("main.asm"), code.to_string(), &[]) {
+            Err(e @ SourceError::Block { .. }) => e.to_string(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn if_else_and_procedures() {
+        let code = "[text]
+.start
+    push -3
+    call .classify
+    push 0
+    call .classify
+    push 7
+    call .classify
+    pop r2
+    pop r1
+    pop r0
+    term
+
+; 1 for negative numbers, 2 for zero and 3 for positive ones
+@proc classify
+    push 0
+    add
+    @if l
+        drop
+        push 1
+    @else
+        @IF z
+            drop
+            push 2
+        @else
+            drop
+            push 3
+        @endif
+    @endif
+@endp
+";
+        let machine = run(code);
+        let registers: Vec<u32> = (0..3).map(|r| machine.read_register(r).unwrap()).collect();
+        assert_eq!(registers, [1, 2, 3]);
+    }
+
+    #[test]
+    pub fn while_loops() {
+        // sum of 1..=10 and of the odd numbers below 10, the loops test the flags of `pop` and `dec`
+        let code = "[text]
+.start
+    move r0 0
+    move r1 0
+    push 10
+    pop r4
+    @while nz
+        push r0
+        push r4
+        add
+        pop r0
+        push r4
+        push 1
+        and
+        pop r5
+        @if nz
+            push r1
+            push r4
+            add
+            pop r1
+        @endif
+        dec r4
+    @endw
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 55);
+        assert_eq!(machine.read_register(1).unwrap(), 25);
+    }
+
+    #[test]
+    pub fn blocks_inside_macros() {
+        let code = "@macro abs reg
+    push %reg
+    push 0
+    add
+    pop %reg
+    @if l
+        push %reg
+        push 0
+        sub
+        pop %reg
+    @endif
+@endm
+
+[text]
+.start
+    move r0 -5
+    move r1 8
+    abs r0
+    abs r1
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 5);
+        assert_eq!(machine.read_register(1).unwrap(), 8);
+    }
+
+    #[test]
+    pub fn labels_inside_blocks() {
+        // generated labels belong to no scope, so a block may hold labels that are not local
+        let code = "[text]
+.start
+    push 0
+    pop r0
+    @if z
+.inner
+        move r1 7
+..skip
+    @endif
+    @while nz
+..loop
+.other
+    @endw
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(1).unwrap(), 7);
+        let errors: Vec<String> = compile("[text]\n.start\n.__if.1.else\n    term\n".to_string()).unwrap_err().into_iter().map(|e| e.message).collect();
+        assert_eq!(errors, vec!["labels starting with '__' are reserved for '@if' and '@while'"]);
+    }
+
+    #[test]
+    pub fn debug_lines_point_at_directives() {
+        let code = "[text]
+.start
+    push 1
+    pop r0
+    @if nz
+        move r1 1
+    @endif
+    term
+";
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let res = compile_with_options(code.to_string(), &options).unwrap();
+        let debug = res.debug.unwrap();
+        let start = debug.label("start").unwrap().address;
+        // `push 1` and `pop r0` take two words each, the jump of `@if` follows
+        assert_eq!(debug.line_for(start + 4).unwrap().line, 5);
+        assert_eq!(debug.line_for(start + 6).unwrap().line, 6);
+    }
+
+    #[test]
+    pub fn invalid_blocks() {
+        assert_eq!(error("@endif\n"), "main.asm:1: '@endif' without '@if'");
+        assert_eq!(error("@if\n@endif\n"), "main.asm:1: condition expected after '@if'");
+        assert_eq!(error("@while q\n@endw\n"), "main.asm:1: unknown condition 'q' after '@while', expected z, nz, g, ge, l or le");
+        assert_eq!(error("[text]\n@if z\n    push 1\n"), "main.asm:2: '@if' is missing '@endif'");
+        assert_eq!(error("@if z\n@else\n@else\n@endif\n"), "main.asm:3: '@if' of line 1 already has an '@else'");
+        assert_eq!(error("@while nz\n@endif\n"), "main.asm:2: '@endif' found while '@while' of line 1 is still open");
+        assert_eq!(error("@if z\n@proc f\n@endp\n@endif\n"), "main.asm:2: '@proc' cannot be placed inside '@if'");
+        assert_eq!(error("@proc\n"), "main.asm:1: procedure name expected after '@proc'");
+        assert_eq!(error("@proc f x\n@endp\n"), "main.asm:1: unexpected 'x' after '@proc'");
+        assert_eq!(error("@proc f\n@endp 1\n"), "main.asm:2: unexpected '1' after '@endp'");
+        // blocks are closed in the file that opens them
+        assert_eq!(error("@macro open\n    @if z\n@endm\nopen\n@endif\n"), "main.asm:2: '@if' is missing '@endif', expanded from main.asm:4");
+    }
+}
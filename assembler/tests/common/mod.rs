@@ -0,0 +1,25 @@
+//! Helpers shared by the assembler tests
+#![allow(dead_code)]
+
+use assembler::compiler::{compile, CompiledFrame};
+use machine::internal::machine::{Machine, MachineOptions};
+
+/// machine of 1024 cells with a stack of 256 loaded with a compiled program, ready to run it
+pub fn load(res: &CompiledFrame) -> Machine {
+    let mut machine = Machine::new(MachineOptions { memory_cells: 1024, memory_stack_size: 256 }).unwrap();
+    machine.load_data(res.header.origin, &res.binary).unwrap();
+    machine.set_start(res.header.start);
+    machine
+}
+
+/// machine that ran a compiled program to its end
+pub fn execute(res: &CompiledFrame) -> Machine {
+    let mut machine = load(res);
+    machine.execute().unwrap();
+    machine
+}
+
+/// machine that ran `code` compiled with the default options
+pub fn run(code: &str) -> Machine {
+    execute(&compile(code.to_string()).unwrap())
+}
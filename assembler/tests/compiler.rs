@@ -1,13 +1,16 @@
 #[cfg(test)]
 pub mod tests {
     use assembler::compiler::compile;
+    use binary::{errors::FormatError, image::{Image, MAX_CELLS}};
     use machine::internal::machine::Machine;
 
     #[test]
     pub fn compile_code() {
         let code = r#"
         @ORG 32
-        
+
+        [text]
+        .start
         CALL .print
         CALL .print
         CALL .print
@@ -33,10 +36,10 @@ pub mod tests {
             INT 0 2
             RET
         "#;
-        let res = compile(code.to_string());
+        let res = compile(code.to_string()).unwrap();
         let mut machine = Machine::new(machine::internal::machine::MachineOptions { memory_cells: 2048, memory_stack_size: 1024 }).unwrap();
-        machine.load_data(res.origin, &res.binary).unwrap();
-        machine.set_origin(res.origin);
+        machine.load_data(res.header.origin, &res.binary).unwrap();
+        machine.set_start(res.header.start);
         machine.execute().unwrap();
         assert_eq!(machine.read_register(0).unwrap(), 30);
     }
@@ -45,6 +48,8 @@ pub mod tests {
     pub fn jump_test() {
         let code = r#"
         @ORG 32
+        [text]
+        .start
         PUSH 10
         PUSH 20
         SUB
@@ -56,11 +61,85 @@ pub mod tests {
         move r0 1998
         term
         "#;
-        let res = compile(code.to_string());
+        let res = compile(code.to_string()).unwrap();
         let mut machine = Machine::new(machine::internal::machine::MachineOptions { memory_cells: 2048, memory_stack_size: 1024 }).unwrap();
-        machine.load_data(res.origin, &res.binary).unwrap();
-        machine.set_origin(res.origin);
+        machine.load_data(res.header.origin, &res.binary).unwrap();
+        machine.set_start(res.header.start);
         machine.execute().unwrap();
         assert_eq!(machine.read_register(0).unwrap(), 1998);
     }
+
+    #[test]
+    pub fn compiled_image() {
+        let code = r#"
+        @org 8
+        [data]
+        $msg dw 1 2
+        [text]
+        .start
+        push $msg
+        term
+        "#;
+        let res = compile(code.to_string()).unwrap();
+        let image = res.image(64).unwrap();
+        assert_eq!(image.origin, 8);
+        assert_eq!(image.start, 8);
+        assert_eq!(image.text.len(), 3);
+        assert_eq!(image.data, vec![1, 2]);
+        assert_eq!(image.min_cells, 8 + 5 + 64);
+        assert_eq!(image.memory(), res.binary);
+        // stacks that do not fit in memory are reported instead of written into unloadable images
+        assert!(matches!(res.image(u32::MAX), Err(FormatError::TooLarge(_))));
+        assert!(matches!(res.image(200_000_000), Err(FormatError::TooLarge(_))));
+        assert_eq!(Image::read(&res.image(MAX_CELLS - 13).unwrap().write()).unwrap().min_cells, MAX_CELLS);
+    }
+
+    #[test]
+    pub fn labels_in_data() {
+        let code = r#"
+        [data]
+        $handlers dw .on_a .on_b .on_c
+        $values dw 5 6 7
+        $pointers dw $values + 1 .on_c - .on_a 3
+        [text]
+        .start
+            move r0 0
+            move r1 2
+            call [$handlers + r1]
+            move r1 0
+            safecall [$handlers + r1]
+            call [$handlers + 1]
+            move r2 [$pointers]
+            move r3 &r2
+            move r4 [$pointers + 1]
+            term
+        .on_a
+            inc r0
+            ret
+        .on_b
+            push r0
+            push 10
+            add
+            pop r0
+            ret
+        .on_c
+            push r0
+            push 100
+            add
+            pop r0
+            ret
+        "#;
+        let res = compile(code.to_string()).unwrap();
+        let mut machine = Machine::new(machine::internal::machine::MachineOptions { memory_cells: 2048, memory_stack_size: 1024 }).unwrap();
+        machine.load_data(res.header.origin, &res.binary).unwrap();
+        machine.set_start(res.header.start);
+        machine.execute().unwrap();
+        // the safecall restores r0 after `.on_a`
+        assert_eq!(machine.read_register(0).unwrap(), 110);
+        assert_eq!(machine.read_register(3).unwrap(), 6);
+        assert_eq!(machine.read_register(4).unwrap(), 11);
+
+        let errors = compile("[data]\n$a b .start\n[text]\n.start\nterm\n".to_string()).unwrap_err();
+        assert_eq!(errors[0].message, "labels and data identifiers cannot be used here");
+    }
 }
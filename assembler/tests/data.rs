@@ -0,0 +1,182 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile, compile_with_options, CompileOptions}, parser::{parse_char_literal, unescape}};
+    use machine::internal::interrupts::io::{read_byte_string, read_half_word_string};
+
+    use crate::common::run;
+
+    fn errors(code: &str) -> Vec<String> {
+        compile(code.to_string()).unwrap_err().into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    pub fn escapes_and_char_literals() {
+        assert_eq!(unescape(r#"a\n\t\0\\\"\'\x41"#).unwrap(), "a\n\t\0\\\"'A");
+        assert_eq!(unescape(r"\q").unwrap_err(), "unknown escape sequence '\\q'");
+        assert_eq!(parse_char_literal("'A'").unwrap().1, 65);
+        assert_eq!(parse_char_literal(r"'\n'").unwrap().1, 10);
+        assert_eq!(parse_char_literal(r"'\''").unwrap().1, 39);
+
+        let code = "[data]
+$msg dw \"say \\\"hi\\\"\\n\"
+$semi b \"a;b\" ; comment
+[text]
+.start
+    move r0 [$msg + 4]
+    move r1 [$msg + 8]
+    move r2 'A' + 1
+    move r3 [$semi]
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), '"' as u32);
+        assert_eq!(machine.read_register(1).unwrap(), '\n' as u32);
+        assert_eq!(machine.read_register(2).unwrap(), 66);
+        assert_eq!(machine.read_register(3).unwrap(), 0x613b6200);
+    }
+
+    #[test]
+    pub fn negative_numbers() {
+        let code = "@define OFFSET -2
+[data]
+$values dw -1 OFFSET -(3 * 4)
+$bytes b -1 -128 127
+$words w -32768 0xffff
+[text]
+.start
+    move r0 [$values]
+    move r1 [$values + 1]
+    move r5 [$values + 2]
+    move r2 [$bytes]
+    move r3 [$words]
+    move r4 -OFFSET
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), u32::MAX);
+        assert_eq!(machine.read_register(1).unwrap(), -2i32 as u32);
+        assert_eq!(machine.read_register(2).unwrap(), 0xff807f00);
+        assert_eq!(machine.read_register(3).unwrap(), 0x8000ffff);
+        assert_eq!(machine.read_register(4).unwrap(), 2);
+        assert_eq!(machine.read_register(5).unwrap(), -12i32 as u32);
+
+        assert_eq!(errors("[data]\n$a b -129 256\n[text]\n.start\n    term\n"), vec![
+            "value -129 does not fit in a byte",
+            "value 256 does not fit in a byte",
+        ]);
+    }
+
+    #[test]
+    pub fn repetition_and_reservations() {
+        let code = "[data]
+$zeros dw 7 times 3
+$mixed b 1 2 times 2 \"ab\" times 2
+$buf resdw 4
+$small resb 5
+$end dw 9
+[text]
+.start
+    move r0 [$zeros + 2]
+    move r1 [$mixed]
+    move r2 [$mixed + 1]
+    push $buf
+    push $end
+    sub
+    pop r3
+    move r4 [$end]
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 7);
+        assert_eq!(machine.read_register(1).unwrap(), 0x01020261);
+        assert_eq!(machine.read_register(2).unwrap(), 0x62616200);
+        // 4 cells of $buf and 2 of $small
+        assert_eq!(machine.read_register(3).unwrap(), 6);
+        assert_eq!(machine.read_register(4).unwrap(), 9);
+
+        assert_eq!(errors("[data]\n$a dw 0 times 0x2000000\n[text]\n.start\n    term\n"), vec![
+            "repetition count 33554432 is too large",
+        ]);
+    }
+
+    #[test]
+    pub fn alignment() {
+        let code = "@org 0x10
+[data]
+$a b 1
+@align 4
+$b dw 2
+@align 8
+[text]
+.start
+    term
+";
+        let res = compile(code.to_string()).unwrap();
+        // one text cell, then $a at 0x11, padded up to 0x14 for $b and 0x18 at the end
+        assert_eq!(res.binary.len(), 8);
+        assert_eq!(res.binary[4], 2);
+
+        assert_eq!(errors("[data]\n@align 0\n[text]\n@align 2\n.start\n    term\n"), vec![
+            "alignment must be at least 1",
+            "this belongs in the [data] section, not [text]",
+        ]);
+        // padding is checked before it is allocated
+        assert_eq!(errors("[data]\n$a dw 1\n@align 0x80000000\n$b dw 2\n[text]\n.start\n    term\n"), vec![
+            "aligning to 2147483648 cells does not fit in memory",
+        ]);
+    }
+
+    #[test]
+    pub fn size_operators() {
+        let code = "[data]
+$msg b \"hello\"
+$table dw 1 2 3
+$buf resw 3
+[text]
+.start
+    move r0 sizeof $msg
+    move r1 lengthof $msg
+    move r2 lengthof $table
+    move r3 sizeof $buf
+    move r4 lengthof $buf * 2
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 2);
+        assert_eq!(machine.read_register(1).unwrap(), 5);
+        assert_eq!(machine.read_register(2).unwrap(), 3);
+        assert_eq!(machine.read_register(3).unwrap(), 2);
+        assert_eq!(machine.read_register(4).unwrap(), 6);
+
+        assert_eq!(errors("[data]\n$a b sizeof $a\n[text]\n.start\n    push sizeof $missing\n    term\n"), vec![
+            "'sizeof' cannot be used here",
+            "undefined identifier '$missing'",
+        ]);
+    }
+
+    #[test]
+    pub fn unicode_strings() {
+        let code = "[data]
+$name b \"Pedram é€\" 0
+$wide w \"a€😀\" 0
+$len dw lengthof $text
+$text b \"ünï\"
+[text]
+.start
+    push $name
+    int 0 5
+    push $len
+    int 0 6
+    term
+";
+        let machine = run(code);
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let debug = compile_with_options(code.to_string(), &options).unwrap().debug.unwrap();
+        let address = |name: &str| debug.data.iter().find(|d| d.name == name).unwrap().address;
+        assert_eq!(read_byte_string(&machine.memory, address("name"), false).unwrap(), "Pedram é€");
+        assert_eq!(read_half_word_string(&machine.memory, address("wide"), false).unwrap(), "a€😀");
+        assert_eq!(read_byte_string(&machine.memory, address("len"), true).unwrap(), "ünï");
+    }
+}
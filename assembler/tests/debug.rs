@@ -0,0 +1,62 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile_with_options, CompileOptions}, debug::DebugInfo, tokens::DataType};
+
+    const CODE: &str = r#"@org 16
+[data]
+$msg dw "hi" 0
+
+[text]
+.start
+    push $msg
+    int 0 3
+    call .print
+    term
+
+.print
+    ret
+"#;
+
+    fn debug_info() -> DebugInfo {
+        let options = CompileOptions { debug: true, file: Some("test.asm".to_string()), ..Default::default() };
+        compile_with_options(CODE.to_string(), &options).unwrap().debug.unwrap()
+    }
+
+    #[test]
+    pub fn no_debug_by_default() {
+        let res = compile_with_options(CODE.to_string(), &CompileOptions::default()).unwrap();
+        assert!(res.debug.is_none());
+    }
+
+    #[test]
+    pub fn line_mapping() {
+        let info = debug_info();
+        assert_eq!(info.files, vec!["test.asm".to_string()]);
+        assert_eq!(info.line_for(16).unwrap().line, 7);
+        assert_eq!(info.line_for(19).unwrap().line, 8);
+        assert_eq!(info.line_for(20).unwrap().line, 8);
+        assert_eq!(info.address_for(0, 13), Some(24));
+        assert_eq!(info.describe(19), "test.asm:8 (.start+3)");
+    }
+
+    #[test]
+    pub fn symbols() {
+        let info = debug_info();
+        assert_eq!(info.label("start").unwrap().address, 16);
+        assert_eq!(info.label("print").unwrap().address, 24);
+        let msg = info.data("msg").unwrap();
+        assert_eq!(msg.address, 25);
+        assert_eq!(msg.size, 3);
+        assert_eq!(msg.typ, DataType::DoubleWord);
+        assert_eq!(info.data_for(27).unwrap().name, "msg");
+        assert_eq!(info.line_for(25).unwrap().line, 3);
+    }
+
+    #[test]
+    pub fn sidecar_roundtrip() {
+        let info = debug_info();
+        let parsed = DebugInfo::parse(&info.to_string()).unwrap();
+        assert_eq!(parsed, info);
+        assert!(DebugInfo::parse("garbage").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile_with_options, CompileOptions}, debugger::{Debugger, Reply, Resume, Stop}};
+
+    use crate::common::load;
+
+    const CODE: &str = "[data]
+$count dw 0
+
+[text]
+.start
+    move r0 3
+.loop
+    push r0
+    safecall .square
+    call .bump
+    dec r0
+    jnz .loop
+    term
+
+.square
+    dup
+    mul
+    drop
+    ret
+
+.bump
+    push [$count]
+    push 1
+    add
+    pop [$count + 0]
+    ret
+";
+
+    fn debugger() -> Debugger {
+        let options = CompileOptions { debug: true, file: Some("test.asm".to_string()), ..Default::default() };
+        let result = compile_with_options(CODE.to_string(), &options).unwrap();
+        Debugger::new(load(&result), result.debug)
+    }
+
+    fn output(debugger: &mut Debugger, command: &str) -> String {
+        match debugger.command(command).unwrap() {
+            Reply::Output(text) => text,
+            Reply::Quit => panic!("'{}' quit", command),
+        }
+    }
+
+    #[test]
+    pub fn breakpoints_and_backtrace() {
+        let mut debugger = debugger();
+        let square = debugger.code_address(".square").unwrap();
+        assert_eq!(debugger.code_address("test.asm:16"), Ok(square));
+        assert_eq!(debugger.code_address("15"), Ok(square));
+        assert_eq!(output(&mut debugger, "break .square"), format!("breakpoint 1 at 0x{:08x} test.asm:16 (.square)", square));
+        assert!(matches!(debugger.resume(Resume::Continue), Stop::Breakpoint { id: 1, .. }));
+        assert_eq!(debugger.machine().register.pc, square);
+        let backtrace = output(&mut debugger, "bt");
+        let lines: Vec<&str> = backtrace.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("#1 ") && lines[1].ends_with("test.asm:9 (.loop+3)"), "{}", lines[1]);
+        assert_eq!(output(&mut debugger, "stack"), "<1> 3");
+        assert!(matches!(debugger.resume(Resume::Continue), Stop::Breakpoint { id: 1, .. }));
+        assert_eq!(output(&mut debugger, "p r0"), "r0 = 0x00000002 2");
+        assert_eq!(output(&mut debugger, "delete 1"), "deleted 1");
+        assert!(matches!(debugger.resume(Resume::Continue), Stop::Terminated));
+        assert!(!debugger.running());
+        assert!(debugger.command("step").is_err());
+    }
+
+    #[test]
+    pub fn step_next_and_finish() {
+        let mut debugger = debugger();
+        output(&mut debugger, "step 2");
+        let call = debugger.machine().register.pc;
+        assert_eq!(debugger.info().unwrap().line_for(call).unwrap().line, 9);
+        // `next` runs the whole safecall and stops at the following instruction
+        assert!(matches!(debugger.resume(Resume::Next), Stop::Done));
+        assert_eq!(debugger.info().unwrap().line_for(debugger.machine().register.pc).unwrap().line, 10);
+        assert!(output(&mut debugger, "step").starts_with(&format!("0x{:08x}", debugger.code_address(".bump").unwrap())));
+        assert_eq!(debugger.backtrace().len(), 2);
+        output(&mut debugger, "finish");
+        assert_eq!(debugger.backtrace().len(), 1);
+        assert_eq!(debugger.info().unwrap().line_for(debugger.machine().register.pc).unwrap().line, 11);
+        assert!(debugger.command("finish").is_err());
+        // an empty line repeats the previous command
+        output(&mut debugger, "next");
+        let pc = debugger.machine().register.pc;
+        output(&mut debugger, "");
+        assert_ne!(debugger.machine().register.pc, pc);
+    }
+
+    #[test]
+    pub fn watchpoints() {
+        let mut debugger = debugger();
+        assert_eq!(output(&mut debugger, "watch $count"), "watchpoint 1: $count");
+        let stop = debugger.resume(Resume::Continue);
+        assert!(matches!(stop, Stop::Watchpoint { id: 1, old: 0, new: 1, .. }), "{:?}", stop);
+        assert!(debugger.describe_stop(&stop).starts_with("watchpoint 1: $count\nold value = 0\nnew value = 1\n"));
+        assert_eq!(output(&mut debugger, "p $count"), "$count = 1");
+        let stop = debugger.resume(Resume::Continue);
+        assert!(matches!(stop, Stop::Watchpoint { old: 1, new: 2, .. }), "{:?}", stop);
+        // starting over resets the watched value
+        debugger.restart();
+        assert!(matches!(debugger.resume(Resume::Continue), Stop::Watchpoint { old: 0, new: 1, .. }));
+        assert_eq!(output(&mut debugger, "info breakpoints"), "1\twatchpoint\t$count = 1");
+    }
+
+    #[test]
+    pub fn inspecting_state() {
+        let mut debugger = debugger();
+        let count = debugger.data_address("$count").unwrap();
+        assert_eq!(count.1, Some(1));
+        assert_eq!(debugger.data_address("$count+1"), Ok((count.0 + 1, None)));
+        // `move r0 3`
+        assert!(output(&mut debugger, "x/3 0").ends_with(" 00000000 00000003"));
+        assert_eq!(output(&mut debugger, "x 0 3"), output(&mut debugger, "x/3 0"));
+        assert_eq!(output(&mut debugger, "info flags"), "zero=0 negative=0 overflow=0 carry=0");
+        assert!(output(&mut debugger, "info registers").ends_with("pc = 0x00000000 0"));
+        assert!(debugger.command("break .nowhere").is_err());
+        assert!(debugger.command("frobnicate").is_err());
+        assert_eq!(debugger.command("quit"), Ok(Reply::Quit));
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::compile, diagnostic::{Diagnostic, Severity}};
+
+    fn errors(code: &str) -> Vec<Diagnostic> {
+        compile(code.to_string()).unwrap_err()
+    }
+
+    #[test]
+    pub fn reports_every_error() {
+        let code = "[text]
+.start
+    push .nowhere
+    push [$missing]
+    move r0 UNKNOWN
+    term
+";
+        let errors = errors(code);
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().all(|e| e.severity == Severity::Error));
+        assert_eq!(errors.iter().map(|e| e.line).collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(errors[0].message, "undefined label '.nowhere'");
+        assert_eq!(errors[1].message, "undefined identifier '$missing'");
+        assert_eq!(errors[2].message, "undefined constant 'UNKNOWN'");
+    }
+
+    #[test]
+    pub fn caret_under_offending_text() {
+        let errors = errors("[text]\n.start\n    push .nowhere\n    term\n");
+        assert_eq!((errors[0].column, errors[0].len), (10, 8));
+        assert_eq!(errors[0].to_string(), "error: undefined label '.nowhere'
+  --> <source>:3:10
+  |
+3 |     push .nowhere
+  |          ^^^^^^^^");
+    }
+
+    #[test]
+    pub fn duplicates_point_at_first_definition() {
+        let code = "@define SIZE 2
+@define SIZE 3
+[data]
+$a dw 1
+$a dw 2
+[text]
+.start
+.start
+    term
+";
+        let errors = errors(code);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[0].notes, vec!["first defined at <source>:1".to_string()]);
+        assert_eq!(errors[1].line, 5);
+        assert_eq!(errors[1].notes, vec!["first defined at <source>:4".to_string()]);
+        assert_eq!(errors[2].line, 8);
+        assert_eq!(errors[2].notes, vec!["first defined at <source>:7".to_string()]);
+    }
+
+    #[test]
+    pub fn unparsed_text() {
+        let errors = errors("[text]\n.start\n    push 1 2\n    frobnicate r1\n    term\n");
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].message.as_str()), (3, "unexpected '2'"));
+        assert_eq!(errors[1].line, 4);
+        assert!(errors[1].message.starts_with("unable to parse"));
+    }
+
+    #[test]
+    pub fn warnings_do_not_fail() {
+        let code = "@org 10
+@org 20
+[data]
+$s b \"hé€\"
+[text]
+.start
+    term
+";
+        let res = compile(code.to_string()).unwrap();
+        assert_eq!(res.header.origin, 20);
+        // non-ASCII text is encoded rather than truncated
+        assert_eq!(res.warnings.len(), 1);
+        assert_eq!(res.warnings[0].severity, Severity::Warning);
+        assert_eq!(res.warnings[0].line, 2);
+    }
+
+    #[test]
+    pub fn errors_inside_macros() {
+        let code = "@macro load
+    push .nowhere
+@endm
+[text]
+.start
+    load
+    term
+";
+        let errors = errors(code);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[0].snippet.trim(), "push .nowhere");
+        assert_eq!(errors[0].notes, vec!["expanded from <source>:6".to_string()]);
+    }
+}
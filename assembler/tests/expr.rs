@@ -0,0 +1,129 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use assembler::{compiler::{compile, compile_object, CompileOptions}, expr::{Base, Evaluator, Value}, parser::{parse_command, parse_expr}, tokens::{Cmd, Expr}};
+    use binary::object::RelocationTarget;
+
+    use crate::common::execute;
+
+    fn eval(code: &str) -> Result<u32, String> {
+        let (rem, expr) = parse_expr(code).unwrap();
+        assert_eq!(rem, "");
+        let defines = HashMap::new();
+        Evaluator::new(&defines, |_| Err("no symbols".to_string())).evaluate(&expr).map(|v| v.value).map_err(|e| e.message)
+    }
+
+    #[test]
+    pub fn precedence_and_operators() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("1 << 4 | 3"), Ok(19));
+        assert_eq!(eval("0xff & ~0x0f"), Ok(0xf0));
+        assert_eq!(eval("~0 >> 28"), Ok(15));
+        assert_eq!(eval("100/7-4"), Ok(10));
+    }
+
+    #[test]
+    pub fn overflow_errors() {
+        assert!(eval("0xffffffff + 1").unwrap_err().contains("overflow"));
+        assert!(eval("1 - 2").unwrap_err().contains("overflow"));
+        assert!(eval("0x10000 * 0x10000").unwrap_err().contains("overflow"));
+        assert!(eval("0x80000000 << 1").unwrap_err().contains("overflow"));
+        assert!(eval("1 >> 32").unwrap_err().contains("overflow"));
+        assert_eq!(eval("1 / 0"), Err("division by zero".to_string()));
+    }
+
+    #[test]
+    pub fn defines_and_symbols() {
+        let (_, a) = parse_expr("SIZE * 2").unwrap();
+        let (_, size) = parse_expr("LOOP").unwrap();
+        let mut defines = HashMap::new();
+        defines.insert("SIZE", Expr::Number(3));
+        let evaluator = Evaluator::new(&defines, |_| Ok(Value { value: 8, base: Base::Local }));
+        assert_eq!(evaluator.evaluate(&a), Ok(Value::absolute(6)));
+        assert!(evaluator.evaluate(&size).is_err());
+
+        let (_, local) = parse_expr(".here + 2").unwrap();
+        assert_eq!(evaluator.evaluate(&local), Ok(Value { value: 10, base: Base::Local }));
+        let (_, distance) = parse_expr(".end - .here").unwrap();
+        assert_eq!(evaluator.evaluate(&distance), Ok(Value::absolute(0)));
+        let (_, scaled) = parse_expr(".here * 2").unwrap();
+        assert!(evaluator.evaluate(&scaled).is_err());
+
+        defines.insert("A", Expr::Define("B"));
+        defines.insert("B", Expr::Define("A"));
+        let evaluator = Evaluator::new(&defines, |_| Err(String::new()));
+        assert!(evaluator.evaluate(&Expr::Define("A")).unwrap_err().message.contains("itself"));
+    }
+
+    #[test]
+    pub fn registers_are_not_constants() {
+        assert!(matches!(parse_command("push r3").unwrap().1, Cmd::PushReg(3)));
+        assert!(matches!(parse_command("push pc").unwrap().1, Cmd::PushReg(100)));
+        assert!(matches!(parse_command("push r3x").unwrap().1, Cmd::PushConst(_)));
+    }
+
+    #[test]
+    pub fn program_with_constants() {
+        let code = "@define SCREEN 0
+@define PRINTNUM 4
+@define COUNT 2 * 2
+@define MASK 0xf0 | 0x0f
+[data]
+$table dw 10 20 30 COUNT * 10
+$small b MASK & 0x7f
+
+[text]
+.start
+    push [$table + COUNT - 1]
+    push $table + (1 << 1)
+    pop r1
+    move r2 [$table + 1]
+    push r2
+    add
+    pop r0
+    move r3 ~MASK >> 24
+    term
+";
+        let res = compile(code.to_string()).unwrap();
+        let machine = execute(&res);
+        assert_eq!(machine.read_register(0).unwrap(), 60);
+        let table = res.header.text_size;
+        assert_eq!(machine.read_register(1).unwrap(), res.header.origin + table + 2);
+        assert_eq!(machine.read_register(3).unwrap(), 0xff);
+        assert_eq!(res.binary[table as usize + 4], 0x7f00_0000);
+    }
+
+    #[test]
+    pub fn data_expression_overflow() {
+        let errors = compile("[data]\n$a dw 0xffffffff + 1\n[text]\n.start\nterm\n".to_string()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("overflow"));
+        assert_eq!(errors[0].line, 2);
+    }
+
+    #[test]
+    pub fn object_relocations() {
+        let code = "@extern .print
+[text]
+.start
+    call .print + 2
+    push .end - .start
+    push .end + 1
+.end
+    term
+";
+        let object = compile_object(code.to_string(), &CompileOptions::default()).unwrap().0;
+        assert_eq!(object.text[1], 2);
+        assert_eq!(object.text[3], 6);
+        assert_eq!(object.text[5], 7);
+        assert_eq!(object.relocations.len(), 2);
+        assert_eq!(object.relocations[0].offset, 1);
+        assert_eq!(object.relocations[0].target, RelocationTarget::Import(0));
+        assert_eq!(object.relocations[1].offset, 5);
+        assert_eq!(object.relocations[1].target, RelocationTarget::Local);
+    }
+}
@@ -0,0 +1,87 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile_with_options, CompileOptions}, format::format_source};
+
+    const MESSY: &str = "
+
+@ORG 0x00
+@MACRO Twice reg   ; doubles
+  INC %reg
+@ENDM
+[DATA]
+$Msg   B \"Hi  ;  there\" 0X0A 0
+$buf RESW 004
+  [text]
+.start   ; entry
+   PUSH   0XFF ; mask
+      Move R0 0B101   ; five
+  Twice R0
+
+
+
+  ; check
+ @IF Z
+        push 'A'
+  @ELSE
+  push ' '
+ @ENDIF
+  jmp @F
+@@
+    TERM ; done
+";
+
+    const FORMATTED: &str = "@org 0x00
+@macro Twice reg  ; doubles
+    inc %reg
+@endm
+[data]
+$Msg b \"Hi  ;  there\" 0x0a 0
+$buf resw 4
+[text]
+.start            ; entry
+    push 0xff     ; mask
+    move r0 0b101 ; five
+    Twice r0
+
+    ; check
+    @if z
+        push 'A'
+    @else
+        push ' '
+    @endif
+    jmp @f
+@@
+    term ; done
+";
+
+    #[test]
+    pub fn canonical_style() {
+        assert_eq!(format_source(MESSY, "messy.asm").unwrap(), FORMATTED);
+        assert_eq!(format_source(FORMATTED, "formatted.asm").unwrap(), FORMATTED);
+    }
+
+    #[test]
+    pub fn comments_are_aligned_per_group() {
+        let code = "[text]\n.start\npush 1 ; one\npush 100 ; hundred\n\nadd ; sum\n; end\nterm\n";
+        let expected = "[text]\n.start\n    push 1   ; one\n    push 100 ; hundred\n\n    add ; sum\n    ; end\n    term\n";
+        assert_eq!(format_source(code, "main.asm").unwrap(), expected);
+    }
+
+    #[test]
+    pub fn formatting_keeps_the_program() {
+        let options = CompileOptions::default();
+        let formatted = format_source(MESSY, "messy.asm").unwrap();
+        let before = compile_with_options(MESSY.to_string(), &options).unwrap();
+        let after = compile_with_options(formatted, &options).unwrap();
+        assert_eq!(before.binary, after.binary);
+    }
+
+    #[test]
+    pub fn invalid_lines_are_reported() {
+        let errors = format_source("[text]\n  push 1 2\n  twice r0\n  @bogus 3\n", "bad.asm").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].line, errors[0].column), (2, 10));
+        assert_eq!(errors[0].message, "unexpected '2'");
+        assert_eq!(errors[1].message, "unable to parse '@bogus 3'");
+    }
+}
@@ -0,0 +1,218 @@
+mod common;
+
+#[cfg(all(test, unix))]
+pub mod tests {
+    use std::{io::{Read, Write}, os::unix::net::UnixStream, thread::JoinHandle};
+
+    use assembler::{compiler::{compile_with_options, CompileOptions}, debug::DebugInfo, debugger::Debugger, gdb::serve};
+
+    use crate::common::load;
+
+    const CODE: &str = "[data]
+$count dw 0
+
+[text]
+.start
+    push 3
+    call .square
+    call .bump
+    term
+
+.square
+    dup
+    mul
+    drop
+    ret
+
+.bump
+    push [$count]
+    push 1
+    add
+    pop [$count + 0]
+    ret
+
+.spin
+    jmp .spin
+";
+
+    /// scripted protocol client of a stub serving `CODE` on another thread
+    struct Client {
+        stream: UnixStream,
+        ack: bool,
+        info: DebugInfo,
+        server: JoinHandle<Debugger>,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            let options = CompileOptions { debug: true, ..Default::default() };
+            let result = compile_with_options(CODE.to_string(), &options).unwrap();
+            let machine = load(&result);
+            let info = result.debug.unwrap();
+            let debugger = Debugger::new(machine, Some(info.clone()));
+            let (stream, server) = UnixStream::pair().unwrap();
+            let server = std::thread::spawn(move || serve(server, debugger).unwrap());
+            Client { stream, ack: true, info, server }
+        }
+
+        fn label(&self, name: &str) -> u32 {
+            self.info.label(name).unwrap().address
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut buf = [0u8; 1];
+            self.stream.read_exact(&mut buf).unwrap();
+            buf[0]
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.stream.write_all(bytes).unwrap();
+        }
+
+        /// send a packet and return the reply
+        fn send(&mut self, packet: &str) -> String {
+            let sum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            self.write(format!("${}#{:02x}", packet, sum).as_bytes());
+            if self.ack {
+                assert_eq!(self.byte(), b'+', "'{}' was not acknowledged", packet);
+            }
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            assert_eq!(self.byte(), b'$');
+            let mut data = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    b'}' => data.push(self.byte() ^ 0x20),
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.byte(), self.byte()];
+            assert_eq!(u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(), data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+            if self.ack {
+                // the stub may have closed the connection after its last reply
+                let _ = self.stream.write_all(b"+");
+            }
+            String::from_utf8(data).unwrap()
+        }
+
+        /// detach and return the debugger as the stub left it
+        fn detach(mut self) -> Debugger {
+            assert_eq!(self.send("D"), "OK");
+            self.server.join().unwrap()
+        }
+    }
+
+    fn word(value: u32) -> String {
+        value.to_le_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    pub fn registers_and_target_description() {
+        let mut client = Client::new();
+        assert!(client.send("qSupported:multiprocess+;swbreak+").contains("qXfer:features:read+"));
+        assert_eq!(client.send("?"), "T0508:00000000;thread:1;");
+        assert_eq!(client.send("g"), "0".repeat(80));
+        assert_eq!(client.send("P1=2a000000"), "OK");
+        assert_eq!(client.send("p1"), "2a000000");
+        assert_eq!(client.send("P9=05000000"), "OK");
+        let registers = client.send("g");
+        assert_eq!(&registers[8..16], "2a000000");
+        assert_eq!(&registers[72..80], "05000000");
+        let registers = format!("{}{}", "01000000".repeat(8), "00000000".repeat(2));
+        assert_eq!(client.send(&format!("G{}", registers)), "OK");
+        assert_eq!(client.send("g"), registers);
+        assert_eq!(client.send("pa"), "E01");
+        let mut xml = String::new();
+        loop {
+            let chunk = client.send(&format!("qXfer:features:read:target.xml:{:x},40", xml.len()));
+            xml.push_str(&chunk[1..]);
+            if chunk.starts_with('l') {
+                break;
+            }
+        }
+        assert!(xml.starts_with("<?xml") && xml.trim_end().ends_with("</target>"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\"/>"));
+        assert_eq!(client.send("qTStatus"), "");
+        let debugger = client.detach();
+        assert_eq!(debugger.machine().register.r7, 1);
+        assert!(!debugger.machine().flag.zero);
+    }
+
+    #[test]
+    pub fn memory() {
+        let mut client = Client::new();
+        let count = client.info.data("count").unwrap().address;
+        // `push 3` in two cells, the first byte of the third
+        let first = client.send("m0,9");
+        assert_eq!(&first[8..16], word(3));
+        assert_eq!(first.len(), 18);
+        assert_eq!(client.send(&format!("M{:x},8:{}{}", count, word(7), word(9))), "OK");
+        assert_eq!(client.send(&format!("m{:x},8", count)), format!("{}{}", word(7), word(9)));
+        assert_eq!(client.send(&format!("M{:x},3:010203", count)), "E01");
+        assert_eq!(client.send("m100000,4"), "E01");
+        // reads stop at the end of memory
+        assert_eq!(client.send("m3ff,8").len(), 8);
+        let debugger = client.detach();
+        assert_eq!(debugger.machine().memory.read(count).unwrap(), 7);
+    }
+
+    #[test]
+    pub fn malformed_packets() {
+        let mut client = Client::new();
+        // empty and unknown packets are not supported, which is no reason to stop serving
+        assert_eq!(client.send(""), "");
+        assert_eq!(client.send("é"), "");
+        assert_eq!(client.send("M0,fffffffffffffffc:"), "E01");
+        assert_eq!(client.send("M0,4:éééé"), "E01");
+        let description = client.send("qXfer:features:read:target.xml:0,ffffffffffffffff");
+        assert!(description.starts_with("l<?xml"), "{}", description);
+        assert_eq!(client.send("qXfer:features:read:target.xml:ffffffffffffffff,ffff"), "l");
+        assert_eq!(client.send("m0,4").len(), 8);
+        client.detach();
+    }
+
+    #[test]
+    pub fn breakpoints_and_stepping() {
+        let mut client = Client::new();
+        let (square, bump) = (client.label("square"), client.label("bump"));
+        assert_eq!(client.send(&format!("Z0,{:x},4", square)), "OK");
+        assert_eq!(client.send("c"), format!("T0508:{};swbreak:;thread:1;", word(square)));
+        assert_eq!(client.send("s"), format!("T0508:{};thread:1;", word(square + 1)));
+        assert_eq!(client.send("vCont?"), "vCont;c;C;s;S");
+        assert_eq!(client.send("vCont;s:1"), format!("T0508:{};thread:1;", word(square + 2)));
+        assert_eq!(client.send(&format!("z0,{:x},4", square)), "OK");
+        assert_eq!(client.send(&format!("Z0,{:x},4", bump)), "OK");
+        assert_eq!(client.send("vCont;c"), format!("T0508:{};swbreak:;thread:1;", word(bump)));
+        assert_eq!(client.send("c"), "W00");
+        assert_eq!(client.send("?"), "W00");
+        client.write(b"$k#6b+");
+        assert!(!client.server.join().unwrap().running());
+    }
+
+    #[test]
+    pub fn watchpoints_interrupts_and_acks() {
+        let mut client = Client::new();
+        // a wrong checksum is asked for again
+        client.write(b"$g#00");
+        assert_eq!(client.byte(), b'-');
+        assert_eq!(client.send("QStartNoAckMode"), "OK");
+        client.ack = false;
+        let count = client.info.data("count").unwrap().address;
+        assert_eq!(client.send(&format!("Z2,{:x},4", count)), "OK");
+        let stop = client.send("c");
+        assert!(stop.starts_with("T05") && stop.contains(&format!("watch:{:x};", count)), "{}", stop);
+        assert_eq!(client.send(&format!("z2,{:x},4", count)), "OK");
+        // a machine that never stops runs until it is interrupted
+        let spin = client.label("spin");
+        client.write(format!("$c{:x}#{:02x}", spin, format!("c{:x}", spin).bytes().fold(0u8, |s, b| s.wrapping_add(b))).as_bytes());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        client.write(&[0x03]);
+        assert_eq!(client.reply(), "T02thread:1;");
+        assert_eq!(client.send("p8"), word(spin));
+        let debugger = client.detach();
+        assert!(debugger.running());
+    }
+}
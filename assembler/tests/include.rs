@@ -0,0 +1,103 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use std::path::{Path, PathBuf};
+
+    use assembler::{compiler::{compile_with_options, CompileOptions}, source::{SourceError, SourceSet}};
+
+    use crate::common::execute;
+
+    /// creates a fresh directory with the given files
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("myvm-include-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn load(root: &Path, include_dirs: &[PathBuf]) -> Result<SourceSet, SourceError> {
+        let content = std::fs::read_to_string(root).unwrap();
+        SourceSet::load(Some(root.to_str().unwrap()), content, include_dirs)
+    }
+
+    #[test]
+    pub fn splice_relative_and_search_dirs() {
+        let dir = fixture("splice", &[
+            ("main.asm", "[text]\n.start\n    push 7\n    call .double\n    call .triple\n    pop r0\n    term\n@include \"lib/double.asm\"\n@include \"triple.asm\"\n"),
+            ("lib/double.asm", "[text]\n.double\n    push 2\n    mul\n    ret\n"),
+            ("inc/triple.asm", "[text]\n.triple\n    push 3\n    mul\n    ret\n"),
+        ]);
+        let root = dir.join("main.asm");
+        let options = CompileOptions {
+            debug: true,
+            file: Some(root.to_str().unwrap().to_string()),
+            include_dirs: vec![dir.join("inc")],
+            ..Default::default()
+        };
+        let res = compile_with_options(std::fs::read_to_string(&root).unwrap(), &options).unwrap();
+        let machine = execute(&res);
+        assert_eq!(machine.read_register(0).unwrap(), 42);
+
+        let debug = res.debug.unwrap();
+        assert_eq!(debug.files.len(), 3);
+        let double = debug.label("double").unwrap().address;
+        let line = debug.line_for(double).unwrap();
+        assert!(debug.files[line.file].ends_with("double.asm"));
+        assert_eq!(line.line, 3);
+    }
+
+    #[test]
+    pub fn not_found() {
+        let dir = fixture("missing", &[("main.asm", "[text]\n@include \"nope.asm\"\n")]);
+        match load(&dir.join("main.asm"), &[]) {
+            Err(SourceError::NotFound { path, chain }) => {
+                assert_eq!(path, "nope.asm");
+                assert_eq!(chain.len(), 1);
+                assert_eq!(chain[0].1, 2);
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn cycle() {
+        let dir = fixture("cycle", &[
+            ("a.asm", "@include \"b.asm\"\n"),
+            ("b.asm", "\n@include \"a.asm\"\n"),
+        ]);
+        match load(&dir.join("a.asm"), &[]) {
+            Err(e @ SourceError::Cycle { .. }) => {
+                let message = e.to_string();
+                assert!(message.contains("a.asm:1 -> "), "{}", message);
+                assert!(message.contains("b.asm:2 -> "), "{}", message);
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn cycle_through_macros_and_other_spellings() {
+        let dir = fixture("cycle-macro", &[
+            ("a.asm", "@macro pull\n@include \"b.asm\"\n@endm\n[text]\npull\n"),
+            ("b.asm", "\n@include \"sub/../a.asm\"\n"),
+            ("sub/keep", ""),
+        ]);
+        assert!(matches!(load(&dir.join("a.asm"), &[]), Err(SourceError::Cycle { .. })));
+    }
+
+    #[test]
+    pub fn duplicate() {
+        let dir = fixture("duplicate", &[
+            ("main.asm", "@include \"a.asm\"\n@include \"b.asm\"\n"),
+            ("a.asm", "@include \"common.asm\"\n"),
+            ("b.asm", "@include \"common.asm\"\n"),
+            ("common.asm", "[text]\n"),
+        ]);
+        assert!(matches!(load(&dir.join("main.asm"), &[]), Err(SourceError::Duplicate { .. })));
+    }
+}
@@ -0,0 +1,85 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::{parser::parse_command, tokens::{Cmd, ConstValue, JumpOperand}};
+
+    use crate::common::run;
+
+    #[test]
+    pub fn jump_operands() {
+        assert!(matches!(parse_command("jmp r0").unwrap().1, Cmd::Jmp(JumpOperand::Reg(0))));
+        assert!(matches!(parse_command("jz &0x40").unwrap().1, Cmd::Jz(JumpOperand::Addr(ConstValue::Number(0x40)))));
+        assert!(matches!(parse_command("jmp [$table + r1]").unwrap().1, Cmd::Jmp(JumpOperand::IdOffsetReg("table", 1))));
+        assert!(matches!(parse_command("jg [$table + 2]").unwrap().1, Cmd::Jg(JumpOperand::Addr(ConstValue::Expr(_)))));
+        assert!(matches!(parse_command("jle [$table]").unwrap().1, Cmd::Jle(JumpOperand::Addr(ConstValue::Expr(_)))));
+        assert!(matches!(parse_command("jnz .loop + 2").unwrap().1, Cmd::Jnz(JumpOperand::Const(ConstValue::Expr(_)))));
+        assert!(matches!(parse_command("jge 0x10").unwrap().1, Cmd::Jge(JumpOperand::Const(ConstValue::Number(0x10)))));
+    }
+
+    #[test]
+    pub fn jump_table() {
+        // r0 accumulates the cases run for every index in r1
+        let code = "[data]
+$table dw 0 0 0
+
+[text]
+.start
+    store $table .case_a
+    store $table + 1 .case_b
+    store $table + 2 .case_c
+    move r0 0
+    move r1 2
+.next
+    jmp [$table + r1]
+.case_a
+    push r0
+    push 1
+    add
+    pop r0
+    jmp .done
+.case_b
+    push r0
+    push 10
+    add
+    pop r0
+    jmp .done
+.case_c
+    push r0
+    push 100
+    add
+    pop r0
+.done
+    dec r1
+    push r1
+    jge .next
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 111);
+    }
+
+    #[test]
+    pub fn register_and_memory_targets() {
+        let code = "[data]
+$target dw 0
+
+[text]
+.start
+    move r2 .via_register
+    jmp r2
+    term
+.via_register
+    store $target .via_memory
+    push 0
+    pop r3
+    jz [$target]
+    term
+.via_memory
+    move r0 42
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 42);
+    }
+}
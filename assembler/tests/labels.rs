@@ -0,0 +1,136 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile, compile_object, compile_with_options, CompileOptions}, parser::{parse_command, parse_token}, tokens::{Cmd, ConstValue, JumpOperand, Token}};
+
+    use crate::common::execute;
+
+    #[test]
+    pub fn names_with_underscores_and_dots() {
+        assert!(matches!(parse_token(".print_loop").unwrap(), ("", Token::Label("print_loop"))));
+        assert!(matches!(parse_token("..loop").unwrap(), ("", Token::Label(".loop"))));
+        assert!(matches!(parse_token(".io.print").unwrap(), ("", Token::Label("io.print"))));
+        assert!(matches!(parse_token("@@").unwrap(), ("", Token::Label("@@"))));
+        assert!(matches!(parse_command("jmp .a.b_c").unwrap().1, Cmd::Jmp(JumpOperand::Const(ConstValue::Label("a.b_c")))));
+        assert!(matches!(parse_command("jnz @b").unwrap().1, Cmd::Jnz(JumpOperand::Const(ConstValue::Label("@b")))));
+        assert!(matches!(parse_token("$my_buf.len dw 1").unwrap(), ("", Token::DataDef("my_buf.len", _, _))));
+    }
+
+    #[test]
+    pub fn local_labels_are_scoped() {
+        let code = "[data]
+$call_count dw 0
+
+[text]
+.start
+    move r0 0
+    call .add_three
+    call .add_two
+    jmp .add_two.done
+..done
+    term
+
+.add_three
+    move r1 3
+..loop
+    inc r0
+    dec r1
+    push r1
+    jnz ..loop
+    ret
+
+.add_two
+    move r1 2
+..loop
+    inc r0
+    dec r1
+    push r1
+    jnz ..loop
+    ret
+..done
+    move r2 1
+    term
+";
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let res = compile_with_options(code.to_string(), &options).unwrap();
+        let machine = execute(&res);
+        assert_eq!(machine.read_register(0).unwrap(), 5);
+        assert_eq!(machine.read_register(2).unwrap(), 1);
+        let debug = res.debug.unwrap();
+        for name in ["start.done", "add_three.loop", "add_two.loop", "add_two.done"] {
+            assert!(debug.label(name).is_some(), "missing {}", name);
+        }
+    }
+
+    #[test]
+    pub fn anonymous_labels() {
+        let code = "[text]
+.start
+    move r0 0
+    move r1 4
+@@
+    inc r0
+    dec r1
+    push r1
+    jz @f
+    jmp @b
+@@
+    push @b
+    pop r2
+    term
+";
+        let res = compile(code.to_string()).unwrap();
+        let machine = execute(&res);
+        assert_eq!(machine.read_register(0).unwrap(), 4);
+        // `push @b` refers to the `@@` right before it
+        assert_eq!(machine.read_register(2).unwrap(), res.header.start + 6 + 2 + 2 + 2 + 2 + 2);
+    }
+
+    #[test]
+    pub fn macro_labels_keep_the_scope() {
+        let code = "@macro count_down reg
+.%%again
+    dec %reg
+    push %reg
+    jnz .%%again
+@endm
+[text]
+.start
+    move r0 3
+    count_down r0
+    jmp ..end
+    move r0 7
+..end
+    term
+";
+        let machine = execute(&compile(code.to_string()).unwrap());
+        assert_eq!(machine.read_register(0).unwrap(), 0);
+    }
+
+    #[test]
+    pub fn label_errors() {
+        let code = "[text]
+.start
+    jmp ..missing
+    jmp @b
+.other
+..twice
+..twice
+    jmp @f
+    term
+";
+        let errors = compile(code.to_string()).unwrap_err();
+        let messages: Vec<(usize, &str)> = errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+        assert_eq!(messages, vec![
+            (7, "label '.other.twice' is defined more than once"),
+            (3, "undefined local label '..missing' in '.start'"),
+            (4, "no anonymous label '@@' before this line"),
+            (8, "no anonymous label '@@' after this line"),
+        ]);
+        assert_eq!((errors[1].column, errors[1].len), (9, 9));
+
+        let errors = compile_object("@global ..loop\n[text]\n.start\n..loop\n    term\n".to_string(), &CompileOptions::default()).unwrap_err();
+        assert_eq!(errors[0].message, "'@global' cannot refer to a local label, use its full name '.scope.loop'");
+    }
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile_object, CompileOptions}, debug::DebugInfo, linker::{link, LinkError}};
+    use binary::object::{Object, RelocationTarget};
+    use machine::internal::machine::{Machine, MachineOptions};
+
+    const MAIN: &str = r#"
+    @org 8
+    @extern .double
+    @extern $value
+    [text]
+    .start
+        push [$value]
+        call .double
+        pop r0
+        move r1 $value
+        term
+    "#;
+
+    const LIB: &str = r#"
+    @global .double
+    @global $value
+    [data]
+    $pad dw 0 0
+    $value dw 21
+    [text]
+    .double
+        push 2
+        mul
+        ret
+    "#;
+
+    fn object(code: &str, file: &str) -> Object {
+        let options = CompileOptions { debug: true, file: Some(file.to_string()), ..Default::default() };
+        compile_object(code.to_string(), &options).unwrap().0
+    }
+
+    #[test]
+    pub fn object_relocations() {
+        let main = object(MAIN, "main.asm");
+        assert_eq!(main.imports.len(), 2);
+        assert_eq!(main.relocations.len(), 3);
+        assert!(main.relocations.iter().all(|r| matches!(r.target, RelocationTarget::Import(_))));
+        assert_eq!(main.export("start").unwrap().address, 0);
+
+        let lib = object(LIB, "lib.asm");
+        assert!(lib.imports.is_empty());
+        assert_eq!(lib.export("value").unwrap().address, lib.text.len() as u32 + 2);
+
+        let table = object("@extern .far\n[data]\n$table dw .start .far + 1 7\n[text]\n.start\n    term\n", "table.asm");
+        assert_eq!(table.data, vec![0, 1, 7]);
+        assert_eq!(table.relocations.len(), 2);
+        assert_eq!((table.relocations[0].offset, table.relocations[0].target), (1, RelocationTarget::Local));
+        assert_eq!((table.relocations[1].offset, table.relocations[1].target), (2, RelocationTarget::Import(0)));
+        assert_eq!(Object::read(&lib.write()).unwrap(), lib);
+    }
+
+    #[test]
+    pub fn link_and_run() {
+        let objects = vec![
+            ("main.o".to_string(), object(MAIN, "main.asm")),
+            ("lib.o".to_string(), object(LIB, "lib.asm")),
+        ];
+        let image = link(&objects, 64).unwrap();
+        assert_eq!(image.origin, 8);
+        assert_eq!(image.start, 8);
+        let mut machine = Machine::new(MachineOptions { memory_cells: image.min_cells, memory_stack_size: 64 }).unwrap();
+        machine.load_data(image.origin, &image.memory()).unwrap();
+        machine.set_start(image.start);
+        machine.execute().unwrap();
+        assert_eq!(machine.read_register(0).unwrap(), 42);
+        assert_eq!(machine.read_register(1).unwrap(), image.symbol("value").unwrap().address);
+
+        let debug = DebugInfo::parse(image.debug.as_ref().unwrap()).unwrap();
+        assert_eq!(debug.files, vec!["main.asm".to_string(), "lib.asm".to_string()]);
+        let double = debug.label("double").unwrap().address;
+        assert_eq!(debug.line_for(double).unwrap().file, 1);
+    }
+
+    #[test]
+    pub fn undefined_symbols() {
+        let objects = vec![("main.o".to_string(), object(MAIN, "main.asm"))];
+        let errors = link(&objects, 64).unwrap_err();
+        assert!(errors.contains(&LinkError::UndefinedSymbol(".double".to_string(), "main.o".to_string())));
+        assert!(errors.contains(&LinkError::UndefinedSymbol("$value".to_string(), "main.o".to_string())));
+    }
+
+    #[test]
+    pub fn duplicate_symbols() {
+        let objects = vec![
+            ("main.o".to_string(), object(MAIN, "main.asm")),
+            ("a.o".to_string(), object(LIB, "lib.asm")),
+            ("b.o".to_string(), object(LIB, "lib.asm")),
+        ];
+        let errors = link(&objects, 64).unwrap_err();
+        assert!(errors.contains(&LinkError::DuplicateSymbol(".double".to_string(), "a.o".to_string(), "b.o".to_string())));
+    }
+
+    #[test]
+    pub fn missing_start() {
+        let objects = vec![("lib.o".to_string(), object(LIB, "lib.asm"))];
+        assert_eq!(link(&objects, 64).unwrap_err(), vec![LinkError::MissingStart]);
+    }
+
+    #[test]
+    pub fn labels_at_the_end_of_text() {
+        // `.tail` follows the last instruction of the first object, so it is where the text of
+        // the second object begins and not the data of the first one
+        let objects = vec![
+            ("head.o".to_string(), object("@global .tail\n[data]\n$pad dw 7\n[text]\n.start\n    push 1\n.tail\n", "head.asm")),
+            ("next.o".to_string(), object("@global .next\n[text]\n.next\n    pop r0\n    term\n", "next.asm")),
+        ];
+        let image = link(&objects, 64).unwrap();
+        let next = image.symbol("next").unwrap().address;
+        assert_eq!(image.symbol("tail").unwrap().address, next);
+        let debug = DebugInfo::parse(image.debug.as_ref().unwrap()).unwrap();
+        assert_eq!(debug.label("tail").unwrap().address, next);
+        assert_eq!(debug.data("pad").unwrap().address, image.origin + image.text.len() as u32);
+    }
+
+    #[test]
+    pub fn too_large() {
+        let objects = vec![("main.o".to_string(), object("@org 0xfffffff0\n[text]\n.start\n    term\n", "main.asm"))];
+        assert_eq!(link(&objects, 64).unwrap_err(), vec![LinkError::TooLarge(0xffff_fff0)]);
+    }
+}
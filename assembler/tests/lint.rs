@@ -0,0 +1,122 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{lint, CompileOptions}, lint::{Lint, LINTS}};
+
+    /// `(line, lint id)` of every warning
+    fn findings(code: &str) -> Vec<(usize, String)> {
+        let warnings = lint(code.to_string(), &CompileOptions::default()).unwrap();
+        warnings.iter()
+            .map(|w| (w.line, w.notes.last().and_then(|n| n.split('\'').nth(1)).unwrap_or("").to_string()))
+            .collect()
+    }
+
+    fn expected(list: &[(usize, &str)]) -> Vec<(usize, String)> {
+        list.iter().map(|&(line, id)| (line, id.to_string())).collect()
+    }
+
+    #[test]
+    pub fn clean_program() {
+        let code = "[data]
+$msg b \"hi\" 0
+$handlers dw .greet
+
+[text]
+.start
+    move r0 0
+    call [$handlers + r0]
+    push 3
+    call .square
+    int 0 4
+    term
+
+.greet
+    push $msg
+    int 0 5
+    ret
+
+.square
+    dup
+    mul
+    ret
+";
+        assert!(findings(code).is_empty());
+    }
+
+    #[test]
+    pub fn unused_and_unreachable() {
+        let code = "[data]
+$used dw 1
+$unused dw 2
+
+[text]
+.start
+    push [$used]
+    int 0 4
+    term
+    push 1
+..never
+    term
+
+.orphan
+    ret
+";
+        assert_eq!(findings(code), expected(&[
+            (3, "unused-data"),
+            (10, "unreachable"),
+            (11, "unused-label"),
+            (12, "unreachable"),
+            (14, "unused-label"),
+            (15, "unreachable"),
+        ]));
+    }
+
+    #[test]
+    pub fn calls_and_registers() {
+        let code = "[text]
+.start
+    push 4
+    safecall .square
+    push r0
+    int 0 4
+    move r3 5
+    push 2
+    push 10
+    div
+    push r3
+    int 0 4
+    call .forever
+    int 1 0
+
+.square
+    dup
+    mul
+    pop r0
+    ret
+
+.forever
+    jmp .forever
+";
+        assert_eq!(findings(code), expected(&[
+            (4, "safecall-result"),
+            (7, "div-clobbers-r3"),
+            (13, "no-return"),
+            (14, "unknown-interrupt"),
+        ]));
+        // registers the caller set itself are kept on purpose
+        let code = "[text]\n.start\n    move r0 3\n..loop\n    safecall .clear\n    dec r0\n    jnz ..loop\n    term\n.clear\n    move r0 0\n    ret\n";
+        assert!(findings(code).is_empty());
+    }
+
+    #[test]
+    pub fn falls_into_data_and_allow_comments() {
+        let code = "[data]\n$x dw 1\n[text]\n.start\n    push [$x]\n    int 0 4\n";
+        assert_eq!(findings(code), expected(&[(6, "falls-into-data")]));
+        let code = "[data]\n$x dw 1 ; lint: allow(unused-data)\n[text]\n.start\n    ; lint: allow(falls-into-data)\n    int 0 4\n";
+        assert!(findings(code).is_empty());
+        let code = "@macro spare\n    .%%spare ; lint: allow(unused-label)\n@endm\n[text]\n.start\n    term\n    spare\n";
+        assert_eq!(findings(code), expected(&[]));
+        for lint in LINTS {
+            assert_eq!(Lint::from_id(lint.id()), Some(lint));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile_with_options, CompileOptions}, listing::Section};
+    use binary::image::SymbolKind;
+
+    const CODE: &str = "@macro twice reg
+    inc %reg
+    inc %reg
+@endm
+[data]
+$msg b \"Hi\" 0
+$table dw 1 2 3 4
+[text]
+.start
+    move r0 1
+    twice r0
+..end
+    term
+";
+
+    #[test]
+    pub fn listing_of_every_line() {
+        let options = CompileOptions { listing: true, ..Default::default() };
+        let res = compile_with_options(CODE.to_string(), &options).unwrap();
+        let listing = res.listing.unwrap();
+        assert_eq!(listing.files.len(), 1);
+        let lines = &listing.files[0].lines;
+        assert_eq!(lines.len(), 13);
+        assert!(lines[0].code.is_empty());
+        // data is laid out after the 8 cells of text
+        assert_eq!((lines[5].code[0].address, lines[5].code[0].words.clone()), (8, vec![0x48690000]));
+        assert_eq!(lines[6].code[0].words, vec![1, 2, 3, 4]);
+        assert_eq!(lines[9].code[0].words, vec![0xf006a006, 0, 1]);
+        assert_eq!(lines[10].code.len(), 2);
+        assert_eq!(lines[10].code[1].expansion.as_deref(), Some("inc r0"));
+
+        let text = listing.to_string();
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows[0], "; <source>");
+        assert_eq!(rows[7], "    7  00000009  00000001 00000002 00000003   $table dw 1 2 3 4");
+        assert_eq!(rows[8], "       0000000c  00000004");
+        assert_eq!(rows[11], "   10  00000000  f006:a006 00000000 00000001      move r0 1");
+        assert_eq!(rows[12], "   11                                             twice r0");
+        assert_eq!(rows[13], "       00000003  f016:0000 00000000             + inc r0");
+    }
+
+    #[test]
+    pub fn symbol_map() {
+        let options = CompileOptions { map: true, ..Default::default() };
+        let res = compile_with_options(format!("@org 0x10\n{}", CODE), &options).unwrap();
+        let map = res.map.unwrap();
+        assert_eq!((map.origin, map.start, map.text_size, map.data_size), (0x10, 0x10, 8, 5));
+        let symbols: Vec<(&str, SymbolKind, Section, u32, u32)> = map.symbols.iter()
+            .map(|s| (s.name.as_str(), s.kind, s.section, s.address, s.size))
+            .collect();
+        assert_eq!(symbols, vec![
+            ("start", SymbolKind::Label, Section::Text, 0x10, 7),
+            ("start.end", SymbolKind::Label, Section::Text, 0x17, 1),
+            ("msg", SymbolKind::Data, Section::Data, 0x18, 1),
+            ("table", SymbolKind::Data, Section::Data, 0x19, 4),
+        ]);
+        assert!(map.to_string().contains("0x00000019  data          4  $table"));
+    }
+}
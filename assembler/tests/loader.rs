@@ -0,0 +1,27 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::loader::{load_image, DEFAULT_CELLS};
+    use binary::image::{Image, MAX_CELLS};
+
+    fn image() -> Image {
+        Image { origin: 16, start: 16, stack_size: 64, text: vec![0xffff0000], ..Default::default() }
+    }
+
+    #[test]
+    pub fn cells() {
+        let machine = load_image(&image(), None, None).unwrap();
+        assert!(machine.memory.read(DEFAULT_CELLS - 1).is_ok() && machine.memory.read(DEFAULT_CELLS).is_err());
+        // origin, one cell of text and the stack
+        assert!(load_image(&image(), Some(80), None).is_err());
+        assert!(load_image(&image(), Some(81), None).unwrap().memory.read(80).is_ok());
+    }
+
+    #[test]
+    pub fn images_that_do_not_fit() {
+        let mut image = image();
+        image.origin = 0xffff_fff0;
+        assert!(load_image(&image, None, None).is_err());
+        assert!(load_image(&self::image(), None, Some(u32::MAX)).is_err());
+        assert!(load_image(&self::image(), None, Some(MAX_CELLS)).is_err());
+    }
+}
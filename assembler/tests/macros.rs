@@ -0,0 +1,112 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::{compiler::{compile_with_options, CompileOptions}, source::{SourceError, SourceSet}};
+
+    use crate::common::run;
+
+    fn load(code: &str) -> Result<SourceSet, SourceError> {
+        SourceSet::load(Some("main.asm"), code.to_string(), &[])
+    }
+
+    #[test]
+    pub fn parameters_and_local_labels() {
+        // `times` adds `value` to r0 `count` times, its loop label is unique per expansion
+        let code = "@macro times count value
+    move r1 %count
+.%%loop
+    push r0
+    push %value
+    add
+    pop r0
+    dec r1
+    push r1
+    jnz .%%loop
+@endm
+
+[text]
+.start
+    move r0 0
+    times 3 5
+    TIMES 2 100
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 215);
+    }
+
+    #[test]
+    pub fn nested_expansion() {
+        let code = "@macro double
+    push 2
+    mul
+@endm
+@macro quadruple
+    double
+    double
+@endm
+
+[text]
+.start
+    push 3
+    quadruple
+    pop r0
+    term
+";
+        let machine = run(code);
+        assert_eq!(machine.read_register(0).unwrap(), 12);
+    }
+
+    #[test]
+    pub fn debug_lines_point_at_invocation() {
+        let code = "@macro twice
+    push 1
+    push 1
+@endm
+[text]
+.start
+    twice
+    term
+";
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let res = compile_with_options(code.to_string(), &options).unwrap();
+        let debug = res.debug.unwrap();
+        let start = debug.label("start").unwrap().address;
+        assert_eq!(debug.line_for(start).unwrap().line, 7);
+        assert_eq!(debug.line_for(start + 2).unwrap().line, 7);
+        assert_eq!(debug.line_for(start + 4).unwrap().line, 8);
+    }
+
+    #[test]
+    pub fn errors_point_at_body_and_invocation() {
+        let code = "@macro inner a
+    push %b
+@endm
+@macro outer
+    inner 1
+@endm
+[text]
+    outer
+";
+        match load(code) {
+            Err(e @ SourceError::Macro { .. }) => {
+                let SourceError::Macro { trace, .. } = &e else { unreachable!() };
+                assert_eq!(trace, &vec![("main.asm".to_string(), 2), ("main.asm".to_string(), 5), ("main.asm".to_string(), 8)]);
+                assert_eq!(e.to_string(), "main.asm:2: unknown macro parameter '%b', expanded from main.asm:5, expanded from main.asm:8");
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn invalid_macros() {
+        let argument_count = load("@macro m a\n@endm\nm 1 2\n");
+        assert!(matches!(&argument_count, Err(SourceError::Macro { trace, .. }) if trace[0].1 == 3));
+        assert!(matches!(load("@macro m\npush 1\n"), Err(SourceError::Macro { .. })));
+        assert!(matches!(load("@endm\n"), Err(SourceError::Macro { .. })));
+        assert!(matches!(load("@macro push\n@endm\n"), Err(SourceError::Macro { .. })));
+        assert!(matches!(load("@macro m\n@endm\n@macro m\n@endm\n"), Err(SourceError::Macro { .. })));
+        assert!(matches!(load("@macro m\nm\n@endm\n[text]\nm\n"), Err(SourceError::Macro { .. })));
+    }
+}
@@ -0,0 +1,110 @@
+mod common;
+
+#[cfg(test)]
+pub mod tests {
+    use assembler::compiler::{compile_with_options, CompileOptions, CompiledFrame};
+
+    use crate::common::execute;
+
+    /// registers, flags and stack left by a program
+    type Observed = (Vec<u32>, [bool; 4], Vec<u32>);
+
+    fn build(code: &str, optimize: bool) -> CompiledFrame {
+        let options = CompileOptions { optimize, ..Default::default() };
+        compile_with_options(code.to_string(), &options).unwrap()
+    }
+
+    fn observe(res: &CompiledFrame) -> Observed {
+        let mut machine = execute(res);
+        let registers = (0..8).map(|r| machine.read_register(r).unwrap()).collect();
+        let flag = &machine.flag;
+        let flags = [flag.zero, flag.negative, flag.overflow, flag.carry];
+        let mut stack = Vec::new();
+        while let Ok(value) = machine.memory.pop() {
+            stack.push(value);
+        }
+        (registers, flags, stack)
+    }
+
+    /// runs `code` with and without the optimizer, returning the cells saved
+    fn saved(code: &str) -> usize {
+        let plain = build(code, false);
+        let optimized = build(code, true);
+        assert_eq!(observe(&plain), observe(&optimized), "behaviour differs for:\n{}", code);
+        plain.binary.len() - optimized.binary.len()
+    }
+
+    fn program(body: &str) -> String {
+        // ends with `drop` so the final flags do not depend on the code tested
+        format!("[text]\n.start\n    move r0 7\n    move r1 0xfffffffe\n    push 5\n{}    push 0\n    drop\n    term\n", body)
+    }
+
+    #[test]
+    pub fn double_swap() {
+        assert_eq!(saved(&program("    push 6\n    swap\n    swap\n")), 2);
+        assert_eq!(saved(&program("    push 6\n    swap\n    swap\n    swap\n    swap\n    swap\n")), 4);
+    }
+
+    #[test]
+    pub fn increment() {
+        // 4 instructions of 7 cells become one of 2
+        assert_eq!(saved(&program("    push r0\n    push 1\n    add\n    pop r0\n")), 5);
+        assert_eq!(saved(&program("    push 1\n    push r1\n    add\n    pop r1\n    jnz .start.x\n    push 9\n..x\n")), 5);
+        // not the same register
+        assert_eq!(saved(&program("    push r0\n    push 1\n    add\n    pop r1\n")), 0);
+    }
+
+    #[test]
+    pub fn decrement() {
+        assert_eq!(saved(&program("    push 1\n    push r0\n    sub\n    pop r0\n    push r0\n    push r0\n    add\n    pop r5\n")), 5);
+        // flags of `sub` are tested afterwards
+        assert_eq!(saved(&program("    push 1\n    push r0\n    sub\n    pop r0\n    jz .start.x\n..x\n")), 0);
+        // operands the other way round compute `1 - r0`
+        assert_eq!(saved(&program("    push r0\n    push 1\n    sub\n    pop r0\n    push r0\n    push 1\n    add\n")), 0);
+    }
+
+    #[test]
+    pub fn push_pop_becomes_move() {
+        assert_eq!(saved(&program("    push r0\n    pop r2\n    push 0\n    pop r4\n")), 2);
+        assert_eq!(saved(&program("    push 3\n    pop r2\n    push r2\n    pop r2\n")), 1 + 4);
+        // `pop` sets the zero flag tested by `jz`
+        assert_eq!(saved(&program("    push 0\n    pop r2\n    jz .start.x\n    move r3 1\n..x\n")), 0);
+        // a label can be reached with other flags
+        assert_eq!(saved(&program("    push r0\n    pop r2\n..x\n    jz .start.y\n..y\n")), 0);
+    }
+
+    #[test]
+    pub fn push_drop() {
+        assert_eq!(saved(&program("    push r0\n    drop\n    push 0\n    drop\n")), 6);
+        assert_eq!(saved(&program("    push r0\n    drop\n    jnz .start.x\n..x\n")), 0);
+    }
+
+    #[test]
+    pub fn unreachable_code() {
+        let code = "[text]
+.start
+    move r0 1
+    jmp .next
+    move r0 2
+    push 3
+.next
+    call .function
+    term
+    move r0 4
+@@
+    jmp @f
+    move r1 5
+@@
+    term
+.function
+    inc r0
+    ret
+    inc r0
+    inc r0
+";
+        assert_eq!(saved(code), 3 + 2 + 3 + 3 + 2 + 2);
+        let res = build(code, true);
+        let (registers, ..) = observe(&res);
+        assert_eq!(registers[0], 2);
+    }
+}
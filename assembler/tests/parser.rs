@@ -1,6 +1,6 @@
 #[cfg(test)]
 pub mod tests {
-    use assembler::{parser::{parse_const_value, parse_meta, parse_number, parse_program, parse_str}};
+    use assembler::{parser::{parse_command, parse_const_value, parse_meta, parse_number, parse_program, parse_program_lines, parse_str}, tokens::{Cmd, ConstValue}};
 
     #[test]
     pub fn number_hex(){
@@ -35,7 +35,7 @@ pub mod tests {
         let data = "@org 0xa";
         let (_, org) = parse_meta(data).unwrap();
         match org {
-            assembler::tokens::MetaType::Org(val) => assert_eq!(val, 10),
+            assembler::tokens::MetaType::Org(assembler::tokens::ConstValue::Number(val)) => assert_eq!(val, 10),
             _ => panic!()
         }
 
@@ -45,6 +45,18 @@ pub mod tests {
             assembler::tokens::MetaType::Include(val) => assert_eq!(val, "Hello World"),
             _ => panic!()
         }
+
+        let (_, global) = parse_meta("@global .print").unwrap();
+        match global {
+            assembler::tokens::MetaType::Global(name) => assert_eq!(name, assembler::tokens::SymbolName::Label("print")),
+            _ => panic!()
+        }
+
+        let (_, ext) = parse_meta("@EXTERN $buffer").unwrap();
+        match ext {
+            assembler::tokens::MetaType::Extern(name) => assert_eq!(name, assembler::tokens::SymbolName::Data("buffer")),
+            _ => panic!()
+        }
     }
 
     #[test]
@@ -78,4 +90,20 @@ pub mod tests {
         "#;
         let _res = parse_program(code).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn parse_program_line_numbers() {
+        let code = "[data]\n\n$a dw 1 2\n[text]\n; comment\n.start\n    PUSH 1\n\n    TERM";
+        let (_, tokens) = parse_program_lines(code).unwrap();
+        let lines: Vec<usize> = tokens.iter().map(|t| t.line).collect();
+        assert_eq!(lines, vec![1, 3, 4, 6, 7, 9]);
+    }
+
+    #[test]
+    pub fn parse_move_register() {
+        assert!(matches!(parse_command("move r7 r6").unwrap().1, Cmd::MoveReg(7, 6)));
+        assert!(matches!(parse_command("MOVE r0 2").unwrap().1, Cmd::MoveConst(0, ConstValue::Number(2))));
+        assert!(matches!(parse_command("move r6 &r7").unwrap().1, Cmd::MoveAddrReg(6, 7)));
+        assert!(matches!(parse_command("pop &r7").unwrap().1, Cmd::PopAddrReg(7)));
+    }
+}
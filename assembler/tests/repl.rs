@@ -0,0 +1,99 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::repl::{Eval, Session, SessionError, SESSION_DATA, UNDO_DEPTH};
+    use binary::image::SymbolKind;
+
+    fn run(session: &mut Session, lines: &[&str]) {
+        for line in lines {
+            session.eval(line).unwrap_or_else(|e| panic!("'{}' failed: {:?}", line, e));
+        }
+    }
+
+    fn output(session: &mut Session, command: &str) -> String {
+        match session.eval(command).unwrap() {
+            Eval::Output(text) => text,
+            other => panic!("'{}' gave {:?}", command, other),
+        }
+    }
+
+    #[test]
+    pub fn lines_run_on_one_machine() {
+        let mut session = Session::new().unwrap();
+        run(&mut session, &["move r0 3", ".loop", "push r0", "dec r0", "jnz .loop"]);
+        assert_eq!(session.machine().memory.stack(), vec![3, 2, 1]);
+        assert_eq!(output(&mut session, ":stack"), "<3> 3 2 1");
+        run(&mut session, &["@define TEN 10", "push TEN", "add"]);
+        assert_eq!(session.machine().memory.stack(), vec![3, 2, 11]);
+        assert!(output(&mut session, ":regs").starts_with("r0 = 0x00000000 0\n"));
+        assert_eq!(output(&mut session, ":flags"), "zero=0 negative=0 overflow=0 carry=0");
+    }
+
+    #[test]
+    pub fn labels_and_data() {
+        let mut session = Session::new().unwrap();
+        run(&mut session, &["$values dw 7 8 9", "push [$values + 1]"]);
+        assert_eq!(session.symbol(SymbolKind::Data, "values"), Some(SESSION_DATA));
+        assert_eq!(session.machine().memory.stack(), vec![8]);
+        assert_eq!(output(&mut session, ":mem $values"), format!("0x{:08x}: 00000007 00000008 00000009", SESSION_DATA));
+        assert_eq!(output(&mut session, ":label answer 0x20"), ".answer = 0x00000020");
+        run(&mut session, &["push .answer"]);
+        assert_eq!(session.machine().memory.stack(), vec![8, 0x20]);
+        assert_eq!(output(&mut session, ":mem .answer 2"), "0x00000020: 00000000 00000000");
+    }
+
+    #[test]
+    pub fn errors_and_undo() {
+        let mut session = Session::new().unwrap();
+        run(&mut session, &["push 1", "push 2"]);
+        let Err(SessionError::Compile(diagnostics)) = session.eval("push 1 2") else { panic!("expected a compile error") };
+        assert_eq!(diagnostics[0].line, 1);
+        assert!(matches!(session.eval("ret"), Err(SessionError::Runtime(_))));
+        assert_eq!(output(&mut session, ":undo"), "undone");
+        assert_eq!(session.machine().memory.stack(), vec![1, 2]);
+        run(&mut session, &[":undo", ":undo"]);
+        assert!(session.machine().memory.stack().is_empty());
+        assert!(matches!(session.eval(":undo"), Err(SessionError::Command(_))));
+        assert!(matches!(session.eval(":nope"), Err(SessionError::Command(_))));
+        assert!(matches!(session.eval("jmp @b"), Err(SessionError::Compile(_))));
+        assert!(matches!(session.eval(".spin"), Ok(Eval::Done)));
+        assert!(matches!(session.eval("jmp .spin"), Err(SessionError::StepLimit)));
+        assert_eq!(session.eval(":q").unwrap(), Eval::Quit);
+        // only the latest inputs can be reverted
+        let mut session = Session::new().unwrap();
+        for _ in 0..UNDO_DEPTH + 2 {
+            run(&mut session, &["push 1"]);
+        }
+        for _ in 0..UNDO_DEPTH {
+            run(&mut session, &[":undo"]);
+        }
+        assert_eq!(session.machine().memory.stack(), vec![1, 1]);
+        assert!(matches!(session.eval(":undo"), Err(SessionError::Command(_))));
+    }
+
+    #[test]
+    pub fn load_and_reset() {
+        let path = std::env::temp_dir().join(format!("myvm-repl-{}.asm", std::process::id()));
+        std::fs::write(&path, "[data]\n$base dw 5\n[text]\n.square\n    dup\n    mul\n    ret\n").unwrap();
+        let mut session = Session::new().unwrap();
+        let loaded = output(&mut session, &format!(":load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, "loaded 3 words of code at 0x00000000");
+        run(&mut session, &["push [$base]", "call .square"]);
+        assert_eq!(session.machine().memory.stack(), vec![25]);
+        assert_eq!(output(&mut session, ":reset"), "machine reset");
+        assert!(session.machine().memory.stack().is_empty());
+        assert_eq!(session.symbol(SymbolKind::Label, "square"), None);
+    }
+
+    #[test]
+    pub fn load_with_undefined_externs() {
+        let path = std::env::temp_dir().join(format!("myvm-repl-extern-{}.asm", std::process::id()));
+        std::fs::write(&path, "@extern .x\n[text]\n.go\n    jmp .x\n").unwrap();
+        let mut session = Session::new().unwrap();
+        let loaded = session.eval(&format!(":load {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(SessionError::Command(message)) if message == "'.x' is not defined"));
+        assert_eq!(session.symbol(SymbolKind::Label, "go"), None);
+        assert!(matches!(session.eval(":undo"), Err(SessionError::Command(_))));
+    }
+}
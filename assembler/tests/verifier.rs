@@ -0,0 +1,83 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::compiler::{compile_with_options, CompileOptions};
+
+    /// `(line, message)` of every warning of the stack verifier
+    fn verify(code: &str) -> Vec<(usize, String)> {
+        let options = CompileOptions { verify_stack: true, ..Default::default() };
+        let res = compile_with_options(code.to_string(), &options).unwrap();
+        res.warnings.iter().map(|w| (w.line, w.message.clone())).collect()
+    }
+
+    #[test]
+    pub fn balanced_program() {
+        let code = "[text]
+.start
+    move r0 3
+..loop
+    push r0
+    call .square
+    int 0 4
+    dec r0
+    jnz ..loop
+    term
+
+.square
+    dup
+    mul
+    ret
+";
+        assert!(verify(code).is_empty());
+        let res = compile_with_options("[text]\n.start\n    add\n    term\n".to_string(), &CompileOptions::default()).unwrap();
+        assert!(res.warnings.is_empty());
+    }
+
+    #[test]
+    pub fn underflow() {
+        let code = "[text]\n.start\n    push 1\n    add\n    term\n";
+        assert_eq!(verify(code), vec![(4, "stack underflow: this takes 2 value(s) but the stack holds 1".to_string())]);
+        let code = "[text]\n.start\n    push 1\n    call .add\n    term\n.add\n    add\n    ret\n";
+        assert_eq!(verify(code), vec![
+            (4, "stack underflow: '.add' takes 2 value(s) but the stack holds 1".to_string()),
+            (8, "'ret' leaves 1 fewer value(s) on the stack than on entry to '.add'".to_string()),
+        ]);
+    }
+
+    #[test]
+    pub fn loops_and_paths() {
+        let code = "[text]
+.start
+    move r0 3
+..loop
+    push r0
+    dec r0
+    jnz ..loop
+    term
+";
+        assert_eq!(verify(code), vec![(7, "stack leak: every iteration of this loop changes the stack depth by +1".to_string())]);
+        let code = "[text]\n.start\n    move r0 0\n    jz ..skip\n    push 1\n..skip\n    term\n";
+        assert_eq!(verify(code), vec![(7, "paths reach this instruction with stack depths that differ by 1".to_string())]);
+    }
+
+    #[test]
+    pub fn procedures_and_directives() {
+        let code = "[text]
+.start
+    push 1
+    call .leak
+    pop r0
+    term
+
+@proc leak
+    push 0
+    @if z
+        push 2
+    @endif
+@endp
+";
+        assert_eq!(verify(code), vec![
+            (13, "'ret' leaves 1 more value(s) on the stack than on entry to '.leak'".to_string()),
+            (13, "paths reach this instruction with stack depths that differ by 1".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq, Eq)]
+/// Binary container errors
+pub enum FormatError {
+    /// File ends before the named structure is complete
+    Truncated(String),
+    /// File does not start with the container magic
+    InvalidMagic,
+    /// Container version is not supported by this reader
+    UnsupportedVersion(u32),
+    /// Stored checksum does not match the content
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// A section is present more than once
+    DuplicateSection(u32),
+    /// A required section is missing
+    MissingSection(String),
+    /// Section payload cannot be decoded
+    InvalidSection(String),
+    /// Entry point lies outside of the loaded image
+    InvalidStart(u32),
+    /// Container holds a different kind of file than expected
+    UnexpectedKind(String),
+    /// Image needs more memory cells than a machine may have
+    TooLarge(String),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Truncated(message) => write!(f, "File is truncated: {}", message),
+            FormatError::InvalidMagic => write!(f, "Not a myvm binary (invalid magic)"),
+            FormatError::UnsupportedVersion(v) => write!(f, "Unsupported binary version {}", v),
+            FormatError::ChecksumMismatch { expected, actual } => write!(f, "Checksum mismatch: expected 0x{:08x}, found 0x{:08x}", expected, actual),
+            FormatError::DuplicateSection(kind) => write!(f, "Duplicate section of kind {}", kind),
+            FormatError::MissingSection(name) => write!(f, "Missing {} section", name),
+            FormatError::InvalidSection(message) => write!(f, "Invalid section: {}", message),
+            FormatError::InvalidStart(start) => write!(f, "Entry point 0x{:08x} is outside of the image", start),
+            FormatError::UnexpectedKind(message) => write!(f, "Unexpected file kind: {}", message),
+            FormatError::TooLarge(message) => write!(f, "Image is too large: {}", message),
+        }
+    }
+}
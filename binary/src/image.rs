@@ -0,0 +1,380 @@
+use crate::{crc::crc32, errors::FormatError};
+
+/// Magic number every container file starts with
+pub const MAGIC: [u8; 4] = *b"MYVM";
+/// Current container version
+pub const VERSION: u32 = 1;
+/// Size of the fixed header in bytes (magic, version, flags, origin, start, cells, stack, section count)
+const HEADER_SIZE: usize = 32;
+/// Header flag marking a relocatable object instead of an executable
+pub const FLAG_OBJECT: u32 = 0x0000_0001;
+/// Most memory cells an image may ask for, for its contents, minimum cells or stack
+pub const MAX_CELLS: u32 = 1 << 26;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Section kind
+///
+/// Identifies the payload of a container section
+pub enum SectionKind {
+    /// executable code, little-endian words
+    Text = 1,
+    /// initialized data, little-endian words loaded right after text
+    Data = 2,
+    /// label and data symbol table
+    Symbols = 3,
+    /// assembler debug information (UTF-8 text)
+    Debug = 4,
+    /// symbols imported by a relocatable object
+    Imports = 5,
+    /// relocation entries of a relocatable object
+    Relocations = 6,
+}
+
+impl SectionKind {
+    pub fn from_num(value: u32) -> Option<SectionKind> {
+        match value {
+            x if x == Self::Text as u32 => Some(Self::Text),
+            x if x == Self::Data as u32 => Some(Self::Data),
+            x if x == Self::Symbols as u32 => Some(Self::Symbols),
+            x if x == Self::Debug as u32 => Some(Self::Debug),
+            x if x == Self::Imports as u32 => Some(Self::Imports),
+            x if x == Self::Relocations as u32 => Some(Self::Relocations),
+            _ => None,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Kind of a symbol table entry
+pub enum SymbolKind {
+    /// code label
+    Label = 0,
+    /// `$identifier` from the data section
+    Data = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Symbol table entry
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub address: u32,
+    /// size in memory cells (zero for labels)
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// # Image
+///
+/// In-memory representation of a myvm executable.
+///
+/// # Layout
+///
+/// All integers are little-endian `u32` unless noted otherwise:
+///
+/// * magic `MYVM` (4 bytes), version, flags (reserved, zero)
+/// * origin, start, minimum memory cells, stack size
+/// * section count followed by sections: kind, length in bytes, payload padded to 4 bytes
+/// * CRC-32 of everything before it
+///
+/// Text is loaded at `origin` and data right after it.
+pub struct Image {
+    pub origin: u32,
+    pub start: u32,
+    /// minimum number of memory cells required to run the image
+    pub min_cells: u32,
+    /// number of memory cells reserved for the stack
+    pub stack_size: u32,
+    pub text: Vec<u32>,
+    pub data: Vec<u32>,
+    pub symbols: Vec<Symbol>,
+    pub debug: Option<String>,
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(FormatError::Truncated(what.to_string()));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub(crate) fn u8(&mut self, what: &str) -> Result<u8, FormatError> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    pub(crate) fn u16(&mut self, what: &str) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.take(2, what)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self, what: &str) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.take(4, what)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+pub(crate) fn words_to_bytes(out: &mut Vec<u8>, words: &[u32]) {
+    for w in words {
+        out.extend_from_slice(&w.to_le_bytes());
+    }
+}
+
+pub(crate) fn bytes_to_words(bytes: &[u8], what: &str) -> Result<Vec<u32>, FormatError> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(FormatError::InvalidSection(format!("{} length is not a multiple of 4", what)));
+    }
+    Ok(bytes.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect())
+}
+
+fn write_section(out: &mut Vec<u8>, kind: SectionKind, payload: &[u8]) {
+    out.extend_from_slice(&(kind as u32).to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+pub(crate) fn encode_symbols(symbols: &[Symbol]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    for s in symbols {
+        out.push(s.kind as u8);
+        out.extend_from_slice(&s.address.to_le_bytes());
+        out.extend_from_slice(&s.size.to_le_bytes());
+        out.extend_from_slice(&(s.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(s.name.as_bytes());
+    }
+    out
+}
+
+pub(crate) fn decode_symbols(payload: &[u8]) -> Result<Vec<Symbol>, FormatError> {
+    let mut reader = Reader::new(payload);
+    let count = reader.u32("symbol count")?;
+    let mut symbols = Vec::new();
+    for _ in 0..count {
+        let kind = match reader.u8("symbol kind")? {
+            0 => SymbolKind::Label,
+            1 => SymbolKind::Data,
+            k => return Err(FormatError::InvalidSection(format!("unknown symbol kind {}", k))),
+        };
+        let address = reader.u32("symbol address")?;
+        let size = reader.u32("symbol size")?;
+        let len = reader.u16("symbol name length")? as usize;
+        let name = std::str::from_utf8(reader.take(len, "symbol name")?)
+            .map_err(|_| FormatError::InvalidSection("symbol name is not UTF-8".to_string()))?;
+        symbols.push(Symbol { name: name.to_string(), kind, address, size });
+    }
+    Ok(symbols)
+}
+
+impl Image {
+    /// total number of cells loaded into memory (text and data)
+    pub fn len(&self) -> u32 {
+        (self.text.len() + self.data.len()) as u32
+    }
+
+    /// true if the image contains neither code nor data
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.data.is_empty()
+    }
+
+    /// text followed by data, ready to be loaded at `origin`
+    pub fn memory(&self) -> Vec<u32> {
+        let mut out = self.text.clone();
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// find symbol by name
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// serialize image into container bytes
+    pub fn write(&self) -> Vec<u8> {
+        let mut sections: Vec<(SectionKind, Vec<u8>)> = Vec::new();
+        let mut text = Vec::new();
+        words_to_bytes(&mut text, &self.text);
+        sections.push((SectionKind::Text, text));
+        if !self.data.is_empty() {
+            let mut data = Vec::new();
+            words_to_bytes(&mut data, &self.data);
+            sections.push((SectionKind::Data, data));
+        }
+        if !self.symbols.is_empty() {
+            sections.push((SectionKind::Symbols, encode_symbols(&self.symbols)));
+        }
+        if let Some(debug) = &self.debug {
+            sections.push((SectionKind::Debug, debug.as_bytes().to_vec()));
+        }
+        write_container(0, [self.origin, self.start, self.min_cells, self.stack_size], sections)
+    }
+
+    /// serialize image into the legacy layout, without symbols, debug information or memory requirements
+    pub fn write_legacy(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        // the legacy start is relative to origin
+        words_to_bytes(&mut out, &[self.origin, self.start.wrapping_sub(self.origin)]);
+        words_to_bytes(&mut out, &self.memory());
+        out
+    }
+
+    /// read image from bytes, accepting both the container and the legacy layout
+    pub fn read(bytes: &[u8]) -> Result<Image, FormatError> {
+        if bytes.starts_with(&MAGIC) {
+            Self::read_container(bytes)
+        } else {
+            Self::read_legacy(bytes)
+        }
+    }
+
+    /// read legacy layout: origin, start relative to origin and raw little-endian words
+    pub fn read_legacy(bytes: &[u8]) -> Result<Image, FormatError> {
+        let mut reader = Reader::new(bytes);
+        let origin = reader.u32("legacy header origin")?;
+        let offset = reader.u32("legacy header start")?;
+        let start = origin.checked_add(offset).ok_or(FormatError::InvalidStart(offset))?;
+        let text = bytes_to_words(&bytes[8..], "legacy body")?;
+        let image = Image { origin, start, text, ..Default::default() };
+        image.validate()?;
+        Ok(image)
+    }
+
+    fn read_container(bytes: &[u8]) -> Result<Image, FormatError> {
+        let container = read_container(bytes)?;
+        if container.flags & FLAG_OBJECT != 0 {
+            return Err(FormatError::UnexpectedKind("relocatable object, link it before execution".to_string()));
+        }
+        let [origin, start, min_cells, stack_size] = container.fields;
+        let mut image = Image { origin, start, min_cells, stack_size, ..Default::default() };
+        for (kind, payload) in container.sections {
+            match kind {
+                SectionKind::Text => image.text = bytes_to_words(payload, "text section")?,
+                SectionKind::Data => image.data = bytes_to_words(payload, "data section")?,
+                SectionKind::Symbols => image.symbols = decode_symbols(payload)?,
+                SectionKind::Debug => image.debug = Some(decode_text(payload, "debug section")?),
+                SectionKind::Imports | SectionKind::Relocations => {
+                    return Err(FormatError::InvalidSection("relocation data in an executable".to_string()));
+                },
+            }
+        }
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// check that the image fits in memory and starts inside its contents
+    pub fn validate(&self) -> Result<(), FormatError> {
+        let end = self.origin as u64 + self.len() as u64;
+        if end > MAX_CELLS as u64 {
+            return Err(FormatError::TooLarge(format!("contents end at cell 0x{:x}", end)));
+        }
+        if self.min_cells > MAX_CELLS {
+            return Err(FormatError::TooLarge(format!("{} minimum memory cells", self.min_cells)));
+        }
+        if self.stack_size > MAX_CELLS {
+            return Err(FormatError::TooLarge(format!("{} stack cells", self.stack_size)));
+        }
+        if (self.start as u64) < self.origin as u64 || self.start as u64 >= end {
+            return Err(FormatError::InvalidStart(self.start));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn decode_text(payload: &[u8], what: &str) -> Result<String, FormatError> {
+    std::str::from_utf8(payload)
+        .map(|s| s.to_string())
+        .map_err(|_| FormatError::InvalidSection(format!("{} is not UTF-8", what)))
+}
+
+/// Raw container: header fields and known sections
+pub(crate) struct Container<'a> {
+    pub flags: u32,
+    /// origin, start, minimum cells and stack size
+    pub fields: [u32; 4],
+    pub sections: Vec<(SectionKind, &'a [u8])>,
+}
+
+pub(crate) fn write_container(flags: u32, fields: [u32; 4], sections: Vec<(SectionKind, Vec<u8>)>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    for v in fields {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+    for (kind, payload) in sections {
+        write_section(&mut out, kind, &payload);
+    }
+    let crc = crc32(&out);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out
+}
+
+pub(crate) fn read_container(bytes: &[u8]) -> Result<Container<'_>, FormatError> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(FormatError::InvalidMagic);
+    }
+    if bytes.len() < HEADER_SIZE + 4 {
+        return Err(FormatError::Truncated("header".to_string()));
+    }
+    let (content, crc) = bytes.split_at(bytes.len() - 4);
+    let expected = u32::from_le_bytes(crc.try_into().unwrap());
+    let actual = crc32(content);
+    let mut reader = Reader::new(content);
+    reader.take(MAGIC.len(), "magic")?;
+    let version = reader.u32("version")?;
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    if expected != actual {
+        return Err(FormatError::ChecksumMismatch { expected, actual });
+    }
+    let flags = reader.u32("flags")?;
+    let fields = [
+        reader.u32("origin")?,
+        reader.u32("start")?,
+        reader.u32("minimum cells")?,
+        reader.u32("stack size")?,
+    ];
+    let count = reader.u32("section count")?;
+    let mut seen: Vec<u32> = Vec::new();
+    let mut sections = Vec::new();
+    for _ in 0..count {
+        let kind = reader.u32("section kind")?;
+        let len = reader.u32("section length")? as usize;
+        let payload = reader.take(len, "section payload")?;
+        reader.take(len.next_multiple_of(4) - len, "section padding")?;
+        if seen.contains(&kind) {
+            return Err(FormatError::DuplicateSection(kind));
+        }
+        seen.push(kind);
+        // unknown sections are skipped so newer writers stay readable
+        if let Some(kind) = SectionKind::from_num(kind) {
+            sections.push((kind, payload));
+        }
+    }
+    if !reader.done() {
+        return Err(FormatError::InvalidSection("trailing bytes after last section".to_string()));
+    }
+    if !seen.contains(&(SectionKind::Text as u32)) {
+        return Err(FormatError::MissingSection("text".to_string()));
+    }
+    Ok(Container { flags, fields, sections })
+}
@@ -0,0 +1,4 @@
+pub mod errors;
+pub mod crc;
+pub mod image;
+pub mod object;
\ No newline at end of file
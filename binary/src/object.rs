@@ -0,0 +1,131 @@
+use crate::{errors::FormatError, image::{bytes_to_words, decode_symbols, decode_text, encode_symbols, read_container, words_to_bytes, write_container, Reader, SectionKind, Symbol, SymbolKind, FLAG_OBJECT}};
+
+/// Relocation target marker for object-local addresses
+const LOCAL_TARGET: u32 = 0xffff_ffff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Symbol an object expects another object to define
+pub struct Import {
+    pub name: String,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a relocated word points to
+pub enum RelocationTarget {
+    /// word holds an address relative to the beginning of the object
+    Local,
+    /// word holds an offset added to the address of `imports[index]`
+    Import(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Word of an object that has to be patched when the object is placed in memory
+pub struct Relocation {
+    /// index of the word in text followed by data
+    pub offset: u32,
+    pub target: RelocationTarget,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// # Object
+///
+/// Relocatable output of the assembler that still has to be linked into an `Image`.
+///
+/// Uses the same container as `Image` with `FLAG_OBJECT` set, addresses in text, data,
+/// exports and debug information are relative to the beginning of the object.
+pub struct Object {
+    /// origin requested with `@org`, used by the linker when this object holds `.start`
+    pub origin: u32,
+    pub text: Vec<u32>,
+    pub data: Vec<u32>,
+    /// symbols visible to other objects
+    pub exports: Vec<Symbol>,
+    pub imports: Vec<Import>,
+    pub relocations: Vec<Relocation>,
+    pub debug: Option<String>,
+}
+
+impl Object {
+    /// find exported symbol by name
+    pub fn export(&self, name: &str) -> Option<&Symbol> {
+        self.exports.iter().find(|s| s.name == name)
+    }
+
+    /// serialize object into container bytes
+    pub fn write(&self) -> Vec<u8> {
+        let mut sections: Vec<(SectionKind, Vec<u8>)> = Vec::new();
+        let mut text = Vec::new();
+        words_to_bytes(&mut text, &self.text);
+        sections.push((SectionKind::Text, text));
+        if !self.data.is_empty() {
+            let mut data = Vec::new();
+            words_to_bytes(&mut data, &self.data);
+            sections.push((SectionKind::Data, data));
+        }
+        sections.push((SectionKind::Symbols, encode_symbols(&self.exports)));
+        let imports: Vec<Symbol> = self.imports.iter()
+            .map(|i| Symbol { name: i.name.clone(), kind: i.kind, address: 0, size: 0 })
+            .collect();
+        sections.push((SectionKind::Imports, encode_symbols(&imports)));
+        let mut relocations = Vec::new();
+        relocations.extend_from_slice(&(self.relocations.len() as u32).to_le_bytes());
+        for r in &self.relocations {
+            let target = match r.target {
+                RelocationTarget::Local => LOCAL_TARGET,
+                RelocationTarget::Import(idx) => idx,
+            };
+            relocations.extend_from_slice(&r.offset.to_le_bytes());
+            relocations.extend_from_slice(&target.to_le_bytes());
+        }
+        sections.push((SectionKind::Relocations, relocations));
+        if let Some(debug) = &self.debug {
+            sections.push((SectionKind::Debug, debug.as_bytes().to_vec()));
+        }
+        write_container(FLAG_OBJECT, [self.origin, 0, 0, 0], sections)
+    }
+
+    /// read object from container bytes
+    pub fn read(bytes: &[u8]) -> Result<Object, FormatError> {
+        let container = read_container(bytes)?;
+        if container.flags & FLAG_OBJECT == 0 {
+            return Err(FormatError::UnexpectedKind("executable image, expected a relocatable object".to_string()));
+        }
+        let mut object = Object { origin: container.fields[0], ..Default::default() };
+        for (kind, payload) in container.sections {
+            match kind {
+                SectionKind::Text => object.text = bytes_to_words(payload, "text section")?,
+                SectionKind::Data => object.data = bytes_to_words(payload, "data section")?,
+                SectionKind::Symbols => object.exports = decode_symbols(payload)?,
+                SectionKind::Imports => {
+                    object.imports = decode_symbols(payload)?.into_iter()
+                        .map(|s| Import { name: s.name, kind: s.kind })
+                        .collect();
+                },
+                SectionKind::Relocations => {
+                    let mut reader = Reader::new(payload);
+                    let count = reader.u32("relocation count")?;
+                    for _ in 0..count {
+                        let offset = reader.u32("relocation offset")?;
+                        let target = match reader.u32("relocation target")? {
+                            LOCAL_TARGET => RelocationTarget::Local,
+                            idx => RelocationTarget::Import(idx),
+                        };
+                        object.relocations.push(Relocation { offset, target });
+                    }
+                },
+                SectionKind::Debug => object.debug = Some(decode_text(payload, "debug section")?),
+            }
+        }
+        let len = (object.text.len() + object.data.len()) as u32;
+        for r in &object.relocations {
+            if r.offset >= len {
+                return Err(FormatError::InvalidSection(format!("relocation at {} is outside of the object", r.offset)));
+            }
+            if let RelocationTarget::Import(idx) = r.target && idx as usize >= object.imports.len() {
+                return Err(FormatError::InvalidSection(format!("relocation refers to unknown import {}", idx)));
+            }
+        }
+        Ok(object)
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+pub mod tests {
+    use binary::{crc::crc32, errors::FormatError, image::{Image, Symbol, SymbolKind, MAGIC, MAX_CELLS}};
+
+    fn sample() -> Image {
+        Image {
+            origin: 16,
+            start: 17,
+            min_cells: 300,
+            stack_size: 256,
+            text: vec![0xf001a001, 10, 0xffff0000],
+            data: vec![72, 105, 0],
+            symbols: vec![
+                Symbol { name: "start".to_string(), kind: SymbolKind::Label, address: 17, size: 0 },
+                Symbol { name: "msg".to_string(), kind: SymbolKind::Data, address: 19, size: 3 },
+            ],
+            debug: Some("myvm-debug 1\n".to_string()),
+        }
+    }
+
+    #[test]
+    pub fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    pub fn roundtrip() {
+        let image = sample();
+        let bytes = image.write();
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(Image::read(&bytes).unwrap(), image);
+        assert_eq!(image.memory(), vec![0xf001a001, 10, 0xffff0000, 72, 105, 0]);
+    }
+
+    #[test]
+    pub fn checksum_mismatch() {
+        let mut bytes = sample().write();
+        bytes[40] ^= 0xff;
+        assert!(matches!(Image::read(&bytes), Err(FormatError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    pub fn truncated() {
+        let bytes = sample().write();
+        assert!(matches!(Image::read(&bytes[..20]), Err(FormatError::Truncated(_))));
+        assert!(matches!(Image::read(&[1, 2, 3]), Err(FormatError::Truncated(_))));
+    }
+
+    #[test]
+    pub fn unsupported_version() {
+        let mut bytes = sample().write();
+        bytes[4] = 9;
+        assert_eq!(Image::read(&bytes), Err(FormatError::UnsupportedVersion(9)));
+    }
+
+    #[test]
+    pub fn invalid_start() {
+        let mut image = sample();
+        image.start = 2;
+        assert_eq!(Image::read(&image.write()), Err(FormatError::InvalidStart(2)));
+    }
+
+    #[test]
+    pub fn legacy() {
+        let mut bytes = Vec::new();
+        for w in [4u32, 0, 0xffff0000] {
+            bytes.extend_from_slice(&w.to_le_bytes());
+        }
+        let image = Image::read(&bytes).unwrap();
+        assert_eq!(image.origin, 4);
+        assert_eq!(image.start, 4);
+        assert_eq!(image.text, vec![0xffff0000]);
+        bytes.push(0);
+        assert!(matches!(Image::read(&bytes), Err(FormatError::InvalidSection(_))));
+        // the start is written relative to origin and read back as an address
+        let image = sample();
+        let bytes = image.write_legacy();
+        assert_eq!(&bytes[..8], &[16, 0, 0, 0, 1, 0, 0, 0]);
+        let read = Image::read(&bytes).unwrap();
+        assert_eq!((read.origin, read.start, read.memory()), (16, 17, image.memory()));
+        assert!(matches!(Image::read_legacy(&[16, 0, 0, 0, 0xf0, 0xff, 0xff, 0xff]), Err(FormatError::InvalidStart(_))));
+    }
+
+    #[test]
+    pub fn too_large() {
+        let mut image = sample();
+        image.origin = 0xffff_fff0;
+        image.start = 0xffff_fff0;
+        assert!(matches!(Image::read(&image.write()), Err(FormatError::TooLarge(_))));
+        let mut image = sample();
+        image.min_cells = MAX_CELLS + 1;
+        assert!(matches!(Image::read(&image.write()), Err(FormatError::TooLarge(_))));
+        let mut image = sample();
+        image.stack_size = u32::MAX;
+        assert!(matches!(Image::read(&image.write()), Err(FormatError::TooLarge(_))));
+    }
+}
@@ -17,18 +17,147 @@ pub enum Commands {
         /// path of output file
         #[arg(short, long)]
         output: String,
+        /// embed debug information (written to `<output>.dbg` with `--legacy`)
+        #[arg(short = 'g', long)]
+        debug: bool,
+        /// stack cells required by the program
+        #[arg(short, long, default_value_t = 256)]
+        stack: u32,
+        /// write the legacy headerless layout (origin, start, words)
+        #[arg(long)]
+        legacy: bool,
+        /// write a relocatable object to be linked with `link`
+        #[arg(long, conflicts_with = "legacy")]
+        object: bool,
+        /// directory searched for `@include` files (can be repeated)
+        #[arg(short = 'I', long = "include")]
+        include: Vec<String>,
+        /// rewrite instruction sequences into cheaper ones and drop unreachable code
+        #[arg(short = 'O', long)]
+        optimize: bool,
+        /// warn about stack underflows and about loops and procedures that change the stack depth
+        #[arg(long, conflicts_with = "object")]
+        verify_stack: bool,
+        /// write a listing with the address and words of every source line
+        #[arg(long, conflicts_with = "object")]
+        listing: Option<String>,
+        /// write a map of every label and `$identifier`
+        #[arg(long, conflicts_with = "object")]
+        map: Option<String>,
+    },
+    /// compile a program written in the structured language into a binary file
+    Build {
+        /// path of source file
+        path: String,
+        /// path of output file
+        #[arg(short, long)]
+        output: String,
+        /// also write the generated assembly
+        #[arg(long)]
+        emit_asm: Option<String>,
+        /// embed debug information for the generated assembly
+        #[arg(short = 'g', long)]
+        debug: bool,
+        /// stack cells required by the program
+        #[arg(short, long, default_value_t = 256)]
+        stack: u32,
+        /// run the peephole optimizer over the generated assembly
+        #[arg(short = 'O', long)]
+        optimize: bool,
     },
+    /// compile a Forth program into a binary file, or start an interactive session without a path
+    Forth {
+        /// path of source file
+        #[arg(requires = "output")]
+        path: Option<String>,
+        /// path of output file
+        #[arg(short, long, requires = "path")]
+        output: Option<String>,
+        /// also write the generated assembly
+        #[arg(long, requires = "path")]
+        emit_asm: Option<String>,
+        /// embed debug information for the generated assembly
+        #[arg(short = 'g', long)]
+        debug: bool,
+        /// stack cells required by the program
+        #[arg(short, long, default_value_t = 256)]
+        stack: u32,
+        /// run the peephole optimizer over the generated assembly
+        #[arg(short = 'O', long)]
+        optimize: bool,
+    },
+    /// link relocatable objects into an executable binary
+    Link {
+        /// object files, text is laid out in the given order
+        #[arg(required = true)]
+        objects: Vec<String>,
+        /// path of output file
+        #[arg(short, long)]
+        output: String,
+        /// stack cells required by the program
+        #[arg(short, long, default_value_t = 256)]
+        stack: u32,
+    },
+    /// rewrite assembly sources in the canonical style
+    Fmt {
+        /// paths of source files
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// only check the files, failing when one of them is not formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// report common mistakes in an assembly program
+    Lint {
+        /// path of source file
+        path: String,
+        /// directory searched for `@include` files (can be repeated)
+        #[arg(short = 'I', long = "include")]
+        include: Vec<String>,
+    },
+    /// assemble and run assembly lines one at a time in an interactive session
+    Repl,
+    /// run a binary under an interactive debugger
+    Debug {
+        /// path of binary file
+        path: String,
+        /// memory cells (defaults to 2048 or what the binary requires)
+        #[arg(short, long)]
+        cells: Option<u32>,
+        /// stack cells (defaults to the stack size stored in the binary)
+        #[arg(short, long)]
+        stack: Option<u32>,
+    },
+    /// serve a binary to gdb over the GDB remote serial protocol
+    Gdb {
+        /// path of binary file
+        path: String,
+        /// local TCP port to listen on
+        #[arg(long, default_value_t = 1234, conflicts_with = "socket")]
+        port: u16,
+        /// Unix socket to listen on instead of a TCP port
+        #[arg(long)]
+        socket: Option<String>,
+        /// memory cells (defaults to 2048 or what the binary requires)
+        #[arg(short, long)]
+        cells: Option<u32>,
+        /// stack cells (defaults to the stack size stored in the binary)
+        #[arg(short, long)]
+        stack: Option<u32>,
+    },
+    /// debug an assembly source or a binary from an editor over the Debug Adapter Protocol on stdio
+    Dap,
     /// execute binary code
     Exec {
         /// path of binary file
         #[arg(short, long)]
         path: String,
-        /// memory cells
-        #[arg(short, long, default_value_t = 2048)]
-        cells: u32,
-        /// stack cells
-        #[arg(short, long, default_value_t = 256)]
-        stack: u32,
+        /// memory cells (defaults to 2048 or what the binary requires)
+        #[arg(short, long)]
+        cells: Option<u32>,
+        /// stack cells (defaults to the stack size stored in the binary)
+        #[arg(short, long)]
+        stack: Option<u32>,
         /// dump memory to stdout after execution
         #[arg(short, long)]
         dump: bool
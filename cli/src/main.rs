@@ -1,51 +1,307 @@
 use std::io::Write;
 use std::io::{Read};
-use std::mem;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
 
-use assembler::compiler::compile;
+use assembler::compiler::{compile_object, compile_with_options, lint, CompileOptions};
+use assembler::linker::link;
+use assembler::loader::{debug_path, load_debug, load_image};
+use assembler::debugger::{Debugger, Reply};
+use assembler::diagnostic::Diagnostic;
+use assembler::format::format_source;
+use assembler::gdb;
+use assembler::repl;
+use binary::image::Image;
+use binary::object::Object;
 use clap::Parser;
-use machine::internal::machine::{Machine, MachineOptions};
+use forth::repl::{Eval, Session, SessionError};
+use machine::internal::machine::Machine;
 
 use crate::args::{Args, Commands};
 
 pub mod args;
 
+fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    for d in diagnostics {
+        eprintln!("{}\n", d);
+    }
+}
+
+/// unwrap a compilation result, printing every diagnostic and exiting on errors
+fn report<T>(result: Result<T, Vec<Diagnostic>>) -> T {
+    result.unwrap_or_else(|diagnostics| {
+        print_diagnostics(&diagnostics);
+        let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+        fail(format!("compilation failed with {} error(s)", errors));
+    })
+}
+
+/// print error and exit with failure status
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// assemble the output of a frontend into a binary file
+fn build(path: &str, assembly: String, output: &str, emit_asm: &Option<String>, debug: bool, stack: u32, optimize: bool) {
+    // debug information refers to the generated assembly, named after where it is written
+    let file = emit_asm.clone().unwrap_or_else(|| format!("{}.asm", path));
+    if let Some(asm_path) = emit_asm {
+        std::fs::write(asm_path, &assembly).expect("unable to write assembly file");
+    }
+    let options = CompileOptions {
+        debug,
+        file: Some(file),
+        optimize,
+        ..Default::default()
+    };
+    let result = report(compile_with_options(assembly, &options));
+    print_diagnostics(&result.warnings);
+    let image = result.image(stack).unwrap_or_else(|e| fail(format!("unable to package '{}': {}", path, e)));
+    std::fs::write(output, image.write()).expect("unable to write in output file");
+}
+
+/// read Forth from stdin line by line until `bye` or the end of input
+fn forth_repl() {
+    let mut session = Session::new().unwrap_or_else(|e| fail(format!("unable to create machine: {}", e)));
+    let stdin = std::io::stdin();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match stdin.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => fail(format!("unable to read input: {}", e)),
+        }
+        if line.trim().eq_ignore_ascii_case("bye") {
+            break;
+        }
+        match session.eval(line.trim_end_matches(['\r', '\n'])) {
+            Ok(Eval::Done) => match session.stack().len() {
+                0 => println!(" ok"),
+                depth => println!(" ok {}", depth),
+            },
+            Ok(Eval::Incomplete) => {},
+            Err(SessionError::Compile(diagnostics)) => print_diagnostics(&diagnostics),
+            Err(SessionError::Runtime(e)) => eprintln!("runtime error: {}", e),
+        }
+        std::io::stdout().flush().expect("unable to write output");
+    }
+}
+
+fn asm_repl() {
+    let mut session = repl::Session::new().unwrap_or_else(|e| fail(format!("unable to create machine: {}", e)));
+    let stdin = std::io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().expect("unable to write output");
+        line.clear();
+        match stdin.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => fail(format!("unable to read input: {}", e)),
+        }
+        match session.eval(&line) {
+            Ok(repl::Eval::Done) => {},
+            Ok(repl::Eval::Output(text)) => println!("{}", text),
+            Ok(repl::Eval::Quit) => break,
+            Err(repl::SessionError::Compile(diagnostics)) => print_diagnostics(&diagnostics),
+            Err(repl::SessionError::Runtime(e)) => eprintln!("runtime error: {}", e),
+            Err(repl::SessionError::StepLimit) => eprintln!("stopped after {} instructions", repl::STEP_LIMIT),
+            Err(repl::SessionError::Command(message)) => eprintln!("error: {}", message),
+        }
+    }
+}
+
+/// read a binary and load it into a machine, sized as given or as the binary requires
+fn read_image(path: &str, cells: Option<u32>, stack: Option<u32>) -> (Image, Machine) {
+    let mut file = std::fs::File::open(path).expect("unable to open binary file");
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer).expect("unable to read binary content");
+    let image = Image::read(&buffer).unwrap_or_else(|e| fail(format!("invalid binary '{}': {}", path, e)));
+    let machine = load_image(&image, cells, stack).unwrap_or_else(|e| fail(e));
+    (image, machine)
+}
+
+/// read debugger commands from stdin until `quit` or the end of input
+fn debug(mut debugger: Debugger) {
+    println!("stopped at entry {}", debugger.location(debugger.machine().register.pc));
+    let stdin = std::io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("(myvm) ");
+        std::io::stdout().flush().expect("unable to write output");
+        line.clear();
+        match stdin.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => fail(format!("unable to read input: {}", e)),
+        }
+        match debugger.command(&line) {
+            Ok(Reply::Output(text)) if text.is_empty() => {},
+            Ok(Reply::Output(text)) => println!("{}", text),
+            Ok(Reply::Quit) => break,
+            Err(message) => eprintln!("error: {}", message),
+        }
+    }
+}
+
 fn main() {
     let cli = Args::parse();
 
     match &cli.command {
-        Some(Commands::Compile { path, output }) => {
+        Some(Commands::Compile { path, output, debug, stack, legacy, object, include, optimize, verify_stack, listing, map }) => {
             let code = std::fs::read_to_string(path.as_str()).expect("unable to open source file");
-            let mut output = std::fs::File::create(output.as_str()).expect("unable to create output file");
+            let options = CompileOptions {
+                debug: *debug,
+                file: Some(path.clone()),
+                include_dirs: include.iter().map(PathBuf::from).collect(),
+                listing: listing.is_some(),
+                map: map.is_some(),
+                optimize: *optimize,
+                verify_stack: *verify_stack,
+            };
+            if *object {
+                let (object, warnings) = report(compile_object(code, &options));
+                print_diagnostics(&warnings);
+                std::fs::write(output, object.write()).expect("unable to write in output file");
+                return;
+            }
             
-            let result = compile(code);
-            output.write_all(&result.header.origin.to_le_bytes()).expect("unable to write in output file");
-            output.write_all(&result.header.start.to_le_bytes()).expect("unable to write in output file");
-            for &num in &result.binary {
-                output.write_all(&num.to_le_bytes()).expect("unable to write in output file");
+            let result = report(compile_with_options(code, &options));
+            print_diagnostics(&result.warnings);
+            if let (Some(path), Some(content)) = (listing, &result.listing) {
+                std::fs::write(path, content.to_string()).expect("unable to write listing file");
+            }
+            if let (Some(path), Some(content)) = (map, &result.map) {
+                std::fs::write(path, content.to_string()).expect("unable to write map file");
+            }
+            let image = result.image(*stack).unwrap_or_else(|e| fail(format!("unable to package '{}': {}", path, e)));
+            if *legacy {
+                if let Some(info) = &result.debug {
+                    std::fs::write(debug_path(output), info.to_string()).expect("unable to write debug file");
+                }
+                std::fs::write(output, image.write_legacy()).expect("unable to write in output file");
+            } else {
+                std::fs::write(output, image.write()).expect("unable to write in output file");
+            }
+        },
+        Some(Commands::Build { path, output, emit_asm, debug, stack, optimize }) => {
+            let code = std::fs::read_to_string(path.as_str()).expect("unable to open source file");
+            let assembly = report(lang::to_assembly(&code, path));
+            build(path, assembly, output, emit_asm, *debug, *stack, *optimize);
+        },
+        Some(Commands::Forth { path: Some(path), output: Some(output), emit_asm, debug, stack, optimize }) => {
+            let code = std::fs::read_to_string(path.as_str()).expect("unable to open source file");
+            let assembly = report(forth::to_assembly(&code, path));
+            build(path, assembly, output, emit_asm, *debug, *stack, *optimize);
+        },
+        Some(Commands::Forth { .. }) => forth_repl(),
+        Some(Commands::Link { objects, output, stack }) => {
+            let mut inputs = Vec::new();
+            for path in objects {
+                let bytes = std::fs::read(path).unwrap_or_else(|e| fail(format!("unable to read '{}': {}", path, e)));
+                let object = Object::read(&bytes).unwrap_or_else(|e| fail(format!("invalid object '{}': {}", path, e)));
+                inputs.push((path.clone(), object));
+            }
+            match link(&inputs, *stack) {
+                Ok(image) => std::fs::write(output, image.write()).expect("unable to write in output file"),
+                Err(errors) => {
+                    for e in &errors {
+                        eprintln!("link error: {}", e);
+                    }
+                    std::process::exit(1);
+                },
+            }
+        },
+        Some(Commands::Fmt { paths, check }) => {
+            let mut failed = false;
+            for path in paths {
+                let code = std::fs::read_to_string(path).unwrap_or_else(|e| fail(format!("unable to read '{}': {}", path, e)));
+                let formatted = match format_source(&code, path) {
+                    Ok(formatted) => formatted,
+                    Err(diagnostics) => {
+                        print_diagnostics(&diagnostics);
+                        failed = true;
+                        continue;
+                    },
+                };
+                if formatted == code {
+                    continue;
+                }
+                if *check {
+                    eprintln!("{} is not formatted", path);
+                    failed = true;
+                } else {
+                    std::fs::write(path, formatted).unwrap_or_else(|e| fail(format!("unable to write '{}': {}", path, e)));
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Lint { path, include }) => {
+            let code = std::fs::read_to_string(path).unwrap_or_else(|e| fail(format!("unable to read '{}': {}", path, e)));
+            let options = CompileOptions {
+                file: Some(path.clone()),
+                include_dirs: include.iter().map(PathBuf::from).collect(),
+                ..Default::default()
+            };
+            let warnings = report(lint(code, &options));
+            print_diagnostics(&warnings);
+            if !warnings.is_empty() {
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Repl) => asm_repl(),
+        Some(Commands::Debug { path, cells, stack }) => {
+            let (image, machine) = read_image(path, *cells, *stack);
+            debug(Debugger::new(machine, load_debug(path, &image)));
+        },
+        Some(Commands::Gdb { path, port, socket, cells, stack }) => {
+            let (image, machine) = read_image(path, *cells, *stack);
+            let debugger = Debugger::new(machine, load_debug(path, &image));
+            let result = match socket {
+                #[cfg(unix)]
+                Some(socket) => {
+                    let listener = UnixListener::bind(socket).unwrap_or_else(|e| fail(format!("unable to listen on '{}': {}", socket, e)));
+                    eprintln!("waiting for gdb on {}", socket);
+                    let served = listener.accept().and_then(|(stream, _)| gdb::serve(stream, debugger));
+                    let _ = std::fs::remove_file(socket);
+                    served
+                },
+                #[cfg(not(unix))]
+                Some(_) => fail("unix sockets are not supported on this platform, listen on a --port instead".to_string()),
+                None => {
+                    let listener = TcpListener::bind(("127.0.0.1", *port)).unwrap_or_else(|e| fail(format!("unable to listen on port {}: {}", port, e)));
+                    eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+                    listener.accept().and_then(|(stream, _)| gdb::serve(stream, debugger))
+                },
+            };
+            if let Err(e) = result {
+                fail(format!("gdb connection failed: {}", e));
+            }
+        },
+        Some(Commands::Dap) => {
+            let stdin = std::io::stdin();
+            let stdout = std::io::stdout();
+            if let Err(e) = dap::server::run(&mut stdin.lock(), &mut stdout.lock()) {
+                fail(format!("debug adapter connection failed: {}", e));
             }
         },
         Some(Commands::Exec { path, cells, stack, dump }) => {
-            let mut file = std::fs::File::open(path).expect("unable to open binary file");
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer).expect("unable to read binary content");
-            if buffer.len() < mem::size_of::<u32>() {
-                panic!("file is too short to contain header");
-            }
-            let origin = u32::from_le_bytes(buffer[..4].try_into().unwrap());
-            let start = u32::from_le_bytes(buffer[4..8].try_into().unwrap());
-            let data: Vec<u32> = buffer[8..]
-                .chunks_exact(4)
-                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
-                .collect();
-            
-            let mut machine = Machine::new(MachineOptions{
-                memory_cells: *cells,
-                memory_stack_size: *stack,
-            }).unwrap();
-            machine.load_data(origin, &data).unwrap();
-            machine.set_start(start);
-            machine.execute().unwrap();
+            let (image, mut machine) = read_image(path, *cells, *stack);
+            if let Err(e) = machine.execute() {
+                let pc = machine.register.pc;
+                match load_debug(path, &image) {
+                    Some(info) => eprintln!("runtime error: {} at {}", e, info.describe(pc)),
+                    None => eprintln!("runtime error: {} at 0x{:08x}", e, pc),
+                }
+                std::process::exit(1);
+            }
             if *dump {
                 println!("{}", machine.memory);
             }
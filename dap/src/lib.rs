@@ -0,0 +1,8 @@
+//! # Debug adapter for myvm programs
+//!
+//! Speaks the Debug Adapter Protocol over stdio: launches an assembly source or a binary,
+//! breakpoints on source lines, stepping into, over and out of procedures, the call stack of
+//! the machine, variables for registers, flags, the stack and data identifiers, and what the
+//! program prints as output events.
+
+pub mod server;
@@ -0,0 +1,424 @@
+use std::{collections::HashMap, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+
+use assembler::{compiler::{compile_with_options, CompileOptions}, debugger::{Debugger, Reply, Resume, Stop}, loader::{load_debug, load_image, DEFAULT_STACK}};
+use binary::image::Image;
+use serde_json::{json, Value};
+use transport::{read_message, write_message};
+
+/// the machine runs a single thread
+const THREAD: u64 = 1;
+
+/// variables references of the scopes
+const REGISTERS: u64 = 1;
+const FLAGS: u64 = 2;
+const STACK: u64 = 3;
+const DATA: u64 = 4;
+/// cells of the data identifier at index `n` of the debug information are `CELLS + n`
+const CELLS: u64 = 5;
+
+/// cells shown in the value of a data identifier before it is expanded
+const PREVIEW: usize = 8;
+
+type Response = Result<Value, String>;
+
+/// Event to send after the response to a request
+type Event = (&'static str, Value);
+
+/// Launched program
+struct Session {
+    debugger: Debugger,
+    stop_on_entry: bool,
+}
+
+#[derive(Default)]
+/// # Debug adapter
+///
+/// Launches one program and answers requests about it. The machine only runs while a request
+/// such as `continue` or `next` is handled, so the events telling why it stopped follow the
+/// response to that request. `pause` cannot interrupt it, but a run gives up after
+/// `STEP_LIMIT` instructions and reports a pause.
+///
+/// The `initialized` event is sent after `launch`, so breakpoints always refer to a loaded
+/// program.
+pub struct Server {
+    seq: u64,
+    session: Option<Session>,
+    /// lines of the breakpoints of every source, by the path the client gave
+    breakpoints: HashMap<String, Vec<usize>>,
+    /// debugger breakpoint numbers placed for every source
+    placed: HashMap<String, Vec<usize>>,
+    /// the client counts lines from 0
+    zero_based: bool,
+    /// `disconnect` was handled
+    done: bool,
+}
+
+/// whether two paths name the same file
+fn same_file(a: &str, b: &str) -> bool {
+    a == b || matches!((std::fs::canonicalize(a), std::fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// source of a file of the debug information, with an absolute path when it exists
+fn source(file: &str) -> Value {
+    let path = std::fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    let name = Path::new(file).file_name().map_or(file.to_string(), |n| n.to_string_lossy().into_owned());
+    json!({ "name": name, "path": path.to_string_lossy() })
+}
+
+fn variable(name: String, value: String, reference: u64) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": reference })
+}
+
+/// machine and debug information of a program, with the compiler warnings of a source
+fn load(args: &Value) -> Result<(Debugger, Vec<String>), String> {
+    let program = args["program"].as_str().ok_or("'launch' needs the path of the 'program' to debug")?;
+    let cells = args["cells"].as_u64().map(|c| c as u32);
+    let stack = args["stack"].as_u64().map(|s| s as u32);
+    if program.ends_with(".asm") {
+        let code = std::fs::read_to_string(program).map_err(|e| format!("unable to read '{}': {}", program, e))?;
+        let include_dirs = args["includeDirs"].as_array().map_or(Vec::new(), |dirs| dirs.iter().filter_map(|d| d.as_str()).map(PathBuf::from).collect());
+        let options = CompileOptions { debug: true, file: Some(program.to_string()), include_dirs, ..Default::default() };
+        let result = compile_with_options(code, &options).map_err(|diagnostics| {
+            diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n\n")
+        })?;
+        let image = result.image(stack.unwrap_or(DEFAULT_STACK)).map_err(|e| format!("unable to package '{}': {}", program, e))?;
+        let machine = load_image(&image, cells, stack)?;
+        let warnings = result.warnings.iter().map(|d| d.to_string()).collect();
+        return Ok((Debugger::new(machine, result.debug), warnings));
+    }
+    let bytes = std::fs::read(program).map_err(|e| format!("unable to read '{}': {}", program, e))?;
+    let image = Image::read(&bytes).map_err(|e| format!("invalid binary '{}': {}", program, e))?;
+    let machine = load_image(&image, cells, stack)?;
+    Ok((Debugger::new(machine, load_debug(program, &image)), Vec::new()))
+}
+
+impl Server {
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn session(&mut self) -> Result<&mut Session, String> {
+        self.session.as_mut().ok_or_else(|| "no program is launched".to_string())
+    }
+
+    /// Place the breakpoints of a source in the debugger, returning them as the client sees
+    /// them
+    fn place_breakpoints(&mut self, path: &str) -> Vec<Value> {
+        let lines = self.breakpoints.get(path).cloned().unwrap_or_default();
+        let offset = self.zero_based as usize;
+        let placed = self.placed.remove(path).unwrap_or_default();
+        let Some(session) = self.session.as_mut() else {
+            return lines.iter().map(|&line| json!({ "verified": false, "line": line, "message": "no program is launched" })).collect();
+        };
+        let debugger = &mut session.debugger;
+        for id in placed {
+            debugger.delete(id);
+        }
+        let file = debugger.info().and_then(|info| info.files.iter().position(|f| same_file(f, path)));
+        let mut ids = Vec::new();
+        let mut found_lines = Vec::new();
+        for line in lines {
+            let found = file.and_then(|file| debugger.info()?.line_at_or_after(file, line + offset)).map(|l| (l.address, l.line));
+            match found {
+                Some((address, actual)) => {
+                    let id = debugger.add_breakpoint(address);
+                    ids.push(id);
+                    found_lines.push((line, Some((id, actual))));
+                },
+                None => found_lines.push((line, None)),
+            }
+        }
+        self.placed.insert(path.to_string(), ids);
+        found_lines.into_iter()
+            .map(|(line, found)| match found {
+                Some((id, actual)) => json!({ "id": id, "verified": true, "line": actual - offset, "source": source(path) }),
+                None => json!({ "verified": false, "line": line, "message": "no code at or after this line" }),
+            })
+            .collect()
+    }
+
+    /// run the program and tell the client why it stopped
+    fn resume(&mut self, mode: Resume, events: &mut Vec<Event>) -> Result<(), String> {
+        let session = self.session()?;
+        let debugger = &mut session.debugger;
+        if !debugger.running() {
+            events.push(("terminated", json!({})));
+            return Ok(());
+        }
+        // stepping out of the outermost frame runs to the end
+        let mode = match mode {
+            Resume::Finish if debugger.backtrace().len() == 1 => Resume::Continue,
+            mode => mode,
+        };
+        let stop = debugger.resume(mode);
+        let output = debugger.machine_mut().take_output();
+        if !output.is_empty() {
+            events.push(("output", json!({ "category": "stdout", "output": output })));
+        }
+        let stopped = |reason: &str| json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true });
+        match stop {
+            Stop::Done => events.push(("stopped", stopped("step"))),
+            Stop::Breakpoint { id, .. } => {
+                let mut body = stopped("breakpoint");
+                body["hitBreakpointIds"] = json!([id]);
+                events.push(("stopped", body));
+            },
+            Stop::Watchpoint { .. } => events.push(("stopped", stopped("data breakpoint"))),
+            Stop::Limit => {
+                let mut body = stopped("pause");
+                body["description"] = json!(debugger.describe_stop(&stop));
+                events.push(("stopped", body));
+            },
+            Stop::Error(ref e) => {
+                let description = debugger.describe_stop(&stop);
+                events.push(("output", json!({ "category": "stderr", "output": format!("{}\n", description) })));
+                let mut body = stopped("exception");
+                body["description"] = json!(description);
+                body["text"] = json!(e.to_string());
+                events.push(("stopped", body));
+            },
+            Stop::Terminated => {
+                events.push(("exited", json!({ "exitCode": 0 })));
+                events.push(("terminated", json!({})));
+            },
+        }
+        Ok(())
+    }
+
+    /// the program was launched or restarted and is configured
+    fn start(&mut self, events: &mut Vec<Event>) -> Result<(), String> {
+        match self.session()?.stop_on_entry {
+            true => {
+                events.push(("stopped", json!({ "reason": "entry", "threadId": THREAD, "allThreadsStopped": true })));
+                Ok(())
+            },
+            false => self.resume(Resume::Continue, events),
+        }
+    }
+
+    fn stack_trace(&mut self, args: &Value) -> Response {
+        let offset = self.zero_based as usize;
+        let debugger = &self.session()?.debugger;
+        let info = debugger.info();
+        let frames: Vec<Value> = debugger.backtrace().iter().enumerate()
+            .map(|(idx, &address)| {
+                let name = info.and_then(|i| i.label_for(address)).map_or(format!("0x{:08x}", address), |l| format!(".{}", l.name));
+                let mut frame = json!({
+                    "id": idx,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:08x}", address),
+                });
+                if let Some((line, file)) = info.and_then(|i| i.line_for(address).map(|l| (l.line, i.files.get(l.file)))) {
+                    frame["line"] = json!(line - offset);
+                    frame["column"] = json!(1);
+                    if let Some(file) = file {
+                        frame["source"] = source(file);
+                    }
+                }
+                frame
+            })
+            .collect();
+        let total = frames.len();
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = args["levels"].as_u64().filter(|l| *l > 0).map_or(total, |l| l as usize);
+        let frames: Vec<Value> = frames.into_iter().skip(start).take(levels).collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": total }))
+    }
+
+    fn variables(&mut self, args: &Value) -> Response {
+        let reference = args["variablesReference"].as_u64().unwrap_or(0);
+        let debugger = &self.session()?.debugger;
+        let machine = debugger.machine();
+        let variables: Vec<Value> = match reference {
+            REGISTERS => {
+                let register = &machine.register;
+                let mut values: Vec<Value> = [register.r0, register.r1, register.r2, register.r3, register.r4, register.r5, register.r6, register.r7]
+                    .iter().enumerate()
+                    .map(|(n, value)| variable(format!("r{}", n), value.to_string(), 0))
+                    .collect();
+                values.push(variable("pc".to_string(), format!("0x{:08x}", register.pc), 0));
+                values
+            },
+            FLAGS => {
+                let flag = &machine.flag;
+                [("zero", flag.zero), ("negative", flag.negative), ("overflow", flag.overflow), ("carry", flag.carry)].iter()
+                    .map(|(name, value)| variable(name.to_string(), value.to_string(), 0))
+                    .collect()
+            },
+            STACK => machine.memory.stack().iter().enumerate().map(|(idx, value)| variable(format!("[{}]", idx), value.to_string(), 0)).collect(),
+            DATA => {
+                let data = debugger.info().map_or(&[][..], |info| &info.data);
+                data.iter().enumerate()
+                    .map(|(idx, symbol)| {
+                        let cells: Vec<String> = (symbol.address..symbol.address + symbol.size.min(PREVIEW as u32 + 1))
+                            .map(|address| machine.memory.read(address).map_or("?".to_string(), |v| v.to_string()))
+                            .collect();
+                        let name = format!("${}", symbol.name);
+                        match symbol.size {
+                            1 => variable(name, cells[0].clone(), 0),
+                            size => {
+                                let more = if size as usize > PREVIEW { ", …" } else { "" };
+                                let value = format!("[{}{}]", cells[..cells.len().min(PREVIEW)].join(", "), more);
+                                let mut variable = variable(name, value, CELLS + idx as u64);
+                                variable["indexedVariables"] = json!(size);
+                                variable
+                            },
+                        }
+                    })
+                    .collect()
+            },
+            reference if reference >= CELLS => {
+                let symbol = debugger.info().and_then(|info| info.data.get((reference - CELLS) as usize)).ok_or("unknown variables reference")?;
+                let start = args["start"].as_u64().unwrap_or(0) as u32;
+                let count = args["count"].as_u64().map_or(symbol.size, |c| c as u32);
+                (start..symbol.size.min(start.saturating_add(count)))
+                    .map(|idx| {
+                        let value = machine.memory.read(symbol.address + idx).map_or("?".to_string(), |v| v.to_string());
+                        variable(format!("[{}]", idx), value, 0)
+                    })
+                    .collect()
+            },
+            _ => return Err("unknown variables reference".to_string()),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn request(&mut self, command: &str, args: &Value, events: &mut Vec<Event>) -> Response {
+        match command {
+            "initialize" => {
+                self.zero_based = args["linesStartAt1"] == false;
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsTerminateRequest": true,
+                    "supportsRestartRequest": true,
+                    "supportsEvaluateForHovers": true,
+                }))
+            },
+            "launch" => {
+                let (mut debugger, warnings) = load(args)?;
+                debugger.machine_mut().capture_output();
+                for warning in warnings {
+                    events.push(("output", json!({ "category": "console", "output": format!("{}\n", warning) })));
+                }
+                self.session = Some(Session { debugger, stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false) });
+                let paths: Vec<String> = self.breakpoints.keys().cloned().collect();
+                for path in paths {
+                    self.place_breakpoints(&path);
+                }
+                events.push(("initialized", json!({})));
+                Ok(Value::Null)
+            },
+            "setBreakpoints" => {
+                let path = args["source"]["path"].as_str().ok_or("breakpoints need the path of their source")?.to_string();
+                let lines: Vec<usize> = match args["breakpoints"].as_array() {
+                    Some(breakpoints) => breakpoints.iter().filter_map(|b| b["line"].as_u64()).map(|l| l as usize).collect(),
+                    None => args["lines"].as_array().map_or(Vec::new(), |l| l.iter().filter_map(|l| l.as_u64()).map(|l| l as usize).collect()),
+                };
+                self.breakpoints.insert(path.clone(), lines);
+                Ok(json!({ "breakpoints": self.place_breakpoints(&path) }))
+            },
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                self.start(events)?;
+                Ok(Value::Null)
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "machine" }] })),
+            "stackTrace" => self.stack_trace(args),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Flags", "variablesReference": FLAGS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+                { "name": "Data", "variablesReference": DATA, "expensive": false },
+            ]})),
+            "variables" => self.variables(args),
+            "continue" => {
+                self.resume(Resume::Continue, events)?;
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "next" => self.resume(Resume::Next, events).map(|_| Value::Null),
+            "stepIn" => self.resume(Resume::Step, events).map(|_| Value::Null),
+            "stepOut" => self.resume(Resume::Finish, events).map(|_| Value::Null),
+            // the machine is never running while a request can arrive
+            "pause" => Ok(Value::Null),
+            "restart" => {
+                let debugger = &mut self.session()?.debugger;
+                debugger.restart();
+                debugger.machine_mut().take_output();
+                self.start(events)?;
+                Ok(Value::Null)
+            },
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default().trim();
+                let debugger = &mut self.session()?.debugger;
+                match debugger.command(&format!("print {}", expression)) {
+                    Ok(Reply::Output(text)) => {
+                        let result = text.split_once(" = ").map_or(text.clone(), |(_, value)| value.to_string());
+                        Ok(json!({ "result": result, "variablesReference": 0 }))
+                    },
+                    Ok(_) => Err(format!("cannot evaluate '{}'", expression)),
+                    Err(message) => Err(message),
+                }
+            },
+            "terminate" => {
+                events.push(("terminated", json!({})));
+                Ok(Value::Null)
+            },
+            "disconnect" => {
+                self.done = true;
+                Ok(Value::Null)
+            },
+            _ => Err(format!("request '{}' is not supported", command)),
+        }
+    }
+
+    /// whether the client disconnected
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    /// handle one message from the client, returning the messages to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        if message["type"] != "request" {
+            return Vec::new();
+        }
+        let command = message["command"].as_str().unwrap_or_default();
+        let mut events = Vec::new();
+        let result = self.request(command, &message["arguments"], &mut events);
+        let seq = self.next_seq();
+        let mut response = json!({
+            "seq": seq,
+            "type": "response",
+            "request_seq": message["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        let mut replies = vec![response];
+        for (event, body) in events {
+            let seq = self.next_seq();
+            replies.push(json!({ "seq": seq, "type": "event", "event": event, "body": body }));
+        }
+        replies
+    }
+}
+
+/// Serve one client until it disconnects or closes its input
+pub fn run(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(reader)? {
+        for reply in server.handle(&message) {
+            write_message(writer, &reply)?;
+        }
+        if server.done() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,232 @@
+#[cfg(test)]
+pub mod tests {
+    use std::{io::{BufReader, Cursor}, path::PathBuf};
+
+    use assembler::compiler::{compile_with_options, CompileOptions};
+    use dap::server::{run, Server};
+    use serde_json::{json, Value};
+    use transport::{read_message, write_message};
+
+    const CODE: &str = "[data]
+$count dw 0
+$table dw 5 times 10
+
+[text]
+.start
+    push 3
+    call .square
+    int 0 4
+    call .bump
+    term
+
+.square
+    dup
+    mul
+    ret
+
+.bump
+    push [$count]
+    push 1
+    add
+    pop [$count + 0]
+    ret
+";
+
+    /// writes `CODE` to `main.asm` in a fresh directory
+    fn program(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("myvm-dap-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.asm");
+        std::fs::write(&path, CODE).unwrap();
+        path
+    }
+
+    /// scripted client of a server
+    struct Client {
+        server: Server,
+        seq: u64,
+    }
+
+    impl Client {
+        /// client of a server that launched `program`, with the breakpoints it placed and the
+        /// events telling where the program first stopped
+        fn launch(program: &PathBuf, stop_on_entry: bool, breakpoints: &[u64]) -> (Client, Vec<Value>, Vec<Value>) {
+            let mut client = Client { server: Server::default(), seq: 0 };
+            let capabilities = client.request("initialize", json!({ "adapterID": "myvm", "linesStartAt1": true }));
+            assert_eq!(capabilities[0]["body"]["supportsConfigurationDoneRequest"], true);
+            let launched = client.request("launch", json!({ "program": program, "stopOnEntry": stop_on_entry }));
+            assert_eq!(launched[0]["success"], true, "{}", launched[0]);
+            assert!(launched.iter().any(|m| m["event"] == "initialized"));
+            let lines: Vec<Value> = breakpoints.iter().map(|l| json!({ "line": l })).collect();
+            let set = client.request("setBreakpoints", json!({ "source": { "path": program }, "breakpoints": lines }));
+            let placed = set[0]["body"]["breakpoints"].as_array().unwrap().clone();
+            let started = client.request("configurationDone", json!({}));
+            assert_eq!(started[0]["success"], true);
+            (client, placed, started[1..].to_vec())
+        }
+
+        /// response and events of a request
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.seq += 1;
+            let replies = self.server.handle(&json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }));
+            assert_eq!(replies[0]["type"], "response");
+            assert_eq!(replies[0]["request_seq"], self.seq);
+            replies
+        }
+
+        fn body(&mut self, command: &str, arguments: Value) -> Value {
+            let replies = self.request(command, arguments);
+            assert_eq!(replies[0]["success"], true, "{}", replies[0]);
+            replies[0]["body"].clone()
+        }
+
+        /// events of a request that runs the program
+        fn run(&mut self, command: &str) -> Vec<Value> {
+            let replies = self.request(command, json!({ "threadId": 1 }));
+            assert_eq!(replies[0]["success"], true, "{}", replies[0]);
+            replies[1..].to_vec()
+        }
+
+        /// line and name of the innermost frame
+        fn top(&mut self) -> (u64, String) {
+            let trace = self.body("stackTrace", json!({ "threadId": 1 }));
+            let frame = &trace["stackFrames"][0];
+            (frame["line"].as_u64().unwrap(), frame["name"].as_str().unwrap().to_string())
+        }
+
+        fn variables(&mut self, reference: u64) -> Vec<(String, String)> {
+            self.body("variables", json!({ "variablesReference": reference }))["variables"].as_array().unwrap().iter()
+                .map(|v| (v["name"].as_str().unwrap().to_string(), v["value"].as_str().unwrap().to_string()))
+                .collect()
+        }
+    }
+
+    fn stopped(events: &[Value]) -> &Value {
+        events.iter().find(|e| e["event"] == "stopped").expect("no stopped event")
+    }
+
+    #[test]
+    pub fn breakpoints_and_call_stack() {
+        let path = program("breakpoints");
+        // line 13 holds the label, so the breakpoint moves to `dup`, line 40 has no code at all
+        let (mut client, placed, events) = Client::launch(&path, false, &[13, 40]);
+        assert_eq!(placed[0]["verified"], true);
+        assert_eq!(placed[0]["line"], 14);
+        assert_eq!(placed[1]["verified"], false);
+        assert_eq!(stopped(&events)["body"]["reason"], "breakpoint");
+        assert_eq!(stopped(&events)["body"]["hitBreakpointIds"], json!([placed[0]["id"]]));
+        let trace = client.body("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(trace["totalFrames"], 2);
+        let frames = trace["stackFrames"].as_array().unwrap();
+        assert_eq!((frames[0]["line"].clone(), frames[0]["name"].clone()), (json!(14), json!(".square")));
+        assert_eq!((frames[1]["line"].clone(), frames[1]["name"].clone()), (json!(8), json!(".start")));
+        assert_eq!(frames[0]["source"]["path"], std::fs::canonicalize(&path).unwrap().to_string_lossy().as_ref());
+        // removing the breakpoint lets the program run to the end
+        let cleared = client.body("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": [] }));
+        assert_eq!(cleared["breakpoints"], json!([]));
+        let events = client.run("continue");
+        let names: Vec<&str> = events.iter().filter_map(|e| e["event"].as_str()).collect();
+        assert_eq!(names, vec!["output", "exited", "terminated"]);
+        assert_eq!(events[0]["body"]["output"], "9");
+    }
+
+    #[test]
+    pub fn stepping() {
+        let path = program("stepping");
+        let (mut client, _, events) = Client::launch(&path, true, &[]);
+        assert_eq!(stopped(&events)["body"]["reason"], "entry");
+        assert_eq!(client.top(), (7, ".start".to_string()));
+        assert_eq!(stopped(&client.run("next"))["body"]["reason"], "step");
+        // stepping over the call stops after it
+        client.run("next");
+        assert_eq!(client.top().0, 9);
+        client.run("next");
+        client.run("stepIn");
+        assert_eq!(client.top(), (19, ".bump".to_string()));
+        client.run("stepIn");
+        assert_eq!(client.top().0, 20);
+        let events = client.run("stepOut");
+        assert_eq!(stopped(&events)["body"]["reason"], "step");
+        assert_eq!(client.top().0, 11);
+        // stepping out of the outermost frame runs to the end
+        let events = client.run("stepOut");
+        assert!(events.iter().any(|e| e["event"] == "terminated"));
+        assert!(client.run("next").iter().any(|e| e["event"] == "terminated"));
+        // a restart runs the program again from its entry point
+        client.request("restart", json!({}));
+        assert_eq!(client.top(), (7, ".start".to_string()));
+    }
+
+    #[test]
+    pub fn variables() {
+        let path = program("variables");
+        let (mut client, _, events) = Client::launch(&path, false, &[22]);
+        assert_eq!(stopped(&events)["body"]["reason"], "breakpoint");
+        let scopes = client.body("scopes", json!({ "frameId": 0 }));
+        let names: Vec<&str> = scopes["scopes"].as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["Registers", "Flags", "Stack", "Data"]);
+        let registers = client.variables(1);
+        assert_eq!(registers.len(), 9);
+        assert_eq!(registers[0], ("r0".to_string(), "0".to_string()));
+        assert!(registers[8].1.starts_with("0x"));
+        assert_eq!(client.variables(2)[0].0, "zero");
+        assert_eq!(client.variables(3), vec![("[0]".to_string(), "1".to_string())]);
+        let data = client.body("variables", json!({ "variablesReference": 4 }));
+        let data = data["variables"].as_array().unwrap();
+        assert_eq!((data[0]["name"].clone(), data[0]["value"].clone(), data[0]["variablesReference"].clone()), (json!("$count"), json!("0"), json!(0)));
+        assert_eq!(data[1]["value"], "[5, 5, 5, 5, 5, 5, 5, 5, …]");
+        assert_eq!(data[1]["indexedVariables"], 10);
+        let cells = data[1]["variablesReference"].as_u64().unwrap();
+        let page = client.body("variables", json!({ "variablesReference": cells, "start": 8, "count": 5 }));
+        assert_eq!(page["variables"].as_array().unwrap().len(), 2);
+        assert_eq!(page["variables"][1]["name"], "[9]");
+        client.run("next");
+        assert_eq!(client.body("evaluate", json!({ "expression": "$count" }))["result"], "1");
+        assert_eq!(client.request("evaluate", json!({ "expression": "r9" }))[0]["message"], "'r9' is not a number");
+        assert_eq!(client.request("variables", json!({ "variablesReference": 99 }))[0]["success"], false);
+    }
+
+    #[test]
+    pub fn binaries_and_framing() {
+        let path = program("binary");
+        let options = CompileOptions { debug: true, file: Some(path.to_string_lossy().into_owned()), ..Default::default() };
+        let result = compile_with_options(CODE.to_string(), &options).unwrap();
+        let binary = path.with_extension("bin");
+        std::fs::write(&binary, result.image(256).unwrap().write()).unwrap();
+
+        let mut input = Vec::new();
+        let requests = [
+            json!({ "seq": 1, "type": "request", "command": "initialize", "arguments": { "linesStartAt1": false } }),
+            json!({ "seq": 2, "type": "request", "command": "launch", "arguments": { "program": binary } }),
+            json!({ "seq": 3, "type": "request", "command": "setBreakpoints", "arguments": { "source": { "path": path }, "breakpoints": [{ "line": 21 }] } }),
+            json!({ "seq": 4, "type": "request", "command": "configurationDone" }),
+            json!({ "seq": 5, "type": "request", "command": "threads" }),
+            json!({ "seq": 6, "type": "request", "command": "readMemory" }),
+            json!({ "seq": 7, "type": "request", "command": "disconnect" }),
+            json!({ "seq": 8, "type": "request", "command": "threads" }),
+        ];
+        for request in &requests {
+            write_message(&mut input, request).unwrap();
+        }
+        let mut output = Vec::new();
+        run(&mut BufReader::new(Cursor::new(input)), &mut output).unwrap();
+        let mut reader = BufReader::new(Cursor::new(output));
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        let responses: Vec<&Value> = messages.iter().filter(|m| m["type"] == "response").collect();
+        // nothing is handled after `disconnect`
+        assert_eq!(responses.len(), 7);
+        assert!(messages.windows(2).all(|m| m[1]["seq"].as_u64() > m[0]["seq"].as_u64()));
+        // lines count from 0, so line 21 is `pop [$count + 0]`
+        assert_eq!(responses[2]["body"]["breakpoints"][0]["line"], 21);
+        let stop = messages.iter().find(|m| m["event"] == "stopped").unwrap();
+        assert_eq!(stop["body"]["reason"], "breakpoint");
+        assert!(messages.iter().any(|m| m["event"] == "output" && m["body"]["output"] == "9"));
+        assert_eq!(responses[4]["body"]["threads"][0]["id"], 1);
+        assert_eq!(responses[5]["success"], false);
+        assert_eq!(responses[6]["command"], "disconnect");
+    }
+}
@@ -14,4 +14,13 @@ $message b "Hello World! This is a test!" 0
 
 push $year
 
+push $name
+int 0 5
+push 10
+int 0 0
+push $message
+int 0 5
+push 10
+int 0 0
+
 term
@@ -0,0 +1,15 @@
+\ print the squares of 1 to 10 and the factorial of 10
+
+: square ( n -- n*n ) dup * ;
+: fact ( n -- n! ) dup 1 > if dup 1- recurse * then ;
+
+variable total
+
+: squares ( n -- )
+    1+ 1 do
+        i square dup . total +!
+    loop ;
+
+." squares: " 10 squares cr
+." sum: " total @ . cr
+." 10! = " 10 fact . cr
@@ -0,0 +1,581 @@
+use std::collections::HashMap;
+
+use assembler::debug::DebugInfo;
+
+use crate::{error::ForthError, lexer::{tokenize, Pos, Token, TokenKind}};
+
+/// cells of the return stack used by `DO LOOP` and `>R`
+pub const RETURN_STACK_CELLS: u32 = 256;
+
+// r7 points to the next free cell of the return stack, r4 to r6 are scratch
+const PRIMITIVES: [(&str, &[&str]); 33] = [
+    ("+", &["add"]),
+    ("-", &["swap", "sub"]),
+    ("*", &["mul"]),
+    ("/", &["swap", "div"]),
+    ("mod", &["swap", "div", "drop", "push r3"]),
+    ("/mod", &["swap", "div", "push r3", "swap"]),
+    ("negate", &["push 0", "sub"]),
+    ("1+", &["push 1", "add"]),
+    ("1-", &["push 1", "swap", "sub"]),
+    ("and", &["and"]),
+    ("or", &["or"]),
+    ("xor", &["xor"]),
+    ("invert", &["not"]),
+    ("lshift", &["pop r5", "shl r5"]),
+    ("rshift", &["pop r5", "shr r5"]),
+    ("dup", &["dup"]),
+    ("drop", &["drop"]),
+    ("swap", &["swap"]),
+    ("over", &["pop r5", "pop r4", "push r4", "push r5", "push r4"]),
+    ("rot", &["pop r5", "pop r4", "pop r6", "push r4", "push r5", "push r6"]),
+    ("nip", &["swap", "drop"]),
+    ("tuck", &["pop r5", "pop r4", "push r5", "push r4", "push r5"]),
+    ("2dup", &["pop r5", "pop r4", "push r4", "push r5", "push r4", "push r5"]),
+    ("2drop", &["drop", "drop"]),
+    (">r", &["pop &r7", "inc r7"]),
+    ("r>", &["dec r7", "move r6 &r7", "push r6"]),
+    ("r@", &["move r6 r7", "dec r6", "move r6 &r6", "push r6"]),
+    // the loop index is on top of the return stack, the limit below it
+    ("i", &["move r6 r7", "dec r6", "move r6 &r6", "push r6"]),
+    ("j", &["move r6 r7", "dec r6", "dec r6", "dec r6", "move r6 &r6", "push r6"]),
+    ("@", &["pop r6", "move r6 &r6", "push r6"]),
+    ("!", &["pop r6", "pop &r6"]),
+    ("+!", &["pop r6", "move r5 &r6", "push r5", "add", "pop &r6"]),
+    ("emit", &["int 0 0"]),
+];
+
+/// comparisons: words, values pushed before comparing and the jump taken when true
+const COMPARISONS: [(&str, &str, &str); 8] = [
+    ("=", "", "jz"),
+    ("<>", "", "jnz"),
+    ("<", "", "jl"),
+    ("<=", "", "jle"),
+    (">", "", "jg"),
+    (">=", "", "jge"),
+    ("0=", "push 0", "jz"),
+    ("0<", "push 0", "jl"),
+];
+
+/// Code or data a word refers to: a label of the unit being compiled, or an address once the
+/// unit defining it has been loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Label(String),
+    Address(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    /// colon definition, called with `call`
+    Word(Target),
+    /// pushes the address of its cell
+    Variable(Target),
+    Constant(u32),
+}
+
+/// Addresses of the runtime support loaded with the first unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Runtime {
+    /// routine printing the signed number on top of the stack
+    pub dot: u32,
+    pub return_stack: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+/// # Dictionary
+///
+/// Words defined so far. Units compiled one after the other into the same machine share one
+/// dictionary, so later units can use what earlier ones defined.
+pub struct Dictionary {
+    entries: HashMap<String, Entry>,
+    pub runtime: Option<Runtime>,
+    /// counter keeping generated names unique
+    next_id: usize,
+}
+
+impl Dictionary {
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(&name.to_lowercase())
+    }
+
+    /// replace labels of the unit just assembled with their addresses
+    pub fn resolve(&mut self, debug: &DebugInfo) {
+        for entry in self.entries.values_mut() {
+            let (Entry::Word(target) | Entry::Variable(target)) = entry else {
+                continue;
+            };
+            let Target::Label(name) = target else {
+                continue;
+            };
+            let address = match name.strip_prefix('$') {
+                Some(data) => debug.data(data).map(|d| d.address),
+                None => debug.label(&name[1..]).map(|l| l.address),
+            };
+            if let Some(address) = address {
+                *target = Target::Address(address);
+            }
+        }
+        if self.runtime.is_none()
+            && let (Some(dot), Some(stack)) = (debug.label("rt.dot"), debug.data("rstack")) {
+            self.runtime = Some(Runtime { dot: dot.address, return_stack: stack.address });
+        }
+    }
+
+    fn unique(&mut self, prefix: &str, name: &str) -> String {
+        self.next_id += 1;
+        // labels only take letters, digits and `_`
+        let readable: String = name.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+        match readable.is_empty() {
+            true => format!("{}.{}", prefix, self.next_id),
+            false => format!("{}.{}.{}", prefix, self.next_id, readable),
+        }
+    }
+}
+
+impl Target {
+    /// operand for `call` or `push`
+    fn operand(&self) -> String {
+        match self {
+            Target::Label(label) => label.clone(),
+            Target::Address(address) => address.to_string(),
+        }
+    }
+}
+
+/// Open control structure
+enum Control {
+    If { otherwise: String, end: Option<String> },
+    Begin { again: String },
+    While { again: String, end: String },
+    Do { again: String },
+}
+
+impl Control {
+    fn opening(&self) -> &'static str {
+        match self {
+            Control::If { .. } => "IF",
+            Control::Begin { .. } => "BEGIN",
+            Control::While { .. } => "WHILE",
+            Control::Do { .. } => "DO",
+        }
+    }
+}
+
+/// Colon definition being compiled
+struct Definition {
+    name: String,
+    label: String,
+    pos: Pos,
+    code: Vec<String>,
+    /// control structures already open when the definition started
+    controls: usize,
+}
+
+struct Compiler<'a> {
+    dictionary: &'a mut Dictionary,
+    errors: Vec<ForthError>,
+    /// code run when the unit is loaded
+    main: Vec<String>,
+    definitions: Vec<String>,
+    data: Vec<String>,
+    current: Option<Definition>,
+    controls: Vec<(Control, Pos)>,
+    /// the last word compiled into `main` was this literal, for `CONSTANT`
+    last_literal: Option<u32>,
+}
+
+/// value of a number literal: decimal, `$` or `0x` hex, `%` binary or a `'c'` character
+pub fn parse_number(text: &str) -> Option<u32> {
+    let chars: Vec<char> = text.chars().collect();
+    if let [ '\'', c, '\''] = chars.as_slice() {
+        return Some(*c as u32);
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix('$').or_else(|| digits.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix('%') {
+        u32::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, line: impl Into<String>) {
+        let line = format!("    {}", line.into());
+        match &mut self.current {
+            Some(definition) => definition.code.push(line),
+            None => self.main.push(line),
+        }
+        self.last_literal = None;
+    }
+
+    fn label(&mut self, label: &str) {
+        let line = format!(".{}", label);
+        match &mut self.current {
+            Some(definition) => definition.code.push(line),
+            None => self.main.push(line),
+        }
+        self.last_literal = None;
+    }
+
+    fn new_label(&mut self) -> String {
+        self.dictionary.unique("l", "")
+    }
+
+    fn error(&mut self, message: impl Into<String>, token: &Token) {
+        self.errors.push(ForthError::new(message, token.pos, token.len));
+    }
+
+    /// name following a defining word
+    fn name_after(&mut self, tokens: &mut std::slice::Iter<Token>, token: &Token) -> Option<String> {
+        match tokens.next() {
+            Some(Token { kind: TokenKind::Word(name), .. }) => Some(name.to_lowercase()),
+            Some(next) => {
+                self.error("expected a name", next);
+                None
+            },
+            None => {
+                let (word, _) = word_text(token);
+                self.errors.push(ForthError::incomplete(format!("{} needs a name", word.to_uppercase()), token.pos, token.len));
+                None
+            },
+        }
+    }
+
+    fn print(&mut self) {
+        let dot = match self.dictionary.runtime {
+            Some(runtime) => runtime.dot.to_string(),
+            None => ".rt.dot".to_string(),
+        };
+        self.emit(format!("call {}", dot));
+    }
+
+    fn compare(&mut self, before: &str, jump: &str) {
+        let (yes, end) = (self.new_label(), self.new_label());
+        if !before.is_empty() {
+            self.emit(before);
+        }
+        self.emit("swap");
+        self.emit("sub");
+        self.emit(format!("{} .{}", jump, yes));
+        self.emit("drop");
+        self.emit("push 0");
+        self.emit(format!("jmp .{}", end));
+        self.label(&yes);
+        self.emit("drop");
+        // true is all bits set
+        self.emit("push 0xffffffff");
+        self.label(&end);
+    }
+
+    fn pop_control(&mut self, token: &Token, expected: &[&str]) -> Option<Control> {
+        let word = word_text(token).0.to_uppercase();
+        match self.controls.pop() {
+            Some((control, _)) if expected.contains(&control.opening()) => Some(control),
+            Some((control, pos)) => {
+                self.error(format!("{} does not match {}", word, control.opening()), token);
+                self.controls.push((control, pos));
+                None
+            },
+            None => {
+                self.error(format!("{} without {}", word, expected.join(" or ")), token);
+                None
+            },
+        }
+    }
+
+    fn control(&mut self, word: &str, token: &Token) -> bool {
+        match word {
+            "if" => {
+                let otherwise = self.new_label();
+                self.emit("pop r5");
+                self.emit(format!("jz .{}", otherwise));
+                self.controls.push((Control::If { otherwise, end: None }, token.pos));
+            },
+            "else" => match self.pop_control(token, &["IF"]) {
+                Some(Control::If { otherwise, end: None }) => {
+                    let end = self.new_label();
+                    self.emit(format!("jmp .{}", end));
+                    self.label(&otherwise);
+                    self.controls.push((Control::If { otherwise, end: Some(end) }, token.pos));
+                },
+                Some(control) => {
+                    self.error("ELSE used twice", token);
+                    self.controls.push((control, token.pos));
+                },
+                None => {},
+            },
+            "then" => if let Some(Control::If { otherwise, end }) = self.pop_control(token, &["IF"]) {
+                self.label(&end.unwrap_or(otherwise));
+            },
+            "begin" => {
+                let again = self.new_label();
+                self.label(&again);
+                self.controls.push((Control::Begin { again }, token.pos));
+            },
+            "until" => if let Some(Control::Begin { again }) = self.pop_control(token, &["BEGIN"]) {
+                self.emit("pop r5");
+                self.emit(format!("jz .{}", again));
+            },
+            "again" => if let Some(Control::Begin { again }) = self.pop_control(token, &["BEGIN"]) {
+                self.emit(format!("jmp .{}", again));
+            },
+            "while" => if let Some(Control::Begin { again }) = self.pop_control(token, &["BEGIN"]) {
+                let end = self.new_label();
+                self.emit("pop r5");
+                self.emit(format!("jz .{}", end));
+                self.controls.push((Control::While { again, end }, token.pos));
+            },
+            "repeat" => if let Some(Control::While { again, end }) = self.pop_control(token, &["WHILE"]) {
+                self.emit(format!("jmp .{}", again));
+                self.label(&end);
+            },
+            "do" => {
+                // limit and then index go to the return stack
+                let again = self.new_label();
+                self.emit("swap");
+                self.emit("pop &r7");
+                self.emit("inc r7");
+                self.emit("pop &r7");
+                self.emit("inc r7");
+                self.label(&again);
+                self.controls.push((Control::Do { again }, token.pos));
+            },
+            "loop" => if let Some(Control::Do { again }) = self.pop_control(token, &["DO"]) {
+                for line in [
+                    "move r6 r7", "dec r6", "move r5 &r6", "inc r5", "push r5", "pop &r6",
+                    "dec r6", "move r4 &r6", "push r4", "push r5", "sub", "drop",
+                ] {
+                    self.emit(line);
+                }
+                self.emit(format!("jl .{}", again));
+                self.emit("dec r7");
+                self.emit("dec r7");
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn word(&mut self, token: &Token, tokens: &mut std::slice::Iter<Token>) {
+        let (text, is_string) = word_text(token);
+        if is_string {
+            let id = self.dictionary.unique("s", "");
+            // backslashes have no special meaning in Forth strings
+            self.data.push(format!("${} b \"{}\" 0", id, text.replace('\\', "\\\\")));
+            self.emit(format!("push ${}", id));
+            self.emit("int 0 5");
+            return;
+        }
+        let word = text.to_lowercase();
+        if self.control(&word, token) {
+            return;
+        }
+        match word.as_str() {
+            ":" => {
+                if let Some(definition) = &self.current {
+                    let message = format!("definition of '{}' is not finished with ;", definition.name);
+                    self.error(message, token);
+                }
+                if let Some(name) = self.name_after(tokens, token) {
+                    let label = self.dictionary.unique("w", &name);
+                    let controls = self.controls.len();
+                    self.current = Some(Definition { name, label, pos: token.pos, code: Vec::new(), controls });
+                }
+                return;
+            },
+            ";" => {
+                let Some(definition) = self.current.take() else {
+                    self.error("; without :", token);
+                    return;
+                };
+                for (control, pos) in self.controls.split_off(definition.controls) {
+                    let message = format!("{} is not closed", control.opening());
+                    self.errors.push(ForthError::new(message, pos, control.opening().len()));
+                }
+                self.definitions.push(format!(".{}", definition.label));
+                self.definitions.extend(definition.code);
+                self.definitions.push("    ret".to_string());
+                self.definitions.push(String::new());
+                let target = Target::Label(format!(".{}", definition.label));
+                self.dictionary.entries.insert(definition.name, Entry::Word(target));
+                return;
+            },
+            "variable" | "constant" if self.current.is_some() => {
+                self.error(format!("{} cannot be used inside a definition", word.to_uppercase()), token);
+                tokens.next();
+                return;
+            },
+            "variable" => {
+                if let Some(name) = self.name_after(tokens, token) {
+                    let id = self.dictionary.unique("v", &name);
+                    self.data.push(format!("${} dw 0", id));
+                    self.dictionary.entries.insert(name, Entry::Variable(Target::Label(format!("${}", id))));
+                }
+                return;
+            },
+            "constant" => {
+                let value = self.last_literal.take();
+                if let Some(name) = self.name_after(tokens, token) {
+                    let Some(value) = value else {
+                        self.error("CONSTANT needs a number right before it", token);
+                        return;
+                    };
+                    self.main.pop();
+                    self.dictionary.entries.insert(name, Entry::Constant(value));
+                }
+                return;
+            },
+            "recurse" => {
+                match &self.current {
+                    Some(definition) => {
+                        let call = format!("call .{}", definition.label);
+                        self.emit(call);
+                    },
+                    None => self.error("RECURSE outside of a definition", token),
+                }
+                return;
+            },
+            "exit" => {
+                match self.current {
+                    Some(_) => self.emit("ret"),
+                    None => self.error("EXIT outside of a definition", token),
+                }
+                return;
+            },
+            "." => return self.print(),
+            "cr" => {
+                self.emit("push 10");
+                self.emit("int 0 0");
+                return;
+            },
+            "space" => {
+                self.emit("push 32");
+                self.emit("int 0 0");
+                return;
+            },
+            _ => {},
+        }
+        match self.dictionary.get(&word).cloned() {
+            Some(Entry::Word(target)) => self.emit(format!("call {}", target.operand())),
+            Some(Entry::Variable(target)) => self.emit(format!("push {}", target.operand())),
+            Some(Entry::Constant(value)) => self.emit(format!("push {}", value)),
+            None => {
+                if let Some((_, code)) = PRIMITIVES.iter().find(|(name, _)| *name == word) {
+                    for line in *code {
+                        self.emit(*line);
+                    }
+                } else if let Some((_, before, jump)) = COMPARISONS.iter().find(|(name, ..)| *name == word) {
+                    self.compare(before, jump);
+                } else if let Some(value) = parse_number(text) {
+                    self.emit(format!("push {}", value));
+                    self.last_literal = Some(value).filter(|_| self.current.is_none());
+                } else {
+                    self.error(format!("undefined word '{}'", text), token);
+                }
+            },
+        }
+    }
+
+}
+
+/// text of a word or string token, and whether it is a string
+fn word_text(token: &Token) -> (&str, bool) {
+    match &token.kind {
+        TokenKind::Word(word) => (word, false),
+        TokenKind::Str(text) => (text, true),
+    }
+}
+
+/// print a signed number followed by a space, `int 0 4` prints numbers as unsigned
+const RUNTIME: &str = ".rt.dot
+    pop r5
+    push r5
+    push 0x80000000
+    and
+    pop r4
+    jz .rt.dot.digits
+    push '-'
+    int 0 0
+    push r5
+    push 0
+    sub
+    pop r5
+.rt.dot.digits
+    push r5
+    int 0 4
+    push 32
+    int 0 0
+    ret
+";
+
+/// # Forth compiler
+///
+/// Translates Forth source into myvm assembly. Code outside of colon definitions runs from
+/// `.start`; each definition becomes a routine called with `call`. The runtime support (the
+/// return stack and number printing) is included when `dictionary` has not loaded it yet, and
+/// `origin` places the unit after the code of earlier units.
+pub fn compile_unit(source: &str, file: &str, dictionary: &mut Dictionary, origin: Option<u32>) -> Result<String, Vec<ForthError>> {
+    let tokens = tokenize(source).map_err(|e| vec![e])?;
+    let runtime = dictionary.runtime.is_none();
+    let mut compiler = Compiler {
+        dictionary,
+        errors: Vec::new(),
+        main: Vec::new(),
+        definitions: Vec::new(),
+        data: Vec::new(),
+        current: None,
+        controls: Vec::new(),
+        last_literal: None,
+    };
+    let mut iter = tokens.iter();
+    while let Some(token) = iter.next() {
+        compiler.word(token, &mut iter);
+    }
+
+    // input ending inside a definition or control structure may be continued
+    if let Some(definition) = &compiler.current {
+        let message = format!("definition of '{}' is not finished with ;", definition.name);
+        compiler.errors.push(ForthError::incomplete(message, definition.pos, 1));
+    } else if let Some((control, pos)) = compiler.controls.last() {
+        let message = format!("{} is not closed", control.opening());
+        compiler.errors.push(ForthError::incomplete(message, *pos, control.opening().len()));
+    }
+    if !compiler.errors.is_empty() {
+        return Err(compiler.errors);
+    }
+
+    let mut out = format!("; generated from {}\n\n", file);
+    if let Some(origin) = origin {
+        out += &format!("@org {}\n\n", origin);
+    }
+    if runtime {
+        compiler.data.insert(0, format!("$rstack resdw {}", RETURN_STACK_CELLS));
+    }
+    if !compiler.data.is_empty() {
+        out += "[data]\n";
+        for line in &compiler.data {
+            out += line;
+            out += "\n";
+        }
+        out += "\n";
+    }
+    out += "[text]\n.start\n";
+    if runtime {
+        out += "    move r7 $rstack\n";
+    }
+    for line in compiler.main.iter().chain(["    term".to_string(), String::new()].iter()).chain(&compiler.definitions) {
+        out += line;
+        out += "\n";
+    }
+    if runtime {
+        out += RUNTIME;
+    }
+    Ok(out)
+}
@@ -0,0 +1,98 @@
+use crate::error::ForthError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Word(String),
+    /// text of `." text"`
+    Str(String),
+}
+
+/// 1-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub pos: Pos,
+    /// length in characters
+    pub len: usize,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    pos: Pos,
+}
+
+impl Lexer<'_> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.chars.peek().copied().filter(|c| !c.is_whitespace()) {
+            word.push(c);
+            self.next();
+        }
+        word
+    }
+
+    /// text up to `end`, which is consumed but not returned
+    fn until(&mut self, end: char) -> Option<String> {
+        let mut text = String::new();
+        loop {
+            match self.next()? {
+                c if c == end => return Some(text),
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+/// split source into words, dropping `\ line` and `( block )` comments
+pub fn tokenize(source: &str) -> Result<Vec<Token>, ForthError> {
+    let mut lexer = Lexer { chars: source.chars().peekable(), pos: Pos { line: 1, column: 1 } };
+    let mut tokens = Vec::new();
+    loop {
+        lexer.skip_whitespace();
+        let start = lexer.pos;
+        let word = lexer.word();
+        let len = word.chars().count();
+        match word.as_str() {
+            "" => return Ok(tokens),
+            "\\" => {
+                lexer.until('\n');
+            },
+            "(" => if lexer.until(')').is_none() {
+                return Err(ForthError::incomplete("unterminated comment", start, 1));
+            },
+            ".\"" => {
+                // a single space separates the word from the text
+                lexer.next();
+                let Some(text) = lexer.until('"') else {
+                    return Err(ForthError::incomplete("unterminated string", start, 2));
+                };
+                let len = if lexer.pos.line == start.line { lexer.pos.column - start.column } else { 2 };
+                tokens.push(Token { kind: TokenKind::Str(text), pos: start, len });
+            },
+            _ => tokens.push(Token { kind: TokenKind::Word(word), pos: start, len }),
+        }
+    }
+}
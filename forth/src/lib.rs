@@ -0,0 +1,27 @@
+//! # Forth frontend
+//!
+//! Compiles Forth to myvm assembly: the data stack is the machine stack, colon definitions are
+//! routines on the call stack and `DO LOOP` keeps its index on a return stack in memory.
+//! `repl::Session` compiles and runs one input line at a time in a single machine.
+
+use assembler::{compiler::{compile_with_options, CompileOptions, CompiledFrame}, diagnostic::Diagnostic};
+
+pub mod compiler;
+pub mod error;
+pub mod lexer;
+pub mod repl;
+
+/// translate a program into myvm assembly
+pub fn to_assembly(source: &str, file: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut dictionary = compiler::Dictionary::default();
+    compiler::compile_unit(source, file, &mut dictionary, None)
+        .map_err(|errors| errors.iter().map(|e| e.to_diagnostic(source, file)).collect())
+}
+
+/// compile a program into the frame `assembler::compiler::compile_with_options` produces,
+/// `options.file` names the program in diagnostics
+pub fn compile(source: &str, options: &CompileOptions) -> Result<CompiledFrame, Vec<Diagnostic>> {
+    let file = options.file.as_deref().unwrap_or("<source>");
+    let assembly = to_assembly(source, file)?;
+    compile_with_options(assembly, options)
+}
@@ -0,0 +1,94 @@
+use assembler::{compiler::{compile_with_options, CompileOptions}, diagnostic::Diagnostic};
+use machine::{errors::VMError, internal::machine::{Machine, MachineOptions}};
+
+use crate::compiler::{compile_unit, Dictionary};
+
+/// memory cells of a session machine
+pub const SESSION_CELLS: u32 = 1 << 16;
+/// stack cells of a session machine
+pub const SESSION_STACK: u32 = 1024;
+
+/// name of the input in diagnostics
+const INPUT: &str = "<input>";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Eval {
+    /// the input was compiled and run
+    Done,
+    /// the input ends inside a definition, comment, string or control structure
+    Incomplete,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Compile(Vec<Diagnostic>),
+    /// the data and return stacks are emptied after a runtime error
+    Runtime(VMError),
+}
+
+/// # Interactive Forth session
+///
+/// Every input is compiled into a unit placed after the previous ones and run at once in the
+/// same machine, so definitions, variables and the data stack carry over from one input to
+/// the next.
+pub struct Session {
+    pub machine: Machine,
+    dictionary: Dictionary,
+    /// first free address after the units loaded so far
+    next: u32,
+    /// lines of an input that is not complete yet
+    pending: String,
+}
+
+impl Session {
+    pub fn new() -> Result<Session, VMError> {
+        let machine = Machine::new(MachineOptions { memory_cells: SESSION_CELLS, memory_stack_size: SESSION_STACK })?;
+        Ok(Session { machine, dictionary: Dictionary::default(), next: 0, pending: String::new() })
+    }
+
+    /// data stack from the bottom to the top
+    pub fn stack(&self) -> Vec<u32> {
+        self.machine.memory.stack()
+    }
+
+    /// compile and run one line of input, or keep it until the lines given so far are complete
+    pub fn eval(&mut self, line: &str) -> Result<Eval, SessionError> {
+        self.pending += line;
+        self.pending += "\n";
+        let source = std::mem::take(&mut self.pending);
+        let mut dictionary = self.dictionary.clone();
+        let assembly = match compile_unit(&source, INPUT, &mut dictionary, Some(self.next)) {
+            Ok(assembly) => assembly,
+            Err(errors) if errors.iter().all(|e| e.incomplete) => {
+                self.pending = source;
+                return Ok(Eval::Incomplete);
+            },
+            Err(errors) => {
+                let diagnostics = errors.iter().map(|e| e.to_diagnostic(&source, INPUT)).collect();
+                return Err(SessionError::Compile(diagnostics));
+            },
+        };
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let frame = compile_with_options(assembly, &options).map_err(SessionError::Compile)?;
+        self.machine.load_data(frame.header.origin, &frame.binary).map_err(SessionError::Runtime)?;
+        if let Some(debug) = &frame.debug {
+            dictionary.resolve(debug);
+        }
+        self.dictionary = dictionary;
+        self.next = frame.header.origin + frame.binary.len() as u32;
+        self.machine.set_start(frame.header.start);
+        self.machine.execute().map_err(|e| {
+            self.reset();
+            SessionError::Runtime(e)
+        })?;
+        Ok(Eval::Done)
+    }
+
+    /// empty the data and return stacks
+    fn reset(&mut self) {
+        while self.machine.memory.pop().is_ok() {}
+        if let Some(runtime) = self.dictionary.runtime {
+            self.machine.register.r7 = runtime.return_stack;
+        }
+    }
+}
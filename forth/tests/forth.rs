@@ -0,0 +1,129 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::compiler::CompileOptions;
+    use forth::{compile, repl::{Eval, Session, SessionError}, to_assembly};
+    use machine::internal::machine::{Machine, MachineOptions};
+
+    /// run a program and read the data stack it leaves
+    fn run(source: &str) -> Vec<u32> {
+        let res = compile(source, &CompileOptions::default()).unwrap();
+        let mut machine = Machine::new(MachineOptions { memory_cells: 4096, memory_stack_size: 256 }).unwrap();
+        machine.load_data(res.header.origin, &res.binary).unwrap();
+        machine.set_start(res.header.start);
+        machine.execute().unwrap();
+        machine.memory.stack()
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        to_assembly(source, "test.fs").unwrap_err().into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    pub fn arithmetic_and_stack_words() {
+        assert_eq!(run("2 3 + 10 4 - 6 7 *"), [5, 6, 42]);
+        assert_eq!(run("17 5 / 17 5 mod 17 5 /mod"), [3, 2, 2, 3]);
+        assert_eq!(run("5 negate 1+ 1 1-"), [(-4i32) as u32, 0]);
+        assert_eq!(run("1 2 over 3 4 swap 5 6 rot"), [1, 2, 1, 4, 5, 6, 3]);
+        assert_eq!(run("1 2 nip 3 tuck 4 5 2dup 2drop drop"), [3, 2, 3, 4]);
+        assert_eq!(run("1 4 lshift 256 4 rshift 12 10 and 12 10 or 12 10 xor 0 invert"), [16, 16, 8, 14, 6, 0xffffffff]);
+        assert_eq!(run("$ff 0x10 %101 'A' -3"), [255, 16, 5, 65, (-3i32) as u32]);
+    }
+
+    #[test]
+    pub fn comparisons() {
+        let t = 0xffffffff;
+        assert_eq!(run("1 1 = 1 2 = 1 2 <> -1 0 < 0 -1 < 2 2 <= 3 2 > 2 3 >= 0 0= -5 0<"), [t, 0, t, t, 0, t, t, 0, t, t]);
+    }
+
+    #[test]
+    pub fn definitions_and_control_flow() {
+        let source = "
+: square ( n -- n*n ) dup * ;
+: abs dup 0< if negate then ;
+: sign dup 0< if drop -1 else 0= if 0 else 1 then then ;
+: fact ( n -- n! ) dup 1 > if dup 1- recurse * then ;
+: sum-to ( n -- sum ) 0 swap 1+ 1 do i + loop ;
+: countdown ( n -- 0 ) begin 1- dup 0= until ;
+: halve ( n -- steps ) 0 swap begin dup 1 > while 2 / swap 1+ swap repeat drop ;
+: table 0 3 0 do 3 0 do i j * + loop loop ;
+: early 10 0 do i 3 = if i exit then loop 99 ;
+7 square -9 abs -4 sign 0 sign 9 sign 5 fact 10 sum-to 5 countdown 64 halve table early
+";
+        assert_eq!(run(source), [49, 9, (-1i32) as u32, 0, 1, 120, 55, 0, 6, 9, 3]);
+    }
+
+    #[test]
+    pub fn variables_and_constants() {
+        let source = "
+variable counter
+10 constant ten
+: bump counter @ ten + counter ! ;
+bump bump 5 counter +! counter @ ten
+";
+        assert_eq!(run(source), [25, 10]);
+    }
+
+    #[test]
+    pub fn generated_assembly() {
+        let assembly = to_assembly(": hi .\" hi\" cr ; hi 42 .", "test.fs").unwrap();
+        assert!(assembly.contains("$rstack resdw 256"));
+        assert!(assembly.contains("$s.2 b \"hi\" 0"));
+        assert!(assembly.contains("call .w.1.hi"));
+        assert!(assembly.contains("call .rt.dot"));
+        assert!(assembly.contains("int 0 0"));
+    }
+
+    #[test]
+    pub fn errors_are_reported() {
+        assert_eq!(errors("foo"), ["undefined word 'foo'"]);
+        assert_eq!(errors("then"), ["THEN without IF"]);
+        assert_eq!(errors(": a ; ;"), ["; without :"]);
+        assert_eq!(errors(": a variable x ;"), ["VARIABLE cannot be used inside a definition"]);
+        assert_eq!(errors("dup constant x"), ["CONSTANT needs a number right before it"]);
+        assert_eq!(errors(": a 1 if ;"), ["IF is not closed"]);
+        assert_eq!(errors(": a"), ["definition of 'a' is not finished with ;"]);
+        assert_eq!(errors("( comment"), ["unterminated comment"]);
+
+        let diagnostic = to_assembly("1 2 +\n  oops", "test.fs").unwrap_err().remove(0);
+        assert_eq!((diagnostic.file.as_str(), diagnostic.line, diagnostic.column, diagnostic.len), ("test.fs", 2, 3, 4));
+    }
+
+    #[test]
+    pub fn session_keeps_definitions_and_stack() {
+        let mut session = Session::new().unwrap();
+        assert_eq!(session.eval(": double 2 * ;").unwrap(), Eval::Done);
+        assert_eq!(session.eval("variable total").unwrap(), Eval::Done);
+        assert_eq!(session.eval("21 double").unwrap(), Eval::Done);
+        assert_eq!(session.stack(), [42]);
+        assert_eq!(session.eval("total ! 3 total +!").unwrap(), Eval::Done);
+        assert_eq!(session.eval("total @ double").unwrap(), Eval::Done);
+        assert_eq!(session.stack(), [90]);
+    }
+
+    #[test]
+    pub fn session_continues_incomplete_input() {
+        let mut session = Session::new().unwrap();
+        assert_eq!(session.eval(": triple").unwrap(), Eval::Incomplete);
+        assert_eq!(session.eval("  3 * ( times").unwrap(), Eval::Incomplete);
+        assert_eq!(session.eval("three ) ;").unwrap(), Eval::Done);
+        assert_eq!(session.eval("5 0 do").unwrap(), Eval::Incomplete);
+        assert_eq!(session.eval("i triple loop").unwrap(), Eval::Done);
+        assert_eq!(session.stack(), [0, 3, 6, 9, 12]);
+    }
+
+    #[test]
+    pub fn session_recovers_from_errors() {
+        let mut session = Session::new().unwrap();
+        session.eval("1 2").unwrap();
+        match session.eval("3 nothing") {
+            Err(SessionError::Compile(diagnostics)) => assert_eq!(diagnostics[0].message, "undefined word 'nothing'"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(session.stack(), [1, 2]);
+        session.eval(": deep 5 0 do 1 0 / loop ;").unwrap();
+        assert!(matches!(session.eval("deep"), Err(SessionError::Runtime(_))));
+        assert_eq!(session.stack(), Vec::<u32>::new());
+        session.eval("3 0 do i loop").unwrap();
+        assert_eq!(session.stack(), [0, 1, 2]);
+    }
+}
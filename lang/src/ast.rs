@@ -0,0 +1,98 @@
+use crate::lexer::Pos;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Identifier together with where it was written
+pub struct Name {
+    pub text: String,
+    pub pos: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub globals: Vec<Var>,
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// `var name = value;`, `var name[size];` or `var name[size] = {a, b};`
+pub struct Var {
+    pub name: Name,
+    /// number of elements for arrays
+    pub size: Option<u32>,
+    pub init: Init,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Init {
+    Zero,
+    Value(Expr),
+    /// array elements, the rest are zero
+    List(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: Name,
+    pub params: Vec<Name>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    Var(Var),
+    /// `name = value;` or `name[index] = value;`
+    Assign(Name, Option<Expr>, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Break(Pos),
+    Continue(Pos),
+    Print(Vec<PrintArg>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrintArg {
+    /// string literal as written
+    Str(String),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(u32),
+    Var(Name),
+    Index(Name, Box<Expr>),
+    Call(Name, Vec<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
@@ -0,0 +1,571 @@
+use std::collections::HashMap;
+
+use crate::{ast::{BinaryOp, Expr, Function, Init, Name, PrintArg, Program, Stmt, UnaryOp, Var}, error::LangError, lexer::Pos};
+
+/// cells of the frame area holding parameters and locals of every active call
+pub const FRAME_CELLS: u32 = 1024;
+
+// r7 holds the frame of the running function as an index into `$frames`, r6 addresses
+// frame cells and passes the frame of a callee, r5 and r4 are scratch
+const FRAME: &str = "r7";
+const ADDRESS: &str = "r6";
+const SCRATCH: &str = "r5";
+
+/// Variable as seen from the code using it
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    /// first cell within the frame, and number of elements for arrays
+    Local(u32, Option<u32>),
+    Global(Option<u32>),
+}
+
+struct Generator<'a> {
+    text: Vec<String>,
+    strings: Vec<String>,
+    errors: Vec<LangError>,
+    labels: usize,
+    /// parameter count by function name
+    functions: HashMap<&'a str, usize>,
+    globals: HashMap<&'a str, Option<u32>>,
+    /// state of the function being generated
+    function: &'a str,
+    scopes: Vec<HashMap<&'a str, Place>>,
+    next_slot: u32,
+    frame_size: u32,
+    /// continue and break labels of the enclosing loops
+    loops: Vec<(String, String)>,
+}
+
+/// error at `name`, quoted in place of `{}` in `message`
+fn name_error(message: &str, name: &Name) -> LangError {
+    let message = message.replace("{}", &format!("'{}'", name.text));
+    LangError::new(message, name.pos, name.text.chars().count())
+}
+
+/// value of a constant expression initializing global `var`
+fn fold(expr: &Expr, var: &Name) -> Result<u32, LangError> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Unary(op, value) => {
+            let value = fold(value, var)?;
+            Ok(match op {
+                UnaryOp::Neg => value.wrapping_neg(),
+                UnaryOp::Not => (value == 0) as u32,
+                UnaryOp::BitNot => !value,
+            })
+        },
+        Expr::Binary(op, lhs, rhs) => {
+            let (a, b) = (fold(lhs, var)?, fold(rhs, var)?);
+            let (sa, sb) = (a as i32, b as i32);
+            Ok(match op {
+                BinaryOp::Add => a.wrapping_add(b),
+                BinaryOp::Sub => a.wrapping_sub(b),
+                BinaryOp::Mul => a.wrapping_mul(b),
+                BinaryOp::Div | BinaryOp::Mod if b == 0 => return Err(name_error("division by zero initializing {}", var)),
+                BinaryOp::Div => a / b,
+                BinaryOp::Mod => a % b,
+                BinaryOp::BitAnd => a & b,
+                BinaryOp::BitOr => a | b,
+                BinaryOp::BitXor => a ^ b,
+                BinaryOp::Shl => a.checked_shl(b).unwrap_or(0),
+                BinaryOp::Shr => a.checked_shr(b).unwrap_or(0),
+                BinaryOp::Eq => (a == b) as u32,
+                BinaryOp::Ne => (a != b) as u32,
+                BinaryOp::Lt => (sa < sb) as u32,
+                BinaryOp::Le => (sa <= sb) as u32,
+                BinaryOp::Gt => (sa > sb) as u32,
+                BinaryOp::Ge => (sa >= sb) as u32,
+                BinaryOp::And => (a != 0 && b != 0) as u32,
+                BinaryOp::Or => (a != 0 || b != 0) as u32,
+            })
+        },
+        Expr::Var(name) | Expr::Index(name, _) | Expr::Call(name, _) => {
+            Err(LangError::new("global initializers must be constant", name.pos, name.text.chars().count()))
+        },
+    }
+}
+
+impl<'a> Generator<'a> {
+    fn emit(&mut self, line: impl Into<String>) {
+        self.text.push(format!("    {}", line.into()));
+    }
+
+    fn label(&mut self, label: &str) {
+        self.text.push(format!(".{}", label));
+    }
+
+    /// label unique to this program, named after the function it is in
+    fn new_label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+        format!("fn.{}.{}{}", self.function, kind, self.labels)
+    }
+
+    fn lookup(&self, name: &Name) -> Option<Place> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name.text.as_str()).copied())
+            .or_else(|| self.globals.get(name.text.as_str()).map(|size| Place::Global(*size)))
+    }
+
+    /// variable used as a scalar or indexed as an array, as `indexed` says
+    fn place(&mut self, name: &Name, indexed: bool) -> Option<Place> {
+        let Some(place) = self.lookup(name) else {
+            self.errors.push(name_error("undefined variable {}", name));
+            return None;
+        };
+        let array = matches!(place, Place::Local(_, Some(_)) | Place::Global(Some(_)));
+        match (array, indexed) {
+            (true, false) => self.errors.push(name_error("array {} cannot be used as a value", name)),
+            (false, true) => self.errors.push(name_error("variable {} is not an array", name)),
+            _ => return Some(place),
+        }
+        None
+    }
+
+    /// leave the frame address of local `slot` in r6
+    fn frame_address(&mut self, slot: u32) {
+        if slot == 0 {
+            self.emit(format!("move {} {}", ADDRESS, FRAME));
+            return;
+        }
+        self.emit(format!("push {}", FRAME));
+        self.emit(format!("push {}", slot));
+        self.emit("add");
+        self.emit(format!("pop {}", ADDRESS));
+    }
+
+    /// leave in r6 the address of the element whose index is on the stack, relative to the
+    /// array start for globals and to `$frames` for locals
+    fn element_address(&mut self, place: Place) {
+        if let Place::Local(slot, _) = place {
+            self.emit(format!("push {}", FRAME));
+            self.emit("add");
+            if slot > 0 {
+                self.emit(format!("push {}", slot));
+                self.emit("add");
+            }
+        }
+        self.emit(format!("pop {}", ADDRESS));
+    }
+
+    /// operand addressing a variable once its address is set up
+    fn operand(name: &Name, place: Place) -> String {
+        match place {
+            Place::Local(..) => format!("[$frames + {}]", ADDRESS),
+            Place::Global(Some(_)) => format!("[$var.{} + {}]", name.text, ADDRESS),
+            Place::Global(None) => format!("[$var.{}]", name.text),
+        }
+    }
+
+    /// pop the value on top of the stack into a variable or array element
+    fn store(&mut self, name: &Name, index: Option<&'a Expr>, place: Place) {
+        match (place, index) {
+            (Place::Local(slot, None), _) => self.frame_address(slot),
+            (_, Some(index)) => {
+                self.expr(index);
+                self.element_address(place);
+            },
+            _ => {},
+        }
+        self.emit(format!("pop {}", Self::operand(name, place)));
+    }
+
+    fn declare(&mut self, name: &'a Name, size: Option<u32>) -> u32 {
+        let slot = self.next_slot;
+        self.next_slot += size.unwrap_or(1);
+        self.frame_size = self.frame_size.max(self.next_slot);
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.insert(&name.text, Place::Local(slot, size)).is_some() {
+            self.errors.push(name_error("variable {} is already declared in this scope", name));
+        }
+        slot
+    }
+
+    fn local(&mut self, var: &'a Var) {
+        match (var.size, &var.init) {
+            (None, init) => {
+                match init {
+                    Init::Value(value) => self.expr(value),
+                    _ => self.emit("push 0"),
+                }
+                let slot = self.declare(&var.name, None);
+                self.store(&var.name, None, Place::Local(slot, None));
+            },
+            (Some(size), init) => {
+                let values = match init {
+                    Init::List(values) => values.as_slice(),
+                    _ => &[],
+                };
+                if values.len() > size as usize {
+                    self.errors.push(name_error(&format!("{} values given for the {} elements of {{}}", values.len(), size), &var.name));
+                }
+                let slot = self.declare(&var.name, Some(size));
+                // zero every element, from the last one down
+                let again = self.new_label("zero");
+                self.emit(format!("move {} {}", SCRATCH, size - 1));
+                self.label(&again);
+                self.emit(format!("push {}", SCRATCH));
+                self.element_address(Place::Local(slot, Some(size)));
+                self.emit("push 0");
+                self.emit(format!("pop [$frames + {}]", ADDRESS));
+                self.emit(format!("dec {}", SCRATCH));
+                self.emit(format!("jge .{}", again));
+                for (idx, value) in values.iter().enumerate() {
+                    self.expr(value);
+                    self.frame_address(slot + idx as u32);
+                    self.emit(format!("pop [$frames + {}]", ADDRESS));
+                }
+            },
+        }
+    }
+
+    fn block(&mut self, stmts: &'a [Stmt]) {
+        let next_slot = self.next_slot;
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+        self.next_slot = next_slot;
+    }
+
+    /// jump to `target` when the value on top of the stack is zero
+    fn branch_if_zero(&mut self, target: &str) {
+        self.emit(format!("pop {}", SCRATCH));
+        self.emit(format!("jz .{}", target));
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Var(var) => self.local(var),
+            Stmt::Assign(name, index, value) => {
+                self.expr(value);
+                if let Some(place) = self.place(name, index.is_some()) {
+                    self.store(name, index.as_ref(), place);
+                }
+            },
+            Stmt::If(cond, then, otherwise) => {
+                let (other, end) = (self.new_label("else"), self.new_label("endif"));
+                self.expr(cond);
+                self.branch_if_zero(&other);
+                self.block(then);
+                if otherwise.is_empty() {
+                    self.label(&other);
+                    return;
+                }
+                self.emit(format!("jmp .{}", end));
+                self.label(&other);
+                self.block(otherwise);
+                self.label(&end);
+            },
+            Stmt::While(cond, body) => {
+                let (again, end) = (self.new_label("while"), self.new_label("endwhile"));
+                self.label(&again);
+                self.expr(cond);
+                self.branch_if_zero(&end);
+                self.loops.push((again.clone(), end.clone()));
+                self.block(body);
+                self.loops.pop();
+                self.emit(format!("jmp .{}", again));
+                self.label(&end);
+            },
+            Stmt::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => self.emit("push 0"),
+                }
+                self.emit("ret");
+            },
+            Stmt::Break(pos) | Stmt::Continue(pos) => {
+                let is_break = matches!(stmt, Stmt::Break(_));
+                let word = if is_break { "break" } else { "continue" };
+                match self.loops.last() {
+                    Some((again, end)) => {
+                        let target = if is_break { end.clone() } else { again.clone() };
+                        self.emit(format!("jmp .{}", target));
+                    },
+                    None => self.errors.push(LangError::new(format!("'{}' outside of a loop", word), *pos, word.len())),
+                }
+            },
+            Stmt::Print(args) => for arg in args {
+                match arg {
+                    PrintArg::Str(text) => {
+                        self.emit(format!("push $str.{}", self.strings.len()));
+                        self.emit("int 0 5");
+                        self.strings.push(text.clone());
+                    },
+                    PrintArg::Expr(value) => {
+                        self.expr(value);
+                        self.emit("safecall .rt.print_int");
+                    },
+                }
+            },
+            Stmt::Expr(value) => {
+                self.expr(value);
+                self.emit("drop");
+            },
+        }
+    }
+
+    /// push 1 or 0 depending on a jump taken after flags are set by `sub`, which left its
+    /// result on the stack
+    fn compare(&mut self, jump: &str) {
+        let (yes, end) = (self.new_label("true"), self.new_label("endcmp"));
+        self.emit(format!("{} .{}", jump, yes));
+        self.emit("drop");
+        self.emit("push 0");
+        self.emit(format!("jmp .{}", end));
+        self.label(&yes);
+        self.emit("drop");
+        self.emit("push 1");
+        self.label(&end);
+    }
+
+    /// `&&` and `||`: the right side is only evaluated when the left one does not decide
+    fn logical(&mut self, op: BinaryOp, lhs: &'a Expr, rhs: &'a Expr) {
+        let (short, end) = (self.new_label("short"), self.new_label("endlogic"));
+        let jump = if op == BinaryOp::And { "jz" } else { "jnz" };
+        for side in [lhs, rhs] {
+            self.expr(side);
+            self.emit(format!("pop {}", SCRATCH));
+            self.emit(format!("{} .{}", jump, short));
+        }
+        let (decided, otherwise) = if op == BinaryOp::And { (0, 1) } else { (1, 0) };
+        self.emit(format!("push {}", otherwise));
+        self.emit(format!("jmp .{}", end));
+        self.label(&short);
+        self.emit(format!("push {}", decided));
+        self.label(&end);
+    }
+
+    /// push the value of an expression
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Number(n) => self.emit(format!("push {}", n)),
+            Expr::Var(name) => {
+                if let Some(place) = self.place(name, false) {
+                    if let Place::Local(slot, _) = place {
+                        self.frame_address(slot);
+                    }
+                    self.emit(format!("push {}", Self::operand(name, place)));
+                }
+            },
+            Expr::Index(name, index) => {
+                self.expr(index);
+                if let Some(place) = self.place(name, true) {
+                    self.element_address(place);
+                    self.emit(format!("push {}", Self::operand(name, place)));
+                }
+            },
+            Expr::Call(name, args) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                match self.functions.get(name.text.as_str()) {
+                    None => self.errors.push(name_error("undefined function {}", name)),
+                    Some(&count) if count != args.len() => self.errors.push(name_error(
+                        &format!("function {{}} takes {} argument(s), {} given", count, args.len()), name,
+                    )),
+                    _ => {},
+                }
+                // the callee frame starts right after the caller's
+                self.emit(format!("push {}", FRAME));
+                self.emit(format!("push frame_{}", self.function));
+                self.emit("add");
+                self.emit(format!("pop {}", ADDRESS));
+                self.emit(format!("safecall .fn.{}", name.text));
+            },
+            Expr::Unary(op, value) => {
+                self.expr(value);
+                match op {
+                    UnaryOp::Neg => {
+                        self.emit("push 0");
+                        self.emit("sub");
+                    },
+                    UnaryOp::BitNot => self.emit("not"),
+                    UnaryOp::Not => {
+                        // compare with zero
+                        self.emit("push 0");
+                        self.emit("sub");
+                        self.compare("jz");
+                    },
+                }
+            },
+            Expr::Binary(op @ (BinaryOp::And | BinaryOp::Or), lhs, rhs) => self.logical(*op, lhs, rhs),
+            Expr::Binary(op, lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+                // `sub` and `div` take their first operand from the top of the stack
+                match op {
+                    BinaryOp::Add => self.emit("add"),
+                    BinaryOp::Mul => self.emit("mul"),
+                    BinaryOp::BitAnd => self.emit("and"),
+                    BinaryOp::BitOr => self.emit("or"),
+                    BinaryOp::BitXor => self.emit("xor"),
+                    BinaryOp::Sub | BinaryOp::Div | BinaryOp::Mod => {
+                        self.emit("swap");
+                        self.emit(if *op == BinaryOp::Sub { "sub" } else { "div" });
+                        if *op == BinaryOp::Mod {
+                            self.emit("drop");
+                            self.emit("push r3");
+                        }
+                    },
+                    BinaryOp::Shl | BinaryOp::Shr => {
+                        self.emit(format!("pop {}", SCRATCH));
+                        self.emit(format!("{} {}", if *op == BinaryOp::Shl { "shl" } else { "shr" }, SCRATCH));
+                    },
+                    _ => {
+                        self.emit("swap");
+                        self.emit("sub");
+                        self.compare(match op {
+                            BinaryOp::Eq => "jz",
+                            BinaryOp::Ne => "jnz",
+                            BinaryOp::Lt => "jl",
+                            BinaryOp::Le => "jle",
+                            BinaryOp::Gt => "jg",
+                            _ => "jge",
+                        });
+                    },
+                }
+            },
+        }
+    }
+
+    fn function(&mut self, function: &'a Function) {
+        self.function = &function.name.text;
+        self.scopes = vec![HashMap::new()];
+        self.next_slot = 0;
+        self.frame_size = 0;
+        let start = self.text.len();
+        self.label(&format!("fn.{}", function.name.text));
+        self.emit(format!("move {} {}", FRAME, ADDRESS));
+        for param in &function.params {
+            self.declare(param, None);
+        }
+        // arguments were pushed in order, so the last one is on top
+        for slot in (0..function.params.len() as u32).rev() {
+            self.frame_address(slot);
+            self.emit(format!("pop [$frames + {}]", ADDRESS));
+        }
+        for stmt in &function.body {
+            self.stmt(stmt);
+        }
+        self.emit("push 0");
+        self.emit("ret");
+        let define = format!("@define frame_{} {}", function.name.text, self.frame_size);
+        self.text.insert(start, define);
+        self.text.push(String::new());
+    }
+}
+
+/// signed decimal printing, `int 0 4` prints numbers as unsigned
+const PRINT_INT: &str = ".rt.print_int
+    pop r5
+    push r5
+    push 0x80000000
+    and
+    pop r4
+    jz .rt.print_int.digits
+    push '-'
+    int 0 0
+    push r5
+    push 0
+    sub
+    pop r5
+.rt.print_int.digits
+    push r5
+    int 0 4
+    ret
+";
+
+/// # Code generation
+///
+/// Translates a parsed program into myvm assembly. Every function gets a frame of
+/// `frame_<name>` cells in `$frames`, holding its parameters followed by its locals; r7 points
+/// to the frame of the running function and `safecall` restores it for the caller. Arguments
+/// and return values are passed on the stack.
+pub fn generate(program: &Program, file: &str) -> Result<String, Vec<LangError>> {
+    let mut generator = Generator {
+        text: Vec::new(),
+        strings: Vec::new(),
+        errors: Vec::new(),
+        labels: 0,
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        function: "",
+        scopes: Vec::new(),
+        next_slot: 0,
+        frame_size: 0,
+        loops: Vec::new(),
+    };
+
+    let mut data = Vec::new();
+    for var in &program.globals {
+        if generator.globals.insert(&var.name.text, var.size).is_some() {
+            generator.errors.push(name_error("global variable {} is already declared", &var.name));
+        }
+        let values = match &var.init {
+            Init::Zero => Ok(Vec::new()),
+            Init::Value(value) => fold(value, &var.name).map(|v| vec![v]),
+            Init::List(values) => values.iter().map(|v| fold(v, &var.name)).collect(),
+        };
+        let values = values.unwrap_or_else(|e| {
+            generator.errors.push(e);
+            Vec::new()
+        });
+        let size = var.size.unwrap_or(1);
+        if values.len() > size as usize {
+            generator.errors.push(name_error(&format!("{} values given for the {} elements of {{}}", values.len(), size), &var.name));
+        }
+        let padding = size.saturating_sub(values.len() as u32);
+        let definition = match (values.is_empty(), padding) {
+            (true, _) if var.size.is_some() => format!("resdw {}", size),
+            (true, _) => "dw 0".to_string(),
+            (false, 0) => format!("dw {}", values.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")),
+            (false, padding) => format!("dw {} 0 times {}", values.iter().map(u32::to_string).collect::<Vec<_>>().join(" "), padding),
+        };
+        data.push(format!("$var.{} {}", var.name.text, definition));
+    }
+
+    for function in &program.functions {
+        if generator.functions.insert(&function.name.text, function.params.len()).is_some() {
+            generator.errors.push(name_error("function {} is already defined", &function.name));
+        }
+        let mut params = HashMap::new();
+        for param in &function.params {
+            if params.insert(&param.text, ()).is_some() {
+                generator.errors.push(name_error("duplicate parameter {}", param));
+            }
+        }
+    }
+    match program.functions.iter().find(|f| f.name.text == "main") {
+        None => generator.errors.push(LangError::new("program has no 'main' function", Pos { line: 1, column: 1 }, 1)),
+        Some(main) if !main.params.is_empty() => generator.errors.push(name_error("function {} cannot take parameters", &main.name)),
+        _ => {},
+    }
+    for function in &program.functions {
+        generator.function(function);
+    }
+    if !generator.errors.is_empty() {
+        return Err(generator.errors);
+    }
+
+    let mut out = format!("; generated from {}\n\n[data]\n", file);
+    for line in data {
+        out += &line;
+        out += "\n";
+    }
+    for (idx, text) in generator.strings.iter().enumerate() {
+        out += &format!("$str.{} b \"{}\" 0\n", idx, text);
+    }
+    // last, so frames growing past the end do not run into other data
+    out += &format!("$frames resdw {}\n\n[text]\n", FRAME_CELLS);
+    out += ".start\n";
+    out += &format!("    move {} 0\n", ADDRESS);
+    out += "    safecall .fn.main\n    drop\n    term\n\n";
+    for line in &generator.text {
+        out += line;
+        out += "\n";
+    }
+    out += PRINT_INT;
+    Ok(out)
+}
@@ -0,0 +1,31 @@
+use assembler::diagnostic::{Diagnostic, Severity};
+
+use crate::lexer::Pos;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Problem found in a program, located by the position and length of the offending text
+pub struct LangError {
+    pub message: String,
+    pub pos: Pos,
+    pub len: usize,
+}
+
+impl LangError {
+    pub fn new(message: impl Into<String>, pos: Pos, len: usize) -> LangError {
+        LangError { message: message.into(), pos, len }
+    }
+
+    /// diagnostic quoting the line of `source` the error is on
+    pub fn to_diagnostic(&self, source: &str, file: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: self.message.clone(),
+            file: file.to_string(),
+            line: self.pos.line,
+            column: self.pos.column,
+            len: self.len,
+            snippet: source.lines().nth(self.pos.line - 1).unwrap_or("").to_string(),
+            notes: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::error::LangError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Number(u32),
+    /// string literal as written, escape sequences are decoded by the assembler
+    Str(String),
+    Ident(String),
+    Keyword(Keyword),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Fn,
+    Var,
+    If,
+    Else,
+    While,
+    Return,
+    Break,
+    Continue,
+    Print,
+}
+
+const KEYWORDS: [(&str, Keyword); 9] = [
+    ("fn", Keyword::Fn),
+    ("var", Keyword::Var),
+    ("if", Keyword::If),
+    ("else", Keyword::Else),
+    ("while", Keyword::While),
+    ("return", Keyword::Return),
+    ("break", Keyword::Break),
+    ("continue", Keyword::Continue),
+    ("print", Keyword::Print),
+];
+
+/// punctuation, longest first so `<=` is not read as `<` `=`
+const PUNCTS: [&str; 29] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "(", ")", "{", "}", "[", "]", ",", ";", "=", "<", ">", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~",
+];
+
+/// 1-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub pos: Pos,
+    /// length in characters
+    pub len: usize,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    pos: Pos,
+}
+
+impl Lexer<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.next();
+            return true;
+        }
+        false
+    }
+
+    /// skip whitespace, `// line` and `/* block */` comments
+    fn skip_trivia(&mut self) -> Result<(), LangError> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                },
+                Some('/') => {
+                    let mut ahead = self.chars.clone();
+                    ahead.next();
+                    match ahead.next() {
+                        Some('/') => while self.peek().is_some_and(|c| c != '\n') {
+                            self.next();
+                        },
+                        Some('*') => {
+                            let start = self.pos;
+                            self.next();
+                            self.next();
+                            loop {
+                                match self.next() {
+                                    Some('*') if self.next_if('/') => break,
+                                    Some(_) => {},
+                                    None => return Err(LangError::new("unterminated comment", start, 2)),
+                                }
+                            }
+                        },
+                        _ => return Ok(()),
+                    }
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// text up to the closing `quote`, escape sequences kept as written
+    fn quoted(&mut self, quote: char, start: Pos) -> Result<String, LangError> {
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(text),
+                Some('\\') => {
+                    text.push('\\');
+                    match self.next() {
+                        Some('\n') | None => break,
+                        Some(c) => text.push(c),
+                    }
+                },
+                Some('\n') | None => break,
+                Some(c) => text.push(c),
+            }
+        }
+        let what = if quote == '"' { "string" } else { "character" };
+        Err(LangError::new(format!("unterminated {}", what), start, 1))
+    }
+
+    fn number(&mut self, first: char, start: Pos) -> Result<u32, LangError> {
+        let mut text = String::from(first);
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            text.push(c);
+            self.next();
+        }
+        let digits = text.replace('_', "");
+        let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+            u32::from_str_radix(hex, 16)
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            u32::from_str_radix(bin, 2)
+        } else {
+            digits.parse()
+        };
+        parsed.map_err(|_| LangError::new(format!("invalid number '{}'", text), start, text.chars().count()))
+    }
+}
+
+/// split source into tokens, ending with `TokenKind::Eof`
+pub fn tokenize(source: &str) -> Result<Vec<Token>, LangError> {
+    let mut lexer = Lexer { chars: source.chars().peekable(), pos: Pos { line: 1, column: 1 } };
+    let mut tokens = Vec::new();
+    loop {
+        lexer.skip_trivia()?;
+        let start = lexer.pos;
+        let Some(c) = lexer.next() else {
+            tokens.push(Token { kind: TokenKind::Eof, pos: start, len: 0 });
+            return Ok(tokens);
+        };
+        let kind = match c {
+            '0'..='9' => TokenKind::Number(lexer.number(c, start)?),
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let mut name = String::from(c);
+                while let Some(c) = lexer.peek().filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                    lexer.next();
+                }
+                match KEYWORDS.iter().find(|(k, _)| *k == name) {
+                    Some((_, keyword)) => TokenKind::Keyword(*keyword),
+                    None => TokenKind::Ident(name),
+                }
+            },
+            '"' => {
+                let text = lexer.quoted('"', start)?;
+                assembler::parser::unescape(&text)
+                    .map_err(|message| LangError::new(message, start, text.chars().count() + 2))?;
+                TokenKind::Str(text)
+            },
+            '\'' => {
+                let text = lexer.quoted('\'', start)?;
+                let value = assembler::parser::unescape(&text)
+                    .map_err(|message| LangError::new(message, start, text.chars().count() + 2))?;
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => TokenKind::Number(c as u32),
+                    _ => return Err(LangError::new("character literal must hold exactly one character", start, text.chars().count() + 2)),
+                }
+            },
+            _ => {
+                let rest: String = std::iter::once(c).chain(lexer.chars.clone().take(1)).collect();
+                match PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+                    Some(punct) => {
+                        if punct.len() == 2 {
+                            lexer.next();
+                        }
+                        TokenKind::Punct(punct)
+                    },
+                    None => return Err(LangError::new(format!("unexpected character '{}'", c), start, 1)),
+                }
+            },
+        };
+        let len = if lexer.pos.line == start.line { lexer.pos.column - start.column } else { 1 };
+        tokens.push(Token { kind, pos: start, len });
+    }
+}
@@ -0,0 +1,29 @@
+//! # Structured language frontend
+//!
+//! A small C-like language with integers, arrays, `if`, `while`, functions and `print`,
+//! compiled to myvm assembly and assembled with the `assembler` crate.
+
+use assembler::{compiler::{compile_with_options, CompileOptions, CompiledFrame}, diagnostic::Diagnostic};
+
+pub mod ast;
+pub mod codegen;
+pub mod error;
+pub mod lexer;
+pub mod parser;
+
+/// translate a program into myvm assembly
+pub fn to_assembly(source: &str, file: &str) -> Result<String, Vec<Diagnostic>> {
+    let diagnostic = |e: error::LangError| vec![e.to_diagnostic(source, file)];
+    let tokens = lexer::tokenize(source).map_err(diagnostic)?;
+    let program = parser::parse(tokens).map_err(diagnostic)?;
+    codegen::generate(&program, file)
+        .map_err(|errors| errors.iter().map(|e| e.to_diagnostic(source, file)).collect())
+}
+
+/// compile a program into the frame `assembler::compiler::compile_with_options` produces,
+/// `options.file` names the program in diagnostics
+pub fn compile(source: &str, options: &CompileOptions) -> Result<CompiledFrame, Vec<Diagnostic>> {
+    let file = options.file.as_deref().unwrap_or("<source>");
+    let assembly = to_assembly(source, file)?;
+    compile_with_options(assembly, options)
+}
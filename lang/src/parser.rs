@@ -0,0 +1,333 @@
+use crate::{ast::{BinaryOp, Expr, Function, Init, Name, PrintArg, Program, Stmt, UnaryOp, Var}, error::LangError, lexer::{Keyword, Token, TokenKind}};
+
+/// binary operators by precedence, loosest first
+const PRECEDENCE: [&[(&str, BinaryOp)]; 9] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[("<", BinaryOp::Lt), ("<=", BinaryOp::Le), (">", BinaryOp::Gt), (">=", BinaryOp::Ge)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+];
+
+const MULTIPLICATIVE: [(&str, BinaryOp); 3] = [("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Mod)];
+
+struct Parser {
+    tokens: Vec<Token>,
+    idx: usize,
+}
+
+type Parsed<T> = Result<T, LangError>;
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.idx]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.idx].clone();
+        if token.kind != TokenKind::Eof {
+            self.idx += 1;
+        }
+        token
+    }
+
+    fn at_punct(&self, punct: &str) -> bool {
+        matches!(self.peek().kind, TokenKind::Punct(p) if p == punct)
+    }
+
+    fn at_keyword(&self, keyword: Keyword) -> bool {
+        self.peek().kind == TokenKind::Keyword(keyword)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        if self.at_punct(punct) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    /// error at the next token
+    fn unexpected(&self, expected: &str) -> LangError {
+        let token = self.peek();
+        let found = match &token.kind {
+            TokenKind::Number(n) => format!("number {}", n),
+            TokenKind::Str(_) => "string".to_string(),
+            TokenKind::Ident(name) => format!("'{}'", name),
+            TokenKind::Keyword(_) | TokenKind::Punct(_) => "'".to_string() + &self.token_text(token) + "'",
+            TokenKind::Eof => "end of file".to_string(),
+        };
+        LangError::new(format!("expected {}, found {}", expected, found), token.pos, token.len.max(1))
+    }
+
+    fn token_text(&self, token: &Token) -> String {
+        match &token.kind {
+            TokenKind::Keyword(keyword) => format!("{:?}", keyword).to_lowercase(),
+            TokenKind::Punct(punct) => punct.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Parsed<()> {
+        match self.eat_punct(punct) {
+            true => Ok(()),
+            false => Err(self.unexpected(&format!("'{}'", punct))),
+        }
+    }
+
+    fn expect_name(&mut self) -> Parsed<Name> {
+        match &self.peek().kind {
+            TokenKind::Ident(name) => {
+                let name = Name { text: name.clone(), pos: self.peek().pos };
+                self.advance();
+                Ok(name)
+            },
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn program(&mut self) -> Parsed<Program> {
+        let mut program = Program { globals: Vec::new(), functions: Vec::new() };
+        loop {
+            match self.peek().kind {
+                TokenKind::Keyword(Keyword::Fn) => program.functions.push(self.function()?),
+                TokenKind::Keyword(Keyword::Var) => program.globals.push(self.var()?),
+                TokenKind::Eof => return Ok(program),
+                _ => return Err(self.unexpected("'fn' or 'var'")),
+            }
+        }
+    }
+
+    fn function(&mut self) -> Parsed<Function> {
+        self.advance();
+        let name = self.expect_name()?;
+        self.expect_punct("(")?;
+        let mut params = Vec::new();
+        if !self.eat_punct(")") {
+            loop {
+                params.push(self.expect_name()?);
+                if self.eat_punct(")") {
+                    break;
+                }
+                self.expect_punct(",")?;
+            }
+        }
+        let body = self.block()?;
+        Ok(Function { name, params, body })
+    }
+
+    fn var(&mut self) -> Parsed<Var> {
+        self.advance();
+        let name = self.expect_name()?;
+        let mut size = None;
+        if self.eat_punct("[") {
+            let token = self.advance();
+            match token.kind {
+                TokenKind::Number(n) if n > 0 => size = Some(n),
+                TokenKind::Number(_) => return Err(LangError::new("array size must be at least 1", token.pos, token.len)),
+                _ => {
+                    self.idx -= 1;
+                    return Err(self.unexpected("array size"));
+                },
+            }
+            self.expect_punct("]")?;
+        }
+        let init = match self.eat_punct("=") {
+            false => Init::Zero,
+            true if size.is_some() => {
+                self.expect_punct("{")?;
+                let mut values = Vec::new();
+                if !self.eat_punct("}") {
+                    loop {
+                        values.push(self.expr()?);
+                        if self.eat_punct("}") {
+                            break;
+                        }
+                        self.expect_punct(",")?;
+                    }
+                }
+                Init::List(values)
+            },
+            true => Init::Value(self.expr()?),
+        };
+        self.expect_punct(";")?;
+        Ok(Var { name, size, init })
+    }
+
+    fn block(&mut self) -> Parsed<Vec<Stmt>> {
+        self.expect_punct("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat_punct("}") {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn condition(&mut self) -> Parsed<Expr> {
+        self.expect_punct("(")?;
+        let cond = self.expr()?;
+        self.expect_punct(")")?;
+        Ok(cond)
+    }
+
+    fn stmt(&mut self) -> Parsed<Stmt> {
+        let token = self.peek().clone();
+        let stmt = match token.kind {
+            TokenKind::Keyword(Keyword::Var) => return Ok(Stmt::Var(self.var()?)),
+            TokenKind::Keyword(Keyword::If) => {
+                self.advance();
+                let cond = self.condition()?;
+                let then = self.block()?;
+                let otherwise = match self.at_keyword(Keyword::Else) {
+                    false => Vec::new(),
+                    true => {
+                        self.advance();
+                        match self.at_keyword(Keyword::If) {
+                            true => vec![self.stmt()?],
+                            false => self.block()?,
+                        }
+                    },
+                };
+                return Ok(Stmt::If(cond, then, otherwise));
+            },
+            TokenKind::Keyword(Keyword::While) => {
+                self.advance();
+                let cond = self.condition()?;
+                return Ok(Stmt::While(cond, self.block()?));
+            },
+            TokenKind::Keyword(Keyword::Return) => {
+                self.advance();
+                match self.at_punct(";") {
+                    true => Stmt::Return(None),
+                    false => Stmt::Return(Some(self.expr()?)),
+                }
+            },
+            TokenKind::Keyword(Keyword::Break) => {
+                self.advance();
+                Stmt::Break(token.pos)
+            },
+            TokenKind::Keyword(Keyword::Continue) => {
+                self.advance();
+                Stmt::Continue(token.pos)
+            },
+            TokenKind::Keyword(Keyword::Print) => {
+                self.advance();
+                self.expect_punct("(")?;
+                let mut args = Vec::new();
+                loop {
+                    match &self.peek().kind {
+                        TokenKind::Str(text) => {
+                            args.push(PrintArg::Str(text.clone()));
+                            self.advance();
+                        },
+                        _ => args.push(PrintArg::Expr(self.expr()?)),
+                    }
+                    if self.eat_punct(")") {
+                        break;
+                    }
+                    self.expect_punct(",")?;
+                }
+                Stmt::Print(args)
+            },
+            _ => {
+                let expr = self.expr()?;
+                match self.at_punct("=") {
+                    false => Stmt::Expr(expr),
+                    true => {
+                        self.advance();
+                        let value = self.expr()?;
+                        match expr {
+                            Expr::Var(name) => Stmt::Assign(name, None, value),
+                            Expr::Index(name, index) => Stmt::Assign(name, Some(*index), value),
+                            _ => return Err(LangError::new("only variables and array elements can be assigned", token.pos, token.len)),
+                        }
+                    },
+                }
+            },
+        };
+        self.expect_punct(";")?;
+        Ok(stmt)
+    }
+
+    fn expr(&mut self) -> Parsed<Expr> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Parsed<Expr> {
+        let operators = match PRECEDENCE.get(level) {
+            Some(operators) => *operators,
+            None => &MULTIPLICATIVE[..],
+        };
+        let operand = |parser: &mut Parser| match level < PRECEDENCE.len() {
+            true => parser.binary(level + 1),
+            false => parser.unary(),
+        };
+        let mut lhs = operand(self)?;
+        'outer: loop {
+            for (punct, op) in operators {
+                if self.eat_punct(punct) {
+                    let rhs = operand(self)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Parsed<Expr> {
+        for (punct, op) in [("-", UnaryOp::Neg), ("!", UnaryOp::Not), ("~", UnaryOp::BitNot)] {
+            if self.eat_punct(punct) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Parsed<Expr> {
+        match self.peek().kind.clone() {
+            TokenKind::Number(n) => {
+                self.advance();
+                Ok(Expr::Number(n))
+            },
+            TokenKind::Ident(_) => {
+                let name = self.expect_name()?;
+                if self.eat_punct("[") {
+                    let index = self.expr()?;
+                    self.expect_punct("]")?;
+                    return Ok(Expr::Index(name, Box::new(index)));
+                }
+                if self.eat_punct("(") {
+                    let mut args = Vec::new();
+                    if !self.eat_punct(")") {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat_punct(")") {
+                                break;
+                            }
+                            self.expect_punct(",")?;
+                        }
+                    }
+                    return Ok(Expr::Call(name, args));
+                }
+                Ok(Expr::Var(name))
+            },
+            TokenKind::Punct("(") => {
+                self.advance();
+                let expr = self.expr()?;
+                self.expect_punct(")")?;
+                Ok(expr)
+            },
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+}
+
+/// parse a whole program
+pub fn parse(tokens: Vec<Token>) -> Result<Program, LangError> {
+    Parser { tokens, idx: 0 }.program()
+}
@@ -0,0 +1,197 @@
+#[cfg(test)]
+pub mod tests {
+    use assembler::compiler::CompileOptions;
+    use lang::{compile, to_assembly};
+    use machine::internal::machine::{Machine, MachineOptions};
+
+    /// run a program and read its global variables
+    fn run(source: &str, globals: &[&str]) -> Vec<u32> {
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let res = compile(source, &options).unwrap();
+        let mut machine = Machine::new(MachineOptions { memory_cells: 4096, memory_stack_size: 256 }).unwrap();
+        machine.load_data(res.header.origin, &res.binary).unwrap();
+        machine.set_start(res.header.start);
+        machine.execute().unwrap();
+        let debug = res.debug.unwrap();
+        globals.iter()
+            .map(|name| {
+                let address = debug.data(&format!("var.{}", name)).unwrap().address;
+                machine.memory.read(address).unwrap()
+            })
+            .collect()
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        to_assembly(source, "test.mv").unwrap_err().into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    pub fn arithmetic_and_precedence() {
+        let source = "
+var a; var b; var c; var d; var e; var f; var g;
+fn main() {
+    a = 2 + 3 * 4;
+    b = (2 + 3) * 4 - 30;
+    c = 17 / 5 + 17 % 5 * 10;
+    d = 1 << 4 | 3 & 1 ^ 2;
+    e = -a + ~0;
+    f = 10 - 3 - 2;
+    g = 256 >> 4;
+}
+";
+        let expected = [14, (-10i32) as u32, 23, 16 | (3 & 1 ^ 2), (-15i32) as u32, 5, 16];
+        assert_eq!(run(source, &["a", "b", "c", "d", "e", "f", "g"]), expected);
+    }
+
+    #[test]
+    pub fn comparisons_and_logic() {
+        let source = "
+var r[12];
+var calls;
+fn side(v) { calls = calls + 1; return v; }
+fn main() {
+    var m = -5;
+    r[0] = m < 3;
+    r[1] = 3 < m;
+    r[2] = m <= -5;
+    r[3] = m > -6;
+    r[4] = m >= 0;
+    r[5] = m == -5;
+    r[6] = m != -5;
+    r[7] = !m;
+    r[8] = !0;
+    r[9] = 0 && side(1);
+    r[10] = 2 || side(1);
+    r[11] = side(3) && side(0);
+}
+";
+        let source_globals = run(source, &["calls"]);
+        assert_eq!(source_globals, [2]);
+        let options = CompileOptions { debug: true, ..Default::default() };
+        let res = compile(source, &options).unwrap();
+        let mut machine = Machine::new(MachineOptions { memory_cells: 4096, memory_stack_size: 256 }).unwrap();
+        machine.load_data(res.header.origin, &res.binary).unwrap();
+        machine.set_start(res.header.start);
+        machine.execute().unwrap();
+        let address = res.debug.unwrap().data("var.r").unwrap().address;
+        let values: Vec<u32> = (0..12).map(|i| machine.memory.read(address + i).unwrap()).collect();
+        assert_eq!(values, [1, 0, 1, 1, 0, 1, 0, 0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    pub fn control_flow() {
+        let source = "
+var sum; var odd; var last; var sign;
+fn main() {
+    var i = 0;
+    while (1) {
+        i = i + 1;
+        if (i > 10) { break; }
+        if (i % 2 == 0) { continue; }
+        odd = odd + 1;
+        sum = sum + i;
+    }
+    last = i;
+    if (sum < 0) { sign = 1; } else if (sum == 0) { sign = 2; } else { sign = 3; }
+}
+";
+        assert_eq!(run(source, &["sum", "odd", "last", "sign"]), [25, 5, 11, 3]);
+    }
+
+    #[test]
+    pub fn functions_and_recursion() {
+        let source = "
+var f10; var fact; var g;
+fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn factorial(n) {
+    var acc = 1;
+    while (n > 1) { acc = acc * n; n = n - 1; }
+    return acc;
+}
+fn diff(a, b, c) { var x = a - b; return x - c; }
+fn nothing() { }
+fn main() {
+    f10 = fib(10);
+    fact = factorial(10);
+    g = diff(100, 30, 7) + nothing();
+}
+";
+        assert_eq!(run(source, &["f10", "fact", "g"]), [55, 3628800, 63]);
+    }
+
+    #[test]
+    pub fn arrays_and_scopes() {
+        let source = "
+var table[5] = {3, 1, 4};
+var total; var zeros; var shadow; var size = 2 * 3 + 1;
+fn sum(n) {
+    var local[4];
+    var i = 0;
+    while (i < 4) { if (local[i] == 0) { zeros = zeros + 1; } local[i] = i * n; i = i + 1; }
+    var s = 0;
+    i = 0;
+    while (i < 4) { s = s + local[i]; i = i + 1; }
+    return s;
+}
+fn main() {
+    var i = 0;
+    while (i < 5) { total = total + table[i]; i = i + 1; }
+    table[4] = sum(2) + sum(1);
+    total = total + table[4];
+    var x = 1;
+    if (1) { var x = 5; shadow = x; }
+    shadow = shadow * 10 + x;
+}
+";
+        assert_eq!(run(source, &["total", "zeros", "shadow", "size"]), [8 + 18, 8, 51, 7]);
+    }
+
+    #[test]
+    pub fn generated_assembly() {
+        let assembly = to_assembly("var s = 'A';\nfn main() { print(\"x = \", s, \"\\n\"); }", "test.mv").unwrap();
+        assert!(assembly.contains("$var.s dw 65"));
+        assert!(assembly.contains("$str.0 b \"x = \" 0"));
+        assert!(assembly.contains("$str.1 b \"\\n\" 0"));
+        assert!(assembly.contains("safecall .rt.print_int"));
+        assert!(assembly.contains("@define frame_main 0"));
+    }
+
+    #[test]
+    pub fn optimized_code_behaves_the_same() {
+        let source = "
+var r;
+fn square(x) { return x * x; }
+fn main() { var i = 0; while (i < 5) { r = r + square(i); i = i + 1; } }
+";
+        let plain = compile(source, &CompileOptions::default()).unwrap();
+        let optimized = compile(source, &CompileOptions { optimize: true, ..Default::default() }).unwrap();
+        assert!(optimized.binary.len() < plain.binary.len());
+        assert_eq!(run(source, &["r"]), [30]);
+    }
+
+    #[test]
+    pub fn errors_are_reported() {
+        assert_eq!(errors("fn main() { x = 1; }"), ["undefined variable 'x'"]);
+        assert_eq!(errors("fn main() { f(); }"), ["undefined function 'f'"]);
+        assert_eq!(errors("fn f(a) { } fn main() { f(1, 2); }"), ["function 'f' takes 1 argument(s), 2 given"]);
+        assert_eq!(errors("fn f() { }"), ["program has no 'main' function"]);
+        assert_eq!(errors("fn main(a) { }"), ["function 'main' cannot take parameters"]);
+        assert_eq!(errors("fn main() { break; }"), ["'break' outside of a loop"]);
+        assert_eq!(errors("var a[2]; fn main() { var b = a; a = 1; }"), ["array 'a' cannot be used as a value", "array 'a' cannot be used as a value"]);
+        assert_eq!(errors("var a; fn main() { a[0] = 1; }"), ["variable 'a' is not an array"]);
+        assert_eq!(errors("fn main() { var a; var a; }"), ["variable 'a' is already declared in this scope"]);
+        assert_eq!(errors("var a[2] = {1, 2, 3}; fn main() { }"), ["3 values given for the 2 elements of 'a'"]);
+        assert_eq!(errors("var a; var b = a + 1; fn main() { }"), ["global initializers must be constant"]);
+        assert_eq!(errors("fn main() { 1 = 2; }"), ["only variables and array elements can be assigned"]);
+        assert_eq!(errors("fn main() { var x = 1 }"), ["expected ';', found '}'"]);
+        assert_eq!(errors("fn main() { print(\"\\q\"); }"), ["unknown escape sequence '\\q'"]);
+        assert_eq!(errors("fn main() { x = 1 @ 2; }"), ["unexpected character '@'"]);
+
+        let diagnostic = to_assembly("fn main() {\n    return y;\n}", "test.mv").unwrap_err().remove(0);
+        assert_eq!((diagnostic.file.as_str(), diagnostic.line, diagnostic.column, diagnostic.len), ("test.mv", 2, 12, 1));
+        assert_eq!(diagnostic.snippet, "    return y;");
+    }
+}
@@ -0,0 +1,195 @@
+use assembler::{parser::parse_line, source::{quoted_chars, strip_comment}, tokens::{is_local_label, MetaType, Token, ANONYMOUS_LABEL}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Label,
+    Data,
+    Section,
+}
+
+/// Text on one line: 0-based line and UTF-16 columns, as the protocol counts them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        self.line == line && (self.start..=self.end).contains(&column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Mention of a label or data identifier
+pub struct Occurrence {
+    /// full name: `.label`, `.label.local` or `$name`
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Span,
+    pub definition: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Entry of the document outline, local labels are children of their label and everything
+/// after a section header is a child of the section
+pub struct Outline {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// the name itself
+    pub span: Span,
+    /// last line belonging to the entry
+    pub end_line: usize,
+    pub children: Vec<Outline>,
+}
+
+/// # Document index
+///
+/// Labels, data identifiers and sections of one source file, found with the assembler's
+/// parser. Local labels are qualified with the label they belong to, like the assembler does.
+pub struct Index {
+    pub occurrences: Vec<Occurrence>,
+    /// section headers, names are written `[name]`
+    pub sections: Vec<Occurrence>,
+    /// paths given to `@include`, in order
+    pub includes: Vec<String>,
+    /// length of every line in UTF-16 units
+    pub line_lengths: Vec<usize>,
+}
+
+/// UTF-16 length of text, the unit of protocol columns
+pub fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl Index {
+    pub fn new(text: &str) -> Index {
+        let mut index = Index { occurrences: Vec::new(), sections: Vec::new(), includes: Vec::new(), line_lengths: Vec::new() };
+        // last label that is not local
+        let mut scope: Option<String> = None;
+        for (line, raw) in text.lines().enumerate() {
+            index.line_lengths.push(utf16_len(raw));
+            let code = strip_comment(raw);
+            let span = |start: usize, end: usize| Span { line, start: utf16_len(&raw[..start]), end: utf16_len(&raw[..end]) };
+            let mut defines = None;
+            match parse_line(code).ok().and_then(|(_, token)| token) {
+                Some(Token::Label(label)) if label != ANONYMOUS_LABEL => {
+                    defines = Some(SymbolKind::Label);
+                    if !is_local_label(label) {
+                        scope = Some(label.to_string());
+                    }
+                },
+                Some(Token::DataDef(..)) => defines = Some(SymbolKind::Data),
+                Some(Token::Section(name)) => {
+                    let start = code.find('[').unwrap_or(0);
+                    let end = code.find(']').map_or(code.len(), |e| e + 1);
+                    index.sections.push(Occurrence { name: name.to_string(), kind: SymbolKind::Section, span: span(start, end), definition: true });
+                },
+                Some(Token::Meta(MetaType::Include(path))) => index.includes.push(path.to_string()),
+                _ => {},
+            }
+            // `@proc name` defines label `.name`
+            let mut words = code.split_whitespace();
+            if words.next().is_some_and(|w| w.eq_ignore_ascii_case("@proc"))
+                && let Some(name) = words.next() {
+                let after = code.len() - code.trim_start().len() + "@proc".len();
+                let start = after + code[after..].find(name).unwrap_or(0);
+                scope = Some(name.to_string());
+                index.occurrences.push(Occurrence { name: format!(".{}", name), kind: SymbolKind::Label, span: span(start, start + name.len()), definition: true });
+            }
+
+            let chars = quoted_chars(code);
+            let mut i = 0;
+            while i < chars.len() {
+                let (start, c, quoted) = chars[i];
+                let after_word = i > 0 && matches!(chars[i - 1].1, c if is_name_char(c) || matches!(c, '.' | '$' | '%' | '@'));
+                if quoted || after_word || !matches!(c, '.' | '$') {
+                    i += 1;
+                    continue;
+                }
+                let local = c == '.' && chars.get(i + 1).is_some_and(|n| n.1 == '.');
+                let name_start = i + 1 + usize::from(local);
+                let mut j = name_start;
+                while j < chars.len() && (is_name_char(chars[j].1) || chars[j].1 == '.') {
+                    j += 1;
+                }
+                while j > name_start && chars[j - 1].1 == '.' {
+                    j -= 1;
+                }
+                if j == name_start {
+                    i = j.max(i + 1);
+                    continue;
+                }
+                let end = chars.get(j).map_or(code.len(), |n| n.0);
+                let text = &code[chars[name_start].0..end];
+                let (name, kind) = match (c, local) {
+                    ('$', _) => (format!("${}", text), SymbolKind::Data),
+                    (_, true) => (format!(".{}.{}", scope.as_deref().unwrap_or(""), text), SymbolKind::Label),
+                    _ => (format!(".{}", text), SymbolKind::Label),
+                };
+                let definition = defines == Some(kind);
+                if definition {
+                    defines = None;
+                }
+                index.occurrences.push(Occurrence { name, kind, span: span(start, end), definition });
+                i = j;
+            }
+        }
+        index
+    }
+
+    /// label or data identifier under a position
+    pub fn at(&self, line: usize, column: usize) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|o| o.span.contains(line, column))
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|o| o.definition && o.name == name)
+    }
+
+    pub fn references<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Occurrence> + 'a {
+        self.occurrences.iter().filter(move |o| o.name == name)
+    }
+
+    /// sections, labels and data identifiers defined in the document
+    pub fn outline(&self) -> Vec<Outline> {
+        let last_line = self.line_lengths.len().saturating_sub(1);
+        let mut definitions: Vec<&Occurrence> = self.occurrences.iter().filter(|o| o.definition).chain(&self.sections).collect();
+        definitions.sort_by_key(|o| (o.span.line, o.span.start));
+        // line before the next definition `stop` accepts
+        let end_of = |idx: usize, stop: fn(&Occurrence) -> bool| definitions[idx + 1..].iter()
+            .find(|n| stop(n))
+            .map_or(last_line, |n| n.span.line.saturating_sub(1).max(definitions[idx].span.line));
+        let mut out: Vec<Outline> = Vec::new();
+        for (idx, o) in definitions.iter().enumerate() {
+            let end_line = match o.kind {
+                SymbolKind::Section => end_of(idx, |n| n.kind == SymbolKind::Section),
+                SymbolKind::Label if is_block(o) => end_of(idx, is_block),
+                _ => o.span.line,
+            };
+            let entry = Outline { name: o.name.clone(), kind: o.kind, span: o.span, end_line, children: Vec::new() };
+            if o.kind == SymbolKind::Section {
+                out.push(entry);
+                continue;
+            }
+            let in_section = out.last().is_some_and(|s| s.kind == SymbolKind::Section);
+            let siblings = if in_section { &mut out.last_mut().unwrap().children } else { &mut out };
+            let local = o.kind == SymbolKind::Label && !is_block(o);
+            match siblings.last_mut().filter(|p| local && p.kind == SymbolKind::Label && !p.name[1..].contains('.')) {
+                Some(parent) => parent.children.push(entry),
+                None => siblings.push(entry),
+            }
+        }
+        out
+    }
+}
+
+/// sections and labels that are not local start a new part of the outline
+fn is_block(o: &Occurrence) -> bool {
+    o.kind == SymbolKind::Section || (o.kind == SymbolKind::Label && !o.name[1..].contains('.'))
+}
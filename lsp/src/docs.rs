@@ -0,0 +1,158 @@
+use assembler::tokens::{Cmd, JumpOperand};
+use machine::internal::opcode::{JumpTarget, Opcode, OpcodeVariant};
+
+/// # Instruction documentation
+///
+/// What one variant of an instruction does and how it is encoded, shown on hover
+pub struct CommandDoc {
+    pub syntax: String,
+    pub description: &'static str,
+    pub opcode: Opcode,
+    pub variant: OpcodeVariant,
+}
+
+impl CommandDoc {
+    /// hover text in markdown
+    pub fn markdown(&self) -> String {
+        format!(
+            "```asm\n{}\n```\n{}\n\nopcode `{:?}` (`0x{:04x}`), variant `{:?}` (`0x{:04x}`)",
+            self.syntax, self.description, self.opcode, self.opcode as u32, self.variant, self.variant as u32,
+        )
+    }
+}
+
+/// short descriptions of the mnemonics, used for completion
+pub const MNEMONIC_DOCS: [(&str, &str); 31] = [
+    ("push", "push a value onto the stack"),
+    ("pop", "pop the stack into a register or memory"),
+    ("add", "add the two values on top of the stack"),
+    ("sub", "subtract the second value from the top value"),
+    ("mul", "multiply the two values on top of the stack"),
+    ("div", "divide the top value by the second value, remainder in r3"),
+    ("drop", "drop the top of the stack"),
+    ("swap", "swap the two values on top of the stack"),
+    ("dup", "duplicate the top of the stack"),
+    ("and", "bitwise and of the two values on top of the stack"),
+    ("or", "bitwise or of the two values on top of the stack"),
+    ("xor", "bitwise xor of the two values on top of the stack"),
+    ("not", "bitwise not of the top of the stack"),
+    ("shr", "shift the top of the stack right"),
+    ("shl", "shift the top of the stack left"),
+    ("inc", "increase a register by 1"),
+    ("dec", "decrease a register by 1"),
+    ("move", "move a value into a register"),
+    ("store", "store a value into memory"),
+    ("call", "call a procedure"),
+    ("safecall", "call a procedure, preserving registers and flags"),
+    ("ret", "return from a procedure"),
+    ("jmp", "jump unconditionally"),
+    ("jnz", "jump if not zero"),
+    ("jz", "jump if zero"),
+    ("jg", "jump if greater"),
+    ("jge", "jump if greater or equal"),
+    ("jl", "jump if less"),
+    ("jle", "jump if less or equal"),
+    ("int", "call an interrupt"),
+    ("term", "terminate execution"),
+];
+
+/// functions of interrupt module 0 (IO)
+const IO_FUNCTIONS: [&str; 9] = [
+    "Pops top of stack and prints it as a character",
+    "Pops number `n` from stack, then pops `n` items and prints them",
+    "Pops a stop value, then continuously pops and prints until reaching stop value",
+    "Pops address of a `dw` string (one character per cell) and prints it until reaching 0",
+    "Pops number from stack and prints it as an unsigned number",
+    "Pops address of a `b` string and prints it as UTF-8 until reaching a zero byte",
+    "Pops address of a cell holding a length in bytes, then prints that many bytes of the `b` string after it as UTF-8",
+    "Pops address of a `w` string and prints it as UTF-16 until reaching a zero half-word",
+    "Pops address of a cell holding a length in half-words, then prints that many half-words of the `w` string after it as UTF-16",
+];
+
+/// documentation of an interrupt function, `None` for unknown ones
+pub fn interrupt_doc(module: u32, function: u32) -> Option<String> {
+    match module {
+        0 => IO_FUNCTIONS.get(function as usize).map(|doc| format!("**IO module, function {}**\n\n{}", function, doc)),
+        _ => None,
+    }
+}
+
+/// condition variant, mnemonic and description of a jump
+fn jump_kind<'a, 'b>(cmd: &'a Cmd<'b>) -> Option<(OpcodeVariant, &'static str, &'static str, &'a JumpOperand<'b>)> {
+    Some(match cmd {
+        Cmd::Jmp(target) => (OpcodeVariant::Default, "JMP", "Jumps unconditionally", target),
+        Cmd::Jnz(target) => (OpcodeVariant::JumpNotZero, "JNZ", "Jumps if the zero flag is clear", target),
+        Cmd::Jz(target) => (OpcodeVariant::JumpZero, "JZ", "Jumps if the zero flag is set", target),
+        Cmd::Jg(target) => (OpcodeVariant::JumpGreater, "JG", "Jumps if greater: zero is clear and negative equals overflow", target),
+        Cmd::Jge(target) => (OpcodeVariant::JumpGreaterEqual, "JGE", "Jumps if greater or equal: negative equals overflow", target),
+        Cmd::Jl(target) => (OpcodeVariant::JumpLesser, "JL", "Jumps if less: negative differs from overflow", target),
+        Cmd::Jle(target) => (OpcodeVariant::JumpLesserEqual, "JLE", "Jumps if less or equal: zero is set or negative differs from overflow", target),
+        _ => return None,
+    })
+}
+
+/// documentation of the variant `cmd` was parsed as
+pub fn command_doc(cmd: &Cmd) -> CommandDoc {
+    if let Some((condition, mnemonic, description, target)) = jump_kind(cmd) {
+        let (operand, variant) = match target {
+            JumpOperand::Const(_) => (".label", condition),
+            JumpOperand::Reg(_) => ("r0", condition.to_indirect_jump(JumpTarget::Reg).unwrap_or(condition)),
+            JumpOperand::Addr(_) => ("&address", condition.to_indirect_jump(JumpTarget::Addr).unwrap_or(condition)),
+            JumpOperand::IdOffsetReg(..) => ("[$table + r0]", condition.to_indirect_jump(JumpTarget::AddrOffsetReg).unwrap_or(condition)),
+        };
+        return CommandDoc { syntax: format!("{} {}", mnemonic, operand), description, opcode: Opcode::Jump, variant };
+    }
+    let (syntax, description, opcode, variant) = match cmd {
+        Cmd::PushConst(_) => ("PUSH value", "Pushes a constant number to the stack", Opcode::Push, OpcodeVariant::PushConst),
+        Cmd::PushReg(_) => ("PUSH r0", "Pushes value of a register onto the stack", Opcode::Push, OpcodeVariant::PushReg),
+        Cmd::PushAddr(_) => ("PUSH &address", "Pushes value from memory address onto the stack", Opcode::Push, OpcodeVariant::PushAddr),
+        Cmd::PushIdAddress(_) => ("PUSH $name", "Pushes address of data label to stack", Opcode::Push, OpcodeVariant::PushConst),
+        Cmd::PushIdValueConst(..) => ("PUSH [$name + offset]", "Pushes value of data label with offset to stack", Opcode::Push, OpcodeVariant::PushAddr),
+        Cmd::PushIdValueReg(..) => ("PUSH [$name + r0]", "Pushes value of data label with offset in register to stack", Opcode::Push, OpcodeVariant::PushAddrOffsetReg),
+        Cmd::PopReg(_) => ("POP r0", "Pops value from stack into a register, setting the zero and negative flags", Opcode::Pop, OpcodeVariant::PopReg),
+        Cmd::PopAddr(_) => ("POP &address", "Pops value from stack into a memory address", Opcode::Pop, OpcodeVariant::PopAddr),
+        Cmd::PopIdValueReg(..) => ("POP [$name + r0]", "Pops value from stack into data label with offset in register", Opcode::Pop, OpcodeVariant::PopAddrOffsetReg),
+        Cmd::PopAddrReg(_) => ("POP &r0", "Pops value from stack into the address held in a register", Opcode::Pop, OpcodeVariant::PopAddrReg),
+        Cmd::Add => ("ADD", "Pops two values, adds them, pushes result", Opcode::Add, OpcodeVariant::Default),
+        Cmd::Sub => ("SUB", "Pops two values, subtracts the second from the top one, pushes result", Opcode::Sub, OpcodeVariant::Default),
+        Cmd::Mul => ("MUL", "Pops two values, multiplies, pushes result", Opcode::Mul, OpcodeVariant::Default),
+        Cmd::Div => ("DIV", "Pops two values, divides the top one by the second (unsigned), pushes result, puts remainder in r3", Opcode::Div, OpcodeVariant::Default),
+        Cmd::Drop => ("DROP", "Drops the top item of the stack", Opcode::Drop, OpcodeVariant::Default),
+        Cmd::Swap => ("SWAP", "Swaps top two items on stack", Opcode::Swap, OpcodeVariant::Default),
+        Cmd::MoveConst(..) => ("MOVE r0 value", "Moves constant into register", Opcode::Move, OpcodeVariant::MoveConst),
+        Cmd::MoveReg(..) => ("MOVE r0 r1", "Moves value from one register to another", Opcode::Move, OpcodeVariant::MoveReg),
+        Cmd::MoveAddr(..) => ("MOVE r0 &address", "Moves value from memory address to register", Opcode::Move, OpcodeVariant::MoveAddr),
+        Cmd::MoveAddrReg(..) => ("MOVE r0 &r1", "Moves value from address in register to register", Opcode::Move, OpcodeVariant::MoveAddrReg),
+        Cmd::MoveIdAddress(..) => ("MOVE r0 $name", "Moves address of data label to register", Opcode::Move, OpcodeVariant::MoveConst),
+        Cmd::MoveIdValueConst(..) => ("MOVE r0 [$name + offset]", "Moves value of data label with offset to register", Opcode::Move, OpcodeVariant::MoveAddr),
+        Cmd::MoveIdValueReg(..) => ("MOVE r0 [$name + r1]", "Moves value of data label with register offset to register", Opcode::Move, OpcodeVariant::MoveAddrOffsetReg),
+        Cmd::StoreConst(..) => ("STORE address value", "Stores constant into memory", Opcode::Store, OpcodeVariant::StoreConst),
+        Cmd::StoreReg(..) => ("STORE address r0", "Stores register value into memory", Opcode::Store, OpcodeVariant::StoreReg),
+        Cmd::And => ("AND", "Pops two values, bitwise AND, pushes result", Opcode::And, OpcodeVariant::Default),
+        Cmd::Or => ("OR", "Pops two values, bitwise OR, pushes result", Opcode::Or, OpcodeVariant::Default),
+        Cmd::Xor => ("XOR", "Pops two values, bitwise XOR, pushes result", Opcode::Xor, OpcodeVariant::Default),
+        Cmd::Not => ("NOT", "Pops one value, bitwise NOT, pushes result", Opcode::Not, OpcodeVariant::Default),
+        Cmd::ShrConst(_) => ("SHR value", "Pops value, shifts right by constant, pushes result", Opcode::SHR, OpcodeVariant::SHRConst),
+        Cmd::ShrReg(_) => ("SHR r0", "Pops value, shifts right by register value, pushes result", Opcode::SHR, OpcodeVariant::SHRReg),
+        Cmd::ShlConst(_) => ("SHL value", "Pops value, shifts left by constant, pushes result", Opcode::SHL, OpcodeVariant::SHLConst),
+        Cmd::ShlReg(_) => ("SHL r0", "Pops value, shifts left by register value, pushes result", Opcode::SHL, OpcodeVariant::SHLReg),
+        Cmd::Inc(_) => ("INC r0", "Increases register by 1", Opcode::Inc, OpcodeVariant::Default),
+        Cmd::Dec(_) => ("DEC r0", "Decreases register by 1", Opcode::Dec, OpcodeVariant::Default),
+        Cmd::CallConst(_) => ("CALL .label", "Calls procedure by label or address", Opcode::Call, OpcodeVariant::CallConst),
+        Cmd::CallReg(_) => ("CALL r0", "Calls procedure at address in register", Opcode::Call, OpcodeVariant::CallReg),
+        Cmd::CallAddr(_) => ("CALL &address", "Calls procedure at address stored in memory", Opcode::Call, OpcodeVariant::CallAddr),
+        Cmd::CallIdValueReg(..) => ("CALL [$table + r0]", "Calls procedure at address stored in data with register offset", Opcode::Call, OpcodeVariant::CallAddrOffsetReg),
+        Cmd::SafeCallConst(_) => ("SAFECALL .label", "Calls procedure by label or address and preserves registers and flags", Opcode::SafeCall, OpcodeVariant::SafeCallConst),
+        Cmd::SafeCallReg(_) => ("SAFECALL r0", "Calls procedure at address in register and preserves registers and flags", Opcode::SafeCall, OpcodeVariant::SafeCallReg),
+        Cmd::SafeCallAddr(_) => ("SAFECALL &address", "Calls procedure at address stored in memory and preserves registers and flags", Opcode::SafeCall, OpcodeVariant::SafeCallAddr),
+        Cmd::SafeCallIdValueReg(..) => ("SAFECALL [$table + r0]", "Calls procedure at address stored in data with register offset and preserves registers and flags", Opcode::SafeCall, OpcodeVariant::SafeCallAddrOffsetReg),
+        Cmd::Ret => ("RET", "Returns from procedure, restoring registers and flags after SAFECALL", Opcode::Ret, OpcodeVariant::Default),
+        Cmd::Dup => ("DUP", "Duplicates top stack item", Opcode::Dup, OpcodeVariant::Default),
+        Cmd::DupConst(_) => ("DUP n", "Duplicates top stack item `n` times", Opcode::Dup, OpcodeVariant::DupConst),
+        Cmd::DupReg(_) => ("DUP r0", "Duplicates top stack item as many times as the register value", Opcode::Dup, OpcodeVariant::DupReg),
+        Cmd::Int(..) => ("INT module function", "Calls an interrupt function of a VM module", Opcode::Int, OpcodeVariant::Default),
+        Cmd::Term => ("TERM", "Terminates code execution", Opcode::Terminate, OpcodeVariant::Default),
+        Cmd::Jmp(_) | Cmd::Jnz(_) | Cmd::Jz(_) | Cmd::Jg(_) | Cmd::Jge(_) | Cmd::Jl(_) | Cmd::Jle(_) => unreachable!("jumps are documented above"),
+    };
+    CommandDoc { syntax: syntax.to_string(), description, opcode, variant }
+}
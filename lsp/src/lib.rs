@@ -0,0 +1,9 @@
+//! # Language server for myvm assembly
+//!
+//! Speaks the Language Server Protocol over stdio: diagnostics on open and save, go to
+//! definition, references and hover for labels and `$identifiers`, hover documentation for
+//! instructions and interrupts, completion of mnemonics and registers and document symbols.
+
+pub mod analysis;
+pub mod docs;
+pub mod server;
@@ -0,0 +1,11 @@
+fn main() {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    match lsp::server::run(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("myvm-lsp: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,366 @@
+use std::{collections::{HashMap, HashSet}, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+
+use assembler::{compiler::{compile_with_options, CompileOptions}, diagnostic::{Diagnostic, Severity}, parser::{parse_line, MNEMONICS}, tokens::{Cmd, ConstValue, Token}};
+use serde_json::{json, Value};
+use transport::{read_message, write_message};
+
+use crate::{analysis::{utf16_len, Index, Occurrence, Outline, Span, SymbolKind}, docs::{command_doc, interrupt_doc, MNEMONIC_DOCS}};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+const INVALID_PARAMS: i64 = -32602;
+
+/// protocol constants of the symbol and completion kinds used
+const SYMBOL_NAMESPACE: u32 = 3;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_KEYWORD: u32 = 14;
+
+/// path of a `file://` URI
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], path.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            },
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&out).into_owned()))
+}
+
+/// `file://` URI of a path
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn range(span: Span) -> Value {
+    json!({
+        "start": { "line": span.line, "character": span.start },
+        "end": { "line": span.line, "character": span.end },
+    })
+}
+
+fn location(uri: &str, occurrence: &Occurrence) -> Value {
+    json!({ "uri": uri, "range": range(occurrence.span) })
+}
+
+/// UTF-16 column of the character at `chars` in a line
+fn utf16_column(line: &str, chars: usize) -> usize {
+    line.chars().take(chars).map(char::len_utf16).sum()
+}
+
+fn diagnostic_json(d: &Diagnostic) -> Value {
+    let span = match d.line {
+        0 => Span { line: 0, start: 0, end: 0 },
+        line => {
+            let start = d.column.saturating_sub(1);
+            Span { line: line - 1, start: utf16_column(&d.snippet, start), end: utf16_column(&d.snippet, start + d.len) }
+        },
+    };
+    let mut message = d.message.clone();
+    for note in &d.notes {
+        message += "\n";
+        message += note;
+    }
+    let severity = match d.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    json!({ "range": range(span), "severity": severity, "source": "myvm", "message": message })
+}
+
+fn outline_json(entry: &Outline, line_lengths: &[usize]) -> Value {
+    let kind = match entry.kind {
+        SymbolKind::Section => SYMBOL_NAMESPACE,
+        SymbolKind::Label => SYMBOL_FUNCTION,
+        SymbolKind::Data => SYMBOL_VARIABLE,
+    };
+    let end = line_lengths.get(entry.end_line).copied().unwrap_or(0);
+    let name = match entry.kind {
+        SymbolKind::Section => format!("[{}]", entry.name),
+        _ => entry.name.clone(),
+    };
+    json!({
+        "name": name,
+        "kind": kind,
+        "range": {
+            "start": { "line": entry.span.line, "character": 0 },
+            "end": { "line": entry.end_line, "character": end },
+        },
+        "selectionRange": range(entry.span),
+        "children": entry.children.iter().map(|c| outline_json(c, line_lengths)).collect::<Vec<_>>(),
+    })
+}
+
+type Response = Result<Value, (i64, String)>;
+
+#[derive(Default)]
+/// # Language server
+///
+/// Keeps the text of open documents and answers requests about them. Diagnostics come from
+/// compiling a document when it is opened or saved; everything else is answered from an
+/// `Index` of the document and the files it includes.
+pub struct Server {
+    /// text of open documents by URI
+    documents: HashMap<String, String>,
+    /// URIs that were given diagnostics when compiling each document
+    published: HashMap<String, Vec<String>>,
+    shutdown: bool,
+}
+
+impl Server {
+    /// text of a document, read from disk when it is not open
+    fn text(&self, uri: &str) -> Option<String> {
+        match self.documents.get(uri) {
+            Some(text) => Some(text.clone()),
+            None => std::fs::read_to_string(uri_to_path(uri)?).ok(),
+        }
+    }
+
+    /// a document and every file it includes, directly or not
+    fn files(&self, uri: &str) -> Vec<(String, Index)> {
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![uri.to_string()];
+        while let Some(uri) = pending.pop() {
+            if !seen.insert(uri.clone()) {
+                continue;
+            }
+            let Some(text) = self.text(&uri) else {
+                continue;
+            };
+            let index = Index::new(&text);
+            if let Some(dir) = uri_to_path(&uri).as_deref().and_then(Path::parent) {
+                pending.extend(index.includes.iter().rev().map(|include| path_to_uri(&dir.join(include))));
+            }
+            out.push((uri, index));
+        }
+        out
+    }
+
+    /// compile a document and publish diagnostics for it and the files it includes
+    fn publish_diagnostics(&mut self, uri: &str) -> Vec<Value> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let path = uri_to_path(uri);
+        let file = path.as_ref().map_or(uri.to_string(), |p| p.to_string_lossy().into_owned());
+        let options = CompileOptions { file: Some(file.clone()), ..Default::default() };
+        let diagnostics = match compile_with_options(text.clone(), &options) {
+            Ok(frame) => frame.warnings,
+            Err(diagnostics) => diagnostics,
+        };
+        let mut by_uri: Vec<(String, Vec<Value>)> = vec![(uri.to_string(), Vec::new())];
+        for d in &diagnostics {
+            let target = match Path::new(&d.file) {
+                _ if d.file == file => uri.to_string(),
+                other if other.is_absolute() => path_to_uri(other),
+                _ => uri.to_string(),
+            };
+            match by_uri.iter_mut().find(|(u, _)| *u == target) {
+                Some((_, list)) => list.push(diagnostic_json(d)),
+                None => by_uri.push((target, vec![diagnostic_json(d)])),
+            }
+        }
+        let uris: Vec<String> = by_uri.iter().map(|(u, _)| u.clone()).collect();
+        // clear files that no longer have problems
+        for old in self.published.insert(uri.to_string(), uris).unwrap_or_default() {
+            if !by_uri.iter().any(|(u, _)| *u == old) {
+                by_uri.push((old, Vec::new()));
+            }
+        }
+        by_uri.into_iter()
+            .map(|(uri, diagnostics)| json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": diagnostics },
+            }))
+            .collect()
+    }
+
+    /// handle a notification, returning the messages to send
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                self.publish_diagnostics(&uri)
+            },
+            "textDocument/didChange" => {
+                // full document sync, the last change holds the whole text
+                if let Some(text) = params["contentChanges"].as_array().and_then(|c| c.last()).and_then(|c| c["text"].as_str()) {
+                    self.documents.insert(uri, text.to_string());
+                }
+                Vec::new()
+            },
+            "textDocument/didSave" => {
+                if let Some(text) = params["text"].as_str() {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                self.publish_diagnostics(&uri)
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.published.remove(&uri).unwrap_or_default().into_iter()
+                    .map(|uri| json!({
+                        "jsonrpc": "2.0",
+                        "method": "textDocument/publishDiagnostics",
+                        "params": { "uri": uri, "diagnostics": [] },
+                    }))
+                    .collect()
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Response {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "server is shutting down".to_string()));
+        }
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let position = &params["position"];
+        let (line, column) = (position["line"].as_u64().unwrap_or(0) as usize, position["character"].as_u64().unwrap_or(0) as usize);
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": true, "change": 1, "save": { "includeText": true } },
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "myvm-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            },
+            "textDocument/definition" => {
+                let files = self.files(uri);
+                let Some(name) = files.first().and_then(|(_, index)| index.at(line, column)).map(|o| o.name.clone()) else {
+                    return Ok(Value::Null);
+                };
+                Ok(files.iter()
+                    .find_map(|(uri, index)| index.definition(&name).map(|o| location(uri, o)))
+                    .unwrap_or(Value::Null))
+            },
+            "textDocument/references" => {
+                let files = self.files(uri);
+                let Some(name) = files.first().and_then(|(_, index)| index.at(line, column)).map(|o| o.name.clone()) else {
+                    return Ok(json!([]));
+                };
+                let declaration = params["context"]["includeDeclaration"].as_bool().unwrap_or(true);
+                let locations: Vec<Value> = files.iter()
+                    .flat_map(|(uri, index)| index.references(&name).filter(|o| declaration || !o.definition).map(|o| location(uri, o)))
+                    .collect();
+                Ok(Value::Array(locations))
+            },
+            "textDocument/hover" => Ok(self.hover(uri, line, column).unwrap_or(Value::Null)),
+            "textDocument/completion" => {
+                let text = self.text(uri).ok_or((INVALID_PARAMS, format!("unknown document '{}'", uri)))?;
+                let before: String = text.lines().nth(line).unwrap_or("").encode_utf16().take(column).map(|u| char::from_u32(u as u32).unwrap_or(' ')).collect();
+                // the first word of a line is an instruction, operands come after it
+                let items: Vec<Value> = match before.trim_start().contains(char::is_whitespace) {
+                    false => MNEMONIC_DOCS.iter()
+                        .map(|(mnemonic, doc)| json!({ "label": mnemonic, "kind": COMPLETION_KEYWORD, "detail": doc }))
+                        .collect(),
+                    true => (0..8)
+                        .map(|r| json!({ "label": format!("r{}", r), "kind": COMPLETION_VARIABLE, "detail": "register" }))
+                        .collect(),
+                };
+                Ok(Value::Array(items))
+            },
+            "textDocument/documentSymbol" => {
+                let text = self.text(uri).ok_or((INVALID_PARAMS, format!("unknown document '{}'", uri)))?;
+                let index = Index::new(&text);
+                Ok(Value::Array(index.outline().iter().map(|e| outline_json(e, &index.line_lengths)).collect()))
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("method '{}' is not supported", method))),
+        }
+    }
+
+    /// instruction documentation on the mnemonic, the definition of a label or data identifier on its name
+    fn hover(&self, uri: &str, line: usize, column: usize) -> Option<Value> {
+        let files = self.files(uri);
+        let (_, index) = files.first()?;
+        if let Some(occurrence) = index.at(line, column) {
+            let (def_uri, def) = files.iter().find_map(|(uri, index)| index.definition(&occurrence.name).map(|d| (uri, d)))?;
+            let text = self.text(def_uri)?;
+            let def_line = text.lines().nth(def.span.line).unwrap_or("").trim();
+            let mut value = format!("```asm\n{}\n```", def_line);
+            if def_uri != uri {
+                let name = uri_to_path(def_uri).map_or(def_uri.to_string(), |p| p.to_string_lossy().into_owned());
+                value += &format!("\ndefined in `{}`", name);
+            }
+            return Some(json!({ "contents": { "kind": "markdown", "value": value }, "range": range(occurrence.span) }));
+        }
+        let text = self.text(uri)?;
+        let raw = text.lines().nth(line)?;
+        let indent = raw.len() - raw.trim_start().len();
+        let mnemonic = raw[indent..].split(|c: char| c.is_whitespace() || c == ';').next().unwrap_or("");
+        let span = Span { line, start: utf16_len(&raw[..indent]), end: utf16_len(&raw[..indent + mnemonic.len()]) };
+        if !MNEMONICS.contains(&mnemonic.to_lowercase().as_str()) || !span.contains(line, column) {
+            return None;
+        }
+        let Ok((_, Some(Token::Command(cmd)))) = parse_line(raw) else {
+            return None;
+        };
+        let mut value = command_doc(&cmd).markdown();
+        if let Cmd::Int(ConstValue::Number(module), ConstValue::Number(function)) = cmd
+            && let Some(doc) = interrupt_doc(module, function) {
+            value += "\n\n";
+            value += &doc;
+        }
+        Some(json!({ "contents": { "kind": "markdown", "value": value }, "range": range(span) }))
+    }
+
+    /// handle one message from the client, returning the messages to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // responses to requests sent by the server, it sends none
+            return Vec::new();
+        };
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notify(method, params);
+        };
+        match self.request(method, params) {
+            Ok(result) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            Err((code, message)) => vec![json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })],
+        }
+    }
+}
+
+/// Serve one client until it sends `exit` or closes its input, returns the exit code: 0 when
+/// `shutdown` was requested first and 1 otherwise
+pub fn run(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<i32> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(reader)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        for reply in server.handle(&message) {
+            write_message(writer, &reply)?;
+        }
+    }
+    Ok(if server.shutdown { 0 } else { 1 })
+}
@@ -0,0 +1,176 @@
+#[cfg(test)]
+pub mod tests {
+    use std::{io::{BufReader, Write}, path::PathBuf, process::{Command, Stdio}};
+
+    use lsp::{analysis::{Index, SymbolKind}, server::path_to_uri};
+    use serde_json::{json, Value};
+    use transport::{read_message, write_message};
+
+    /// creates a fresh directory with the given files
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("myvm-lsp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (path, content) in files {
+            std::fs::write(dir.join(path), content).unwrap();
+        }
+        dir
+    }
+
+    const MAIN: &str = "@include \"lib.asm\"
+[data]
+$msg b \"hi\" 0
+
+[text]
+.start
+    push $msg
+    int 0 5
+    call .twice
+..loop
+    dec r0
+    jnz ..loop
+    term
+";
+
+    const LIB: &str = "[text]
+.twice ; doubles the top of the stack
+    push 2
+    mul
+    ret
+";
+
+    #[test]
+    pub fn index_qualifies_local_labels() {
+        let index = Index::new(MAIN);
+        let names: Vec<(&str, bool)> = index.occurrences.iter().map(|o| (o.name.as_str(), o.definition)).collect();
+        assert_eq!(names, [
+            ("$msg", true), (".start", true), ("$msg", false), (".twice", false), (".start.loop", true), (".start.loop", false),
+        ]);
+        let jump = &index.occurrences[5];
+        assert_eq!((jump.span.line, jump.span.start, jump.span.end), (11, 8, 14));
+        assert_eq!(index.at(11, 10), Some(jump));
+        assert_eq!(index.definition(".start.loop").unwrap().span.line, 9);
+        assert_eq!(index.includes, ["lib.asm"]);
+
+        let proc = Index::new("@proc print\n..done\n    jmp ..done\n@endp\n; .not_a_label '$nor_data'\n");
+        let names: Vec<&str> = proc.occurrences.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, [".print", ".print.done", ".print.done"]);
+    }
+
+    #[test]
+    pub fn outline_nests_labels_in_sections() {
+        let outline = Index::new(MAIN).outline();
+        let summary: Vec<(&str, SymbolKind, usize, usize)> = outline.iter().map(|e| (e.name.as_str(), e.kind, e.children.len(), e.end_line)).collect();
+        assert_eq!(summary, [("data", SymbolKind::Section, 1, 3), ("text", SymbolKind::Section, 1, 12)]);
+        let start = &outline[1].children[0];
+        assert_eq!((start.name.as_str(), start.span.line, start.end_line), (".start", 5, 12));
+        assert_eq!(start.children[0].name, ".start.loop");
+    }
+
+    struct Client {
+        child: std::process::Child,
+        reader: BufReader<std::process::ChildStdout>,
+        next_id: u64,
+    }
+
+    impl Client {
+        fn start() -> Client {
+            let mut child = Command::new(env!("CARGO_BIN_EXE_myvm-lsp"))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let reader = BufReader::new(child.stdout.take().unwrap());
+            Client { child, reader, next_id: 1 }
+        }
+
+        fn notify(&mut self, method: &str, params: Value) {
+            let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+            write_message(self.child.stdin.as_mut().unwrap(), &message).unwrap();
+        }
+
+        fn receive(&mut self) -> Value {
+            read_message(&mut self.reader).unwrap().unwrap()
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> Value {
+            let id = self.next_id;
+            self.next_id += 1;
+            let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            write_message(self.child.stdin.as_mut().unwrap(), &message).unwrap();
+            let reply = self.receive();
+            assert_eq!(reply["id"], id);
+            reply
+        }
+    }
+
+    fn at(uri: &str, line: u32, character: u32) -> Value {
+        json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+    }
+
+    #[test]
+    pub fn scripted_session() {
+        let dir = fixture("session", &[("main.asm", MAIN), ("lib.asm", LIB)]);
+        let uri = path_to_uri(&dir.join("main.asm"));
+        let lib_uri = path_to_uri(&dir.join("lib.asm"));
+        let mut client = Client::start();
+
+        let init = client.request("initialize", json!({ "processId": null, "rootUri": null, "capabilities": {} }));
+        assert_eq!(init["result"]["capabilities"]["hoverProvider"], true);
+        client.notify("initialized", json!({}));
+
+        // the buffer has an error the file on disk does not have
+        let broken = MAIN.replace("jnz ..loop", "jnz ..missing");
+        client.notify("textDocument/didOpen", json!({ "textDocument": { "uri": uri, "languageId": "myvm", "version": 1, "text": broken } }));
+        let published = client.receive();
+        assert_eq!(published["method"], "textDocument/publishDiagnostics");
+        assert_eq!(published["params"]["uri"], uri.as_str());
+        let diagnostic = &published["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["message"], "undefined local label '..missing' in '.start'");
+        assert_eq!(diagnostic["range"], json!({ "start": { "line": 11, "character": 8 }, "end": { "line": 11, "character": 17 } }));
+
+        client.notify("textDocument/didChange", json!({ "textDocument": { "uri": uri, "version": 2 }, "contentChanges": [{ "text": MAIN }] }));
+        client.notify("textDocument/didSave", json!({ "textDocument": { "uri": uri } }));
+        let published = client.receive();
+        assert_eq!(published["params"]["diagnostics"], json!([]));
+
+        let definition = client.request("textDocument/definition", at(&uri, 8, 11));
+        assert_eq!(definition["result"], json!({ "uri": lib_uri, "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 6 } } }));
+
+        let mut references = client.request("textDocument/references", json!({
+            "textDocument": { "uri": uri }, "position": { "line": 2, "character": 2 }, "context": { "includeDeclaration": false },
+        }));
+        let references = references["result"].as_array_mut().unwrap();
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0]["range"]["start"], json!({ "line": 6, "character": 9 }));
+
+        let hover = client.request("textDocument/hover", at(&uri, 7, 5));
+        let text = hover["result"]["contents"]["value"].as_str().unwrap();
+        assert!(text.contains("INT module function"), "{}", text);
+        assert!(text.contains("prints it as UTF-8"), "{}", text);
+        let hover = client.request("textDocument/hover", at(&uri, 11, 4));
+        let text = hover["result"]["contents"]["value"].as_str().unwrap();
+        assert!(text.contains("JNZ .label") && text.contains("variant `JumpNotZero` (`0xa00b`)"), "{}", text);
+        let hover = client.request("textDocument/hover", at(&uri, 8, 12));
+        assert!(hover["result"]["contents"]["value"].as_str().unwrap().starts_with("```asm\n.twice ; doubles the top of the stack\n```"));
+
+        let completion = client.request("textDocument/completion", at(&uri, 10, 4));
+        assert!(completion["result"].as_array().unwrap().iter().any(|i| i["label"] == "safecall"));
+        let completion = client.request("textDocument/completion", at(&uri, 10, 8));
+        assert_eq!(completion["result"].as_array().unwrap().len(), 8);
+
+        let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": uri } }));
+        let names: Vec<&str> = symbols["result"].as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["[data]", "[text]"]);
+        assert_eq!(symbols["result"][1]["children"][0]["children"][0]["name"], ".start.loop");
+
+        let unknown = client.request("textDocument/formatting", json!({}));
+        assert_eq!(unknown["error"]["code"], -32601);
+
+        let shutdown = client.request("shutdown", Value::Null);
+        assert_eq!(shutdown["result"], Value::Null);
+        client.notify("exit", Value::Null);
+        client.child.stdin.as_mut().unwrap().flush().unwrap();
+        assert!(client.child.wait().unwrap().success());
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// VM flags
 pub struct Flag {
     pub zero: bool,
@@ -11,4 +11,10 @@ impl Flag {
     pub fn new() -> Self {
         Self { zero: false, negative: false, overflow: false, carry: false }
     }
-}
\ No newline at end of file
+}
+
+impl Default for Flag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{errors::VMError, internal::{interrupts::io::{print_counted_function, print_data_string_function, print_function, print_number_function, print_until_function}, machine::Machine}};
+use crate::{errors::VMError, internal::{interrupts::io::{print_byte_string_function, print_counted_byte_string_function, print_counted_function, print_counted_half_word_string_function, print_data_string_function, print_function, print_half_word_string_function, print_number_function, print_until_function}, machine::Machine}};
 
 const IOMODULE: u32 = 0x0000_0000;
 
@@ -7,6 +7,10 @@ const PRINT_COUNTED_FUNC: u32 = 0x0000_0001;
 const PRINT_UNTIL_FUNC: u32 = 0x0000_0002;
 const PRINT_DATA_STRING_FUNC: u32 = 0x0000_0003;
 const PRINT_NUMBER_FUNC: u32 = 0x0000_0004;
+const PRINT_BYTE_STRING_FUNC: u32 = 0x0000_0005;
+const PRINT_COUNTED_BYTE_STRING_FUNC: u32 = 0x0000_0006;
+const PRINT_HALF_WORD_STRING_FUNC: u32 = 0x0000_0007;
+const PRINT_COUNTED_HALF_WORD_STRING_FUNC: u32 = 0x0000_0008;
 
 pub fn interrupt_handler(machine: &mut Machine, module: u32, function: u32) -> Result<(), VMError> {
     match module {
@@ -27,6 +31,18 @@ pub fn interrupt_handler(machine: &mut Machine, module: u32, function: u32) -> R
                 PRINT_NUMBER_FUNC => {
                     print_number_function(machine)?;
                 },
+                PRINT_BYTE_STRING_FUNC => {
+                    print_byte_string_function(machine)?;
+                },
+                PRINT_COUNTED_BYTE_STRING_FUNC => {
+                    print_counted_byte_string_function(machine)?;
+                },
+                PRINT_HALF_WORD_STRING_FUNC => {
+                    print_half_word_string_function(machine)?;
+                },
+                PRINT_COUNTED_HALF_WORD_STRING_FUNC => {
+                    print_counted_half_word_string_function(machine)?;
+                },
                 _ => {
                     return Err(VMError::InvalidFunction);
                 }
@@ -1,8 +1,8 @@
-use crate::{errors::VMError, internal::machine::Machine};
+use crate::{errors::VMError, internal::{machine::Machine, memory::Memory}};
 
 pub fn print_function(machine: &mut Machine) -> Result<(), VMError> {
     let code = machine.memory.pop()?;
-    print!("{}", char::from_u32(code).unwrap_or('☐'));
+    machine.print(&char::from_u32(code).unwrap_or('☐').to_string());
     Ok(())
 }
 
@@ -10,7 +10,7 @@ pub fn print_counted_function(machine: &mut Machine) -> Result<(), VMError> {
     let number = machine.memory.pop()?;
     for _ in 0..number{
         let code = machine.memory.pop()?;
-        print!("{}", char::from_u32(code).unwrap_or('☐'));
+        machine.print(&char::from_u32(code).unwrap_or('☐').to_string());
     }
     Ok(())
 }
@@ -19,7 +19,7 @@ pub fn print_until_function(machine: &mut Machine) -> Result<(), VMError> {
     let chr = machine.memory.pop()?;
     loop{
         let code = machine.memory.pop()?;
-        print!("{}", char::from_u32(code).unwrap_or('☐'));
+        machine.print(&char::from_u32(code).unwrap_or('☐').to_string());
         if code == chr {
             break;
         }
@@ -29,18 +29,83 @@ pub fn print_until_function(machine: &mut Machine) -> Result<(), VMError> {
 
 pub fn print_data_string_function(machine: &mut Machine) -> Result<(), VMError> {
     let mut addr = machine.memory.pop()?;
-    Ok(loop {
+    loop {
         let character = machine.memory.read(addr)?;
         if character == 0 {
             break;
         }
-        print!("{}", char::from_u32(character).unwrap_or('☐'));
+        machine.print(&char::from_u32(character).unwrap_or('☐').to_string());
         addr += 1;
-    })
+    }
+    Ok(())
+}
+
+/// Reads a string of bytes packed four to a cell (as `b` data is stored) and decodes it as UTF-8.
+/// Unless `counted`, the string ends at the first zero byte; if it is, the cell at `addr` holds the length in bytes and the string starts at the next cell.
+pub fn read_byte_string(memory: &Memory, addr: u32, counted: bool) -> Result<String, VMError> {
+    let bytes = read_packed(memory, addr, counted, 8, |unit| unit as u8)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Reads a string of half-words packed two to a cell (as `w` data is stored) and decodes it as UTF-16.
+/// Unless `counted`, the string ends at the first zero half-word; if it is, the cell at `addr` holds the length in half-words and the string starts at the next cell.
+pub fn read_half_word_string(memory: &Memory, addr: u32, counted: bool) -> Result<String, VMError> {
+    let units = read_packed(memory, addr, counted, 16, |unit| unit as u16)?;
+    Ok(String::from_utf16_lossy(&units))
+}
+
+fn read_packed<T: Default + PartialEq>(memory: &Memory, addr: u32, counted: bool, bits: u32, convert: fn(u32) -> T) -> Result<Vec<T>, VMError> {
+    let per_cell = 32 / bits;
+    let (start, length) = if counted {
+        (addr.checked_add(1).ok_or(VMError::InvalidAddress)?, Some(memory.read(addr)?))
+    } else {
+        (addr, None)
+    };
+    let mut result = Vec::new();
+    let mut index = 0;
+    while length.is_none_or(|length| index < length) {
+        let cell = memory.read(start.checked_add(index / per_cell).ok_or(VMError::InvalidAddress)?)?;
+        // first unit is in the highest bits
+        let unit = convert(cell >> (32 - bits * (index % per_cell + 1)));
+        if length.is_none() && unit == T::default() {
+            break;
+        }
+        result.push(unit);
+        index += 1;
+    }
+    Ok(result)
+}
+
+pub fn print_byte_string_function(machine: &mut Machine) -> Result<(), VMError> {
+    let addr = machine.memory.pop()?;
+    let text = read_byte_string(&machine.memory, addr, false)?;
+    machine.print(&text);
+    Ok(())
+}
+
+pub fn print_counted_byte_string_function(machine: &mut Machine) -> Result<(), VMError> {
+    let addr = machine.memory.pop()?;
+    let text = read_byte_string(&machine.memory, addr, true)?;
+    machine.print(&text);
+    Ok(())
+}
+
+pub fn print_half_word_string_function(machine: &mut Machine) -> Result<(), VMError> {
+    let addr = machine.memory.pop()?;
+    let text = read_half_word_string(&machine.memory, addr, false)?;
+    machine.print(&text);
+    Ok(())
+}
+
+pub fn print_counted_half_word_string_function(machine: &mut Machine) -> Result<(), VMError> {
+    let addr = machine.memory.pop()?;
+    let text = read_half_word_string(&machine.memory, addr, true)?;
+    machine.print(&text);
+    Ok(())
 }
 
 pub fn print_number_function(machine: &mut Machine) -> Result<(), VMError> {
     let number = machine.memory.pop()?;
-    print!("{}", number);
+    machine.print(&number.to_string());
     Ok(())
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::{errors::VMError, internal::{flag::Flag, interrupts::handler::interrupt_handler, memory::Memory, opcode::{Opcode, OpcodeVariant}, register::Register}};
+use crate::{errors::VMError, internal::{flag::Flag, interrupts::handler::interrupt_handler, memory::Memory, opcode::{JumpTarget, Opcode, OpcodeVariant}, register::Register}};
 
 #[derive(Debug)]
 /// Machine initialization options
@@ -7,7 +7,7 @@ pub struct MachineOptions {
     pub memory_stack_size: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// # Machine
 /// 
 /// Main VM struct which is containing all of required components for VM
@@ -16,6 +16,8 @@ pub struct Machine {
     pub register: Register,
     pub flag: Flag,
     call_stack: Vec<u32>,
+    /// text printed by interrupts, kept here instead of going to stdout while it is `Some`
+    output: Option<String>,
 }
 
 fn preserve_state(machine: &mut Machine) {
@@ -54,12 +56,13 @@ impl Machine {
     /// creates new virtual machine
     pub fn new(options: MachineOptions) -> Result<Self, VMError> {
         let memory = Memory::new(options.memory_cells, options.memory_stack_size)?;
-        return Ok(Self{
-            memory: memory,
+        Ok(Self{
+            memory,
             register: Register::new(),
             flag: Flag::new(),
             call_stack: Vec::new(),
-        });
+            output: None,
+        })
     }
 
     /// load data into memory
@@ -75,7 +78,20 @@ impl Machine {
 
     /// read register value
     pub fn read_register(&self, reg_num: u32) -> Result<u32, VMError> {
-        Ok(self.register.get(reg_num)?)
+        self.register.get(reg_num)
+    }
+
+    /// whether the condition of a direct jump variant holds
+    fn jump_condition(&self, condition: OpcodeVariant) -> bool {
+        match condition {
+            OpcodeVariant::JumpNotZero => !self.flag.zero,
+            OpcodeVariant::JumpZero => self.flag.zero,
+            OpcodeVariant::JumpGreater => !self.flag.zero && (self.flag.negative == self.flag.overflow),
+            OpcodeVariant::JumpGreaterEqual => self.flag.negative == self.flag.overflow,
+            OpcodeVariant::JumpLesser => self.flag.negative != self.flag.overflow,
+            OpcodeVariant::JumpLesserEqual => self.flag.zero || (self.flag.negative != self.flag.overflow),
+            _ => true,
+        }
     }
 
     /// execute next command
@@ -136,6 +152,24 @@ impl Machine {
                 self.flag.negative = (value as i32) < 0;
                 self.memory.write(next, &[value])?;
             },
+            (Opcode::Pop, OpcodeVariant::PopAddrReg) => {
+                self.register.pc += 1;
+                let reg = self.memory.read(self.register.pc)?;
+                let value = self.memory.pop()?;
+                self.flag.zero = value == 0;
+                self.flag.negative = (value as i32) < 0;
+                self.memory.write(self.register.get(reg)?, &[value])?;
+            },
+            (Opcode::Pop, OpcodeVariant::PopAddrOffsetReg) => {
+                self.register.pc += 1;
+                let address = self.memory.read(self.register.pc)?;
+                self.register.pc += 1;
+                let reg = self.memory.read(self.register.pc)?;
+                let value = self.memory.pop()?;
+                self.flag.zero = value == 0;
+                self.flag.negative = (value as i32) < 0;
+                self.memory.write(address.wrapping_add(self.register.get(reg)?), &[value])?;
+            },
             (Opcode::Drop, OpcodeVariant::Default) => {
                 let value = self.memory.pop()?;
                 self.flag.zero = value == 0;
@@ -378,6 +412,16 @@ impl Machine {
                 self.register.pc = addr;
                 jumped = true;
             },
+            (Opcode::Call, OpcodeVariant::CallAddrOffsetReg) => {
+                self.register.pc += 1;
+                let address = self.memory.read(self.register.pc)?;
+                self.register.pc += 1;
+                let reg = self.memory.read(self.register.pc)?;
+                let addr = self.memory.read(address.wrapping_add(self.register.get(reg)?))?;
+                self.call_stack.push(self.register.pc);
+                self.register.pc = addr;
+                jumped = true;
+            },
             (Opcode::SafeCall, OpcodeVariant::SafeCallConst) => {
                 self.register.pc += 1;
                 let addr = self.memory.read(self.register.pc)?;
@@ -402,11 +446,20 @@ impl Machine {
                 self.register.pc = addr;
                 jumped = true;
             },
+            (Opcode::SafeCall, OpcodeVariant::SafeCallAddrOffsetReg) => {
+                self.register.pc += 1;
+                let address = self.memory.read(self.register.pc)?;
+                self.register.pc += 1;
+                let reg = self.memory.read(self.register.pc)?;
+                let addr = self.memory.read(address.wrapping_add(self.register.get(reg)?))?;
+                self.call_stack.push(self.register.pc);
+                preserve_state(self);
+                self.register.pc = addr;
+                jumped = true;
+            },
             (Opcode::Ret, OpcodeVariant::Default) => {
-                if self.call_stack.len() > 0 {
-                    if self.call_stack[self.call_stack.len() - 1] == 0x1998 {
-                        rollback_state(self);
-                    }
+                if self.call_stack.last() == Some(&0x1998) {
+                    rollback_state(self);
                 }
                 let addr = self.call_stack.pop();
                 if addr.is_none() {
@@ -477,6 +530,26 @@ impl Machine {
                 let function = self.memory.read(self.register.pc)?;
                 interrupt_handler(self, module, function)?;
             },
+            (Opcode::Jump, variant) => {
+                let Some((condition, target)) = variant.indirect_jump() else {
+                    return Err(VMError::InvalidOpcode);
+                };
+                self.register.pc += 1;
+                let operand = self.memory.read(self.register.pc)?;
+                let addr = match target {
+                    JumpTarget::Reg => self.register.get(operand)?,
+                    JumpTarget::Addr => self.memory.read(operand)?,
+                    JumpTarget::AddrOffsetReg => {
+                        self.register.pc += 1;
+                        let reg = self.memory.read(self.register.pc)?;
+                        self.memory.read(operand.wrapping_add(self.register.get(reg)?))?
+                    },
+                };
+                if self.jump_condition(condition) {
+                    self.register.pc = addr;
+                    jumped = true;
+                }
+            },
             _ => {
                 return Err(VMError::InvalidOpcode);
             },
@@ -487,6 +560,46 @@ impl Machine {
         Ok(false)
     }
 
+    /// keep what interrupts print, to be taken with `take_output`, instead of printing it
+    pub fn capture_output(&mut self) {
+        self.output.get_or_insert_with(String::new);
+    }
+
+    /// text printed since the last call, when output is captured
+    pub fn take_output(&mut self) -> String {
+        self.output.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// print text for the program, to stdout unless output is captured
+    pub fn print(&mut self, text: &str) {
+        match &mut self.output {
+            Some(output) => output.push_str(text),
+            None => print!("{}", text),
+        }
+    }
+
+    /// address of the last word of every `call` and `safecall` that has not returned yet, the
+    /// innermost last
+    pub fn call_frames(&self) -> Vec<u32> {
+        let mut frames = Vec::new();
+        let mut idx = self.call_stack.len();
+        while idx > 0 {
+            // registers and flags preserved by `safecall` sit above its return address
+            if self.call_stack[idx - 1] == 0x1998 && idx > 13 {
+                idx -= 13;
+            }
+            frames.push(self.call_stack[idx - 1]);
+            idx -= 1;
+        }
+        frames.reverse();
+        frames
+    }
+
+    /// execute the instruction at the PC register address, returns true when it is `term`
+    pub fn step(&mut self) -> Result<bool, VMError> {
+        self.execute_next()
+    }
+
     /// Execute code that loaded into memory. start from PC register address.
     pub fn execute(&mut self) -> Result<(), VMError> {
         loop {
@@ -36,7 +36,7 @@ pub fn hexdump_to_string(data: &[u32]) -> String {
     output
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// # Memory
 /// Memory structure is main storage of VM that contains all required data for VM in order to work
 pub struct Memory {
@@ -89,6 +89,12 @@ impl Memory {
         Ok(result)
     }
 
+    /// values on the stack, from the bottom to the top
+    pub fn stack(&self) -> Vec<u32> {
+        let len = self.memory.len();
+        (0..self.sp as usize).map(|idx| self.memory[len - idx - 1]).collect()
+    }
+
     /// write data into memory
     pub fn write(&mut self, address: u32, data: &[u32]) -> Result<(), VMError> {
         let len = self.memory.len();
@@ -96,9 +102,7 @@ impl Memory {
         if address as usize + data.len() - 1 > last_address {
             return Err(VMError::InvalidAddress);
         }
-        for i in 0..data.len() {
-            self.memory[address as usize + i] = data[i];
-        }
+        self.memory[address as usize..address as usize + data.len()].copy_from_slice(data);
         Ok(())
     }
 
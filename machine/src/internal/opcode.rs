@@ -107,8 +107,96 @@ pub enum OpcodeVariant {
     SafeCallReg = 0xa020,
     /// call address value address and preserve registers and flags
     SafeCallAddr = 0xa021,
+    /// jump to register value address
+    JumpReg = 0xa022,
+    /// jump to address value address
+    JumpAddr = 0xa023,
+    /// jump to address value address with offset in register
+    JumpAddrOffsetReg = 0xa024,
+    /// jump if not zero to register value address
+    JumpNotZeroReg = 0xa025,
+    /// jump if not zero to address value address
+    JumpNotZeroAddr = 0xa026,
+    /// jump if not zero to address value address with offset in register
+    JumpNotZeroAddrOffsetReg = 0xa027,
+    /// jump if zero to register value address
+    JumpZeroReg = 0xa028,
+    /// jump if zero to address value address
+    JumpZeroAddr = 0xa029,
+    /// jump if zero to address value address with offset in register
+    JumpZeroAddrOffsetReg = 0xa02a,
+    /// jump if greater to register value address
+    JumpGreaterReg = 0xa02b,
+    /// jump if greater to address value address
+    JumpGreaterAddr = 0xa02c,
+    /// jump if greater to address value address with offset in register
+    JumpGreaterAddrOffsetReg = 0xa02d,
+    /// jump if greater or equal to register value address
+    JumpGreaterEqualReg = 0xa02e,
+    /// jump if greater or equal to address value address
+    JumpGreaterEqualAddr = 0xa02f,
+    /// jump if greater or equal to address value address with offset in register
+    JumpGreaterEqualAddrOffsetReg = 0xa030,
+    /// jump if lesser to register value address
+    JumpLesserReg = 0xa031,
+    /// jump if lesser to address value address
+    JumpLesserAddr = 0xa032,
+    /// jump if lesser to address value address with offset in register
+    JumpLesserAddrOffsetReg = 0xa033,
+    /// jump if lesser or equal to register value address
+    JumpLesserEqualReg = 0xa034,
+    /// jump if lesser or equal to address value address
+    JumpLesserEqualAddr = 0xa035,
+    /// jump if lesser or equal to address value address with offset in register
+    JumpLesserEqualAddrOffsetReg = 0xa036,
+    /// call address value address with offset in register
+    CallAddrOffsetReg = 0xa037,
+    /// call address value address with offset in register and preserve registers and flags
+    SafeCallAddrOffsetReg = 0xa038,
+    /// pop stack into address with offset in register
+    PopAddrOffsetReg = 0xa039,
+    /// pop stack into address held in register
+    PopAddrReg = 0xa03a,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// # Jump target
+///
+/// Where an indirect jump takes the address to jump to from
+pub enum JumpTarget {
+    /// register holding the address
+    Reg,
+    /// memory cell holding the address
+    Addr,
+    /// memory cell at an address plus the value of a register
+    AddrOffsetReg,
+}
+
+/// indirect jump variants with the direct jump variant of the same condition and their target
+const INDIRECT_JUMPS: &[(OpcodeVariant, OpcodeVariant, JumpTarget)] = &[
+    (OpcodeVariant::JumpReg, OpcodeVariant::Default, JumpTarget::Reg),
+    (OpcodeVariant::JumpAddr, OpcodeVariant::Default, JumpTarget::Addr),
+    (OpcodeVariant::JumpAddrOffsetReg, OpcodeVariant::Default, JumpTarget::AddrOffsetReg),
+    (OpcodeVariant::JumpNotZeroReg, OpcodeVariant::JumpNotZero, JumpTarget::Reg),
+    (OpcodeVariant::JumpNotZeroAddr, OpcodeVariant::JumpNotZero, JumpTarget::Addr),
+    (OpcodeVariant::JumpNotZeroAddrOffsetReg, OpcodeVariant::JumpNotZero, JumpTarget::AddrOffsetReg),
+    (OpcodeVariant::JumpZeroReg, OpcodeVariant::JumpZero, JumpTarget::Reg),
+    (OpcodeVariant::JumpZeroAddr, OpcodeVariant::JumpZero, JumpTarget::Addr),
+    (OpcodeVariant::JumpZeroAddrOffsetReg, OpcodeVariant::JumpZero, JumpTarget::AddrOffsetReg),
+    (OpcodeVariant::JumpGreaterReg, OpcodeVariant::JumpGreater, JumpTarget::Reg),
+    (OpcodeVariant::JumpGreaterAddr, OpcodeVariant::JumpGreater, JumpTarget::Addr),
+    (OpcodeVariant::JumpGreaterAddrOffsetReg, OpcodeVariant::JumpGreater, JumpTarget::AddrOffsetReg),
+    (OpcodeVariant::JumpGreaterEqualReg, OpcodeVariant::JumpGreaterEqual, JumpTarget::Reg),
+    (OpcodeVariant::JumpGreaterEqualAddr, OpcodeVariant::JumpGreaterEqual, JumpTarget::Addr),
+    (OpcodeVariant::JumpGreaterEqualAddrOffsetReg, OpcodeVariant::JumpGreaterEqual, JumpTarget::AddrOffsetReg),
+    (OpcodeVariant::JumpLesserReg, OpcodeVariant::JumpLesser, JumpTarget::Reg),
+    (OpcodeVariant::JumpLesserAddr, OpcodeVariant::JumpLesser, JumpTarget::Addr),
+    (OpcodeVariant::JumpLesserAddrOffsetReg, OpcodeVariant::JumpLesser, JumpTarget::AddrOffsetReg),
+    (OpcodeVariant::JumpLesserEqualReg, OpcodeVariant::JumpLesserEqual, JumpTarget::Reg),
+    (OpcodeVariant::JumpLesserEqualAddr, OpcodeVariant::JumpLesserEqual, JumpTarget::Addr),
+    (OpcodeVariant::JumpLesserEqualAddrOffsetReg, OpcodeVariant::JumpLesserEqual, JumpTarget::AddrOffsetReg),
+];
+
 impl Opcode {
     pub fn from_num(value: u32) -> Result<Opcode, VMError> {
         match value {
@@ -137,9 +225,7 @@ impl Opcode {
             x if x == Self::Inc as u32 => Ok(Self::Inc),
             x if x == Self::Dec as u32 => Ok(Self::Dec),
             x if x == Self::SafeCall as u32 => Ok(Self::SafeCall),
-            _ => {
-                return Err(VMError::InvalidOpcode);
-            }
+            _ => Err(VMError::InvalidOpcode),
         }
     }
 
@@ -189,7 +275,42 @@ impl OpcodeVariant {
             x if x == Self::SafeCallAddr as u32 => Ok(Self::SafeCallAddr),
             x if x == Self::SafeCallConst as u32 => Ok(Self::SafeCallConst),
             x if x == Self::SafeCallReg as u32 => Ok(Self::SafeCallReg),
+            x if x == Self::JumpReg as u32 => Ok(Self::JumpReg),
+            x if x == Self::JumpAddr as u32 => Ok(Self::JumpAddr),
+            x if x == Self::JumpAddrOffsetReg as u32 => Ok(Self::JumpAddrOffsetReg),
+            x if x == Self::JumpNotZeroReg as u32 => Ok(Self::JumpNotZeroReg),
+            x if x == Self::JumpNotZeroAddr as u32 => Ok(Self::JumpNotZeroAddr),
+            x if x == Self::JumpNotZeroAddrOffsetReg as u32 => Ok(Self::JumpNotZeroAddrOffsetReg),
+            x if x == Self::JumpZeroReg as u32 => Ok(Self::JumpZeroReg),
+            x if x == Self::JumpZeroAddr as u32 => Ok(Self::JumpZeroAddr),
+            x if x == Self::JumpZeroAddrOffsetReg as u32 => Ok(Self::JumpZeroAddrOffsetReg),
+            x if x == Self::JumpGreaterReg as u32 => Ok(Self::JumpGreaterReg),
+            x if x == Self::JumpGreaterAddr as u32 => Ok(Self::JumpGreaterAddr),
+            x if x == Self::JumpGreaterAddrOffsetReg as u32 => Ok(Self::JumpGreaterAddrOffsetReg),
+            x if x == Self::JumpGreaterEqualReg as u32 => Ok(Self::JumpGreaterEqualReg),
+            x if x == Self::JumpGreaterEqualAddr as u32 => Ok(Self::JumpGreaterEqualAddr),
+            x if x == Self::JumpGreaterEqualAddrOffsetReg as u32 => Ok(Self::JumpGreaterEqualAddrOffsetReg),
+            x if x == Self::JumpLesserReg as u32 => Ok(Self::JumpLesserReg),
+            x if x == Self::JumpLesserAddr as u32 => Ok(Self::JumpLesserAddr),
+            x if x == Self::JumpLesserAddrOffsetReg as u32 => Ok(Self::JumpLesserAddrOffsetReg),
+            x if x == Self::JumpLesserEqualReg as u32 => Ok(Self::JumpLesserEqualReg),
+            x if x == Self::JumpLesserEqualAddr as u32 => Ok(Self::JumpLesserEqualAddr),
+            x if x == Self::JumpLesserEqualAddrOffsetReg as u32 => Ok(Self::JumpLesserEqualAddrOffsetReg),
+            x if x == Self::CallAddrOffsetReg as u32 => Ok(Self::CallAddrOffsetReg),
+            x if x == Self::SafeCallAddrOffsetReg as u32 => Ok(Self::SafeCallAddrOffsetReg),
+            x if x == Self::PopAddrOffsetReg as u32 => Ok(Self::PopAddrOffsetReg),
+            x if x == Self::PopAddrReg as u32 => Ok(Self::PopAddrReg),
             _ => Err(VMError::InvalidOpcode)
         }
     }
-}
\ No newline at end of file
+
+    /// condition (as the direct jump variant) and target of an indirect jump variant
+    pub fn indirect_jump(self) -> Option<(OpcodeVariant, JumpTarget)> {
+        INDIRECT_JUMPS.iter().find(|j| j.0 == self).map(|j| (j.1, j.2))
+    }
+
+    /// indirect jump variant with the condition of this direct jump variant
+    pub fn to_indirect_jump(self, target: JumpTarget) -> Option<OpcodeVariant> {
+        INDIRECT_JUMPS.iter().find(|j| j.1 == self && j.2 == target).map(|j| j.0)
+    }
+}
@@ -1,6 +1,6 @@
 use crate::errors::VMError;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// # Registers
 /// 
 /// required registers for VM
@@ -78,4 +78,10 @@ impl Register {
             _ => Err(VMError::InvalidRegister),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Default for Register {
+    fn default() -> Self {
+        Self::new()
+    }
+}
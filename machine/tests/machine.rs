@@ -1,6 +1,6 @@
 #[cfg(test)]
 pub mod tests {
-    use machine::internal::machine::{Machine, MachineOptions};
+    use machine::{errors::VMError, internal::{interrupts::io::{read_byte_string, read_half_word_string}, machine::{Machine, MachineOptions}, memory::Memory}};
 
     #[test]
     pub fn create() {
@@ -26,12 +26,38 @@ pub mod tests {
         ];
         let mut machine = Machine::new(MachineOptions{memory_cells: 1024, memory_stack_size: 256}).unwrap();
         machine.load_data(10, &code).unwrap();
-        machine.set_origin(10);
+        machine.set_start(10);
         machine.execute().unwrap();
         assert_eq!(machine.read_register(0).unwrap(), 5);
         assert_eq!(machine.read_register(1).unwrap(), 0);
     }
 
+    #[test]
+    pub fn pop_variants() {
+        let code = [
+            0xf001a001, 7, // 10: PUSH 7
+            0xf002a004, 0, // 12: POP r0
+            0xf001a001, 8, // 14: PUSH 8
+            0xf002a005, 60, // 16: POP &60
+            0xf006a006, 1, 2, // 18: MOVE r1 2
+            0xf001a001, 9, // 21: PUSH 9
+            0xf002a039, 60, 1, // 23: POP [60 + r1]
+            0xf006a006, 2, 64, // 26: MOVE r2 64
+            0xf001a001, 0xffffffff, // 29: PUSH -1
+            0xf002a03a, 2, // 31: POP &r2
+            0xffff0000, // 33: TERM
+        ];
+        let mut machine = Machine::new(MachineOptions{memory_cells: 1024, memory_stack_size: 256}).unwrap();
+        machine.load_data(10, &code).unwrap();
+        machine.set_start(10);
+        machine.execute().unwrap();
+        assert_eq!(machine.read_register(0).unwrap(), 7);
+        assert_eq!(machine.memory.read(60).unwrap(), 8);
+        assert_eq!(machine.memory.read(62).unwrap(), 9);
+        assert_eq!(machine.memory.read(64).unwrap(), 0xffffffff);
+        assert!(machine.flag.negative && !machine.flag.zero);
+    }
+
     #[test]
     pub fn execute_interrupt() {
         let code = [
@@ -53,7 +79,65 @@ pub mod tests {
         ];
         let mut machine = Machine::new(MachineOptions{memory_cells: 1024, memory_stack_size: 256}).unwrap();
         machine.load_data(10, &code).unwrap();
-        machine.set_origin(10);
+        machine.set_start(10);
         machine.execute().unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn indirect_jumps() {
+        let code = [
+            0xf006a006, 0, 20, // 10: MOVE r0 20
+            0xf008a022, 0, // 13: JMP r0
+            0xffff0000, // 15: TERM (skipped)
+            0, 0, 0, 0, // 16..19
+            0xf006a006, 1, 1, // 20: MOVE r1 1
+            0xf008a024, 40, 1, // 23: JMP [40 + r1]
+            0xffff0000, // 26: TERM (skipped)
+            0xf001a001, 0, // 27: PUSH 0
+            0xf002a004, 2, // 29: POP r2 (sets zero flag)
+            0xf008a029, 42, // 31: JZ &42
+            0xffff0000, // 33: TERM (skipped)
+            0xf006a006, 3, 7, // 34: MOVE r3 7
+            0xffff0000, // 37: TERM
+            0, 0, // 38..39
+            15, 27, 34, // 40: jump table
+        ];
+        let mut machine = Machine::new(MachineOptions{memory_cells: 1024, memory_stack_size: 256}).unwrap();
+        machine.load_data(10, &code).unwrap();
+        machine.set_start(10);
+        machine.execute().unwrap();
+        assert_eq!(machine.read_register(1).unwrap(), 1);
+        assert_eq!(machine.read_register(3).unwrap(), 7);
+    }
+
+    #[test]
+    pub fn packed_strings() {
+        let mut memory = Memory::new(64, 8).unwrap();
+        // "hé!" as UTF-8 bytes, zero-terminated, then counted to the middle of "é"
+        memory.write(0, &[0x68c3a921, 0x00000000]).unwrap();
+        memory.write(2, &[2, 0x68c3a921]).unwrap();
+        assert_eq!(read_byte_string(&memory, 0, false).unwrap(), "hé!");
+        assert_eq!(read_byte_string(&memory, 2, true).unwrap(), "h\u{fffd}");
+        // "a€" as UTF-16 half-words, zero-terminated, then counted
+        memory.write(4, &[0x006120ac, 0x00000000]).unwrap();
+        memory.write(6, &[1, 0x006120ac]).unwrap();
+        assert_eq!(read_half_word_string(&memory, 4, false).unwrap(), "a€");
+        assert_eq!(read_half_word_string(&memory, 6, true).unwrap(), "a");
+    }
+
+    #[test]
+    pub fn packed_strings_at_invalid_addresses() {
+        let code = [
+            0xf001a001, 0xffffffff, // PUSH 0xffffffff
+            0xf0120000, 0, 6, // INT 0 6
+            0xffff0000, // TERM
+        ];
+        let mut machine = Machine::new(MachineOptions{memory_cells: 1024, memory_stack_size: 256}).unwrap();
+        machine.load_data(10, &code).unwrap();
+        machine.set_start(10);
+        assert!(matches!(machine.execute(), Err(VMError::InvalidAddress)));
+        let memory = Memory::new(64, 8).unwrap();
+        assert!(matches!(read_byte_string(&memory, u32::MAX, true), Err(VMError::InvalidAddress)));
+        assert!(matches!(read_half_word_string(&memory, u32::MAX, false), Err(VMError::InvalidAddress)));
+    }
+}
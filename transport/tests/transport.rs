@@ -0,0 +1,32 @@
+#[cfg(test)]
+pub mod tests {
+    use std::io::{BufReader, Cursor, ErrorKind};
+
+    use serde_json::json;
+    use transport::{read_message, write_message};
+
+    #[test]
+    pub fn round_trip() {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({ "id": 1, "text": "héllo" })).unwrap();
+        write_message(&mut output, &json!([])).unwrap();
+        // the length counts bytes, not characters
+        assert!(output.starts_with(b"Content-Length: 24\r\n\r\n{"));
+        let mut reader = BufReader::new(Cursor::new(output));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({ "id": 1, "text": "héllo" })));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!([])));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    pub fn headers() {
+        let input = "content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}";
+        assert_eq!(read_message(&mut BufReader::new(Cursor::new(input))).unwrap(), Some(json!({})));
+        let error = read_message(&mut BufReader::new(Cursor::new("Content-Type: json\r\n\r\n{}"))).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = read_message(&mut BufReader::new(Cursor::new("Content-Length: 3\r\n\r\n{]}"))).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = read_message(&mut BufReader::new(Cursor::new("Content-Length: 10\r\n\r\n{}"))).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}